@@ -5,7 +5,7 @@ extern crate rcc;
 fn main() {
     fuzz!(|data: &[u8]| {
         if let Ok(s) = std::str::from_utf8(data) {
-            rcc::compile(s.into(), "<fuzz test>".into(), false, false, false);
+            rcc::compile(s.into(), "<fuzz test>".into(), &Default::default());
         }
     });
 }
@@ -40,9 +40,50 @@ pub fn union_size(symbols: &[Symbol]) -> Result<SIZE_T, &'static str> {
 }
 
 pub fn struct_size(symbols: &[Symbol]) -> Result<SIZE_T, &'static str> {
-    symbols.iter().try_fold(0, |offset, symbol| {
-        Ok(Type::next_offset(offset, &symbol.ctype)?)
-    })
+    struct_layout(symbols).map(|(_, size)| size)
+}
+
+/// Calculate where each member of a struct is stored.
+///
+/// Returns the offset of each member in bytes, the offset in bits of each member
+/// from the start of its storage unit (always 0 unless the member is a bit-field),
+/// and the size of the struct.
+///
+/// Adjacent bit-fields are packed into the same storage unit as long as they fit;
+/// a bit-field which does not fit starts a new storage unit.
+/// Storage units are the size of the declared type of the bit-field.
+pub fn struct_layout(symbols: &[Symbol]) -> Result<(Vec<(SIZE_T, u16)>, SIZE_T), &'static str> {
+    let char_bit = SIZE_T::from(CHAR_BIT);
+    let mut offsets = Vec::with_capacity(symbols.len());
+    // the number of bits used so far
+    let mut bits = 0;
+    // the end of the last storage unit used by a bit-field, in bytes
+    let mut end = 0;
+    for symbol in symbols {
+        match &symbol.ctype {
+            Bitfield(inner, width) => {
+                let unit = inner.sizeof()? * char_bit;
+                let width = SIZE_T::from(*width);
+                // doesn't fit in the current storage unit, start a new one
+                if bits % unit + width > unit {
+                    bits += unit - bits % unit;
+                }
+                let start = bits - bits % unit;
+                offsets.push((start / char_bit, (bits - start) as u16));
+                bits += width;
+                end = max(end, (start + unit) / char_bit);
+            }
+            ctype => {
+                // round up to the next byte
+                let current_offset = (bits + char_bit - 1) / char_bit;
+                let next_offset = Type::next_offset(current_offset, ctype)?;
+                offsets.push((next_offset - ctype.sizeof()?, 0));
+                bits = next_offset * char_bit;
+            }
+        }
+    }
+    let size = max(end, (bits + char_bit - 1) / char_bit);
+    Ok((offsets, size))
 }
 
 pub fn struct_align(members: &[Symbol]) -> Result<SIZE_T, &'static str> {
@@ -71,8 +112,14 @@ impl Type {
             Double => Ok(DOUBLE_SIZE.into()),
             Pointer(_) => Ok(PTR_SIZE.into()),
             // now for the hard ones
-            Array(t, ArrayType::Fixed(l)) => t.sizeof().and_then(|n| Ok(n * l)),
-            Array(_, ArrayType::Unbounded) => Err("cannot take sizeof variable length array"),
+            Array(t, ArrayType::Fixed(l)) => t.sizeof().and_then(|n| {
+                n.checked_mul(*l)
+                    .ok_or("array is too large to fit in memory")
+            }),
+            Array(_, ArrayType::Unbounded) => Err("cannot take sizeof array of unknown length"),
+            Array(_, ArrayType::Variable(_)) => {
+                Err("cannot take sizeof variable length array at compile time")
+            }
             Enum(_, symbols) => {
                 let uchar = CHAR_BIT as usize;
                 // integer division, but taking the ceiling instead of the floor
@@ -87,7 +134,7 @@ impl Type {
             }
             Union(struct_type) => union_size(&struct_type.members()),
             Struct(struct_type) => struct_size(&struct_type.members()),
-            Bitfield(_) => unimplemented!("sizeof(bitfield)"),
+            Bitfield(inner, _) => inner.sizeof(),
             // illegal operations
             Function(_) => Err("cannot take `sizeof` a function"),
             Void => Err("cannot take `sizeof` void"),
@@ -111,7 +158,7 @@ impl Type {
             // Not sure why, but who am I to argue
             // Anyway, Faerie panics if the alignment isn't a power of two so it's probably for the best
             Union(struct_type) | Struct(struct_type) => struct_align(&struct_type.members()),
-            Bitfield(_) => unimplemented!("alignof bitfield"),
+            Bitfield(inner, _) => inner.alignof(),
             Function(_) => Err("cannot take `alignof` function"),
             Void => Err("cannot take `alignof` void"),
             VaList => Err("cannot take `alignof` va_list"),
@@ -122,15 +169,14 @@ impl Type {
         IrType::int(CHAR_BIT * PTR_SIZE).expect("pointer size should be valid")
     }
     pub fn struct_offset(&self, members: &[Symbol], member: InternedStr) -> u64 {
-        let mut current_offset = 0;
-        for formal in members {
-            if formal.id == member {
-                return current_offset;
-            }
-            current_offset = Self::next_offset(current_offset, &formal.ctype)
-                .expect("structs should have valid size and alignment");
-        }
-        unreachable!("cannot call struct_offset for member not in struct");
+        let index = members
+            .iter()
+            .position(|formal| formal.id == member)
+            .expect("cannot call struct_offset for member not in struct");
+        // later members don't affect the offset
+        let (offsets, _) = struct_layout(&members[..=index])
+            .expect("structs should have valid size and alignment");
+        offsets[index].0
     }
     fn next_offset(mut current_offset: u64, ctype: &Type) -> Result<u64, &'static str> {
         let align = ctype.alignof()?;
@@ -258,6 +304,7 @@ mod tests {
             init: false,
             qualifiers: Qualifiers::NONE,
             storage_class: StorageClass::Auto,
+            attributes: Default::default(),
         }
     }
     fn struct_for_types(types: Vec<Type>) -> Type {
@@ -323,4 +370,19 @@ mod tests {
         assert_offset(vec![Type::Int(true), Type::Char(true)], 1, 4);
         assert_eq!(char_struct.sizeof().unwrap(), 5);
     }
+    #[test]
+    fn union_layout() {
+        let types = vec![type_for_size(1), type_for_size(16), type_for_size(4)];
+        let stype = match struct_for_types(types) {
+            Type::Struct(stype) => stype,
+            _ => unreachable!(),
+        };
+        let union_type = Type::Union(stype.clone());
+        // the size of a union is the size of its largest member
+        assert_eq!(union_type.sizeof().unwrap(), 16);
+        // and every member starts at the beginning of the union
+        for member in stype.members().iter() {
+            assert_eq!(union_type.member_offset(member.id), Ok(0));
+        }
+    }
 }
@@ -1,7 +1,10 @@
 use std::collections::VecDeque;
 use thiserror::Error;
 
-use super::{lex::Token, Expr, Locatable, Location, Type};
+use super::{
+    lex::{StrPrefix, Token},
+    Expr, Locatable, Location, Qualifiers, Std, Type,
+};
 
 /// RecoverableResult is a type that represents a Result that can be recovered from.
 ///
@@ -10,29 +13,113 @@ use super::{lex::Token, Expr, Locatable, Location, Type};
 /// [`Recover`]: trait.Recover.html
 pub type RecoverableResult<T, E = CompileError> = Result<T, (E, T)>;
 pub type CompileResult<T> = Result<T, CompileError>;
-pub type CompileError = Locatable<Error>;
 pub type CompileWarning = Locatable<Warning>;
 
+/// An error in the program being compiled.
+///
+/// Like a `Locatable<Error>`, but also records which part of the compiler reported it.
+/// Two errors are equal if their `data` is equal, the same as for `Locatable`.
+#[derive(Clone, Debug)]
+pub struct CompileError {
+    pub data: Error,
+    pub location: Location,
+    /// The phase which reported this error, see [`Phase`]
+    ///
+    /// [`Phase`]: enum.Phase.html
+    pub phase: Phase,
+}
+
 /// ErrorHandler is a struct that hold errors generated by the compiler
 ///
 /// An error handler is used because multiple errors may be generated by each
 /// part of the compiler, this cannot be represented well with Rust's normal
 /// `Result`.
 #[derive(Debug, Default, PartialEq)]
-pub(crate) struct ErrorHandler {
+pub struct ErrorHandler {
     errors: VecDeque<CompileError>,
-    pub(crate) warnings: VecDeque<CompileWarning>,
+    warnings: VecDeque<CompileWarning>,
+    /// The most warnings `drain_warnings` will return before suppressing the rest,
+    /// or `None` for no limit
+    warning_limit: Option<usize>,
+    /// The number of warnings `drain_warnings` has returned so far
+    warnings_drained: usize,
+    /// The phase that owns this handler
+    phase: Phase,
+}
+
+/// The part of the compiler which reported an error.
+///
+/// Errors are passed along from one phase to the next (e.g. the parser reports lex errors),
+/// so this is determined by the kind of error where possible,
+/// and by the phase that reported it otherwise.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Lex,
+    Preprocess,
+    Parse,
+    Sema,
+    Codegen,
+}
+
+impl Default for Phase {
+    fn default() -> Self {
+        Phase::Sema
+    }
+}
+
+impl Phase {
+    /// The phase which reports errors of this kind.
+    ///
+    /// Semantic errors can be reported by the code generator as well as the parser,
+    /// so they default to `Sema` until they are pushed to an `ErrorHandler`.
+    fn of(error: &Error) -> Phase {
+        match error {
+            Error::Lex(_) => Phase::Lex,
+            Error::PreProcessor(_) => Phase::Preprocess,
+            Error::Syntax(_) => Phase::Parse,
+            Error::Semantic(_) => Phase::Sema,
+        }
+    }
+}
+
+impl std::fmt::Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Phase::Lex => "lex",
+            Phase::Preprocess => "preprocess",
+            Phase::Parse => "parse",
+            Phase::Sema => "sema",
+            Phase::Codegen => "codegen",
+        };
+        write!(f, "{}", name)
+    }
 }
 
 impl ErrorHandler {
     /// Construct a new error handler.
-    pub(crate) fn new() -> ErrorHandler {
+    pub fn new() -> ErrorHandler {
         Default::default()
     }
 
+    /// Construct a new error handler for `phase`.
+    ///
+    /// Semantic errors pushed to this handler will be attributed to `phase`.
+    pub(crate) fn for_phase(phase: Phase) -> ErrorHandler {
+        ErrorHandler {
+            phase,
+            ..Default::default()
+        }
+    }
+
     /// Add an error to the error handler.
-    pub(crate) fn push_back<E: Into<CompileError>>(&mut self, error: E) {
-        self.errors.push_back(error.into());
+    ///
+    /// Semantic errors are attributed to the phase of this handler.
+    pub fn push_back<E: Into<CompileError>>(&mut self, error: E) {
+        let mut error = error.into();
+        if error.is_semantic_err() {
+            error.phase = self.phase;
+        }
+        self.errors.push_back(error);
     }
 
     /// Remove the first error from the queue
@@ -43,12 +130,67 @@ impl ErrorHandler {
     /// Stopgap to make it easier to transition to lazy warnings.
     ///
     /// TODO: Remove this method
-    pub(crate) fn warn<W: Into<Warning>>(&mut self, warning: W, location: Location) {
+    pub fn warn<W: Into<Warning>>(&mut self, warning: W, location: Location) {
         self.warnings.push_back(location.with(warning.into()));
     }
+    /// Remove and return every warning reported so far.
+    ///
+    /// If a warning limit is set and more than that many warnings have been drained in total,
+    /// the extra warnings are collapsed into a single `Warning::Suppressed`
+    /// at the location of the first one.
+    pub(crate) fn drain_warnings(&mut self) -> impl Iterator<Item = CompileWarning> {
+        let mut warnings = std::mem::replace(&mut self.warnings, VecDeque::new());
+        if let Some(limit) = self.warning_limit {
+            let remaining = limit.saturating_sub(self.warnings_drained);
+            if warnings.len() > remaining {
+                let suppressed = warnings.split_off(remaining);
+                let location = suppressed[0].location;
+                warnings.push_back(location.with(Warning::Suppressed(suppressed.len())));
+            }
+        }
+        self.warnings_drained += warnings.len();
+        warnings.into_iter()
+    }
+    /// Suppress all but the first `limit` warnings returned by `drain_warnings`.
+    pub(crate) fn set_warning_limit(&mut self, limit: Option<usize>) {
+        self.warning_limit = limit;
+    }
     /// Add an iterator of errors to the error queue
     pub(crate) fn extend<E: Into<CompileError>>(&mut self, iter: impl Iterator<Item = E>) {
-        self.errors.extend(iter.map(Into::into));
+        for error in iter {
+            self.push_back(error);
+        }
+    }
+}
+
+// These don't consume the queue, unlike iterating over the handler,
+// so callers can check whether compilation failed and still report every error afterwards.
+impl ErrorHandler {
+    /// Whether any errors have been reported.
+    pub fn has_errors(&self) -> bool {
+        !self.errors.is_empty()
+    }
+
+    /// The number of errors which have been reported and not yet removed.
+    pub fn error_count(&self) -> usize {
+        self.errors.len()
+    }
+
+    /// Whether any warnings have been reported.
+    pub fn has_warnings(&self) -> bool {
+        !self.warnings.is_empty()
+    }
+
+    /// The number of warnings which have been reported and not yet removed.
+    pub fn warning_count(&self) -> usize {
+        self.warnings.len()
+    }
+
+    /// The fix-its for every error and warning which has been reported and not yet removed.
+    pub fn fixits(&self) -> Vec<FixIt> {
+        let errors = self.errors.iter().filter_map(CompileError::fixit);
+        let warnings = self.warnings.iter().filter_map(CompileWarning::fixit);
+        errors.chain(warnings).collect()
     }
 }
 
@@ -93,6 +235,9 @@ pub enum SemanticError {
     #[error("not a constant expression: {0}")]
     NotConstant(Expr),
 
+    #[error("case label does not reduce to an integer constant")]
+    NonConstantCaseLabel(Expr),
+
     // String is the reason it couldn't be assigned
     #[error("cannot assign to {0}")]
     NotAssignable(String),
@@ -100,6 +245,15 @@ pub enum SemanticError {
     #[error("cannot take address of {0}")]
     InvalidAddressOf(&'static str),
 
+    #[error("array subscript is not an integer (has type '{0}')")]
+    ArraySubscriptNotInteger(Type),
+
+    #[error("void value not ignored as it ought to be")]
+    VoidValueUsed,
+
+    #[error("incompatible types '{0}' and '{1}'")]
+    IncompatibleTypes(Type, Type),
+
     #[error("cannot divide by zero")]
     DivideByZero,
 
@@ -123,18 +277,76 @@ pub enum SemanticError {
     #[error("use of undeclared label {0}")]
     UndeclaredLabel(crate::intern::InternedStr),
 
+    // String is the label, `case N`, or `default`
+    #[error("jump to '{0}' enters the scope of a variable length array")]
+    GotoIntoVlaScope(String),
+
     #[error("{}case outside of switch statement", if *(.is_default) { "default " } else { "" })]
     CaseOutsideSwitch { is_default: bool },
 
     #[error("cannot have multiple default cases in a switch statement")]
     MultipleDefaultCase,
 
+    // String is the value of the label after conversion to the type of the condition
+    #[error("duplicate case value {0} in switch statement")]
+    DuplicateCase(String),
+
+    #[error("'break' statement not in loop or switch statement")]
+    BreakOutsideLoop,
+
+    #[error("'continue' statement not in loop{}", if *(.in_switch) { " (a switch statement is not a loop)" } else { "" })]
+    ContinueOutsideLoop { in_switch: bool },
+
+    #[error(
+        "nested functions are not supported. hint: try declaring '{0}' as `static` at file scope"
+    )]
+    NestedFunction(crate::intern::InternedStr),
+
+    #[error("declaration for parameter '{0}' but no such parameter")]
+    NotAParameter(crate::intern::InternedStr),
+
+    #[error("thread-local variable '{0}' is not supported by this backend")]
+    ThreadLocalUnsupported(crate::intern::InternedStr),
+
     #[error("void must be the first and only parameter if specified")]
     InvalidVoidParameter,
 
     #[error("expected expression, got typedef")]
     TypedefInExpressionContext,
 
+    #[error("variable-length arrays are not permitted")]
+    VlaForbidden,
+
+    #[error("ISO C forbids {0}")]
+    Extension(&'static str),
+
+    #[error("type specifier missing; implicit int is not allowed in {0}")]
+    MissingTypeSpecifier(Std),
+
+    #[error("called object of type '{0}' is not a function")]
+    NotAFunction(Type),
+
+    #[error("switch quantity not an integer (got '{0}')")]
+    NonIntegerSwitch(Type),
+
+    #[error("cannot take `sizeof` incomplete type '{0}'")]
+    SizeofIncompleteType(Type),
+
+    #[error("conflicting types for '{name}'")]
+    ConflictingTypes {
+        name: crate::intern::InternedStr,
+        /// where `name` was first declared
+        previous: Location,
+    },
+
+    #[error("too many elements in initializer for array of size {size} (got {count})")]
+    InvalidArrayInitializer { size: u64, count: usize },
+
+    #[error(
+        "member '{0}' is ambiguous: it is declared in more than one anonymous struct or union"
+    )]
+    AmbiguousMember(crate::intern::InternedStr),
+
     #[doc(hidden)]
     #[error("internal error: do not construct nonexhaustive variants")]
     __Nonexhaustive,
@@ -152,6 +364,12 @@ pub enum SyntaxError {
     #[error("expected statement, got {0}")]
     NotAStatement(super::Keyword),
 
+    #[error("unclosed '{{' at end of file")]
+    UnclosedBrace,
+
+    #[error("expected ';', got '{0}'")]
+    MissingSemicolon(Token),
+
     #[doc(hidden)]
     #[error("internal error: do not construct nonexhaustive variants")]
     __Nonexhaustive,
@@ -186,6 +404,18 @@ pub enum CppError {
     #[error("#endif without #if")]
     UnexpectedEndIf,
 
+    #[error("could not find header {0}")]
+    HeaderNotFound(String),
+
+    #[error("expected \"FILENAME\" or <FILENAME> after macro replacement in #include, got '{0}'")]
+    InvalidHeaderName(String),
+
+    #[error("#include nested too deeply")]
+    IncludeDepth,
+
+    #[error("macro expansion nested too deeply")]
+    ExpansionDepth,
+
     #[doc(hidden)]
     #[error("internal error: do not construct nonexhaustive variants")]
     __Nonexhaustive,
@@ -200,6 +430,12 @@ pub enum LexError {
     #[error("unterminated /* comment")]
     UnterminatedComment,
 
+    #[error("missing terminating '\"' character in string literal{}", if *(.at_newline) { " (use '\\n' for a newline, or end the line with '\\' to continue the string)" } else { "" })]
+    UnterminatedString { at_newline: bool },
+
+    #[error("cannot concatenate string literals with different prefixes ({0}\"\" and {1}\"\")")]
+    IncompatibleStringPrefixes(StrPrefix, StrPrefix),
+
     #[doc(hidden)]
     #[error("internal error: do not construct nonexhaustive variants")]
     __Nonexhaustive,
@@ -212,6 +448,98 @@ pub enum Warning {
     #[error("{0}")]
     Generic(String),
 
+    #[error("#pragma message: {0}")]
+    PragmaMessage(String),
+
+    #[error("unknown pragma ignored")]
+    UnknownPragma,
+
+    #[error("comment after #endif names '{0}', which is not part of the matching #if")]
+    MismatchedEndif(crate::intern::InternedStr),
+
+    #[error("macro '{0}' redefined with a different replacement list")]
+    MacroRedefinition(crate::intern::InternedStr),
+
+    #[error("implicit declaration of function '{0}'")]
+    ImplicitFunctionDeclaration(crate::intern::InternedStr),
+
+    #[error("label '{0}' is unreachable")]
+    UnreachableLabel(crate::intern::InternedStr),
+
+    #[error("unknown conversion specifier '{0}' in format string")]
+    FormatSpecifier(String),
+
+    #[error("format string expects {0} arguments, but {1} were given")]
+    FormatArgumentCount(usize, usize),
+
+    #[error("format '{0}' expects {1}, but the argument has type '{2}'")]
+    FormatType(String, &'static str, Type),
+
+    #[error("statement has no effect")]
+    NoEffect,
+
+    #[error("cast from pointer to '{0}{1}' to '{2}' discards qualifiers")]
+    CastDiscardsQualifiers(Qualifiers, Type, Type),
+
+    #[error("{} body is empty but the {} body is not", if *(.is_else) { "else" } else { "if" }, if *(.is_else) { "if" } else { "else" })]
+    EmptyBranch { is_else: bool },
+
+    #[error("unused variable '{0}'")]
+    UnusedVariable(crate::intern::InternedStr),
+
+    #[error("'{name}' is deprecated{}", if .message.is_empty() { String::new() } else { format!(": {}", .message) })]
+    Deprecated {
+        name: crate::intern::InternedStr,
+        message: crate::intern::InternedStr,
+        /// where `name` was declared
+        declared: Location,
+    },
+
+    #[error("unknown attribute '{0}' ignored")]
+    UnknownAttribute(crate::intern::InternedStr),
+
+    #[error("integer constant {0} is so large that it is unsigned")]
+    UnsignedConstant(u64),
+
+    #[error("implicit conversion from '{0}' to '{1}' may lose precision")]
+    ImplicitConversion(Type, Type),
+
+    #[error("left shift is performed in '{0}' before being converted to '{1}'; cast the left operand to '{1}' to keep the high bits")]
+    NarrowShift(Type, Type),
+
+    #[error("{0} further warnings suppressed")]
+    Suppressed(usize),
+
+    #[error("using the result of an assignment as a condition; did you mean '=='?")]
+    AssignmentInCondition,
+
+    #[error("assigning '{0}' to itself has no effect")]
+    SelfAssignment(crate::intern::InternedStr),
+
+    #[error("address of local variable '{0}' will dangle once the function returns")]
+    DanglingLocalAddress(crate::intern::InternedStr),
+
+    #[error("case label value {0} is converted to {1} in a switch on '{2}'")]
+    CaseConversion(i64, i64, Type),
+
+    #[error("case label value {0} is out of range for a switch on '{1}' and can never match")]
+    CaseOutOfRange(i64, Type),
+
+    #[error("'{0}' is not a standard signature for main (expected 'int main(void)' or 'int main(int, char **)')")]
+    NonstandardMain(Type),
+
+    #[error("ISO C forbids {0}")]
+    Extension(&'static str),
+
+    #[error("type specifier missing, defaults to 'int'")]
+    ImplicitInt,
+
+    #[error("sizeof on array function parameter '{0}' returns the size of '{1}'")]
+    SizeofArrayParameter(crate::intern::InternedStr, Type),
+
+    #[error("switch has only a 'default' label and always runs its body; use a block instead")]
+    SwitchOnlyDefault,
+
     #[doc(hidden)]
     #[error("internal error: do not construct nonexhaustive variants")]
     __Nonexhaustive,
@@ -223,7 +551,106 @@ impl<T: Into<String>> From<T> for Warning {
     }
 }
 
+impl Warning {
+    /// The name used to refer to this kind of warning, e.g. in `rcc:allow(unused-variable)`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Warning::Generic(_) => "generic",
+            Warning::PragmaMessage(_) => "pragma-message",
+            Warning::UnknownPragma => "unknown-pragma",
+            Warning::MismatchedEndif(_) => "mismatched-endif",
+            Warning::MacroRedefinition(_) => "macro-redefinition",
+            Warning::ImplicitFunctionDeclaration(_) => "implicit-function-declaration",
+            Warning::UnreachableLabel(_) => "unreachable-label",
+            Warning::FormatSpecifier(_) => "format-specifier",
+            Warning::FormatArgumentCount(_, _) => "format-argument-count",
+            Warning::FormatType(_, _, _) => "format-type",
+            Warning::NoEffect => "no-effect",
+            Warning::CastDiscardsQualifiers(_, _, _) => "cast-discards-qualifiers",
+            Warning::EmptyBranch { .. } => "empty-branch",
+            Warning::UnusedVariable(_) => "unused-variable",
+            Warning::Deprecated { .. } => "deprecated",
+            Warning::UnknownAttribute(_) => "unknown-attribute",
+            Warning::UnsignedConstant(_) => "unsigned-constant",
+            Warning::ImplicitConversion(_, _) => "implicit-conversion",
+            Warning::NarrowShift(_, _) => "narrow-shift",
+            Warning::Suppressed(_) => "suppressed",
+            Warning::AssignmentInCondition => "assignment-in-condition",
+            Warning::SelfAssignment(_) => "self-assignment",
+            Warning::DanglingLocalAddress(_) => "dangling-local-address",
+            Warning::CaseConversion(_, _, _) => "case-conversion",
+            Warning::CaseOutOfRange(_, _) => "case-out-of-range",
+            Warning::NonstandardMain(_) => "nonstandard-main",
+            Warning::Extension(_) => "extension",
+            Warning::ImplicitInt => "implicit-int",
+            Warning::SizeofArrayParameter(_, _) => "sizeof-array-parameter",
+            Warning::SwitchOnlyDefault => "switch-only-default",
+            Warning::__Nonexhaustive => unreachable!("do not construct nonexhaustive variants"),
+        }
+    }
+}
+
+/// A comment which silences warnings on its own line and the line after it.
+///
+/// `// NOLINT` silences every warning, and `// rcc:allow(<name>)` only warnings
+/// with that `Warning::name`. Both forms can also be written as `/* */` comments.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Suppression {
+    pub(crate) filename: crate::intern::InternedStr,
+    /// The offset of the start of the comment's line
+    pub(crate) start: u32,
+    /// The offset of the end of the line after the comment
+    pub(crate) end: u32,
+    /// The name of the warning to silence, or `None` for every warning
+    pub(crate) warning: Option<String>,
+}
+
+impl Suppression {
+    /// If `comment` (without the comment delimiters) silences warnings,
+    /// a suppression starting at `start` which lasts until the end of the file.
+    pub(crate) fn from_comment(
+        comment: &str,
+        filename: crate::intern::InternedStr,
+        start: u32,
+    ) -> Option<Suppression> {
+        let comment = comment.trim();
+        const ALLOW: &str = "rcc:allow(";
+        let warning = if comment == "NOLINT" {
+            None
+        } else if comment.starts_with(ALLOW) && comment.ends_with(')') {
+            let name = &comment[ALLOW.len()..comment.len() - 1];
+            Some(name.trim().to_string())
+        } else {
+            return None;
+        };
+        Some(Suppression {
+            filename,
+            start,
+            end: u32::max_value(),
+            warning,
+        })
+    }
+    /// Whether this suppression silences `warning`.
+    pub(crate) fn covers(&self, warning: &CompileWarning) -> bool {
+        let offset = warning.location.span.start().to_usize() as u32;
+        warning.location.filename == self.filename
+            && self.start <= offset
+            && offset <= self.end
+            && self
+                .warning
+                .as_ref()
+                .map_or(true, |name| name == warning.data.name())
+    }
+}
+
 impl CompileError {
+    pub fn new(data: Error, location: Location) -> CompileError {
+        CompileError {
+            phase: Phase::of(&data),
+            data,
+            location,
+        }
+    }
     pub(crate) fn semantic(err: Locatable<String>) -> Self {
         Self::from(err)
     }
@@ -239,6 +666,61 @@ impl CompileError {
     pub fn is_semantic_err(&self) -> bool {
         self.data.is_semantic_err()
     }
+    /// A machine-applicable fix for this error, if there is one.
+    pub fn fixit(&self) -> Option<FixIt> {
+        match &self.data {
+            Error::Syntax(SyntaxError::MissingSemicolon(_)) => {
+                Some(FixIt::insert(self.location, ";"))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl CompileWarning {
+    /// A machine-applicable fix for this warning, if there is one.
+    pub fn fixit(&self) -> Option<FixIt> {
+        match &self.data {
+            Warning::AssignmentInCondition => Some(FixIt {
+                location: self.location,
+                replacement: "==".into(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// A suggested edit which fixes a diagnostic: replace the source at `location` with `replacement`.
+///
+/// If the span of `location` is empty, the replacement is inserted instead.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FixIt {
+    pub location: Location,
+    pub replacement: String,
+}
+
+impl FixIt {
+    /// Insert `text` just before `location`.
+    fn insert(location: Location, text: &str) -> FixIt {
+        let start = location.span.start();
+        FixIt {
+            location: Location {
+                span: codespan::Span::new(start, start),
+                ..location
+            },
+            replacement: text.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for FixIt {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.location.span.start() == self.location.span.end() {
+            write!(f, "insert '{}'", self.replacement)
+        } else {
+            write!(f, "replace with '{}'", self.replacement)
+        }
+    }
 }
 
 impl Error {
@@ -265,27 +747,46 @@ impl Error {
     }
 }
 
+impl PartialEq for CompileError {
+    fn eq(&self, other: &Self) -> bool {
+        self.data == other.data
+    }
+}
+
+impl From<Locatable<Error>> for CompileError {
+    fn from(err: Locatable<Error>) -> Self {
+        CompileError::new(err.data, err.location)
+    }
+}
+
 impl From<Locatable<String>> for CompileError {
     fn from(err: Locatable<String>) -> Self {
-        err.map(|s| SemanticError::Generic(s).into())
+        err.map(|s| Error::Semantic(SemanticError::Generic(s)))
+            .into()
     }
 }
 
 impl From<Locatable<SemanticError>> for CompileError {
     fn from(err: Locatable<SemanticError>) -> Self {
-        err.map(Error::Semantic)
+        err.map(Error::Semantic).into()
     }
 }
 
 impl From<Locatable<SyntaxError>> for CompileError {
     fn from(err: Locatable<SyntaxError>) -> Self {
-        err.map(Error::Syntax)
+        err.map(Error::Syntax).into()
     }
 }
 
 impl From<Locatable<CppError>> for CompileError {
     fn from(err: Locatable<CppError>) -> Self {
-        err.map(Error::PreProcessor)
+        err.map(Error::PreProcessor).into()
+    }
+}
+
+impl From<Locatable<LexError>> for CompileError {
+    fn from(err: Locatable<LexError>) -> Self {
+        err.map(Error::Lex).into()
     }
 }
 
@@ -301,6 +802,12 @@ impl<S: Into<String>> From<S> for SemanticError {
     }
 }
 
+impl<S: Into<String>> From<S> for LexError {
+    fn from(msg: S) -> Self {
+        LexError::Generic(msg.into())
+    }
+}
+
 impl<S: Into<String>> From<S> for SyntaxError {
     fn from(err: S) -> Self {
         SyntaxError::Generic(err.into())
@@ -337,11 +844,11 @@ mod tests {
     use super::*;
 
     fn dummy_error() -> CompileError {
-        Location::default().with(Error::Lex(LexError::UnterminatedComment))
+        Location::default().error(LexError::UnterminatedComment)
     }
 
     fn new_error(error: Error) -> CompileError {
-        Location::default().with(error)
+        Location::default().error(error)
     }
 
     #[test]
@@ -354,10 +861,113 @@ mod tests {
             ErrorHandler {
                 errors: vec_deque![dummy_error()],
                 warnings: VecDeque::new(),
+                warning_limit: None,
+                warnings_drained: 0,
+                phase: Phase::Sema,
             }
         );
     }
 
+    #[test]
+    fn test_error_handler_phases() {
+        let semantic = || new_error(Error::Semantic(SemanticError::EmptyProgram));
+        let syntax = || new_error(Error::Syntax(SyntaxError::from("oranges")));
+        let cpp = || new_error(Error::PreProcessor(CppError::Generic("apples".into())));
+        let phases = |handler: ErrorHandler| handler.map(|error| error.phase).collect::<Vec<_>>();
+
+        // semantic errors belong to the phase that reported them
+        let mut parser = ErrorHandler::for_phase(Phase::Sema);
+        parser.push_back(semantic());
+        // errors passed along from earlier phases keep their own phase
+        parser.push_back(syntax());
+        parser.push_back(dummy_error());
+        parser.push_back(cpp());
+        assert_eq!(
+            phases(parser),
+            vec![Phase::Sema, Phase::Parse, Phase::Lex, Phase::Preprocess]
+        );
+
+        let mut codegen = ErrorHandler::for_phase(Phase::Codegen);
+        codegen.extend(vec![semantic(), semantic()].into_iter());
+        assert_eq!(phases(codegen), vec![Phase::Codegen, Phase::Codegen]);
+
+        let mut cpp_handler = ErrorHandler::for_phase(Phase::Preprocess);
+        cpp_handler.push_back(semantic());
+        assert_eq!(phases(cpp_handler), vec![Phase::Preprocess]);
+
+        assert_eq!(Phase::Codegen.to_string(), "codegen");
+    }
+
+    #[test]
+    fn test_error_handler_counts() {
+        let mut error_handler = ErrorHandler::new();
+        assert!(!error_handler.has_errors());
+        assert!(!error_handler.has_warnings());
+        assert_eq!(error_handler.error_count(), 0);
+
+        error_handler.push_back(dummy_error());
+        error_handler.push_back(dummy_error());
+        error_handler.warn("", Location::default());
+        assert!(error_handler.has_errors());
+        assert_eq!(error_handler.error_count(), 2);
+        assert!(error_handler.has_warnings());
+        assert_eq!(error_handler.warning_count(), 1);
+
+        // checking the counts doesn't remove anything
+        assert_eq!(error_handler.error_count(), 2);
+        assert_eq!(error_handler.collect::<Vec<_>>().len(), 2);
+    }
+
+    #[test]
+    fn test_error_handler_drain_warnings() {
+        let mut error_handler = ErrorHandler::new();
+        error_handler.warn("first", Location::default());
+        error_handler.warn("second", Location::default());
+        let drained: Vec<_> = error_handler
+            .drain_warnings()
+            .map(|warning| warning.data)
+            .collect();
+        assert_eq!(
+            drained,
+            vec![Warning::from("first"), Warning::from("second")]
+        );
+        // draining removes the warnings
+        assert!(!error_handler.has_warnings());
+        assert_eq!(error_handler.drain_warnings().count(), 0);
+    }
+
+    #[test]
+    fn test_error_handler_warning_limit() {
+        let drain = |handler: &mut ErrorHandler| -> Vec<_> {
+            handler
+                .drain_warnings()
+                .map(|warning| warning.data)
+                .collect()
+        };
+        let mut error_handler = ErrorHandler::new();
+        error_handler.set_warning_limit(Some(2));
+        for _ in 0..5 {
+            error_handler.warn("", Location::default());
+        }
+        assert_eq!(
+            drain(&mut error_handler),
+            vec![Warning::from(""), Warning::from(""), Warning::Suppressed(3)]
+        );
+        // the limit counts every warning drained so far
+        error_handler.warn("", Location::default());
+        assert_eq!(drain(&mut error_handler), vec![Warning::Suppressed(1)]);
+
+        // at the limit, nothing is suppressed
+        let mut error_handler = ErrorHandler::new();
+        error_handler.set_warning_limit(Some(1));
+        error_handler.warn("", Location::default());
+        assert_eq!(drain(&mut error_handler), vec![Warning::from("")]);
+        assert_eq!(
+            Warning::Suppressed(3).to_string(),
+            "3 further warnings suppressed"
+        );
+    }
+
     #[test]
     fn test_error_handler_into_iterator() {
         let mut error_handler = ErrorHandler::new();
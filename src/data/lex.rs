@@ -1,3 +1,5 @@
+use super::types::Type;
+use crate::get_str;
 use crate::intern::InternedStr;
 
 use codespan::Span;
@@ -10,6 +12,10 @@ use std::cmp::Ordering;
 pub struct Location {
     pub span: Span,
     pub filename: InternedStr,
+    /// If this location came from replacing a macro, the name of the macro.
+    ///
+    /// The span is still where the macro was used, not where it was defined.
+    pub expansion: Option<InternedStr>,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -70,6 +76,8 @@ pub enum Keyword {
     // weird qualifiers
     Atomic,
     ThreadLocal,
+    /// `__thread`, which the parser treats as `_Thread_local`
+    GnuThreadLocal,
     // function qualifiers
     Inline,
     NoReturn,
@@ -86,6 +94,8 @@ pub enum Keyword {
     StaticAssert,
     Alignas,
     Alignof,
+    BuiltinExpect,
+    BuiltinUnreachable,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -119,8 +129,30 @@ pub enum Literal {
     Int(i64),
     UnsignedInt(u64),
     Float(f64),
-    Str(InternedStr),
+    Str(InternedStr, StrPrefix),
     Char(u8),
+    /// A character constant with an encoding prefix, like `L'a'`.
+    ///
+    /// Expressions store this as an integer with the element type of the prefix.
+    WideChar(u32, StrPrefix),
+}
+
+/// The encoding prefix of a string literal or character constant.
+///
+/// See sections 6.4.4.4 and 6.4.5 of the C11 standard.
+/// `u8` is only allowed on string literals.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum StrPrefix {
+    /// `"abc"`
+    Plain,
+    /// `u8"abc"`
+    Utf8,
+    /// `L"abc"`
+    Wide,
+    /// `u"abc"`
+    Utf16,
+    /// `U"abc"`
+    Utf32,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -178,7 +210,7 @@ impl Location {
     }
 
     pub fn error<E: Into<super::error::Error>>(self, error: E) -> super::CompileError {
-        self.with(error.into())
+        super::CompileError::new(error.into(), self)
     }
 }
 
@@ -211,6 +243,39 @@ impl Token {
     pub const EQUAL: Token = Token::Assignment(AssignmentToken::Equal);
 }
 
+impl StrPrefix {
+    /// The type of each element of a string literal with this prefix:
+    /// `char`, `wchar_t`, `char16_t`, or `char32_t`.
+    pub fn element_type(self) -> Type {
+        match self {
+            StrPrefix::Plain | StrPrefix::Utf8 => Type::Char(true),
+            StrPrefix::Wide => Type::Int(true),
+            StrPrefix::Utf16 => Type::Short(false),
+            StrPrefix::Utf32 => Type::Int(false),
+        }
+    }
+    /// The number of elements needed to store `s` with this encoding.
+    pub fn encoded_len(self, s: InternedStr) -> usize {
+        match self {
+            StrPrefix::Plain | StrPrefix::Utf8 => s.len(),
+            StrPrefix::Utf16 => get_str!(s).encode_utf16().count(),
+            StrPrefix::Wide | StrPrefix::Utf32 => get_str!(s).chars().count(),
+        }
+    }
+    /// The prefix of two adjacent string literals after concatenation,
+    /// or `None` if they cannot be concatenated.
+    ///
+    /// An unprefixed literal takes on the prefix of the other literal.
+    pub fn concat(self, other: StrPrefix) -> Option<StrPrefix> {
+        match (self, other) {
+            (StrPrefix::Plain, other) => Some(other),
+            (this, StrPrefix::Plain) => Some(this),
+            (this, other) if this == other => Some(this),
+            _ => None,
+        }
+    }
+}
+
 impl Literal {
     pub fn is_zero(&self) -> bool {
         match *self {
@@ -276,6 +341,7 @@ impl Default for Location {
         Self {
             span: (0..1).into(),
             filename: Default::default(),
+            expansion: None,
         }
     }
 }
@@ -292,8 +358,11 @@ impl std::fmt::Display for Keyword {
             | Keyword::Generic => write!(f, "_{:?}", self),
             Keyword::NoReturn => write!(f, "_Noreturn"),
             Keyword::ThreadLocal => write!(f, "_Thread_local"),
+            Keyword::GnuThreadLocal => write!(f, "__thread"),
             Keyword::StaticAssert => write!(f, "_Static_assert"),
             Keyword::VaList => write!(f, "va_list"),
+            Keyword::BuiltinExpect => write!(f, "__builtin_expect"),
+            Keyword::BuiltinUnreachable => write!(f, "__builtin_unreachable"),
             _ => write!(f, "{}", &format!("{:?}", self).to_lowercase()),
         }
     }
@@ -351,12 +420,26 @@ impl std::fmt::Display for Literal {
             Int(i) => write!(f, "{}", i),
             UnsignedInt(u) => write!(f, "{}", u),
             Float(n) => write!(f, "{}", n),
-            Str(s) => write!(f, "\"{}\"", s),
+            Str(s, prefix) => write!(f, "{}\"{}\"", prefix, s),
             Char(c) => write!(f, "{}", c),
+            WideChar(c, _) => write!(f, "{}", c),
         }
     }
 }
 
+impl std::fmt::Display for StrPrefix {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let prefix = match self {
+            StrPrefix::Plain => "",
+            StrPrefix::Utf8 => "u8",
+            StrPrefix::Wide => "L",
+            StrPrefix::Utf16 => "u",
+            StrPrefix::Utf32 => "U",
+        };
+        write!(f, "{}", prefix)
+    }
+}
+
 impl std::fmt::Display for ComparisonToken {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         use ComparisonToken::*;
@@ -404,7 +487,7 @@ impl From<ComparisonToken> for Token {
 mod test {
     use crate::*;
     fn cpp(s: &str) -> PreProcessor {
-        PreProcessor::new("<integration-test>", s.chars(), false)
+        PreProcessor::new("<integration-test>", s.chars(), false, Default::default())
     }
     #[test]
     fn assignment_display() {
@@ -8,13 +8,15 @@ use crate::arch::SIZE_T;
 pub mod error;
 pub mod lex;
 pub mod types;
+pub mod visit;
 pub mod prelude {
     pub(crate) use super::error::{ErrorHandler, Recover, RecoverableResult};
     pub use super::{
         error::{CompileError, CompileResult, CompileWarning, Error, SemanticError, SyntaxError},
         lex::{Literal, Locatable, Location, Token},
         types::{StructRef, StructType, Type},
-        Declaration, Expr, ExprType, Stmt, StmtType, Symbol,
+        Declaration, Endianness, Expr, ExprType, ImplicitFunctions, Pedantic, Std, Stmt, StmtType,
+        Symbol,
     };
     pub use crate::intern::InternedStr;
 }
@@ -130,6 +132,14 @@ pub enum ExprType {
     Assign(Box<Expr>, Box<Expr>, AssignmentToken),
     // Ternary: if ? then : else
     Ternary(Box<Expr>, Box<Expr>, Box<Expr>),
+    // GNU extension: `x ?: y`, which only evaluates `x` once
+    OmittedTernary(Box<Expr>, Box<Expr>),
+    // GNU extension: `__builtin_expect(expr, c)`, a hint that `expr` is probably `c`
+    Expect(Box<Expr>, i64),
+    // GNU extension: `__builtin_unreachable()`, traps if executed
+    Unreachable,
+    // GNU extension: `({ stmts; value; })`, which has the value of its last expression statement
+    StmtExpr(Vec<Stmt>, Option<Box<Expr>>),
     Comma(Box<Expr>, Box<Expr>),
     // &expr in static context
     // requires cooperation with the linker
@@ -147,6 +157,48 @@ pub enum StorageClass {
     Typedef = Keyword::Typedef as isize,
 }
 
+/// The version of the C standard to follow.
+///
+/// Features introduced in a later standard give a diagnostic in an earlier one.
+/// Variants are ordered, so `std < Std::C99` means 'before C99'.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Std {
+    C89,
+    C99,
+    C11,
+}
+
+/// What to do when a function is called before it has been declared.
+///
+/// C89 implicitly declares such functions as `extern int f();`; C99 removed this.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ImplicitFunctions {
+    /// Declare the function without a diagnostic
+    Allow,
+    /// Declare the function and emit a warning
+    Warn,
+    /// Reject the call as a use of an undeclared identifier
+    Error,
+}
+
+/// What to do when the program uses a GNU extension, like `x ?: y`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Pedantic {
+    /// Accept the extension without a diagnostic
+    Off,
+    /// Accept the extension and emit a warning (`--pedantic`)
+    Warn,
+    /// Reject the extension (`--pedantic-errors`)
+    Error,
+}
+
+/// The byte order used to lay out initialized data in the object file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Endianness {
+    Big,
+    Little,
+}
+
 /* structs */
 #[derive(Clone, Debug)]
 pub struct Symbol {
@@ -155,6 +207,7 @@ pub struct Symbol {
     pub qualifiers: Qualifiers,
     pub storage_class: StorageClass,
     pub init: bool,
+    pub attributes: Attributes,
 }
 
 #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
@@ -162,6 +215,18 @@ pub struct Qualifiers {
     pub volatile: bool,
     pub c_const: bool,
     pub inline: bool,
+    pub thread_local: bool,
+}
+
+/// The GNU attributes of a symbol, accumulated over all its declarations.
+/// Unlike qualifiers, these don't affect whether two declarations are compatible.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Attributes {
+    /// Set by `__attribute__((unused))`: don't warn if the variable is never used
+    pub unused: bool,
+    /// Set by `__attribute__((deprecated))`, with the message given to the attribute
+    /// (empty if there wasn't one): warn each time the symbol is used
+    pub deprecated: Option<InternedStr>,
 }
 
 #[derive(Debug)]
@@ -173,21 +238,25 @@ impl Qualifiers {
         c_const: false,
         volatile: false,
         inline: false,
+        thread_local: false,
     };
     pub const VOLATILE: Qualifiers = Qualifiers {
         c_const: false,
         volatile: true,
         inline: false,
+        thread_local: false,
     };
     pub const CONST: Qualifiers = Qualifiers {
         c_const: true,
         volatile: false,
         inline: false,
+        thread_local: false,
     };
     pub const CONST_VOLATILE: Qualifiers = Qualifiers {
         c_const: true,
         volatile: true,
         inline: false,
+        thread_local: false,
     };
 }
 
@@ -196,6 +265,7 @@ pub enum LengthError {
     Dynamic,
     NonIntegral,
     Negative,
+    Zero,
 }
 
 impl Expr {
@@ -217,6 +287,50 @@ impl Expr {
             x => unreachable!("should have been caught already: {:?}", x),
         }
     }
+    /// Whether evaluating this expression could do anything besides compute a value.
+    ///
+    /// Assignments, increments, function calls, and accesses to `volatile` variables
+    /// have side effects. Casts to `void` are also counted, since they're used to
+    /// explicitly discard a value.
+    pub fn has_side_effects(&self) -> bool {
+        use ExprType::*;
+        match &self.expr {
+            Assign(_, _, _) | PostIncrement(_, _) | FuncCall(_, _) | Unreachable => true,
+            Cast(_) if self.ctype == Type::Void => true,
+            Id(symbol) => symbol.qualifiers.volatile,
+            Literal(_) | Sizeof(_) => false,
+            StmtExpr(stmts, value) => {
+                !stmts.is_empty() || value.as_ref().map_or(false, |v| v.has_side_effects())
+            }
+            Member(inner, _)
+            | Cast(inner)
+            | Deref(inner)
+            | Negate(inner)
+            | BitwiseNot(inner)
+            | StaticRef(inner)
+            | Expect(inner, _)
+            | Noop(inner) => inner.has_side_effects(),
+            LogicalOr(left, right)
+            | BitwiseOr(left, right)
+            | LogicalAnd(left, right)
+            | BitwiseAnd(left, right)
+            | Xor(left, right)
+            | Mul(left, right)
+            | Div(left, right)
+            | Mod(left, right)
+            | Add(left, right)
+            | Sub(left, right)
+            | Shift(left, right, _)
+            | Compare(left, right, _)
+            | OmittedTernary(left, right)
+            | Comma(left, right) => left.has_side_effects() || right.has_side_effects(),
+            Ternary(condition, then, otherwise) => {
+                condition.has_side_effects()
+                    || then.has_side_effects()
+                    || otherwise.has_side_effects()
+            }
+        }
+    }
     pub fn zero(location: Location) -> Expr {
         Expr {
             ctype: Type::Int(true),
@@ -243,6 +357,7 @@ impl From<LengthError> for &'static str {
             Dynamic => "Length of variable-length array cannot be known at compile time",
             NonIntegral => "The length of an array must be an integer",
             Negative => "The length of an array must not be negative",
+            Zero => "The length of an array must be greater than zero",
         }
     }
 }
@@ -265,6 +380,12 @@ impl<K: Hash + Eq, V> Scope<K, V> {
             .filter_map(|(key, value)| if key == name { Some(value) } else { None })
             .next()
     }
+    pub fn get_mut(&mut self, name: &K) -> Option<&mut V> {
+        self.0
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+    }
     // returns whether the _immediate_ scope contains `name`
     #[inline]
     pub fn insert(&mut self, key: K, value: V) -> Option<V> {
@@ -281,6 +402,18 @@ impl<K: Hash + Eq, V> Scope<K, V> {
     pub fn is_global(&self) -> bool {
         self.0.len() == 1
     }
+    /// The number of scopes entered and not yet left, which is 0 at file scope
+    pub fn depth(&self) -> usize {
+        self.0.len() - 1
+    }
+    /// The depth of the innermost scope which contains `name`
+    pub fn depth_of(&self, name: &K) -> Option<usize> {
+        self.0.iter().rposition(|scope| scope.contains_key(name))
+    }
+    /// Whether the innermost declaration of `name` is at block scope
+    pub fn is_local(&self, name: &K) -> bool {
+        self.0[1..].iter().any(|scope| scope.contains_key(name))
+    }
     pub fn _remove(&mut self, key: &K) -> Option<V> {
         debug_assert!(!self.0.is_empty());
         self.0.last_mut().unwrap().remove(key)
@@ -323,6 +456,77 @@ impl Display for StorageClass {
     }
 }
 
+impl Default for Std {
+    fn default() -> Std {
+        Std::C11
+    }
+}
+
+impl ImplicitFunctions {
+    /// The behavior required by `std`: a warning in C89 and an error in later standards.
+    pub fn default_for(std: Std) -> ImplicitFunctions {
+        if std < Std::C99 {
+            ImplicitFunctions::Warn
+        } else {
+            ImplicitFunctions::Error
+        }
+    }
+}
+
+impl Default for Pedantic {
+    fn default() -> Pedantic {
+        Pedantic::Off
+    }
+}
+
+impl Endianness {
+    /// The byte order of the target being compiled for.
+    pub fn target() -> Endianness {
+        match crate::arch::TARGET
+            .endianness()
+            .expect("target should be big or little endian")
+        {
+            target_lexicon::Endianness::Big => Endianness::Big,
+            target_lexicon::Endianness::Little => Endianness::Little,
+        }
+    }
+}
+
+impl std::str::FromStr for Endianness {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Endianness, String> {
+        match s {
+            "big" => Ok(Endianness::Big),
+            "little" => Ok(Endianness::Little),
+            _ => Err(format!(
+                "unknown endianness '{}' (expected one of big, little)",
+                s
+            )),
+        }
+    }
+}
+
+impl std::str::FromStr for Std {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Std, String> {
+        match s {
+            "c89" | "c90" | "ansi" => Ok(Std::C89),
+            "c99" => Ok(Std::C99),
+            "c11" => Ok(Std::C11),
+            _ => Err(format!(
+                "unknown standard '{}' (expected one of c89, c90, ansi, c99, c11)",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for Std {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", &format!("{:?}", self).to_lowercase())
+    }
+}
+
 impl Display for Qualifiers {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -365,6 +569,21 @@ impl Display for Expr {
             ExprType::Ternary(cond, left, right) => {
                 write!(f, "({}) ? ({}) : ({})", cond, left, right)
             }
+            ExprType::OmittedTernary(left, right) => write!(f, "({}) ?: ({})", left, right),
+            ExprType::Unreachable => write!(f, "__builtin_unreachable()"),
+            ExprType::StmtExpr(stmts, value) => {
+                write!(f, "({{ ")?;
+                for stmt in stmts {
+                    write!(f, "{} ", stmt.data)?;
+                }
+                if let Some(value) = value {
+                    write!(f, "{}; ", value)?;
+                }
+                write!(f, "}})")
+            }
+            ExprType::Expect(expr, expected) => {
+                write!(f, "__builtin_expect({}, {})", expr, expected)
+            }
             ExprType::FuncCall(left, params) => write!(f, "({})({})", left, join(params)),
             ExprType::Cast(expr) => write!(f, "({})({})", self.ctype, expr),
             ExprType::Sizeof(ty) => write!(f, "sizeof({})", ty),
@@ -546,7 +765,7 @@ impl Display for Declaration {
 }
 
 impl PartialEq for Symbol {
-    // don't require both symbols to be `init` to be equal
+    // don't require both symbols to be `init` or have the same attributes to be equal
     fn eq(&self, other: &Self) -> bool {
         self.ctype == other.ctype
             && self.id == other.id
@@ -564,6 +783,7 @@ impl Eq for Symbol {}
 
 #[cfg(test)]
 mod tests {
+    use super::ImplicitFunctions;
     use crate::{Parser, PreProcessor};
 
     #[test]
@@ -578,9 +798,12 @@ mod tests {
             "struct s",
         ];
         for ty in types.iter() {
-            let mut lexer = PreProcessor::new("<integration-test>", ty.chars(), false);
+            let mut lexer =
+                PreProcessor::new("<integration-test>", ty.chars(), false, Default::default());
             let first = lexer.next().unwrap().unwrap();
-            let mut parser = Parser::new(first, &mut lexer, false);
+            let std = Default::default();
+            let implicit_functions = ImplicitFunctions::default_for(std);
+            let mut parser = Parser::new(first, &mut lexer, false, std, implicit_functions);
 
             let parsed_ty = parser.type_name().unwrap().data.0;
             assert_eq!(&parsed_ty.to_string(), *ty);
@@ -1,4 +1,4 @@
-use super::Symbol;
+use super::{Expr, Symbol};
 use crate::arch::SIZE_T;
 use crate::intern::InternedStr;
 pub use struct_ref::{StructRef, StructType};
@@ -148,8 +148,11 @@ pub enum Type {
     Struct(StructType),
     /// Enums should always have members, since tentative definitions are not allowed
     Enum(Option<InternedStr>, Vec<(InternedStr, i64)>),
-    /// This should probably be merged into Structs at some point
-    Bitfield(Vec<BitfieldType>),
+    /// A bit-field member of a struct or union: the declared type and the width in bits.
+    ///
+    /// This only appears as the type of a struct member;
+    /// expressions accessing the member have the declared type instead.
+    Bitfield(Box<Type>, u16),
     /// This is the type used for variadic arguments.
     VaList,
     /// A semantic error occured while parsing this type.
@@ -160,6 +163,8 @@ pub enum Type {
 pub enum ArrayType {
     Fixed(SIZE_T),
     Unbounded,
+    /// A variable-length array, with the expression giving its length at runtime
+    Variable(Box<Expr>),
 }
 
 #[derive(Clone, Debug, Eq)]
@@ -177,14 +182,6 @@ pub struct FunctionType {
     pub varargs: bool,
 }
 
-#[allow(dead_code)]
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub struct BitfieldType {
-    pub offset: i32,
-    pub name: Option<InternedStr>,
-    pub ctype: Type,
-}
-
 impl Type {
     /// https://stackoverflow.com/questions/14821936/what-is-a-scalar-object-in-c#14822074
     #[inline]
@@ -278,6 +275,14 @@ impl Type {
             _ => false,
         }
     }
+    /// Whether this is a variable-length array, or an array of them.
+    pub fn is_vla(&self) -> bool {
+        match self {
+            Type::Array(_, ArrayType::Variable(_)) => true,
+            Type::Array(inner, _) => inner.is_vla(),
+            _ => false,
+        }
+    }
     #[inline]
     pub fn is_complete(&self) -> bool {
         match self {
@@ -300,6 +305,28 @@ impl Type {
             _ => Err(()),
         }
     }
+    /// If `member` is a bit-field, return its offset in bits from the start
+    /// of its storage unit (see `member_offset`) and its width in bits.
+    pub fn bitfield_offset(&self, member: InternedStr) -> Option<(u16, u16)> {
+        let members = match self {
+            Type::Struct(stype) | Type::Union(stype) => stype.members(),
+            _ => return None,
+        };
+        let index = members.iter().position(|symbol| symbol.id == member)?;
+        let width = match members[index].ctype {
+            Type::Bitfield(_, width) => width,
+            _ => return None,
+        };
+        let offset = match self {
+            Type::Struct(_) => {
+                let (offsets, _) = crate::arch::struct_layout(&members[..=index])
+                    .expect("structs should have valid size and alignment");
+                offsets[index].1
+            }
+            _ => 0,
+        };
+        Some((offset, width))
+    }
 }
 
 impl PartialEq for ArrayType {
@@ -364,7 +391,7 @@ fn print_pre(ctype: &Type, f: &mut Formatter) -> fmt::Result {
         Union(_) => write!(f, "<anonymous union>"),
         Struct(StructType::Named(ident, _)) => write!(f, "struct {}", ident),
         Struct(_) => write!(f, "<anonymous struct>"),
-        Bitfield(_) => unimplemented!("printing bitfield type"),
+        Bitfield(inner, _) => print_pre(inner, f),
         VaList => write!(f, "va_list"),
         Error => write!(f, "<type error>"),
     }
@@ -395,8 +422,10 @@ fn print_post(ctype: &Type, f: &mut Formatter) -> fmt::Result {
         Type::Pointer(to) => print_post(to, f),
         Type::Array(to, size) => {
             write!(f, "[")?;
-            if let ArrayType::Fixed(size) = size {
-                write!(f, "{}", size)?;
+            match size {
+                ArrayType::Fixed(size) => write!(f, "{}", size)?,
+                ArrayType::Variable(size) => write!(f, "{}", size)?,
+                ArrayType::Unbounded => {}
             }
             write!(f, "]")?;
             print_post(to, f)
@@ -421,6 +450,7 @@ fn print_post(ctype: &Type, f: &mut Formatter) -> fmt::Result {
             comma_seperated.push(')');
             write!(f, "{}", comma_seperated)
         }
+        Type::Bitfield(_, width) => write!(f, " : {}", width),
         _ => Ok(()),
     }
 }
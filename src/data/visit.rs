@@ -0,0 +1,201 @@
+//! Walk the abstract syntax tree without modifying it, e.g. to build a linter.
+//!
+//! Implement `Visitor` and override the methods for the nodes you care about.
+//! An override can call the matching `walk_*` function to keep descending.
+use super::lex::Locatable;
+use super::{Declaration, Expr, ExprType, Initializer, Stmt, StmtType};
+
+pub trait Visitor {
+    fn visit_decl(&mut self, decl: &Locatable<Declaration>) {
+        walk_decl(self, decl)
+    }
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        walk_stmt(self, stmt)
+    }
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr)
+    }
+}
+
+/// Visit the initializer of `decl`, including the body of a function definition.
+pub fn walk_decl<V: Visitor + ?Sized>(visitor: &mut V, decl: &Locatable<Declaration>) {
+    if let Some(init) = &decl.data.init {
+        walk_initializer(visitor, init);
+    }
+}
+
+fn walk_initializer<V: Visitor + ?Sized>(visitor: &mut V, init: &Initializer) {
+    match init {
+        Initializer::Scalar(expr) => visitor.visit_expr(expr),
+        Initializer::InitializerList(inits) => {
+            for init in inits {
+                walk_initializer(visitor, init);
+            }
+        }
+        Initializer::FunctionBody(stmts) => {
+            for stmt in stmts {
+                visitor.visit_stmt(stmt);
+            }
+        }
+    }
+}
+
+/// Visit each statement, expression, and declaration directly inside `stmt`.
+pub fn walk_stmt<V: Visitor + ?Sized>(visitor: &mut V, stmt: &Stmt) {
+    match &stmt.data {
+        StmtType::Compound(stmts) => {
+            for stmt in stmts {
+                visitor.visit_stmt(stmt);
+            }
+        }
+        StmtType::If(condition, body, otherwise) => {
+            visitor.visit_expr(condition);
+            visitor.visit_stmt(body);
+            if let Some(otherwise) = otherwise {
+                visitor.visit_stmt(otherwise);
+            }
+        }
+        StmtType::Do(body, condition) => {
+            visitor.visit_stmt(body);
+            visitor.visit_expr(condition);
+        }
+        StmtType::While(condition, body) => {
+            visitor.visit_expr(condition);
+            if let Some(body) = body {
+                visitor.visit_stmt(body);
+            }
+        }
+        StmtType::For(init, condition, post_loop, body) => {
+            if let Some(init) = init {
+                visitor.visit_stmt(init);
+            }
+            if let Some(condition) = condition {
+                visitor.visit_expr(condition);
+            }
+            if let Some(post_loop) = post_loop {
+                visitor.visit_expr(post_loop);
+            }
+            if let Some(body) = body {
+                visitor.visit_stmt(body);
+            }
+        }
+        StmtType::Switch(condition, body) => {
+            visitor.visit_expr(condition);
+            visitor.visit_stmt(body);
+        }
+        StmtType::Label(_, inner) | StmtType::Case(_, inner) | StmtType::Default(inner) => {
+            if let Some(inner) = inner {
+                visitor.visit_stmt(inner);
+            }
+        }
+        StmtType::Expr(expr) | StmtType::Return(Some(expr)) => visitor.visit_expr(expr),
+        StmtType::Decl(decls) => {
+            for decl in decls {
+                visitor.visit_decl(decl);
+            }
+        }
+        StmtType::Goto(_) | StmtType::Continue | StmtType::Break | StmtType::Return(None) => {}
+    }
+}
+
+/// Visit each expression directly inside `expr`,
+/// and the statements of a statement expression.
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expr) {
+    use ExprType::*;
+    match &expr.expr {
+        Id(_) | Literal(_) | Sizeof(_) | Unreachable => {}
+        FuncCall(func, args) => {
+            visitor.visit_expr(func);
+            for arg in args {
+                visitor.visit_expr(arg);
+            }
+        }
+        Member(inner, _)
+        | PostIncrement(inner, _)
+        | Cast(inner)
+        | Deref(inner)
+        | Negate(inner)
+        | BitwiseNot(inner)
+        | Expect(inner, _)
+        | StaticRef(inner)
+        | Noop(inner) => visitor.visit_expr(inner),
+        LogicalOr(left, right)
+        | BitwiseOr(left, right)
+        | LogicalAnd(left, right)
+        | BitwiseAnd(left, right)
+        | Xor(left, right)
+        | Mul(left, right)
+        | Div(left, right)
+        | Mod(left, right)
+        | Add(left, right)
+        | Sub(left, right)
+        | Shift(left, right, _)
+        | Compare(left, right, _)
+        | Assign(left, right, _)
+        | OmittedTernary(left, right)
+        | Comma(left, right) => {
+            visitor.visit_expr(left);
+            visitor.visit_expr(right);
+        }
+        Ternary(condition, then, otherwise) => {
+            visitor.visit_expr(condition);
+            visitor.visit_expr(then);
+            visitor.visit_expr(otherwise);
+        }
+        StmtExpr(stmts, value) => {
+            for stmt in stmts {
+                visitor.visit_stmt(stmt);
+            }
+            if let Some(value) = value {
+                visitor.visit_expr(value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::tests::parser;
+
+    #[derive(Default)]
+    struct GotoCounter {
+        gotos: usize,
+        exprs: usize,
+    }
+
+    impl Visitor for GotoCounter {
+        fn visit_stmt(&mut self, stmt: &Stmt) {
+            if let StmtType::Goto(_) = stmt.data {
+                self.gotos += 1;
+            }
+            walk_stmt(self, stmt);
+        }
+        fn visit_expr(&mut self, expr: &Expr) {
+            self.exprs += 1;
+            walk_expr(self, expr);
+        }
+    }
+
+    #[test]
+    fn count_gotos() {
+        let program = "int main(void) {
+            int i = 0;
+        start:
+            if (i > 10) goto end; else { i++; }
+            for (;;) { while (1) goto end; }
+            switch (i) { case 1: goto start; default: ; }
+            ({ goto end; 1; });
+        end:
+            return i;
+        }";
+        let (decls, errs) = parser(program).collect_results();
+        assert!(errs.is_empty(), "{:?}", errs);
+        let mut counter = GotoCounter::default();
+        for decl in &decls {
+            counter.visit_decl(decl);
+        }
+        assert_eq!(counter.gotos, 4);
+        assert!(counter.exprs > 0);
+    }
+}
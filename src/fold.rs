@@ -112,7 +112,7 @@ impl Expr {
         use crate::data::lex::ComparisonToken::*;
         let location = self.location;
         let folded = match self.expr {
-            ExprType::Literal(_) => self.expr,
+            ExprType::Literal(_) | ExprType::Unreachable | ExprType::StmtExpr(_, _) => self.expr,
             ExprType::Id(ref name) => match &self.ctype {
                 Type::Enum(_, members) => match members.iter().find(|member| member.0 == name.id) {
                     Some(enum_literal) => ExprType::Literal(Int(enum_literal.1)),
@@ -121,6 +121,8 @@ impl Expr {
                 // TODO: if a variable were const, could we const fold Ids?
                 _ => self.expr,
             },
+            // the size of a variable-length array is only known at runtime
+            ExprType::Sizeof(ref ctype) if ctype.is_vla() => self.expr,
             ExprType::Sizeof(ctype) => {
                 let sizeof = ctype.sizeof().map_err(|data| Locatable {
                     data: data.to_string(),
@@ -269,13 +271,24 @@ impl Expr {
                     otherwise.const_fold()?,
                 );
                 match condition.expr {
-                    ExprType::Literal(Int(0)) => otherwise.expr,
-                    ExprType::Literal(Int(_)) => then.expr,
+                    ExprType::Literal(_) if condition.is_zero() => otherwise.expr,
+                    ExprType::Literal(_) => then.expr,
                     _ => {
                         ExprType::Ternary(Box::new(condition), Box::new(then), Box::new(otherwise))
                     }
                 }
             }
+            ExprType::OmittedTernary(then, otherwise) => {
+                let (then, otherwise) = (then.const_fold()?, otherwise.const_fold()?);
+                match then.expr {
+                    ExprType::Literal(_) if then.is_zero() => otherwise.expr,
+                    ExprType::Literal(_) => then.expr,
+                    _ => ExprType::OmittedTernary(Box::new(then), Box::new(otherwise)),
+                }
+            }
+            ExprType::Expect(expr, expected) => {
+                ExprType::Expect(Box::new(expr.const_fold()?), expected)
+            }
             ExprType::FuncCall(func, params) => {
                 let func = func.const_fold()?;
                 #[rustfmt::skip]
@@ -420,16 +433,14 @@ fn const_cast(token: &Literal, ctype: &Type) -> Option<Literal> {
     let token = match (token, ctype) {
         (Int(i), Type::Bool) => Int((*i != 0) as i64),
         (Int(i), Type::Double) | (Int(i), Type::Float) => Float(*i as f64),
-        (Int(i), ty) if ty.is_integral() && ty.is_signed() => Int(*i),
-        (Int(i), ty) if ty.is_integral() => UnsignedInt(*i as u64),
+        (Int(i), ty) if ty.is_integral() => truncate(*i as u64, ty),
         (UnsignedInt(u), Type::Bool) => Int((*u != 0) as i64),
         (UnsignedInt(u), Type::Double) | (UnsignedInt(u), Type::Float) => Float(*u as f64),
-        (UnsignedInt(u), ty) if ty.is_integral() && ty.is_signed() => Int(*u as i64),
-        (UnsignedInt(u), ty) if ty.is_integral() => UnsignedInt(*u),
+        (UnsignedInt(u), ty) if ty.is_integral() => truncate(*u, ty),
         (Float(f), Type::Bool) => Int((*f != 0.0) as i64),
         (Float(f), Type::Double) | (Float(f), Type::Float) => Float(*f),
-        (Float(f), ty) if ty.is_integral() && ty.is_signed() => Int(*f as i64),
-        (Float(f), ty) if ty.is_integral() => UnsignedInt(*f as u64),
+        (Float(f), ty) if ty.is_integral() && ty.is_signed() => truncate(*f as i64 as u64, ty),
+        (Float(f), ty) if ty.is_integral() => truncate(*f as u64, ty),
         (Int(i), _) if ctype.is_pointer() && *i >= 0 => UnsignedInt(*i as u64),
         (UnsignedInt(u), _) if ctype.is_pointer() => UnsignedInt(*u),
         (Char(c), _) if ctype.is_pointer() => UnsignedInt(u64::from(*c)),
@@ -438,6 +449,21 @@ fn const_cast(token: &Literal, ctype: &Type) -> Option<Literal> {
     Some(token)
 }
 
+/// Convert the bits of an integer to the integral type `ctype`,
+/// discarding the bits which don't fit and sign-extending if `ctype` is signed.
+fn truncate(bits: u64, ctype: &Type) -> Literal {
+    let width = ctype
+        .sizeof()
+        .map_or(64, |size| size * u64::from(CHAR_BIT))
+        .min(64);
+    let unused = 64 - width;
+    if ctype.is_signed() {
+        Int(((bits << unused) as i64) >> unused)
+    } else {
+        UnsignedInt((bits << unused) >> unused)
+    }
+}
+
 fn shift_right(
     left: Expr,
     right: Expr,
@@ -570,6 +596,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_casts() {
+        let folded = |s| test_const_fold(s).unwrap().expr;
+        assert_eq!(folded("(char)300"), parse_expr("44").unwrap().expr);
+        assert_eq!(
+            folded("(signed char)200"),
+            ExprType::Literal(Literal::Int(-56))
+        );
+        assert_eq!(
+            folded("(unsigned char)-1"),
+            parse_expr("255u").unwrap().expr
+        );
+        assert_eq!(folded("(double)5"), parse_expr("5.0").unwrap().expr);
+        assert_eq!(folded("(int)4.7"), parse_expr("4").unwrap().expr);
+        assert_eq!(folded("(int*)8"), parse_expr("8u").unwrap().expr);
+    }
+
     #[test]
     fn test_subtraction() {
         assert_eq!(
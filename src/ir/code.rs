@@ -0,0 +1,127 @@
+//! Compile a single function to machine code without writing an object file.
+//!
+//! Cranelift emits the code for the function, followed by its jump tables.
+//! Every reference to another function or to data becomes a `Relocation`,
+//! which the caller has to resolve before running the code.
+
+use std::collections::HashMap;
+
+use cranelift::codegen::{
+    binemit::{Addend, CodeOffset, NullStackmapSink, NullTrapSink, Reloc, RelocSink},
+    ir::{function::Function, ConstantOffset, ExternalName, JumpTable},
+    isa::TargetIsa,
+    Context,
+};
+
+/// A function compiled to machine code, see `function_code`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompiledFunction {
+    /// The machine code, followed by any jump tables the function uses
+    pub code: Vec<u8>,
+    /// The places in `code` which refer to a symbol outside the function
+    pub relocations: Vec<Relocation>,
+}
+
+/// A reference from machine code to a symbol, which has to be filled in by the caller.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Relocation {
+    /// The offset in the code of the bytes to fill in
+    pub offset: u32,
+    /// How to compute the bytes from the address of `symbol`
+    pub kind: Reloc,
+    /// The name of the function or data referred to
+    pub symbol: String,
+    /// A constant to add to the address of `symbol`
+    pub addend: i64,
+}
+
+/// Compile `func` to machine code for `isa`.
+///
+/// `names` holds the symbol for each `ExternalName::User` declared in the module,
+/// see `Compiler::external_names`.
+pub(super) fn function_code(
+    func: Function,
+    isa: &dyn TargetIsa,
+    names: &HashMap<(u32, u32), String>,
+) -> Result<CompiledFunction, String> {
+    let mut ctx = Context::for_function(func);
+    let mut code = Vec::new();
+    let mut relocs = Relocations::default();
+    ctx.compile_and_emit(
+        isa,
+        &mut code,
+        &mut relocs,
+        &mut NullTrapSink {},
+        &mut NullStackmapSink {},
+    )
+    .map_err(|err| err.to_string())?;
+    if let Some(unsupported) = relocs.unsupported {
+        return Err(unsupported);
+    }
+    let libcall_names = cranelift_module::default_libcall_names();
+    let relocations = relocs
+        .external
+        .into_iter()
+        .map(|(offset, kind, name, addend)| {
+            let symbol = match &name {
+                ExternalName::User { namespace, index } => names
+                    .get(&(*namespace, *index))
+                    .cloned()
+                    .ok_or_else(|| format!("no symbol was declared for {}", name))?,
+                ExternalName::LibCall(libcall) => libcall_names(*libcall),
+                ExternalName::TestCase { .. } => {
+                    return Err(format!("no symbol was declared for {}", name))
+                }
+            };
+            Ok(Relocation {
+                offset,
+                kind,
+                symbol,
+                addend,
+            })
+        })
+        .collect::<Result<_, String>>()?;
+    Ok(CompiledFunction { code, relocations })
+}
+
+/// The external relocations of a function, before their names are resolved.
+#[derive(Default)]
+struct Relocations {
+    external: Vec<(CodeOffset, Reloc, ExternalName, Addend)>,
+    /// The first relocation which can't be represented as a `Relocation`
+    unsupported: Option<String>,
+}
+
+impl Relocations {
+    fn unsupported(&mut self, description: String) {
+        self.unsupported.get_or_insert(description);
+    }
+}
+
+impl RelocSink for Relocations {
+    fn reloc_ebb(&mut self, _offset: CodeOffset, reloc: Reloc, _ebb_offset: CodeOffset) {
+        self.unsupported(format!("ebb relocation {} is not supported", reloc));
+    }
+    fn reloc_external(
+        &mut self,
+        offset: CodeOffset,
+        reloc: Reloc,
+        name: &ExternalName,
+        addend: Addend,
+    ) {
+        self.external.push((offset, reloc, name.clone(), addend));
+    }
+    fn reloc_constant(&mut self, _offset: CodeOffset, reloc: Reloc, _constant: ConstantOffset) {
+        self.unsupported(format!(
+            "constant pool relocation {} is not supported",
+            reloc
+        ));
+    }
+    fn reloc_jt(&mut self, _offset: CodeOffset, reloc: Reloc, _jt: JumpTable) {
+        match reloc {
+            // jump tables are emitted right after the code, so the offset is already known
+            Reloc::X86PCRelRodata4 => {}
+            _ => self.unsupported(format!("jump table relocation {} is not supported", reloc)),
+        }
+    }
+}
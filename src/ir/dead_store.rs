@@ -0,0 +1,263 @@
+//! Remove stores to local variables which are overwritten before they are read.
+//!
+//! This only looks at one EBB at a time and forgets everything at a branch,
+//! since the branch target could read the variable.
+//! Locals whose address is used for anything besides a direct load or store
+//! are never touched, since they could be read through a pointer,
+//! and neither are `volatile` locals.
+
+use std::collections::{HashMap, HashSet};
+
+use cranelift::codegen::ir::{
+    entities::StackSlot, function::Function, DataFlowGraph, Inst, InstructionData, Opcode, Value,
+    ValueDef,
+};
+
+enum Access {
+    Read(StackSlot),
+    /// a store which overwrites the whole stack slot
+    Overwrite(StackSlot),
+}
+
+/// Remove dead stores from `func`, skipping the stack slots in `volatile`.
+///
+/// Returns the number of stores removed.
+pub(super) fn eliminate_dead_stores(func: &mut Function, volatile: &[StackSlot]) -> usize {
+    let mut skip = address_taken(func);
+    skip.extend(volatile.iter().copied());
+    let mut dead = Vec::new();
+    for ebb in func.layout.ebbs() {
+        // the last store to each slot which hasn't been read yet
+        let mut pending: HashMap<StackSlot, Inst> = HashMap::new();
+        for inst in func.layout.ebb_insts(ebb) {
+            if func.dfg[inst].opcode().is_branch() {
+                pending.clear();
+                continue;
+            }
+            match access(func, inst) {
+                Some(Access::Read(slot)) => {
+                    pending.remove(&slot);
+                }
+                Some(Access::Overwrite(slot)) if !skip.contains(&slot) => {
+                    if let Some(previous) = pending.insert(slot, inst) {
+                        dead.push(previous);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    for &inst in &dead {
+        func.layout.remove_inst(inst);
+    }
+    dead.len()
+}
+
+/// If `value` is the result of `stack_addr`, the slot and offset it points to.
+fn stack_addr(dfg: &DataFlowGraph, value: Value) -> Option<(StackSlot, i32)> {
+    match dfg.value_def(value) {
+        ValueDef::Result(inst, _) => match dfg[inst] {
+            InstructionData::StackLoad {
+                opcode: Opcode::StackAddr,
+                stack_slot,
+                offset,
+            } => Some((stack_slot, offset.into())),
+            _ => None,
+        },
+        ValueDef::Param(_, _) => None,
+    }
+}
+
+/// The index of the address argument of `inst`, if it's a load or store
+/// which `access` knows how to handle.
+fn address_index(data: &InstructionData) -> Option<usize> {
+    match data {
+        InstructionData::Load {
+            opcode: Opcode::Load,
+            ..
+        } => Some(0),
+        InstructionData::Store {
+            opcode: Opcode::Store,
+            ..
+        } => Some(1),
+        _ => None,
+    }
+}
+
+/// The stack slots whose address is used for anything other than a direct load or store.
+fn address_taken(func: &Function) -> HashSet<StackSlot> {
+    let dfg = &func.dfg;
+    let mut taken = HashSet::new();
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            let address = address_index(&dfg[inst]);
+            for (i, &arg) in dfg.inst_args(inst).iter().enumerate() {
+                if let Some((slot, offset)) = stack_addr(dfg, arg) {
+                    if address != Some(i) || offset != 0 {
+                        taken.insert(slot);
+                    }
+                }
+            }
+        }
+    }
+    taken
+}
+
+/// How `inst` accesses a stack slot, if at all.
+///
+/// Stores which only overwrite part of the slot count as reads,
+/// so they don't hide an earlier store to the rest of the slot.
+fn access(func: &Function, inst: Inst) -> Option<Access> {
+    let dfg = &func.dfg;
+    let overwrites = |slot: StackSlot, value: Value, offset: i32| {
+        offset == 0 && dfg.value_type(value).bytes() == func.stack_slots[slot].size
+    };
+    match dfg[inst] {
+        InstructionData::Load {
+            opcode: Opcode::Load,
+            arg,
+            ..
+        } => stack_addr(dfg, arg).map(|(slot, _)| Access::Read(slot)),
+        InstructionData::StackLoad {
+            opcode: Opcode::StackLoad,
+            stack_slot,
+            ..
+        } => Some(Access::Read(stack_slot)),
+        InstructionData::Store {
+            opcode: Opcode::Store,
+            args,
+            offset,
+            ..
+        } => {
+            let (slot, addr_offset) = stack_addr(dfg, args[1])?;
+            if overwrites(slot, args[0], addr_offset + i32::from(offset)) {
+                Some(Access::Overwrite(slot))
+            } else {
+                Some(Access::Read(slot))
+            }
+        }
+        InstructionData::StackStore {
+            opcode: Opcode::StackStore,
+            arg,
+            stack_slot,
+            offset,
+        } => {
+            if overwrites(stack_slot, arg, offset.into()) {
+                Some(Access::Overwrite(stack_slot))
+            } else {
+                Some(Access::Read(stack_slot))
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::eliminate_dead_stores;
+    use cranelift::codegen::ir::{
+        function::Function, stackslot::StackSlotData, stackslot::StackSlotKind, types,
+        ExternalName, InstBuilder, MemFlags, Opcode,
+    };
+    use cranelift::codegen::isa::CallConv;
+    use cranelift::prelude::{AbiParam, FunctionBuilder, FunctionBuilderContext, Signature, Value};
+
+    /// Build `int f(int c) { int x; <body>; return x; }`.
+    ///
+    /// `body` is given the builder, the address of `x`, and `c`.
+    fn function<F>(body: F) -> Function
+    where
+        F: FnOnce(&mut FunctionBuilder, Value, Value),
+    {
+        let mut signature = Signature::new(CallConv::SystemV);
+        signature.params.push(AbiParam::new(types::I32));
+        signature.returns.push(AbiParam::new(types::I32));
+        let mut func = Function::with_name_signature(ExternalName::user(0, 0), signature);
+        let mut ctx = FunctionBuilderContext::new();
+        let mut builder = FunctionBuilder::new(&mut func, &mut ctx);
+        let start = builder.create_ebb();
+        builder.append_ebb_params_for_function_params(start);
+        builder.switch_to_block(start);
+        let c = builder.ebb_params(start)[0];
+        let slot = builder.create_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, 4));
+        let x = builder.ins().stack_addr(types::I64, slot, 0);
+        body(&mut builder, x, c);
+        let x = builder.ins().stack_addr(types::I64, slot, 0);
+        let value = builder.ins().load(types::I32, MemFlags::new(), x, 0);
+        builder.ins().return_(&[value]);
+        builder.seal_all_blocks();
+        builder.finalize();
+        func
+    }
+    fn stores(func: &Function) -> usize {
+        func.layout
+            .ebbs()
+            .flat_map(|ebb| func.layout.ebb_insts(ebb))
+            .filter(|&inst| func.dfg[inst].opcode() == Opcode::Store)
+            .count()
+    }
+    #[test]
+    fn overwritten() {
+        // x = 1; x = 2;
+        let mut func = function(|builder, x, _| {
+            let one = builder.ins().iconst(types::I32, 1);
+            builder.ins().store(MemFlags::new(), one, x, 0);
+            let two = builder.ins().iconst(types::I32, 2);
+            builder.ins().store(MemFlags::new(), two, x, 0);
+        });
+        assert_eq!(eliminate_dead_stores(&mut func, &[]), 1);
+        assert_eq!(stores(&func), 1);
+    }
+    #[test]
+    fn read_between() {
+        // x = 1; c = x; x = c;
+        let mut func = function(|builder, x, _| {
+            let one = builder.ins().iconst(types::I32, 1);
+            builder.ins().store(MemFlags::new(), one, x, 0);
+            let read = builder.ins().load(types::I32, MemFlags::new(), x, 0);
+            builder.ins().store(MemFlags::new(), read, x, 0);
+        });
+        assert_eq!(eliminate_dead_stores(&mut func, &[]), 0);
+    }
+    #[test]
+    fn address_taken() {
+        // x = 1; *(&x + 0) = 2;
+        let mut func = function(|builder, x, _| {
+            let one = builder.ins().iconst(types::I32, 1);
+            builder.ins().store(MemFlags::new(), one, x, 0);
+            let alias = builder.ins().iadd_imm(x, 0);
+            let two = builder.ins().iconst(types::I32, 2);
+            builder.ins().store(MemFlags::new(), two, alias, 0);
+        });
+        assert_eq!(eliminate_dead_stores(&mut func, &[]), 0);
+    }
+    #[test]
+    fn volatile_and_branches() {
+        let overwritten = |builder: &mut FunctionBuilder, x: Value, _: Value| {
+            let one = builder.ins().iconst(types::I32, 1);
+            builder.ins().store(MemFlags::new(), one, x, 0);
+            let two = builder.ins().iconst(types::I32, 2);
+            builder.ins().store(MemFlags::new(), two, x, 0);
+        };
+        let mut func = function(overwritten);
+        let slot = func.stack_slots.keys().next().unwrap();
+        assert_eq!(eliminate_dead_stores(&mut func, &[slot]), 0);
+
+        // x = 1; if (c) return x; x = 2;
+        let mut func = function(|builder, x, c| {
+            let one = builder.ins().iconst(types::I32, 1);
+            builder.ins().store(MemFlags::new(), one, x, 0);
+            let early = builder.create_ebb();
+            builder.ins().brnz(c, early, &[]);
+            let two = builder.ins().iconst(types::I32, 2);
+            builder.ins().store(MemFlags::new(), two, x, 0);
+            let after = builder.create_ebb();
+            builder.ins().jump(after, &[]);
+            builder.switch_to_block(early);
+            let read = builder.ins().load(types::I32, MemFlags::new(), x, 0);
+            builder.ins().return_(&[read]);
+            builder.switch_to_block(after);
+        });
+        assert_eq!(eliminate_dead_stores(&mut func, &[]), 0);
+    }
+}
@@ -0,0 +1,368 @@
+//! Emit DWARF line-number information for compiled functions.
+//!
+//! Every statement sets the source location of the instructions generated for it.
+//! After a function is compiled, the code offset of each instruction is paired with
+//! the line of its source location, and once the whole module is finished
+//! the rows are written out by `gimli` as a DWARF version 2 `.debug_line` section,
+//! along with the compilation unit in `.debug_info` needed to find it.
+//!
+//! Only statements in the main file are recorded; `#include`d files are skipped.
+
+use cranelift::codegen::{entity::EntityRef, ir::function::Function, isa::TargetIsa};
+use cranelift_module::FuncId;
+use cranelift_object::ObjectProduct;
+use gimli::write::{
+    Address, AttributeValue, DwarfUnit, EndianVec, LineProgram, LineString, Sections, Writer,
+};
+use gimli::{Encoding, Format, LineEncoding, RunTimeEndian, SectionId};
+use object::write::{Relocation, SectionKind};
+use object::{RelocationEncoding, RelocationKind};
+
+use crate::arch::PTR_SIZE;
+use crate::data::Endianness;
+use crate::intern::InternedStr;
+
+/// The line-number rows for every function compiled so far.
+pub(super) struct DebugLines {
+    filename: InternedStr,
+    /// The byte offset of the start of each line in the source
+    line_starts: Vec<u32>,
+    functions: Vec<FunctionLines>,
+}
+
+struct FunctionLines {
+    id: FuncId,
+    /// The size of the compiled function in bytes
+    size: u32,
+    /// (code offset, line), ordered by code offset
+    rows: Vec<(u32, u32)>,
+}
+
+/// A DWARF section being written, along with the relocations it needs
+/// once it's added to the object file.
+#[derive(Clone, Debug)]
+struct Section {
+    data: EndianVec<RunTimeEndian>,
+    relocations: Vec<DebugReloc>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct DebugReloc {
+    /// The offset of the relocated bytes in the section
+    offset: u64,
+    /// The number of relocated bytes
+    size: u8,
+    target: RelocTarget,
+    addend: i64,
+}
+
+/// What a relocation in a debug section refers to
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum RelocTarget {
+    /// The address of a compiled function
+    Function(FuncId),
+    /// An offset into another debug section
+    Section(SectionId),
+}
+
+impl DebugLines {
+    pub(super) fn new(filename: InternedStr, source: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i as u32 + 1))
+            .collect();
+        DebugLines {
+            filename,
+            line_starts,
+            functions: Vec::new(),
+        }
+    }
+    /// The file whose locations are recorded
+    pub(super) fn filename(&self) -> InternedStr {
+        self.filename
+    }
+    /// The 1-based line number of the byte offset `offset`
+    fn line(&self, offset: u32) -> u32 {
+        let index = match self.line_starts.binary_search(&offset) {
+            Ok(index) => index,
+            Err(index) => index - 1,
+        };
+        index as u32 + 1
+    }
+    /// Record the rows for a function after it has been compiled.
+    ///
+    /// `func` must have been compiled so that its instructions have encodings.
+    pub(super) fn add_function(&mut self, id: FuncId, func: &Function, isa: &dyn TargetIsa) {
+        let encinfo = isa.encoding_info();
+        let mut rows: Vec<(u32, u32)> = Vec::new();
+        let mut size = 0;
+        for ebb in func.layout.ebbs() {
+            for (offset, inst, inst_size) in func.inst_offsets(ebb, &encinfo) {
+                size = size.max(offset + inst_size);
+                let srcloc = func.srclocs[inst];
+                if srcloc.is_default() {
+                    continue;
+                }
+                let line = self.line(srcloc.bits());
+                if rows.last().map_or(true, |&(_, last)| last != line) {
+                    rows.push((offset, line));
+                }
+            }
+        }
+        self.functions.push(FunctionLines { id, size, rows });
+    }
+    /// Build the compilation unit, with a line-number program
+    /// containing a sequence for each function.
+    fn unit(&self) -> DwarfUnit {
+        let encoding = Encoding {
+            address_size: PTR_SIZE as u8,
+            format: Format::Dwarf32,
+            version: 2,
+        };
+        let mut dwarf = DwarfUnit::new(encoding);
+        let filename = self.filename.resolve_and_clone().into_bytes();
+        let mut program = LineProgram::new(
+            encoding,
+            LineEncoding::default(),
+            LineString::String(Vec::new()),
+            LineString::String(filename.clone()),
+            None,
+        );
+        let directory = program.default_directory();
+        let file = program.add_file(LineString::String(filename.clone()), directory, None);
+        for function in &self.functions {
+            program.begin_sequence(Some(Address::Symbol {
+                symbol: function.id.index(),
+                addend: 0,
+            }));
+            for &(offset, line) in &function.rows {
+                let row = program.row();
+                row.address_offset = u64::from(offset);
+                row.line = u64::from(line);
+                row.file = file;
+                program.generate_row();
+            }
+            program.end_sequence(u64::from(function.size));
+        }
+        dwarf.unit.line_program = program;
+
+        let root = dwarf.unit.root();
+        let entry = dwarf.unit.get_mut(root);
+        entry.set(gimli::DW_AT_name, AttributeValue::String(filename));
+        entry.set(
+            gimli::DW_AT_language,
+            AttributeValue::Language(gimli::DW_LANG_C99),
+        );
+        dwarf
+    }
+    /// Write out the debug sections, laid out in the byte order of the target.
+    fn sections(&self) -> gimli::write::Result<Sections<Section>> {
+        let endian = match Endianness::target() {
+            Endianness::Big => RunTimeEndian::Big,
+            Endianness::Little => RunTimeEndian::Little,
+        };
+        let mut sections = Sections::new(Section::new(endian));
+        let mut dwarf = self.unit();
+        dwarf.write(&mut sections)?;
+        Ok(sections)
+    }
+    /// Add the debug sections to the object file.
+    pub(super) fn emit(&self, product: &mut ObjectProduct) -> Result<(), String> {
+        let sections = self.sections().map_err(|err| err.to_string())?;
+        // add every section before any relocations, since they can refer to each other
+        let mut ids = Vec::new();
+        sections.for_each(|id, section| -> Result<(), String> {
+            if !section.data.slice().is_empty() {
+                let object_id = product.object.add_section(
+                    Vec::new(),
+                    id.name().as_bytes().to_vec(),
+                    SectionKind::Debug,
+                );
+                product
+                    .object
+                    .append_section_data(object_id, section.data.slice(), 1);
+                ids.push((id, object_id));
+            }
+            Ok(())
+        })?;
+        sections.for_each(|id, section| -> Result<(), String> {
+            let object_id = match ids.iter().find(|&&(other, _)| other == id) {
+                Some(&(_, object_id)) => object_id,
+                None => return Ok(()),
+            };
+            for reloc in &section.relocations {
+                let symbol = match reloc.target {
+                    RelocTarget::Function(func_id) => product.function_symbol(func_id),
+                    RelocTarget::Section(target) => {
+                        let &(_, target) = ids
+                            .iter()
+                            .find(|&&(other, _)| other == target)
+                            .expect("should only refer to sections which were written");
+                        product.object.section_symbol(target)
+                    }
+                };
+                product.object.add_relocation(
+                    object_id,
+                    Relocation {
+                        offset: reloc.offset,
+                        size: reloc.size * 8,
+                        kind: RelocationKind::Absolute,
+                        encoding: RelocationEncoding::Generic,
+                        symbol,
+                        addend: reloc.addend,
+                    },
+                )?;
+            }
+            Ok(())
+        })
+    }
+}
+
+impl Section {
+    fn new(endian: RunTimeEndian) -> Self {
+        Section {
+            data: EndianVec::new(endian),
+            relocations: Vec::new(),
+        }
+    }
+    /// Record a relocation for the `size` bytes at `offset`.
+    ///
+    /// The addend is stored in the relocation, so the bytes themselves should be zero.
+    fn relocate(&mut self, offset: usize, size: u8, target: RelocTarget, addend: i64) {
+        self.relocations.push(DebugReloc {
+            offset: offset as u64,
+            size,
+            target,
+            addend,
+        });
+    }
+}
+
+impl Writer for Section {
+    type Endian = RunTimeEndian;
+
+    fn endian(&self) -> Self::Endian {
+        self.data.endian()
+    }
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+    fn write(&mut self, bytes: &[u8]) -> gimli::write::Result<()> {
+        self.data.write(bytes)
+    }
+    fn write_at(&mut self, offset: usize, bytes: &[u8]) -> gimli::write::Result<()> {
+        self.data.write_at(offset, bytes)
+    }
+    fn write_address(&mut self, address: Address, size: u8) -> gimli::write::Result<()> {
+        match address {
+            Address::Constant(value) => self.write_udata(value, size),
+            Address::Symbol { symbol, addend } => {
+                let target = RelocTarget::Function(FuncId::new(symbol));
+                self.relocate(self.len(), size, target, addend);
+                self.write_udata(0, size)
+            }
+        }
+    }
+    fn write_offset(
+        &mut self,
+        value: usize,
+        section: SectionId,
+        size: u8,
+    ) -> gimli::write::Result<()> {
+        let target = RelocTarget::Section(section);
+        self.relocate(self.len(), size, target, value as i64);
+        self.write_udata(0, size)
+    }
+    fn write_offset_at(
+        &mut self,
+        offset: usize,
+        value: usize,
+        section: SectionId,
+        size: u8,
+    ) -> gimli::write::Result<()> {
+        self.relocate(offset, size, RelocTarget::Section(section), value as i64);
+        self.write_udata_at(offset, 0, size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines() {
+        let lines = DebugLines::new("<test-suite>".into(), "int i;\nint j;\n\nint k;");
+        assert_eq!(lines.line(0), 1);
+        assert_eq!(lines.line(6), 1);
+        assert_eq!(lines.line(7), 2);
+        assert_eq!(lines.line(15), 4);
+    }
+    #[test]
+    fn statement_rows() {
+        use crate::data::{prelude::*, Initializer};
+        use crate::ir::Compiler;
+
+        let program = "int main(void) {\n    int i = 1;\n    i += 2;\n\n    return i;\n}";
+        let filename = InternedStr::get_or_intern("<test-suite>");
+        let (hir, _) = crate::parse(program, "<test-suite>".into(), &Default::default());
+        let mut compiler = Compiler::new("<test-suite>".into(), false, Endianness::target());
+        compiler.debug_lines = Some(DebugLines::new(filename, program));
+        for decl in hir.unwrap() {
+            let symbol = decl.data.symbol;
+            match (symbol.ctype, decl.data.init) {
+                (Type::Function(ftype), Some(Initializer::FunctionBody(stmts))) => compiler
+                    .compile_func(symbol.id, ftype, symbol.storage_class, stmts, decl.location)
+                    .unwrap(),
+                _ => unreachable!(),
+            }
+        }
+        let lines = compiler.debug_lines.unwrap();
+        assert_eq!(lines.functions.len(), 1);
+        let rows = &lines.functions[0].rows;
+        let line_numbers: Vec<_> = rows.iter().map(|&(_, line)| line).collect();
+        assert_eq!(line_numbers, vec![2, 3, 5]);
+        assert!(rows.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+    #[test]
+    fn line_program() {
+        let mut lines = DebugLines::new("<test-suite>".into(), "");
+        lines.functions.push(FunctionLines {
+            id: FuncId::new(0),
+            size: 20,
+            rows: vec![(0, 1), (4, 2), (12, 4)],
+        });
+        let sections = lines.sections().unwrap();
+        // the sequence starts at the address of the function
+        let function = RelocTarget::Function(FuncId::new(0));
+        let relocations = &sections.debug_line.relocations;
+        assert_eq!(relocations.len(), 1);
+        let reloc = &relocations[0];
+        assert_eq!((reloc.size, reloc.target, reloc.addend), (8, function, 0));
+        // and the compilation unit points to the line program
+        let line_program = RelocTarget::Section(SectionId::DebugLine);
+        assert!(sections
+            .debug_info
+            .relocations
+            .iter()
+            .any(|reloc| reloc.target == line_program));
+
+        let debug_line = &sections.debug_line;
+        let debug_line = gimli::DebugLine::new(debug_line.data.slice(), debug_line.endian());
+        let program = debug_line
+            .program(gimli::DebugLineOffset(0), 8, None, None)
+            .unwrap();
+        let mut rows = program.rows();
+        let mut found = Vec::new();
+        while let Some((_, row)) = rows.next_row().unwrap() {
+            found.push((row.address(), row.line(), row.end_sequence()));
+        }
+        assert_eq!(
+            found,
+            vec![
+                (0, Some(1), false),
+                (4, Some(2), false),
+                (12, Some(4), false),
+                (20, Some(4), true),
+            ]
+        );
+    }
+}
@@ -1,10 +1,11 @@
-use cranelift::codegen::ir::{condcodes, types, MemFlags};
+use cranelift::codegen::ir::{condcodes, types, MemFlags, TrapCode};
 use cranelift::prelude::{FunctionBuilder, InstBuilder, Type as IrType, Value as IrValue};
 use log::debug;
 
-use super::{Compiler, Id};
+use super::{Callee, Compiler, Id};
 use crate::data::prelude::*;
 use crate::data::{
+    error::Warning,
     lex::{AssignmentToken, ComparisonToken, Literal, Token},
     Expr, ExprType,
 };
@@ -43,12 +44,22 @@ impl Compiler {
 
             // unary operators
             ExprType::Deref(pointer) => {
+                let bitfield = Self::bitfield(&pointer);
                 let val = self.compile_expr(*pointer, builder)?;
                 let flags = MemFlags::new();
+                let load_type = match bitfield {
+                    Some(_) => Self::bitfield_unit(ir_type),
+                    None => ir_type,
+                };
+                let mut ir_val = builder.ins().load(load_type, flags, val.ir_val, 0);
+                if let Some(bitfield) = bitfield {
+                    let signed = expr.ctype.is_signed();
+                    ir_val = Self::load_bitfield(ir_val, ir_type, signed, bitfield, builder);
+                }
                 Ok(Value {
                     ir_type,
                     ctype: expr.ctype,
-                    ir_val: builder.ins().load(ir_type, flags, val.ir_val, 0),
+                    ir_val,
                 })
             }
             // NOTE: this may be an implicit cast (float f = 1.2) not an explicit cast (1 + (int)1.2)
@@ -126,14 +137,23 @@ impl Compiler {
                 })
             }
             ExprType::PostIncrement(lval, increase) => {
+                let bitfield = Self::bitfield(&lval);
+                let loaded_ctype = lval.ctype.clone();
                 let lval = self.compile_expr(*lval, builder)?;
-                let loaded_ctype = match lval.ctype {
-                    Type::Pointer(t) => *t,
-                    _ => lval.ctype,
-                };
                 let ir_type = loaded_ctype.as_ir_type();
+                let load_type = match bitfield {
+                    Some(_) => Self::bitfield_unit(ir_type),
+                    None => ir_type,
+                };
+                let mut ir_val = builder
+                    .ins()
+                    .load(load_type, MemFlags::new(), lval.ir_val, 0);
+                if let Some(bitfield) = bitfield {
+                    let signed = loaded_ctype.is_signed();
+                    ir_val = Self::load_bitfield(ir_val, ir_type, signed, bitfield, builder);
+                }
                 let previous_value = Value {
-                    ir_val: builder.ins().load(ir_type, MemFlags::new(), lval.ir_val, 0),
+                    ir_val,
                     ir_type,
                     ctype: loaded_ctype,
                 };
@@ -148,9 +168,21 @@ impl Compiler {
                     ),
                 };
                 let new_value = add_func(builder.ins(), previous_value.ir_val, addend_ir);
-                builder
-                    .ins()
-                    .store(MemFlags::new(), new_value, lval.ir_val, 0);
+                if let Some(bitfield) = bitfield {
+                    let signed = previous_value.ctype.is_signed();
+                    Self::store_bitfield(
+                        new_value,
+                        lval.ir_val,
+                        ir_type,
+                        signed,
+                        bitfield,
+                        builder,
+                    );
+                } else {
+                    builder
+                        .ins()
+                        .store(MemFlags::new(), new_value, lval.ir_val, 0);
+                }
                 Ok(previous_value)
             }
             ExprType::Noop(inner) => {
@@ -163,12 +195,69 @@ impl Compiler {
             ExprType::Ternary(condition, left, right) => {
                 self.ternary(*condition, *left, *right, builder)
             }
-            ExprType::Sizeof(_) => unimplemented!("sizeof variable length arrays"),
+            ExprType::OmittedTernary(left, right) => self.omitted_ternary(*left, *right, builder),
+            // the hint is only used by branches, see `Compiler::branch`
+            ExprType::Expect(inner, _) => self.compile_expr(*inner, builder),
+            ExprType::Unreachable => {
+                // Just a placeholder, like the return value of a `void` function.
+                let ir_val = builder.ins().iconst(types::I32, 0);
+                builder.ins().trap(TrapCode::UnreachableCodeReached);
+                // anything evaluated after this (e.g. `__builtin_unreachable(), 1`) is dead code
+                let dead = builder.create_ebb();
+                builder.switch_to_block(dead);
+                builder.seal_block(dead);
+                Ok(Value {
+                    ir_val,
+                    ir_type: types::I32,
+                    ctype: Type::Void,
+                })
+            }
+            ExprType::StmtExpr(stmts, value) => self.stmt_expr(stmts, value, builder),
+            // only `sizeof` a variable-length array is left after constant folding
+            ExprType::Sizeof(ctype) => {
+                let size = self.vla_sizeof(&ctype, location, builder)?;
+                let ir_val = Self::cast_ir(Type::ptr_type(), ir_type, size, false, false, builder);
+                Ok(Value {
+                    ir_val,
+                    ir_type,
+                    ctype: expr.ctype,
+                })
+            }
             ExprType::StaticRef(_) => {
                 unreachable!("static refs can only appear in top level declarations")
             }
         }
     }
+    /// Compile the statements of `({ stmts; value; })`, then the value.
+    ///
+    /// The statements are a block of their own, so any VLAs they declare are freed at the end.
+    fn stmt_expr(
+        &mut self,
+        stmts: Vec<Stmt>,
+        value: Option<Box<Expr>>,
+        builder: &mut FunctionBuilder,
+    ) -> IrResult {
+        self.vlas.push(Vec::new());
+        self.compile_all(stmts, builder)?;
+        // e.g. `({ return 1; 2; })`
+        if builder.is_filled() {
+            let dead = builder.create_ebb();
+            builder.switch_to_block(dead);
+            builder.seal_block(dead);
+        }
+        let val = match value {
+            Some(value) => self.compile_expr(*value, builder)?,
+            // Just a placeholder, like the return value of a `void` function.
+            None => Value {
+                ir_val: builder.ins().iconst(types::I32, 0),
+                ir_type: types::I32,
+                ctype: Type::Void,
+            },
+        };
+        self.free_vlas(self.vlas.len() - 1, builder);
+        self.vlas.pop();
+        Ok(val)
+    }
     fn ternary(
         &mut self,
         condition: Expr,
@@ -200,6 +289,41 @@ impl Compiler {
             ctype: left_val.ctype,
         })
     }
+    /// `left ?: right`: unlike `ternary`, `left` is both the condition and the result,
+    /// so it is only evaluated once
+    fn omitted_ternary(
+        &mut self,
+        left: Expr,
+        right: Expr,
+        builder: &mut FunctionBuilder,
+    ) -> IrResult {
+        let target_ebb = builder.create_ebb();
+        let target_type = left.ctype.as_ir_type();
+        builder.append_ebb_param(target_ebb, target_type);
+
+        let left_val = self.compile_expr(left, builder)?;
+        let condition = Self::cast_ir(
+            left_val.ir_type,
+            types::B1,
+            left_val.ir_val,
+            left_val.ctype.is_signed(),
+            false,
+            builder,
+        );
+        builder
+            .ins()
+            .brnz(condition, target_ebb, &[left_val.ir_val]);
+
+        let right_val = self.compile_expr(right, builder)?;
+        builder.ins().jump(target_ebb, &[right_val.ir_val]);
+        builder.switch_to_block(target_ebb);
+
+        Ok(Value {
+            ir_val: *builder.ebb_params(target_ebb).first().unwrap(),
+            ir_type: target_type,
+            ctype: left_val.ctype,
+        })
+    }
     fn logical_expr(
         &mut self,
         left: Expr,
@@ -247,8 +371,8 @@ impl Compiler {
             (Literal::Float(f), types::F32) => builder.ins().f32const(f as f32),
             (Literal::Float(f), types::F64) => builder.ins().f64const(f),
             (Literal::Char(c), _) => builder.ins().iconst(ir_type, i64::from(c)),
-            (Literal::Str(string), _) => {
-                let str_id = self.compile_string(string, location)?;
+            (Literal::Str(string, prefix), _) => {
+                let str_id = self.compile_string(string, prefix, location)?;
                 let str_addr = self.module.declare_data_in_func(str_id, builder.func);
                 builder.ins().global_value(Type::ptr_type(), str_addr)
             }
@@ -286,9 +410,10 @@ impl Compiler {
             self.compile_expr(left, builder)?,
             self.compile_expr(right, builder)?,
         );
-        Self::binary_assign_ir(left, right, ctype, token, builder)
+        self.binary_assign_ir(left, right, ctype, token, builder)
     }
     fn binary_assign_ir(
+        &self,
         left: Value,
         right: Value,
         ctype: Type,
@@ -299,6 +424,19 @@ impl Compiler {
         assert_eq!(left.ir_type, right.ir_type);
         let ir_type = ctype.as_ir_type();
         let signed = ctype.is_signed();
+        // types narrower than `int` are promoted before the arithmetic, so they can't overflow;
+        // the result is only truncated when it's converted back
+        let promoted = ctype.clone().integer_promote() == ctype;
+        if self.trap_overflow && signed && promoted && ir_type.is_int() {
+            if let Token::Plus | Token::Minus | Token::Star = token {
+                let ir_val = Self::checked_arithmetic(left.ir_val, right.ir_val, token, builder);
+                return Ok(Value {
+                    ir_val,
+                    ir_type,
+                    ctype,
+                });
+            }
+        }
         let func = match (token, ir_type, signed) {
             (Token::Plus, ty, _) if ty.is_int() => b::iadd,
             (Token::Plus, ty, _) if ty.is_float() => b::fadd,
@@ -331,6 +469,48 @@ impl Compiler {
             ctype,
         })
     }
+    /// Signed `+`, `-`, or `*` of `left` and `right` which traps if the result overflows.
+    ///
+    /// Addition overflows if both operands have a different sign than the result,
+    /// subtraction if the operands have different signs and the result has the sign of `right`,
+    /// and multiplication if the high half of the product isn't the sign extension of the low half.
+    fn checked_arithmetic(
+        left: IrValue,
+        right: IrValue,
+        token: Token,
+        builder: &mut FunctionBuilder,
+    ) -> IrValue {
+        use condcodes::IntCC;
+        let (result, overflowed) = match token {
+            Token::Plus => {
+                let result = builder.ins().iadd(left, right);
+                let left_sign = builder.ins().bxor(left, result);
+                let right_sign = builder.ins().bxor(right, result);
+                let both = builder.ins().band(left_sign, right_sign);
+                let overflowed = builder.ins().icmp_imm(IntCC::SignedLessThan, both, 0);
+                (result, overflowed)
+            }
+            Token::Minus => {
+                let result = builder.ins().isub(left, right);
+                let operand_sign = builder.ins().bxor(left, right);
+                let result_sign = builder.ins().bxor(left, result);
+                let both = builder.ins().band(operand_sign, result_sign);
+                let overflowed = builder.ins().icmp_imm(IntCC::SignedLessThan, both, 0);
+                (result, overflowed)
+            }
+            Token::Star => {
+                let result = builder.ins().imul(left, right);
+                let high = builder.ins().smulhi(left, right);
+                let bits = builder.func.dfg.value_type(result).bits();
+                let sign = builder.ins().sshr_imm(result, i64::from(bits - 1));
+                let overflowed = builder.ins().icmp(IntCC::NotEqual, high, sign);
+                (result, overflowed)
+            }
+            _ => unreachable!("only +, -, and * can be checked for overflow"),
+        };
+        builder.ins().trapnz(overflowed, TrapCode::IntegerOverflow);
+        result
+    }
     fn cast(&mut self, expr: Expr, ctype: Type, builder: &mut FunctionBuilder) -> IrResult {
         // calculate this here before it's moved to `compile_expr`
         let orig_signed = expr.ctype.is_signed();
@@ -354,7 +534,7 @@ impl Compiler {
             ctype,
         })
     }
-    fn cast_ir(
+    pub(super) fn cast_ir(
         from: IrType,
         to: IrType,
         val: IrValue,
@@ -415,11 +595,15 @@ impl Compiler {
             // instead, this converts from bool to signed int and then int to float
             (b, f) if b.is_bool() && f.is_float() => {
                 let int_val = Self::cast_ir(b, types::I32, val, false, true, builder);
-                Self::cast_ir(types::I8, f, int_val, true, true, builder)
+                Self::cast_ir(types::I32, f, int_val, true, true, builder)
             }
             (f, b) if b.is_bool() && f.is_float() => {
-                let int_val = Self::cast_ir(f, types::I32, val, true, true, builder);
-                Self::cast_ir(types::I8, b, int_val, true, false, builder)
+                let zero = if f == types::F32 {
+                    builder.ins().f32const(0.0)
+                } else {
+                    builder.ins().f64const(0.0)
+                };
+                builder.ins().fcmp(condcodes::FloatCC::NotEqual, val, zero)
             }
             _ => unreachable!("cast from {} to {}", from, to),
         }
@@ -443,6 +627,7 @@ impl Compiler {
                 builder.ins().global_value(ptr_type, global)
             }
             Id::Local(stack_slot) => builder.ins().stack_addr(ptr_type, *stack_slot, 0),
+            Id::VariableArray(stack_slot) => builder.ins().stack_load(ptr_type, *stack_slot, 0),
         };
         let ctype = Type::Pointer(Box::new(var.ctype));
         Ok(Value {
@@ -483,6 +668,38 @@ impl Compiler {
             ctype: left.ctype,
         })
     }
+    /// If `lval = rval` assigns a non-volatile variable to itself, the name of the variable.
+    fn self_assignment(lval: &Expr, rval: &Expr) -> Option<InternedStr> {
+        let target = match &lval.expr {
+            ExprType::Id(symbol) if !symbol.qualifiers.volatile => symbol,
+            _ => return None,
+        };
+        // a scalar is loaded with `Deref`, a struct is used directly, see `Expr::rval`
+        let source = match &rval.expr {
+            ExprType::Deref(inner) => &inner.expr,
+            other => other,
+        };
+        match source {
+            ExprType::Id(symbol) if symbol.id == target.id => Some(target.id),
+            _ => None,
+        }
+    }
+    /// If `expr` is the address of a local variable, the name of the variable.
+    ///
+    /// This is either `&x` or an array `x` which decayed to a pointer.
+    pub(super) fn local_address(&self, expr: &Expr) -> Option<InternedStr> {
+        match &expr.expr {
+            // an rvalue `Id` is the address of the variable, see `Expr::rval`
+            ExprType::Id(symbol) if !expr.lval && expr.ctype.is_pointer() => {
+                match self.scope.get(&symbol.id) {
+                    Some(Id::Local(_)) | Some(Id::VariableArray(_)) => Some(symbol.id),
+                    _ => None,
+                }
+            }
+            ExprType::Cast(inner) | ExprType::Noop(inner) => self.local_address(inner),
+            _ => None,
+        }
+    }
     fn assignment(
         &mut self,
         lval: Expr,
@@ -492,6 +709,21 @@ impl Compiler {
     ) -> IrResult {
         let ctype = lval.ctype.clone();
         let location = lval.location;
+        if let ExprType::Id(target) = &lval.expr {
+            if let (Some(Id::Global(_)), Some(local)) =
+                (self.scope.get(&target.id), self.local_address(&rval))
+            {
+                self.error_handler
+                    .warn(Warning::DanglingLocalAddress(local), location);
+            }
+        }
+        if token == AssignmentToken::Equal {
+            if let Some(name) = Self::self_assignment(&lval, &rval) {
+                self.error_handler
+                    .warn(Warning::SelfAssignment(name), location);
+            }
+        }
+        let bitfield = Self::bitfield(&lval);
         let (target, value) = (
             self.compile_expr(lval, builder)?,
             self.compile_expr(rval, builder)?,
@@ -523,10 +755,18 @@ impl Compiler {
         if token != AssignmentToken::Equal {
             // need to deref explicitly to get an rval, the frontend didn't do it for us
             let ir_type = ctype.as_ir_type();
+            let load_type = match bitfield {
+                Some(_) => Self::bitfield_unit(ir_type),
+                None => ir_type,
+            };
+            let mut ir_val = builder
+                .ins()
+                .load(load_type, MemFlags::new(), target.ir_val, 0);
+            if let Some(bitfield) = bitfield {
+                ir_val = Self::load_bitfield(ir_val, ir_type, ctype.is_signed(), bitfield, builder);
+            }
             let target = Value {
-                ir_val: builder
-                    .ins()
-                    .load(ir_type, MemFlags::new(), target.ir_val, 0),
+                ir_val,
                 ir_type,
                 ctype: ctype.clone(),
             };
@@ -537,14 +777,105 @@ impl Compiler {
                     target.ir_type
                 );
             }
-            value =
-                Self::binary_assign_ir(target, value, ctype, token.without_assignment(), builder)?;
+            value = self.binary_assign_ir(
+                target,
+                value,
+                ctype.clone(),
+                token.without_assignment(),
+                builder,
+            )?;
+        }
+        if let Some(bitfield) = bitfield {
+            // the value of the assignment is the value stored, which may have been truncated
+            value.ir_val = Self::store_bitfield(
+                value.ir_val,
+                target_val,
+                value.ir_type,
+                ctype.is_signed(),
+                bitfield,
+                builder,
+            );
+            return Ok(value);
         }
         builder
             .ins()
             .store(MemFlags::new(), value.ir_val, target_val, 0);
         Ok(value)
     }
+    /// If `expr` is a bit-field member of a struct,
+    /// return its offset within its storage unit and its width, both in bits.
+    fn bitfield(expr: &Expr) -> Option<(u16, u16)> {
+        match &expr.expr {
+            ExprType::Member(cstruct, id) => cstruct.ctype.bitfield_offset(*id),
+            _ => None,
+        }
+    }
+    /// The type of the storage unit for a bit-field whose value has type `ir_type`.
+    ///
+    /// `_Bool` bit-fields share a byte with their neighbours, so the unit can't be a `b1`.
+    fn bitfield_unit(ir_type: IrType) -> IrType {
+        if ir_type == types::B1 {
+            types::I8
+        } else {
+            ir_type
+        }
+    }
+    /// Given the storage unit for a bit-field, return the value of the bit-field,
+    /// sign or zero extended to the full width of `ir_type`.
+    fn load_bitfield(
+        unit: IrValue,
+        ir_type: IrType,
+        signed: bool,
+        (offset, width): (u16, u16),
+        builder: &mut FunctionBuilder,
+    ) -> IrValue {
+        let bits = i64::from(Self::bitfield_unit(ir_type).bits());
+        let (offset, width) = (i64::from(offset), i64::from(width));
+        // move the highest bit of the bit-field to the highest bit of the unit,
+        // then shift back down so that the sign bit is extended if necessary
+        let shifted = builder.ins().ishl_imm(unit, bits - offset - width);
+        let value = if signed {
+            builder.ins().sshr_imm(shifted, bits - width)
+        } else {
+            builder.ins().ushr_imm(shifted, bits - width)
+        };
+        if ir_type == types::B1 {
+            builder.ins().icmp_imm(condcodes::IntCC::NotEqual, value, 0)
+        } else {
+            value
+        }
+    }
+    /// Store `value` into the bit-field in the storage unit at `target`,
+    /// leaving all other bits of the storage unit unchanged.
+    ///
+    /// Returns the new value of the bit-field.
+    fn store_bitfield(
+        value: IrValue,
+        target: IrValue,
+        ir_type: IrType,
+        signed: bool,
+        bitfield: (u16, u16),
+        builder: &mut FunctionBuilder,
+    ) -> IrValue {
+        let unit_type = Self::bitfield_unit(ir_type);
+        let value = if ir_type == types::B1 {
+            builder.ins().bint(unit_type, value)
+        } else {
+            value
+        };
+        let bits = u32::from(unit_type.bits());
+        let (offset, width) = (u32::from(bitfield.0), u32::from(bitfield.1));
+        // cranelift expects immediates to be sign extended from the width of the type
+        let immediate = |mask: u64| ((mask << (64 - bits)) as i64) >> (64 - bits);
+        let mask = (u64::max_value() >> (64 - width)) << offset;
+        let unit = builder.ins().load(unit_type, MemFlags::new(), target, 0);
+        let cleared = builder.ins().band_imm(unit, immediate(!mask));
+        let shifted = builder.ins().ishl_imm(value, i64::from(offset));
+        let inserted = builder.ins().band_imm(shifted, immediate(mask));
+        let new_unit = builder.ins().bor(cleared, inserted);
+        builder.ins().store(MemFlags::new(), new_unit, target, 0);
+        Self::load_bitfield(new_unit, ir_type, signed, bitfield, builder)
+    }
     fn call(
         &mut self,
         func: FuncCall,
@@ -559,6 +890,27 @@ impl Compiler {
             Type::Function(ftype) => ftype,
             _ => unreachable!("parser should only allow calling functions"),
         };
+        let arg_symbol = |arg: &Expr| Symbol {
+            ctype: arg.ctype.clone(),
+            id: Default::default(),
+            init: true,
+            qualifiers: Qualifiers::NONE,
+            storage_class: StorageClass::Auto,
+            attributes: Default::default(),
+        };
+        if let FuncCall::Named(func_name) = &func {
+            if self.scope.get(func_name).is_none() {
+                // implicitly declared function (C89), the parser never emitted a declaration
+                let signature = ftype.signature(self.module.isa());
+                self.declare_func(*func_name, &signature, StorageClass::Extern, false)?;
+            }
+        }
+        // functions declared without a prototype, like `int f();`,
+        // are called with the types of their (promoted) arguments
+        let unprototyped = ftype.params.is_empty() && !args.is_empty();
+        if unprototyped {
+            ftype.params = args.iter().map(arg_symbol).collect();
+        }
         let mut float_variadic = 0;
         if ftype.varargs {
             // needs to be done before we move the args by compiling them
@@ -572,13 +924,7 @@ impl Compiler {
                     float_variadic += 1;
                 }
                 debug!("adding variadic arg with type {}", arg.ctype);
-                ftype.params.push(Symbol {
-                    ctype: arg.ctype.clone(),
-                    id: Default::default(),
-                    init: true,
-                    qualifiers: Qualifiers::NONE,
-                    storage_class: StorageClass::Auto,
-                });
+                ftype.params.push(arg_symbol(arg));
             }
         }
         let mut compiled_args: Vec<IrValue> = args
@@ -590,6 +936,13 @@ impl Compiler {
             let float_ir = builder.ins().iconst(types::I8, float_variadic);
             compiled_args.push(float_ir);
         }
+        if let Some(caller) = self.current_function {
+            let callee = match func {
+                FuncCall::Named(func_name) => Callee::Named(func_name),
+                FuncCall::Indirect(_) => Callee::Indirect,
+            };
+            self.call_graph.entry(caller).or_default().insert(callee);
+        }
         let call = match func {
             FuncCall::Named(func_name) => {
                 let func_id = match self.scope.get(&func_name) {
@@ -599,22 +952,27 @@ impl Compiler {
                 let func_ref = self.module.declare_func_in_func(func_id, builder.func);
                 let call = builder.ins().call(func_ref, compiled_args.as_slice());
                 // stolen from https://github.com/bjorn3/rustc_codegen_cranelift/blob/82fde5b62281fa51a/src/abi/mod.rs#L535
-                if ftype.varargs {
+                if ftype.varargs || unprototyped {
                     let call_sig = builder.func.dfg.call_signature(call).unwrap();
-                    let al = self
-                        .module
-                        .isa()
-                        .register_info()
-                        .parse_regunit("rax")
-                        .expect("x86 should have an rax register");
-                    let float_arg = AbiParam::special_reg(types::I8, ArgumentPurpose::Normal, al);
-                    // NOTE: this is added both here and in signature() because we overwrite the previous params
-                    let abi_params = ftype
+                    let mut abi_params: Vec<_> = ftype
                         .params
                         .into_iter()
                         .map(|param| AbiParam::new(param.ctype.as_ir_type()))
-                        .chain(std::iter::once(float_arg))
                         .collect();
+                    if ftype.varargs {
+                        let al = self
+                            .module
+                            .isa()
+                            .register_info()
+                            .parse_regunit("rax")
+                            .expect("x86 should have an rax register");
+                        // NOTE: this is added both here and in signature() because we overwrite the previous params
+                        abi_params.push(AbiParam::special_reg(
+                            types::I8,
+                            ArgumentPurpose::Normal,
+                            al,
+                        ));
+                    }
                     builder.func.dfg.signatures[call_sig].params = abi_params;
                 }
                 call
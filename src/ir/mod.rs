@@ -1,9 +1,13 @@
+mod code;
+mod dead_store;
+mod debug;
 mod expr;
 mod static_init;
 mod stmt;
 
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::convert::TryFrom;
+use std::fmt;
 
 use cranelift::codegen::{
     self,
@@ -11,19 +15,30 @@ use cranelift::codegen::{
         entities::StackSlot,
         function::Function,
         stackslot::{StackSlotData, StackSlotKind},
-        ExternalName, InstBuilder, MemFlags,
+        ExternalName, InstBuilder, MemFlags, TrapCode,
     },
     isa,
     settings::{self, Configurable},
 };
-use cranelift::frontend::Switch;
-use cranelift::prelude::{Ebb, FunctionBuilder, FunctionBuilderContext, Signature};
+use cranelift::prelude::{
+    types, AbiParam, Ebb, FunctionBuilder, FunctionBuilderContext, Signature, Type as IrType,
+    Value as IrValue,
+};
 use cranelift_module::{self, DataId, FuncId, Linkage, Module as CraneliftModule};
 use cranelift_object::{ObjectBackend, ObjectBuilder, ObjectProduct, ObjectTrapCollection};
 
-use crate::arch::TARGET;
-use crate::data::{prelude::*, types::FunctionType, Initializer, Scope, StorageClass};
+use crate::arch::{CALLING_CONVENTION, PTR_SIZE, TARGET};
+use crate::data::{
+    error::Phase,
+    lex::StrPrefix,
+    prelude::*,
+    types::{ArrayType, FunctionType},
+    Initializer, Scope, StorageClass,
+};
 use crate::utils;
+use debug::DebugLines;
+
+pub use code::{CompiledFunction, Relocation};
 
 type Module = CraneliftModule<ObjectBackend>;
 
@@ -31,39 +46,149 @@ enum Id {
     Function(FuncId),
     Global(DataId),
     Local(StackSlot),
+    /// A variable-length array: the stack slot holds the address of the array, then its length
+    VariableArray(StackSlot),
+}
+
+/// A `switch` whose body is being compiled
+#[derive(Debug)]
+struct SwitchContext {
+    /// the case labels seen so far, converted to the promoted type of the condition
+    cases: Vec<(u64, Ebb)>,
+    /// if this is still `None` at the end of the body, there was no default case
+    default: Option<Ebb>,
+    end: Ebb,
+    /// the number of enclosing blocks
+    depth: usize,
+    /// the type of the condition before promotion
+    ctype: Type,
+    /// if the `Switch` was emitted before the body,
+    /// the blocks it jumps to for each label and for `default`
+    planned: Option<(Vec<(u64, Ebb)>, Option<Ebb>)>,
+}
+
+/// A statement which `break` can jump out of
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Breakable {
+    Loop,
+    Switch,
+}
+
+/// A function called from another function
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Callee {
+    Named(InternedStr),
+    /// A call through a function pointer, which could be to any function
+    Indirect,
+}
+
+impl fmt::Display for Callee {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Callee::Named(name) => write!(f, "{}", name),
+            Callee::Indirect => write!(f, "<indirect>"),
+        }
+    }
 }
 
+/// The functions called by each function defined in a translation unit
+pub type CallGraph = HashMap<InternedStr, HashSet<Callee>>;
+
 struct Compiler {
     module: Module,
     scope: Scope<InternedStr, Id>,
     debug: bool,
-    // if false, we last saw a switch
-    last_saw_loop: bool,
-    strings: HashMap<InternedStr, DataId>,
-    loops: Vec<(Ebb, Ebb)>,
-    // switch, default, end
-    // if default is empty once we get to the end of a switch body,
-    // we didn't see a default case
-    switches: Vec<(Switch, Option<Ebb>, Ebb)>,
-    labels: HashMap<InternedStr, Ebb>,
+    /// the loops and switches enclosing the current statement, innermost last
+    breakable: Vec<Breakable>,
+    strings: HashMap<(InternedStr, StrPrefix), DataId>,
+    // continue target, end, number of enclosing blocks
+    loops: Vec<(Ebb, Ebb, usize)>,
+    switches: Vec<SwitchContext>,
+    /// the percentage of the range of a switch's case labels which must be used
+    /// before it is lowered to a jump table, see `jump_table_range`
+    jump_table_density: u8,
+    /// if set, a switch fills the current block and emits its `Switch` in a new block
+    /// after compiling the body, working around https://github.com/CraneStation/cranelift/issues/1057.
+    /// Otherwise, it finds every label in the body and emits the `Switch` before compiling it,
+    /// which saves a block if the body starts with a label.
+    switch_dummy_block: bool,
+    /// the variable-length arrays declared in each enclosing block of the current function
+    vlas: Vec<Vec<StackSlot>>,
+    /// the location of the length of each variable-length array declared in the current function,
+    /// with the stack slot holding its address and length, see `vla_sizeof`
+    vla_lengths: Vec<(Location, StackSlot)>,
+    /// the labels in the current function, with where they were declared
+    /// and the variable-length arrays in scope there
+    labels: HashMap<InternedStr, (Ebb, Location, Vec<StackSlot>)>,
+    /// labels which are the target of a `goto` but haven't been declared yet,
+    /// with the location of every `goto`, the variable-length arrays in scope there,
+    /// and the block which frees them if the `goto` leaves their scope
+    pending_gotos: HashMap<InternedStr, (Ebb, Vec<(Location, Vec<StackSlot>, Option<Ebb>)>)>,
+    /// the stack slots of `volatile` locals in the current function
+    volatile_locals: Vec<StackSlot>,
+    /// the function currently being compiled
+    current_function: Option<InternedStr>,
+    /// the functions called by each function compiled so far
+    call_graph: CallGraph,
+    /// the symbol for each `ExternalName::User` declared in the module, by namespace and index;
+    /// `cranelift_module` uses namespace 0 for functions and 1 for data
+    external_names: HashMap<(u32, u32), String>,
+    /// the line-number information to emit, if debug info was requested
+    debug_lines: Option<DebugLines>,
+    /// the byte order of initialized data
+    endianness: Endianness,
+    /// if set, signed `+`, `-`, and `*` trap on overflow instead of wrapping
+    trap_overflow: bool,
     error_handler: ErrorHandler,
 }
 
 /// Compile a program from a high level IR to a Cranelift Module
+///
+/// If `debug_info` is set, it holds the filename and source of the main file,
+/// and line-number information is emitted for each function defined in it.
+/// Static data is laid out in the byte order given by `endianness`.
+/// If `trap_overflow` is set, signed addition, subtraction, and multiplication
+/// trap when they overflow; otherwise they wrap, as unsigned arithmetic always does.
+///
+/// Also returns the functions called by each function defined in the program.
 pub(crate) fn compile(
     program: Vec<Locatable<Declaration>>,
     debug: bool,
+    debug_info: Option<(InternedStr, &str)>,
+    endianness: Endianness,
+    trap_overflow: bool,
+    jump_table_density: Option<u8>,
 ) -> (
-    Result<ObjectProduct, CompileError>,
+    Result<(ObjectProduct, CallGraph), VecDeque<CompileError>>,
     VecDeque<CompileWarning>,
 ) {
     let name = program.first().map_or_else(
         || "<empty>".to_string(),
         |decl| decl.location.filename.resolve_and_clone(),
     );
-    // really we'd like to have all errors but that requires a refactor
-    let mut err = None;
-    let mut compiler = Compiler::new(name, debug);
+    let mut compiler = Compiler::new(name, debug, endianness);
+    compiler.trap_overflow = trap_overflow;
+    if let Some(density) = jump_table_density {
+        compiler.jump_table_density = density;
+    }
+    compiler.debug_lines = debug_info.map(|(filename, source)| DebugLines::new(filename, source));
+    // declare every function defined in this file before compiling any bodies,
+    // so that an implicit declaration (C89) of a function defined later
+    // refers to the definition instead of an import with the wrong signature
+    for decl in &program {
+        if let (Type::Function(func_type), Some(Initializer::FunctionBody(_))) =
+            (&decl.data.symbol.ctype, &decl.data.init)
+        {
+            let signature = func_type.signature(compiler.module.isa());
+            let sc = decl.data.symbol.storage_class;
+            if let Err(e) = compiler.declare_func(decl.data.symbol.id, &signature, sc, true) {
+                compiler.error_handler.push_back(e);
+                let errs = compiler.error_handler.by_ref().collect();
+                let warnings = compiler.error_handler.drain_warnings().collect();
+                return (Err(errs), warnings);
+            }
+        }
+    }
     for decl in program {
         let current = match (decl.data.symbol.ctype.clone(), decl.data.init) {
             (Type::Function(func_type), None) => compiler
@@ -88,21 +213,98 @@ pub(crate) fn compile(
             }
             (_, init) => compiler.store_static(decl.data.symbol, init, decl.location),
         };
+        // really we'd like to have all errors but that requires a refactor
         if let Err(e) = current {
-            err = Some(e);
+            compiler.error_handler.push_back(e);
+            break;
+        }
+    }
+    let warns = compiler.error_handler.drain_warnings().collect();
+    if compiler.error_handler.has_errors() {
+        return (Err(compiler.error_handler.by_ref().collect()), warns);
+    }
+    let mut product = compiler.module.finish();
+    if let Some(lines) = compiler.debug_lines {
+        if let Err(err) = lines.emit(&mut product) {
+            utils::fatal(err, 4);
+        }
+    }
+    (Ok((product, compiler.call_graph)), warns)
+}
+
+/// Compile the function `name` from a high level IR to machine code.
+///
+/// Every other function in `program` is only declared, not compiled,
+/// so calls to it are returned as relocations like any other undefined reference.
+/// Global variables are still laid out, but the caller has to place them in memory.
+pub(crate) fn function_code(
+    program: Vec<Locatable<Declaration>>,
+    name: InternedStr,
+    debug: bool,
+    endianness: Endianness,
+    trap_overflow: bool,
+    jump_table_density: Option<u8>,
+) -> (
+    Result<CompiledFunction, VecDeque<CompileError>>,
+    VecDeque<CompileWarning>,
+) {
+    let filename = program.first().map_or_else(
+        || "<empty>".to_string(),
+        |decl| decl.location.filename.resolve_and_clone(),
+    );
+    let mut compiler = Compiler::new(filename, debug, endianness);
+    compiler.trap_overflow = trap_overflow;
+    if let Some(density) = jump_table_density {
+        compiler.jump_table_density = density;
+    }
+    let mut compiled = None;
+    for decl in program {
+        let symbol = decl.data.symbol;
+        let current = match (symbol.ctype.clone(), decl.data.init) {
+            (Type::Function(func_type), Some(Initializer::FunctionBody(stmts)))
+                if symbol.id == name =>
+            {
+                compiler
+                    .function_code(
+                        symbol.id,
+                        func_type,
+                        symbol.storage_class,
+                        stmts,
+                        decl.location,
+                    )
+                    .map(|code| compiled = Some(code))
+            }
+            (Type::Function(func_type), _) => compiler
+                .declare_func(
+                    symbol.id,
+                    &func_type.signature(compiler.module.isa()),
+                    symbol.storage_class,
+                    false,
+                )
+                .map(|_| ()),
+            (Type::Void, _) => unreachable!("parser let an incomplete type through"),
+            (_, init) => compiler.store_static(symbol, init, decl.location),
+        };
+        if let Err(err) = current {
+            compiler.error_handler.push_back(err);
             break;
         }
     }
-    let warns = compiler.error_handler.warnings;
-    if let Some(err) = err {
-        (Err(err), warns)
-    } else {
-        (Ok(compiler.module.finish()), warns)
+    if compiled.is_none() && !compiler.error_handler.has_errors() {
+        let location = Location::default();
+        let err = location.error(SemanticError::UndeclaredVar(name));
+        compiler.error_handler.push_back(err);
+    }
+    let errs: VecDeque<_> = compiler.error_handler.by_ref().collect();
+    let warnings = compiler.error_handler.drain_warnings().collect();
+    match compiled {
+        Some(code) if errs.is_empty() => (Ok(code), warnings),
+        _ => (Err(errs), warnings),
     }
 }
 
 impl Compiler {
-    fn new(name: String, debug: bool) -> Compiler {
+    fn new(name: String, debug: bool, endianness: Endianness) -> Compiler {
         let mut flags_builder = settings::builder();
         // allow creating shared libraries
         flags_builder
@@ -138,11 +340,22 @@ impl Compiler {
             scope: Scope::new(),
             loops: Vec::new(),
             switches: Vec::new(),
+            jump_table_density: stmt::DEFAULT_JUMP_TABLE_DENSITY,
+            switch_dummy_block: true,
+            vlas: Vec::new(),
+            vla_lengths: Vec::new(),
             labels: HashMap::new(),
-            // the initial value doesn't really matter
-            last_saw_loop: true,
+            pending_gotos: HashMap::new(),
+            breakable: Vec::new(),
+            volatile_locals: Vec::new(),
+            current_function: None,
+            call_graph: CallGraph::new(),
+            external_names: HashMap::new(),
+            debug_lines: None,
+            endianness,
+            trap_overflow: false,
             strings: Default::default(),
-            error_handler: Default::default(),
+            error_handler: ErrorHandler::for_phase(Phase::Codegen),
             debug,
         }
     }
@@ -180,12 +393,14 @@ impl Compiler {
             .declare_function(get_str!(id), linkage, &signature)
             .unwrap_or_else(|err| utils::fatal(err, 6));
         self.scope.insert(id, Id::Function(func_id));
+        self.external_names
+            .insert((0, func_id.as_u32()), id.resolve_and_clone());
         Ok(func_id)
     }
     /// declare an object on the stack
     fn declare_stack(
         &mut self,
-        decl: Declaration,
+        mut decl: Declaration,
         location: Location,
         builder: &mut FunctionBuilder,
     ) -> CompileResult<()> {
@@ -198,6 +413,22 @@ impl Compiler {
             )?;
             return Ok(());
         }
+        // `static _Thread_local` at block scope
+        if decl.symbol.qualifiers.thread_local {
+            return Err(location.error(SemanticError::ThreadLocalUnsupported(decl.symbol.id)));
+        }
+        if let Type::Array(element, ArrayType::Variable(length)) = decl.symbol.ctype {
+            if decl.init.is_some() {
+                semantic_err!(
+                    "variable length arrays cannot be initialized".into(),
+                    location
+                );
+            }
+            return self.declare_vla(decl.symbol.id, &element, *length, location, builder);
+        }
+        if let Some(init) = &decl.init {
+            static_init::infer_array_bounds(&mut decl.symbol.ctype, init);
+        }
         let u64_size = match decl.symbol.ctype.sizeof() {
             Ok(size) => size,
             Err(err) => {
@@ -221,31 +452,247 @@ impl Compiler {
             offset: None,
         };
         let stack_slot = builder.create_stack_slot(data);
+        if decl.symbol.qualifiers.volatile {
+            self.volatile_locals.push(stack_slot);
+        }
         self.scope.insert(decl.symbol.id, Id::Local(stack_slot));
         if let Some(init) = decl.init {
-            self.store_stack(init, stack_slot, builder)?;
+            self.store_stack(init, &decl.symbol.ctype, stack_slot, location, builder)?;
         }
         Ok(())
     }
+    /// Declare a variable-length array.
+    ///
+    /// Cranelift can't grow the stack at runtime, so the array is allocated with `malloc`.
+    /// The address is stored in a stack slot and freed by `free_vlas`
+    /// on every path which leaves the block: its end, `break`, `continue`, `return`, and `goto`.
+    fn declare_vla(
+        &mut self,
+        id: InternedStr,
+        element: &Type,
+        length: Expr,
+        location: Location,
+        builder: &mut FunctionBuilder,
+    ) -> CompileResult<()> {
+        let element_size = match element.sizeof() {
+            Ok(size) => size,
+            Err(err) => semantic_err!(err.into(), location),
+        };
+        let ptr_type = Type::ptr_type();
+        let length_location = length.location;
+        // the parser has already converted the length to `size_t`
+        let length = self.compile_expr(length, builder)?;
+        let size = builder.ins().imul_imm(length.ir_val, element_size as i64);
+
+        let malloc = self.libc_function("malloc", &[ptr_type], Some(ptr_type));
+        let malloc = self.module.declare_func_in_func(malloc, builder.func);
+        let call = builder.ins().call(malloc, &[size]);
+        let addr = builder.inst_results(call)[0];
+        // an array on the stack would overflow it instead of returning NULL
+        builder.ins().trapz(addr, TrapCode::StackOverflow);
+
+        let slot = builder.create_stack_slot(StackSlotData {
+            kind: StackSlotKind::ExplicitSlot,
+            size: (2 * PTR_SIZE).into(),
+            offset: None,
+        });
+        builder.ins().stack_store(addr, slot, 0);
+        builder
+            .ins()
+            .stack_store(length.ir_val, slot, i32::from(PTR_SIZE));
+        self.scope.insert(id, Id::VariableArray(slot));
+        self.vla_lengths.push((length_location, slot));
+        self.vlas
+            .last_mut()
+            .expect("should be inside a function")
+            .push(slot);
+        Ok(())
+    }
+    /// The variable-length arrays in scope at the current statement, outermost first.
+    fn live_vlas(&self) -> Vec<StackSlot> {
+        self.vlas.iter().flatten().copied().collect()
+    }
+    /// The size in bytes of `ctype`, which may be a variable-length array, at runtime.
+    ///
+    /// The length of an array is the one computed when it was declared (C11 6.7.6.2p5),
+    /// even if the variables in its length expression changed since then.
+    /// The length of an array type which was never declared, like `sizeof(int[n])`,
+    /// is computed now.
+    pub(crate) fn vla_sizeof(
+        &mut self,
+        ctype: &Type,
+        location: Location,
+        builder: &mut FunctionBuilder,
+    ) -> CompileResult<IrValue> {
+        let ptr_type = Type::ptr_type();
+        match ctype {
+            Type::Array(element, ArrayType::Variable(length)) => {
+                let element_size = self.vla_sizeof(element, location, builder)?;
+                let declared = self
+                    .vla_lengths
+                    .iter()
+                    .find(|(declared, _)| *declared == length.location);
+                let length = match declared {
+                    Some(&(_, slot)) => {
+                        builder
+                            .ins()
+                            .stack_load(ptr_type, slot, i32::from(PTR_SIZE))
+                    }
+                    None => self.compile_expr((**length).clone(), builder)?.ir_val,
+                };
+                Ok(builder.ins().imul(length, element_size))
+            }
+            _ => match ctype.sizeof() {
+                Ok(size) => Ok(builder.ins().iconst(ptr_type, size as i64)),
+                Err(err) => semantic_err!(err.into(), location),
+            },
+        }
+    }
+    /// Free the variable-length arrays declared in the blocks nested `depth` or more deep,
+    /// in the reverse order they were declared.
+    fn free_vlas(&mut self, depth: usize, builder: &mut FunctionBuilder) {
+        let slots: Vec<_> = self.vlas[depth..].iter().flatten().copied().collect();
+        self.free_slots(&slots, builder);
+    }
+    /// Free the variable-length arrays stored in `slots` (outermost first),
+    /// in the reverse order they were declared.
+    fn free_slots(&mut self, slots: &[StackSlot], builder: &mut FunctionBuilder) {
+        if slots.is_empty() {
+            return;
+        }
+        let ptr_type = Type::ptr_type();
+        let free = self.libc_function("free", &[ptr_type], None);
+        let free = self.module.declare_func_in_func(free, builder.func);
+        for &slot in slots.iter().rev() {
+            let addr = builder.ins().stack_load(ptr_type, slot, 0);
+            builder.ins().call(free, &[addr]);
+        }
+    }
+    /// Declare a function from the C standard library which the compiler calls implicitly.
+    fn libc_function(&mut self, name: &str, params: &[IrType], ret: Option<IrType>) -> FuncId {
+        let signature = Signature {
+            call_conv: *CALLING_CONVENTION,
+            params: params.iter().map(|&param| AbiParam::new(param)).collect(),
+            returns: ret.into_iter().map(AbiParam::new).collect(),
+        };
+        self.module
+            .declare_function(name, Linkage::Import, &signature)
+            .unwrap_or_else(|err| utils::fatal(err, 6))
+    }
     fn store_stack(
         &mut self,
         init: Initializer,
+        ctype: &Type,
         stack_slot: StackSlot,
+        location: Location,
+        builder: &mut FunctionBuilder,
+    ) -> CompileResult<()> {
+        let is_aggregate = match (&init, ctype) {
+            (Initializer::InitializerList(_), _) | (_, Type::Array(_, _)) => true,
+            _ => false,
+        };
+        if is_aggregate {
+            // elements without an initializer are set to 0
+            let size = builder.func.stack_slots[stack_slot].size;
+            Self::zero_stack(stack_slot, size, builder);
+        }
+        // TODO: replace with `builder.ins().stack_store(val.ir_val, stack_slot, offset);`
+        // when Cranelift implements stack_store for i8 and i16
+        let addr = builder.ins().stack_addr(Type::ptr_type(), stack_slot, 0);
+        self.store_initializer(init, ctype, addr, 0, location, builder)
+    }
+    /// Store `init` at `addr + offset`, recursing into initializer lists.
+    fn store_initializer(
+        &mut self,
+        init: Initializer,
+        ctype: &Type,
+        addr: IrValue,
+        offset: i32,
+        location: Location,
         builder: &mut FunctionBuilder,
     ) -> CompileResult<()> {
         match init {
-            Initializer::Scalar(expr) => {
-                let val = self.compile_expr(*expr, builder)?;
-                // TODO: replace with `builder.ins().stack_store(val.ir_val, stack_slot, 0);`
-                // when Cranelift implements stack_store for i8 and i16
-                let addr = builder.ins().stack_addr(Type::ptr_type(), stack_slot, 0);
-                builder.ins().store(MemFlags::new(), val.ir_val, addr, 0);
-            }
-            Initializer::InitializerList(_) => unimplemented!("aggregate dynamic initialization"),
+            Initializer::Scalar(expr) => match (&expr.expr, ctype) {
+                (ExprType::Literal(Literal::Str(string, prefix)), Type::Array(_, _)) => {
+                    let bytes = static_init::string_initializer(
+                        *string,
+                        *prefix,
+                        ctype,
+                        self.endianness,
+                        &location,
+                    )?;
+                    for (i, &byte) in bytes.iter().enumerate() {
+                        let byte = builder.ins().iconst(types::I8, i64::from(byte));
+                        builder
+                            .ins()
+                            .store(MemFlags::new(), byte, addr, offset + i as i32);
+                    }
+                }
+                _ => {
+                    let val = self.compile_expr(*expr, builder)?;
+                    builder
+                        .ins()
+                        .store(MemFlags::new(), val.ir_val, addr, offset);
+                }
+            },
+            Initializer::InitializerList(mut inits) => match ctype {
+                Type::Array(element, ArrayType::Fixed(size)) => {
+                    if inits.len() as u64 > *size {
+                        return Err(location.error(SemanticError::InvalidArrayInitializer {
+                            size: *size,
+                            count: inits.len(),
+                        }));
+                    }
+                    let element_size = match element.sizeof() {
+                        Ok(size) => size,
+                        Err(err) => semantic_err!(err.into(), location),
+                    };
+                    for (i, init) in inits.into_iter().enumerate() {
+                        // the whole array fits in a stack slot, so this can't overflow
+                        let offset = offset + (i as u64 * element_size) as i32;
+                        self.store_initializer(init, element, addr, offset, location, builder)?;
+                    }
+                }
+                ty if ty.is_scalar() => {
+                    assert_eq!(inits.len(), 1);
+                    self.store_initializer(
+                        inits.remove(0),
+                        ctype,
+                        addr,
+                        offset,
+                        location,
+                        builder,
+                    )?;
+                }
+                _ => unimplemented!("aggregate dynamic initialization"),
+            },
             Initializer::FunctionBody(_) => unreachable!("functions can't be stored on the stack"),
         }
         Ok(())
     }
+    /// Set the first `size` bytes of `stack_slot` to 0.
+    fn zero_stack(stack_slot: StackSlot, size: u32, builder: &mut FunctionBuilder) {
+        let addr = builder.ins().stack_addr(Type::ptr_type(), stack_slot, 0);
+        let mut offset = 0;
+        if size >= 8 {
+            let zero = builder.ins().iconst(types::I64, 0);
+            while offset + 8 <= size {
+                builder
+                    .ins()
+                    .store(MemFlags::new(), zero, addr, offset as i32);
+                offset += 8;
+            }
+        }
+        if offset < size {
+            let zero = builder.ins().iconst(types::I8, 0);
+            while offset < size {
+                builder
+                    .ins()
+                    .store(MemFlags::new(), zero, addr, offset as i32);
+                offset += 1;
+            }
+        }
+    }
     // TODO: this is grossly inefficient, ask Cranelift devs if
     // there's an easier way to make parameters modifiable.
     fn store_stack_params(
@@ -290,6 +737,9 @@ impl Compiler {
             // See https://github.com/CraneStation/cranelift/issues/433
             let addr = builder.ins().stack_addr(Type::ptr_type(), slot, 0);
             builder.ins().store(MemFlags::new(), ir_val, addr, 0);
+            if param.qualifiers.volatile {
+                self.volatile_locals.push(slot);
+            }
             self.scope.insert(param.id, Id::Local(slot));
         }
         Ok(())
@@ -302,6 +752,40 @@ impl Compiler {
         stmts: Vec<Stmt>,
         location: Location,
     ) -> CompileResult<()> {
+        let (func_id, func) = self.build_func(id, func_type, sc, stmts, location)?;
+        let mut ctx = codegen::Context::for_function(func);
+        if let Err(err) = self.module.define_function(func_id, &mut ctx) {
+            println!("{}", ctx.func);
+            utils::fatal(err, 4);
+        }
+        if let Some(lines) = &mut self.debug_lines {
+            lines.add_function(func_id, &ctx.func, self.module.isa());
+        }
+
+        Ok(())
+    }
+    /// Compile a function to machine code without defining it in the module.
+    fn function_code(
+        &mut self,
+        id: InternedStr,
+        func_type: FunctionType,
+        sc: StorageClass,
+        stmts: Vec<Stmt>,
+        location: Location,
+    ) -> CompileResult<CompiledFunction> {
+        let (_, func) = self.build_func(id, func_type, sc, stmts, location)?;
+        code::function_code(func, self.module.isa(), &self.external_names)
+            .map_err(|err| location.error(SemanticError::Generic(err)))
+    }
+    /// Generate the IR for a function and verify it.
+    fn build_func(
+        &mut self,
+        id: InternedStr,
+        func_type: FunctionType,
+        sc: StorageClass,
+        stmts: Vec<Stmt>,
+        location: Location,
+    ) -> CompileResult<(FuncId, Function)> {
         let signature = func_type.signature(self.module.isa());
         let func_id = self.declare_func(id.clone(), &signature, sc, true)?;
         // external name is meant to be a lookup in a symbol table,
@@ -316,11 +800,28 @@ impl Compiler {
         builder.switch_to_block(func_start);
 
         let should_ret = func_type.should_return();
+        self.vlas = vec![Vec::new()];
+        self.vla_lengths.clear();
+        self.current_function = Some(id);
+        self.call_graph.entry(id).or_default();
+        // the parameters and locals go out of scope at the end of the function,
+        // even if it had an error
+        self.scope.enter_scope();
+        let mut body = Ok(());
         if func_type.has_params() {
-            self.store_stack_params(func_type.params, func_start, &location, &mut builder)?;
+            body = self.store_stack_params(func_type.params, func_start, &location, &mut builder);
+        }
+        if body.is_ok() {
+            body = self.compile_all(stmts, &mut builder);
         }
-        self.compile_all(stmts, &mut builder)?;
-        if !builder.is_filled() {
+        self.scope.leave_scope();
+        body?;
+        self.undeclared_labels()?;
+        if !builder.is_filled() && builder.is_unreachable() {
+            // e.g. after a `switch` where every case returns or calls `__builtin_unreachable()`
+            builder.ins().trap(TrapCode::UnreachableCodeReached);
+        } else if !builder.is_filled() {
+            self.free_vlas(0, &mut builder);
             if id == InternedStr::get_or_intern("main") {
                 let ir_int = func_type.return_type.as_ir_type();
                 let zero = [builder.ins().iconst(ir_int, 0)];
@@ -340,6 +841,9 @@ impl Compiler {
         }
         builder.seal_all_blocks();
         builder.finalize();
+        self.unreachable_labels(&func);
+        dead_store::eliminate_dead_stores(&mut func, &self.volatile_locals);
+        self.volatile_locals.clear();
 
         let flags = settings::Flags::new(settings::builder());
 
@@ -352,12 +856,54 @@ impl Compiler {
             utils::fatal(err, 3);
         }
 
-        let mut ctx = codegen::Context::for_function(func);
-        if let Err(err) = self.module.define_function(func_id, &mut ctx) {
-            println!("{}", ctx.func);
-            utils::fatal(err, 4);
+        Ok((func_id, func))
+    }
+    /// Report every `goto` to a label which was never declared in the current function.
+    ///
+    /// Labels can be declared after the `goto` which uses them,
+    /// so this can only be checked once the whole function has been compiled.
+    /// All but the last error are added to the error handler.
+    fn undeclared_labels(&mut self) -> CompileResult<()> {
+        let mut undeclared: Vec<_> = self
+            .pending_gotos
+            .drain()
+            .flat_map(|(name, (_, gotos))| {
+                gotos
+                    .into_iter()
+                    .map(move |(location, _, _)| (name, location))
+            })
+            .collect();
+        undeclared.sort_by_key(|(_, location)| location.span.start());
+        let last = match undeclared.pop() {
+            Some((name, location)) => location.error(SemanticError::UndeclaredLabel(name)),
+            None => return Ok(()),
+        };
+        for (name, location) in undeclared {
+            self.error_handler
+                .push_back(location.error(SemanticError::UndeclaredLabel(name)));
         }
+        Err(last)
+    }
+    /// Warn about labels which can never be reached, then forget all labels in the function.
+    ///
+    /// A label is unreachable if it is never the target of a `goto`
+    /// and the code before it always returns or jumps elsewhere.
+    /// Either way, its EBB has no predecessors.
+    fn unreachable_labels(&mut self, func: &Function) {
+        use crate::data::error::Warning;
+        use cranelift::codegen::flowgraph::ControlFlowGraph;
 
-        Ok(())
+        let cfg = ControlFlowGraph::with_function(func);
+        let mut unreachable: Vec<_> = self
+            .labels
+            .drain()
+            .filter(|(_, (ebb, _, _))| cfg.pred_iter(*ebb).next().is_none())
+            .map(|(name, (_, location, _))| (name, location))
+            .collect();
+        unreachable.sort_by_key(|(_, location)| location.span.start());
+        for (name, location) in unreachable {
+            self.error_handler
+                .warn(Warning::UnreachableLabel(name), location);
+        }
     }
 }
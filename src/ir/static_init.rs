@@ -7,9 +7,13 @@ use cranelift::codegen::ir::types;
 use cranelift_module::{DataContext, DataId, Linkage};
 
 use super::{Compiler, Id};
-use crate::arch::{PTR_SIZE, TARGET};
+use crate::arch::PTR_SIZE;
 use crate::data::prelude::*;
-use crate::data::{lex::Literal, types::ArrayType, Initializer, StorageClass};
+use crate::data::{
+    lex::{Literal, StrPrefix},
+    types::ArrayType,
+    Initializer, StorageClass,
+};
 
 const_assert!(PTR_SIZE <= std::usize::MAX as u16);
 const ZERO_PTR: [u8; PTR_SIZE as usize] = [0; PTR_SIZE as usize];
@@ -26,6 +30,11 @@ impl Compiler {
             data: err,
             location,
         };
+        // cranelift-module has no way to declare TLS data objects,
+        // so storing this as a plain global would silently share it between threads
+        if symbol.qualifiers.thread_local {
+            return Err(location.error(SemanticError::ThreadLocalUnsupported(symbol.id)));
+        }
         let linkage = symbol.storage_class.try_into().map_err(err_closure)?;
         let align = symbol
             .ctype
@@ -54,6 +63,8 @@ impl Compiler {
             })?;
 
         self.scope.insert(symbol.id, Id::Global(id));
+        self.external_names
+            .insert((1, id.as_u32()), symbol.id.resolve_and_clone());
 
         if linkage == Linkage::Import {
             debug_assert!(init.is_none());
@@ -62,18 +73,7 @@ impl Compiler {
 
         let mut ctx = DataContext::new();
         if let Some(init) = init {
-            if let Type::Array(_, size @ ArrayType::Unbounded) = &mut symbol.ctype {
-                if let Some(len) = match &init {
-                    Initializer::InitializerList(list) => Some(list.len()),
-                    Initializer::Scalar(expr) => match &expr.expr {
-                        ExprType::Literal(Literal::Str(s)) => Some(s.len()),
-                        _ => None,
-                    },
-                    _ => None,
-                } {
-                    *size = ArrayType::Fixed(len.try_into().unwrap());
-                };
-            }
+            infer_array_bounds(&mut symbol.ctype, &init);
             let size_t = symbol.ctype.sizeof().map_err(|err| Locatable {
                 data: err.to_string(),
                 location,
@@ -103,16 +103,18 @@ impl Compiler {
     pub(crate) fn compile_string(
         &mut self,
         string: InternedStr,
+        prefix: StrPrefix,
         location: Location,
     ) -> CompileResult<DataId> {
-        let name = format!("str.{}", string.to_usize());
+        let name = format!("{}str.{}", prefix, string.to_usize());
         let str_id = match self.module.declare_data(&name, Linkage::Local, false, None) {
             Ok(id) => id,
             Err(err) => semantic_err!(format!("error declaring static string: {}", err), location),
         };
-        if self.strings.insert(string, str_id).is_none() {
+        self.external_names.insert((1, str_id.as_u32()), name);
+        if self.strings.insert((string, prefix), str_id).is_none() {
             let mut ctx = DataContext::new();
-            ctx.define(string.resolve_and_clone().into_boxed_str().into());
+            ctx.define(encode_string(string, prefix, self.endianness));
             self.module
                 .define_data(str_id, &ctx)
                 .map_err(|err| Locatable {
@@ -134,8 +136,8 @@ impl Compiler {
         match expr.expr {
             ExprType::StaticRef(inner) => match inner.expr {
                 ExprType::Id(symbol) => self.static_ref(symbol, 0, offset, ctx),
-                ExprType::Literal(Literal::Str(str_ref)) => {
-                    let str_id = self.compile_string(str_ref, expr.location)?;
+                ExprType::Literal(Literal::Str(str_ref, prefix)) => {
+                    let str_id = self.compile_string(str_ref, prefix, expr.location)?;
                     let str_addr = self.module.declare_data_in_data(str_id, ctx);
                     ctx.write_data_addr(offset, str_addr, 0);
                 }
@@ -149,23 +151,22 @@ impl Compiler {
                     if let ExprType::Id(symbol) = struct_expr.expr {
                         self.static_ref(symbol, member_offset.try_into().unwrap(), offset, ctx);
                     } else {
-                        semantic_err!(
-                            "expression is not a compile time constant".into(),
-                            struct_expr.location
-                        );
+                        let location = struct_expr.location;
+                        return Err(location.error(SemanticError::NotConstant(*struct_expr)));
                     }
                 }
                 _ => semantic_err!("cannot take the address of an rvalue".into(), expr.location),
             },
             ExprType::Literal(token) => {
-                let bytes =
-                    token.into_bytes(&expr.ctype, &expr.location, &mut self.error_handler)?;
+                let bytes = token.into_bytes(
+                    &expr.ctype,
+                    &expr.location,
+                    self.endianness,
+                    &mut self.error_handler,
+                )?;
                 buf.copy_from_slice(&bytes);
             }
-            _ => semantic_err!(
-                "expression is not a compile time constant".into(),
-                expr.location
-            ),
+            _ => return Err(expr.location.error(SemanticError::NotConstant(expr))),
         }
         Ok(())
     }
@@ -180,7 +181,9 @@ impl Compiler {
                 let global_val = self.module.declare_data_in_data(*data_id, ctx);
                 ctx.write_data_addr(offset, global_val, member_offset);
             }
-            Some(Id::Local(_)) => unreachable!("cannot have local variable at global scope"),
+            Some(Id::Local(_)) | Some(Id::VariableArray(_)) => {
+                unreachable!("cannot have local variable at global scope")
+            }
             None => unreachable!("parser should catch undeclared variables"),
         }
     }
@@ -188,7 +191,7 @@ impl Compiler {
         &mut self,
         ctx: &mut DataContext,
         buf: &mut [u8],
-        mut offset: u32,
+        offset: u32,
         initializer: Initializer,
         ctype: &Type,
         location: &Location,
@@ -200,14 +203,10 @@ impl Compiler {
                 }
                 Type::Array(ty, ArrayType::Fixed(size)) => {
                     if initializers.len() as u64 > *size {
-                        Err(CompileError::semantic(Locatable {
-                            data: format!(
-                                "too many elements for array (expected {}, got {})",
-                                size,
-                                initializers.len()
-                            ),
-                            // TODO: this location points to the declarator, not the initializer
-                            location: *location,
+                        // TODO: this location points to the declarator, not the initializer
+                        Err(location.error(SemanticError::InvalidArrayInitializer {
+                            size: *size,
+                            count: initializers.len(),
                         }))
                     } else {
                         self.init_array(ctx, buf, offset, initializers, ty, location)
@@ -226,37 +225,92 @@ impl Compiler {
                     location,
                 ),
                 Type::Struct(struct_ref) => {
-                    let mut current_offset = 0;
                     for (member, init) in struct_ref.members().iter().zip(initializers.into_iter())
                     {
-                        let size_host: usize = member
+                        let member_offset: usize = ctype
+                            .member_offset(member.id)
+                            .expect("only structs and unions can have members")
+                            .try_into()
+                            .expect("cannot intialize struct larger than host address space");
+                        let size: usize = member
                             .ctype
                             .sizeof()
                             .map_err(|err| CompileError::semantic(location.with(err.to_string())))?
-                            .try_into()
-                            .expect("cannot initialize struct larger than u32");
-                        let size: u32 = size_host
                             .try_into()
                             .expect("cannot intialize struct larger than host address space");
-                        let buf_slice = &mut buf[current_offset..current_offset + size_host];
-                        self.init_symbol(ctx, buf_slice, offset, init, &member.ctype, location)?;
-                        offset += size;
-                        current_offset += size_host;
+                        let buf_slice = &mut buf[member_offset..member_offset + size];
+                        let offset = offset
+                            + u32::try_from(member_offset)
+                                .expect("cannot initialize struct larger than u32");
+                        match (&member.ctype, ctype.bitfield_offset(member.id)) {
+                            (Type::Bitfield(inner, _), Some(bitfield)) => self.init_bitfield(
+                                ctx, buf_slice, offset, init, inner, bitfield, location,
+                            )?,
+                            _ => self.init_symbol(
+                                ctx,
+                                buf_slice,
+                                offset,
+                                init,
+                                &member.ctype,
+                                location,
+                            )?,
+                        }
                     }
                     Ok(())
                 }
-                Type::Bitfield(_) => unimplemented!("bitfield initalizers"),
+                Type::Bitfield(_, _) => unreachable!("bitfields are initialized by their struct"),
 
                 Type::Function(_) => unreachable!("function initializers"),
                 Type::Void => unreachable!("initializer for void type"),
                 _ => unreachable!("scalar types should have been handled"),
             },
-            Initializer::Scalar(expr) => self.init_expr(ctx, buf, offset, *expr),
+            Initializer::Scalar(expr) => match (&expr.expr, ctype) {
+                (ExprType::Literal(Literal::Str(string, prefix)), Type::Array(_, _)) => {
+                    let bytes =
+                        string_initializer(*string, *prefix, ctype, self.endianness, location)?;
+                    buf[..bytes.len()].copy_from_slice(&bytes);
+                    Ok(())
+                }
+                _ => self.init_expr(ctx, buf, offset, *expr),
+            },
             Initializer::FunctionBody(_) => {
                 panic!("function definitions should go through compile_function, not store_static")
             }
         }
     }
+    /// Store the value of a bit-field in `unit`, the storage unit it shares with its neighbours,
+    /// leaving the bits of the other bit-fields unchanged.
+    #[allow(clippy::too_many_arguments)]
+    fn init_bitfield(
+        &mut self,
+        ctx: &mut DataContext,
+        unit: &mut [u8],
+        offset: u32,
+        initializer: Initializer,
+        inner_type: &Type,
+        (bit_offset, width): (u16, u16),
+        location: &Location,
+    ) -> CompileResult<()> {
+        let mut value = vec![0; unit.len()];
+        self.init_symbol(ctx, &mut value, offset, initializer, inner_type, location)?;
+        let big_endian = self.endianness == Endianness::Big;
+        let to_int = |bytes: &[u8]| {
+            let push_byte = |int: u64, byte: &u8| (int << 8) | u64::from(*byte);
+            if big_endian {
+                bytes.iter().fold(0, push_byte)
+            } else {
+                bytes.iter().rev().fold(0, push_byte)
+            }
+        };
+        let mask = (u64::max_value() >> (64 - width)) << bit_offset;
+        let packed = (to_int(unit) & !mask) | ((to_int(&value) << bit_offset) & mask);
+        let len = unit.len();
+        for (i, byte) in unit.iter_mut().enumerate() {
+            let index = if big_endian { len - 1 - i } else { i };
+            *byte = (packed >> (index * 8)) as u8;
+        }
+        Ok(())
+    }
     fn init_array(
         &mut self,
         ctx: &mut DataContext,
@@ -327,18 +381,85 @@ macro_rules! bytes {
     }};
 }
 
+/// Complete the type of an array declared without a size, like `int a[] = {1, 2}`,
+/// using the number of elements in its initializer.
+pub(super) fn infer_array_bounds(ctype: &mut Type, init: &Initializer) {
+    if let Type::Array(_, size @ ArrayType::Unbounded) = ctype {
+        if let Some(len) = match init {
+            Initializer::InitializerList(list) => Some(list.len()),
+            Initializer::Scalar(expr) => match &expr.expr {
+                ExprType::Literal(Literal::Str(s, prefix)) => Some(prefix.encoded_len(*s)),
+                _ => None,
+            },
+            _ => None,
+        } {
+            *size = ArrayType::Fixed(len.try_into().unwrap());
+        };
+    }
+}
+
+/// The bytes used to initialize the array `ctype` with a string literal.
+///
+/// If the array has no room for the null terminator, it is left off (C11 6.7.9p14).
+/// Any other string that doesn't fit is an error.
+pub(super) fn string_initializer(
+    string: InternedStr,
+    prefix: StrPrefix,
+    ctype: &Type,
+    endianness: Endianness,
+    location: &Location,
+) -> CompileResult<Box<[u8]>> {
+    let (size, element_size) = match ctype {
+        Type::Array(element, ArrayType::Fixed(size)) => {
+            let element_size = element
+                .sizeof()
+                .map_err(|err| CompileError::semantic(location.with(err.to_string())))?;
+            (*size, element_size)
+        }
+        _ => unreachable!("only arrays can be initialized by a string literal"),
+    };
+    let bytes = encode_string(string, prefix, endianness);
+    let byte_size = size * element_size;
+    if bytes.len() as u64 <= byte_size {
+        Ok(bytes)
+    } else if bytes.len() as u64 - element_size == byte_size {
+        Ok(bytes[..byte_size as usize].into())
+    } else {
+        Err(location.error(SemanticError::InvalidArrayInitializer {
+            size,
+            // don't count the null terminator
+            count: prefix.encoded_len(string) - 1,
+        }))
+    }
+}
+
+/// Encode each element of a string literal in the given byte order, including the null terminator.
+fn encode_string(string: InternedStr, prefix: StrPrefix, endianness: Endianness) -> Box<[u8]> {
+    let big_endian = endianness == Endianness::Big;
+    let string = string.resolve_and_clone();
+    match prefix {
+        StrPrefix::Plain | StrPrefix::Utf8 => string.into_boxed_str().into(),
+        StrPrefix::Utf16 => string
+            .encode_utf16()
+            .flat_map(|c| bytes!(c, big_endian).into_vec())
+            .collect(),
+        StrPrefix::Wide | StrPrefix::Utf32 => string
+            .chars()
+            .flat_map(|c| bytes!(u32::from(c), big_endian).into_vec())
+            .collect(),
+    }
+}
+
 impl Literal {
     fn into_bytes(
         self,
         ctype: &Type,
         location: &Location,
+        endianness: Endianness,
         error_handler: &mut ErrorHandler,
     ) -> CompileResult<Box<[u8]>> {
         let ir_type = ctype.as_ir_type();
-        let big_endian = TARGET
-            .endianness()
-            .expect("target should be big or little endian")
-            == target_lexicon::Endianness::Big;
+        let big_endian = endianness == Endianness::Big;
 
         match self {
             Literal::Int(i) => Ok(match ir_type {
@@ -398,8 +519,11 @@ impl Literal {
                     x, f
                 )),
             }),
-            Literal::Str(string) => Ok(string.resolve_and_clone().into_boxed_str().into()),
+            Literal::Str(string, prefix) => Ok(encode_string(string, prefix, endianness)),
             Literal::Char(c) => Ok(Box::new([c])),
+            Literal::WideChar(..) => {
+                unreachable!("wide characters should be converted to integers by the parser")
+            }
         }
     }
 }
@@ -419,3 +543,45 @@ impl TryFrom<StorageClass> for Linkage {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn string_literals_are_pooled() {
+        let mut compiler = Compiler::new("<test-suite>".into(), false, Endianness::Little);
+        let location = Location::default();
+        let hello = InternedStr::get_or_intern("hello\0");
+        let help = InternedStr::get_or_intern("help\0");
+        let first = compiler
+            .compile_string(hello, StrPrefix::Plain, location)
+            .unwrap();
+        let second = compiler
+            .compile_string(hello, StrPrefix::Plain, location)
+            .unwrap();
+        assert_eq!(first, second);
+        assert_eq!(compiler.strings.len(), 1);
+        let other = compiler
+            .compile_string(help, StrPrefix::Plain, location)
+            .unwrap();
+        assert_ne!(first, other);
+        let wide = compiler
+            .compile_string(hello, StrPrefix::Wide, location)
+            .unwrap();
+        assert_ne!(first, wide);
+        assert_eq!(compiler.strings.len(), 3);
+    }
+    #[test]
+    fn strings_are_nul_terminated() {
+        let hello = InternedStr::get_or_intern("hi\0");
+        assert_eq!(
+            &*encode_string(hello, StrPrefix::Plain, Endianness::Little),
+            b"hi\0"
+        );
+        assert_eq!(
+            &*encode_string(hello, StrPrefix::Utf16, Endianness::Big),
+            &[0, b'h', 0, b'i', 0, 0]
+        );
+    }
+}
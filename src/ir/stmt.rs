@@ -1,9 +1,19 @@
-use cranelift::codegen::cursor::Cursor;
+use cranelift::codegen::{
+    cursor::Cursor,
+    ir::{types, JumpTableData, SourceLoc, TrapCode, Value as IrValue},
+};
 use cranelift::frontend::Switch;
 use cranelift::prelude::{Ebb, FunctionBuilder, InstBuilder};
 
-use super::Compiler;
-use crate::data::prelude::*;
+use super::{Breakable, Compiler, SwitchContext};
+use crate::data::{error::Warning, lex::ComparisonToken, prelude::*};
+
+/// The default for `Compiler::jump_table_density`.
+pub(super) const DEFAULT_JUMP_TABLE_DENSITY: u8 = 40;
+/// Switches with fewer cases than this are never lowered to a jump table.
+const MIN_JUMP_TABLE_CASES: usize = 4;
+/// Jump tables never have more entries than this, however dense the labels are.
+const MAX_JUMP_TABLE_LEN: i128 = 1 << 16;
 
 impl Compiler {
     pub(crate) fn compile_all(
@@ -24,8 +34,24 @@ impl Compiler {
         if builder.is_filled() && !stmt.data.is_jump_target() {
             return Err(stmt.location.error(SemanticError::UnreachableStatement));
         }
+        if let Some(lines) = &self.debug_lines {
+            if stmt.location.filename == lines.filename() {
+                builder.set_srcloc(SourceLoc::new(stmt.location.span.start().to_usize() as u32));
+            }
+        }
         match stmt.data {
-            StmtType::Compound(stmts) => self.compile_all(stmts, builder),
+            StmtType::Compound(stmts) => {
+                self.scope.enter_scope();
+                self.vlas.push(Vec::new());
+                let result = self.compile_all(stmts, builder);
+                if result.is_ok() && !builder.is_filled() {
+                    self.free_vlas(self.vlas.len() - 1, builder);
+                }
+                self.vlas.pop();
+                // declarations in the block no longer shadow the outer ones
+                self.scope.leave_scope();
+                result
+            }
             // INVARIANT: symbol has not yet been declared in this scope
             StmtType::Decl(decls) => {
                 for decl in decls {
@@ -34,15 +60,28 @@ impl Compiler {
                 Ok(())
             }
             StmtType::Expr(expr) => {
+                if !expr.has_side_effects() {
+                    self.error_handler.warn(Warning::NoEffect, stmt.location);
+                }
+                // fill the current block so that code after this is known to be unreachable
+                if expr.is_unreachable() {
+                    builder.ins().trap(TrapCode::UnreachableCodeReached);
+                    return Ok(());
+                }
                 self.compile_expr(expr, builder)?;
                 Ok(())
             }
             StmtType::Return(expr) => {
                 let mut ret = vec![];
                 if let Some(e) = expr {
+                    if let Some(local) = self.local_address(&e) {
+                        let warning = Warning::DanglingLocalAddress(local);
+                        self.error_handler.warn(warning, stmt.location);
+                    }
                     let val = self.compile_expr(e, builder)?;
                     ret.push(val.ir_val);
                 }
+                self.free_vlas(0, builder);
                 builder.ins().return_(&ret);
                 Ok(())
             }
@@ -55,21 +94,61 @@ impl Compiler {
             StmtType::Break | StmtType::Continue => {
                 self.loop_exit(stmt.data == StmtType::Break, stmt.location, builder)
             }
-            StmtType::For(init, condition, post_loop, body) => self.for_loop(
-                init,
-                condition.map(|e| *e),
-                post_loop.map(|e| *e),
-                body,
-                stmt.location,
-                builder,
-            ),
+            StmtType::For(init, condition, post_loop, body) => {
+                // a declaration in the first clause is only in scope for the loop
+                self.scope.enter_scope();
+                let result = self.for_loop(
+                    init,
+                    condition.map(|e| *e),
+                    post_loop.map(|e| *e),
+                    body,
+                    builder,
+                );
+                self.scope.leave_scope();
+                result
+            }
             StmtType::Do(body, condition) => self.do_loop(*body, condition, builder),
             StmtType::Switch(condition, body) => self.switch(condition, *body, builder),
             StmtType::Label(name, inner) => {
-                let new_block = builder.create_ebb();
+                let vlas = self.live_vlas();
+                // the blocks of earlier `goto`s which leave the scope of a VLA
+                let mut leaving = Vec::new();
+                // if there was a `goto` before the label, it already has a block
+                let new_block = match self.pending_gotos.remove(&name) {
+                    Some((ebb, gotos)) => {
+                        // C11 6.8.6.1p1: a goto can't jump into the scope of a VLA
+                        let mut entering = Vec::new();
+                        for (location, goto_vlas, free_block) in gotos {
+                            if !vlas.iter().all(|v| goto_vlas.contains(v)) {
+                                entering.push(location);
+                            } else if let Some(free_block) = free_block {
+                                leaving.push((free_block, goto_vlas));
+                            }
+                        }
+                        if let Some(last) = entering.pop() {
+                            let err = || SemanticError::GotoIntoVlaScope(name.to_string());
+                            for location in entering {
+                                self.error_handler.push_back(location.error(err()));
+                            }
+                            return Err(last.error(err()));
+                        }
+                        ebb
+                    }
+                    None => builder.create_ebb(),
+                };
                 Self::jump_to_block(new_block, builder);
+                for (free_block, goto_vlas) in leaving {
+                    builder.switch_to_block(free_block);
+                    let freed: Vec<_> = goto_vlas
+                        .into_iter()
+                        .filter(|v| !vlas.contains(v))
+                        .collect();
+                    self.free_slots(&freed, builder);
+                    builder.ins().jump(new_block, &[]);
+                }
                 builder.switch_to_block(new_block);
-                if let Some(previous) = self.labels.insert(name, new_block) {
+                let label = (new_block, stmt.location, vlas);
+                if let Some((previous, _, _)) = self.labels.insert(name, label) {
                     Err(stmt
                         .location
                         .error(SemanticError::LabelRedeclaration(previous)))
@@ -79,13 +158,44 @@ impl Compiler {
                     Ok(())
                 }
             }
-            StmtType::Goto(name) => match self.labels.get(&name) {
-                Some(ebb) => {
-                    Self::jump_to_block(*ebb, builder);
-                    Ok(())
-                }
-                None => Err(stmt.location.error(SemanticError::UndeclaredLabel(name))),
-            },
+            StmtType::Goto(name) => {
+                let vlas = self.live_vlas();
+                let ebb = match self.labels.get(&name) {
+                    Some((ebb, _, label_vlas)) => {
+                        // jumping backwards out of a VLA's scope is fine, but not into it
+                        if !label_vlas.iter().all(|v| vlas.contains(v)) {
+                            let err = SemanticError::GotoIntoVlaScope(name.to_string());
+                            return Err(stmt.location.error(err));
+                        }
+                        let ebb = *ebb;
+                        let freed: Vec<_> = vlas
+                            .into_iter()
+                            .filter(|v| !label_vlas.contains(v))
+                            .collect();
+                        self.free_slots(&freed, builder);
+                        ebb
+                    }
+                    // a forward jump: `compile_func` reports an error if the label is never declared
+                    None => {
+                        // which VLAs are in scope at the label isn't known yet,
+                        // so jump to a block which frees the others once it is
+                        let free_block = if vlas.is_empty() {
+                            None
+                        } else {
+                            Some(builder.create_ebb())
+                        };
+                        let (ebb, gotos) = self
+                            .pending_gotos
+                            .entry(name)
+                            .or_insert_with(|| (builder.create_ebb(), Vec::new()));
+                        let ebb = free_block.unwrap_or(*ebb);
+                        gotos.push((stmt.location, vlas, free_block));
+                        ebb
+                    }
+                };
+                Self::jump_to_block(ebb, builder);
+                Ok(())
+            }
             StmtType::Case(constexpr, inner) => self.case(constexpr, inner, stmt.location, builder),
             StmtType::Default(inner) => self.default(inner, stmt.location, builder),
         }
@@ -104,12 +214,10 @@ impl Compiler {
         //      Fallthrough to if_body + compile_all
         //      If else_block exists, jump to end_block + compile_all
         //      Otherwise, fallthrough to end_block
-        let condition = self.compile_expr(condition, builder)?;
         let (if_body, end_body) = (builder.create_ebb(), builder.create_ebb());
         if let Some(other) = otherwise {
             let else_body = builder.create_ebb();
-            builder.ins().brz(condition.ir_val, else_body, &[]);
-            builder.ins().jump(if_body, &[]);
+            self.branch(condition, if_body, else_body, builder)?;
 
             builder.switch_to_block(if_body);
             self.compile_stmt(body, builder)?;
@@ -127,8 +235,7 @@ impl Compiler {
                 builder.switch_to_block(end_body);
             }
         } else {
-            builder.ins().brz(condition.ir_val, end_body, &[]);
-            builder.ins().jump(if_body, &[]);
+            self.branch(condition, if_body, end_body, builder)?;
 
             builder.switch_to_block(if_body);
             self.compile_stmt(body, builder)?;
@@ -138,24 +245,59 @@ impl Compiler {
         };
         Ok(())
     }
+    /// Jump to `then` if `condition` is nonzero and to `otherwise` if it is zero.
+    ///
+    /// Blocks are laid out in the order they are first used, so normally `then` follows
+    /// the branch directly. If `condition` is `__builtin_expect(expr, 0)`,
+    /// `otherwise` is inserted into the layout now so that it follows the branch instead.
+    fn branch(
+        &mut self,
+        condition: Expr,
+        then: Ebb,
+        otherwise: Ebb,
+        builder: &mut FunctionBuilder,
+    ) -> CompileResult<()> {
+        let unlikely = expected_value(&condition) == Some(false);
+        let condition = self.compile_expr(condition, builder)?;
+        if unlikely {
+            builder.ins().brnz(condition.ir_val, then, &[]);
+            builder.ins().jump(otherwise, &[]);
+            if !builder.func.layout.is_ebb_inserted(otherwise) {
+                builder.func.layout.append_ebb(otherwise);
+            }
+        } else {
+            builder.ins().brz(condition.ir_val, otherwise, &[]);
+            builder.ins().jump(then, &[]);
+        }
+        Ok(())
+    }
     /// Enter a loop context:
     /// - Create a new start and end EBB
     /// - Switch to the start EBB
-    /// - Return (start, end, previous_last_saw_loop)
-    fn enter_loop(&mut self, builder: &mut FunctionBuilder) -> (Ebb, Ebb, bool) {
+    /// - Return (start, end)
+    ///
+    /// `continue` jumps to `continue_target` if given, otherwise to the start EBB.
+    fn enter_loop(
+        &mut self,
+        continue_target: Option<Ebb>,
+        builder: &mut FunctionBuilder,
+    ) -> (Ebb, Ebb) {
         let (loop_body, end_body) = (builder.create_ebb(), builder.create_ebb());
-        self.loops.push((loop_body, end_body));
-        let old_saw_loop = self.last_saw_loop;
-        self.last_saw_loop = true;
+        self.loops.push((
+            continue_target.unwrap_or(loop_body),
+            end_body,
+            self.vlas.len(),
+        ));
+        self.breakable.push(Breakable::Loop);
 
         builder.ins().jump(loop_body, &[]);
         builder.switch_to_block(loop_body);
-        (loop_body, end_body, old_saw_loop)
+        (loop_body, end_body)
     }
     /// Exit a loop
-    fn exit_loop(&mut self, old_saw_loop: bool) {
+    fn exit_loop(&mut self) {
         self.loops.pop();
-        self.last_saw_loop = old_saw_loop;
+        self.breakable.pop();
     }
     fn while_stmt(
         &mut self,
@@ -163,12 +305,13 @@ impl Compiler {
         maybe_body: Option<Stmt>,
         builder: &mut FunctionBuilder,
     ) -> CompileResult<()> {
-        let (loop_body, end_body, old_saw_loop) = self.enter_loop(builder);
+        let (loop_body, end_body) = self.enter_loop(None, builder);
 
         // for loops can loop forever: `for (;;) {}`
         if let Some(condition) = maybe_condition {
-            let condition = self.compile_expr(condition, builder)?;
-            builder.ins().brz(condition.ir_val, end_body, &[]);
+            let body = builder.create_ebb();
+            self.branch(condition, body, end_body, builder)?;
+            builder.switch_to_block(body);
         }
 
         if let Some(body) = maybe_body {
@@ -177,7 +320,7 @@ impl Compiler {
         Self::jump_to_block(loop_body, builder);
 
         builder.switch_to_block(end_body);
-        self.exit_loop(old_saw_loop);
+        self.exit_loop();
         Ok(())
     }
     fn do_loop(
@@ -186,7 +329,7 @@ impl Compiler {
         condition: Expr,
         builder: &mut FunctionBuilder,
     ) -> CompileResult<()> {
-        let (loop_body, end_body, old_saw_loop) = self.enter_loop(builder);
+        let (loop_body, end_body) = self.enter_loop(None, builder);
 
         self.compile_stmt(body, builder)?;
         if builder.is_filled() {
@@ -194,12 +337,10 @@ impl Compiler {
                 .location
                 .error(SemanticError::UnreachableStatement));
         }
-        let condition = self.compile_expr(condition, builder)?;
-        builder.ins().brz(condition.ir_val, end_body, &[]);
-        Self::jump_to_block(loop_body, builder);
+        self.branch(condition, loop_body, end_body, builder)?;
 
         builder.switch_to_block(end_body);
-        self.exit_loop(old_saw_loop);
+        self.exit_loop();
         Ok(())
     }
     fn for_loop(
@@ -208,34 +349,35 @@ impl Compiler {
         condition: Option<Expr>,
         post_loop: Option<Expr>,
         body: Option<Box<Stmt>>,
-        location: Location,
         builder: &mut FunctionBuilder,
     ) -> CompileResult<()> {
         if let Some(init) = init {
             self.compile_stmt(*init, builder)?;
         }
-        let mut body = body.map(|x| *x);
+        // `continue` has to run the post-loop expression before checking the condition again
+        let post_body = builder.create_ebb();
+        let (loop_body, end_body) = self.enter_loop(Some(post_body), builder);
+
+        // for loops can loop forever: `for (;;) {}`
+        if let Some(condition) = condition {
+            let body = builder.create_ebb();
+            self.branch(condition, body, end_body, builder)?;
+            builder.switch_to_block(body);
+        }
+        if let Some(body) = body {
+            self.compile_stmt(*body, builder)?;
+        }
+        Self::jump_to_block(post_body, builder);
+
+        builder.switch_to_block(post_body);
         if let Some(post_loop) = post_loop {
-            let post_loop = Stmt {
-                data: StmtType::Expr(post_loop),
-                location,
-            };
-            if let Some(Stmt {
-                data: StmtType::Compound(stmts),
-                ..
-            }) = &mut body
-            {
-                stmts.push(post_loop);
-            } else if let Some(other) = body {
-                body = Some(Stmt {
-                    data: StmtType::Compound(vec![other, post_loop]),
-                    location,
-                });
-            } else {
-                body = Some(post_loop);
-            };
+            self.compile_expr(post_loop, builder)?;
         }
-        self.while_stmt(condition, body, builder)
+        builder.ins().jump(loop_body, &[]);
+
+        builder.switch_to_block(end_body);
+        self.exit_loop();
+        Ok(())
     }
     fn switch(
         &mut self,
@@ -243,36 +385,144 @@ impl Compiler {
         body: Stmt,
         builder: &mut FunctionBuilder,
     ) -> CompileResult<()> {
+        // `Switch::emit` needs an integer, a float or pointer would generate invalid IR
+        if !condition.ctype.is_integral() {
+            let err = SemanticError::NonIntegerSwitch(condition.ctype);
+            return Err(condition.location.error(err));
+        }
+        let ctype = condition.ctype.clone();
+        // C11 6.8.4.2p5: the integer promotions are performed on the condition
+        let promoted = ctype.clone().integer_promote();
         let cond_val = self.compile_expr(condition, builder)?;
-        // works around https://github.com/CraneStation/cranelift/issues/1057
-        // instead of switching to back to the current block to emit the Switch,
-        // fill a new dummy block
-        let dummy_block = builder.create_ebb();
-        Self::jump_to_block(dummy_block, builder);
+        let cond_val = Self::cast_ir(
+            ctype.as_ir_type(),
+            promoted.as_ir_type(),
+            cond_val.ir_val,
+            ctype.is_signed(),
+            promoted.is_signed(),
+            builder,
+        );
+        let signed = promoted.is_signed();
+        let end = builder.create_ebb();
+        // The current block has to be filled before compiling the body
+        // (https://github.com/CraneStation/cranelift/issues/1057),
+        // so the `Switch` can't be emitted here once the body has been compiled.
+        // Either jump to a dummy block and emit the `Switch` there afterwards,
+        // or emit it now, with a block for each label in the body.
+        let (switch_block, planned) = if self.switch_dummy_block {
+            let dummy_block = builder.create_ebb();
+            Self::jump_to_block(dummy_block, builder);
+            (Some(dummy_block), None)
+        } else {
+            let (mut labels, mut has_default) = (Vec::new(), false);
+            switch_labels(&body, &mut labels, &mut has_default);
+            let mut cases: Vec<(u64, Ebb)> = Vec::new();
+            for label in labels {
+                let label = convert_case(label, &promoted);
+                // duplicates are reported when the label is compiled
+                if cases.iter().all(|&(other, _)| other != label) {
+                    cases.push((label, builder.create_ebb()));
+                }
+            }
+            let default = if has_default {
+                Some(builder.create_ebb())
+            } else {
+                None
+            };
+            self.emit_switch(
+                cases.clone(),
+                default.unwrap_or(end),
+                cond_val,
+                signed,
+                builder,
+            );
+            (None, Some((cases, default)))
+        };
 
-        let start_block = builder.create_ebb();
+        // if the body starts with a label, start in the block for that label
+        let first_block = match (&planned, body.data.first_label()) {
+            (Some((cases, _)), Some(StmtType::Case(label, _))) => {
+                let label = convert_case(*label, &promoted);
+                cases
+                    .iter()
+                    .find(|&&(other, _)| other == label)
+                    .map(|&(_, ebb)| ebb)
+            }
+            (Some((_, default)), Some(StmtType::Default(_))) => *default,
+            _ => None,
+        };
+        let start_block = first_block.unwrap_or_else(|| builder.create_ebb());
         builder.switch_to_block(start_block);
-        self.last_saw_loop = false;
-
-        self.switches
-            .push((Switch::new(), None, builder.create_ebb()));
+        self.breakable.push(Breakable::Switch);
+        self.switches.push(SwitchContext {
+            cases: Vec::new(),
+            default: None,
+            end,
+            depth: self.vlas.len(),
+            ctype,
+            planned,
+        });
         self.compile_stmt(body, builder)?;
-        let (switch, default, end) = self.switches.pop().unwrap();
+        let SwitchContext { cases, default, .. } = self.switches.pop().unwrap();
+        self.breakable.pop();
 
         Self::jump_to_block(end, builder);
-        builder.switch_to_block(dummy_block);
-        switch.emit(
-            builder,
-            cond_val.ir_val,
-            if let Some(default) = default {
-                default
-            } else {
-                end
-            },
-        );
+        if let Some(switch_block) = switch_block {
+            builder.switch_to_block(switch_block);
+            self.emit_switch(cases, default.unwrap_or(end), cond_val, signed, builder);
+        }
         builder.switch_to_block(end);
+        // every `break` has been compiled, so this has all its predecessors
+        builder.seal_block(end);
         Ok(())
     }
+    /// Jump to the block for the case label `cond`, or to `default` if there isn't one.
+    fn emit_switch(
+        &self,
+        cases: Vec<(u64, Ebb)>,
+        default: Ebb,
+        cond: IrValue,
+        signed: bool,
+        builder: &mut FunctionBuilder,
+    ) {
+        let labels = cases.iter().map(|&(label, _)| label);
+        if let Some((min, len)) = jump_table_range(labels, signed, self.jump_table_density) {
+            Self::emit_jump_table(cases, default, cond, min, len, builder);
+        } else {
+            let mut switch = Switch::new();
+            for (label, ebb) in cases {
+                switch.set_entry(label, ebb);
+            }
+            switch.emit(builder, cond, default);
+        }
+    }
+    /// Jump to the block for the case label `cond`, using a jump table with `len` entries
+    /// starting at the label `min`.
+    fn emit_jump_table(
+        cases: Vec<(u64, Ebb)>,
+        default: Ebb,
+        cond: IrValue,
+        min: u64,
+        len: u64,
+        builder: &mut FunctionBuilder,
+    ) {
+        let mut table = vec![default; len as usize];
+        for (label, ebb) in cases {
+            table[label.wrapping_sub(min) as usize] = ebb;
+        }
+        let mut data = JumpTableData::with_capacity(table.len());
+        for ebb in table {
+            data.push_entry(ebb);
+        }
+        let jump_table = builder.create_jump_table(data);
+        // Labels below `min` wrap around to large unsigned values,
+        // so every label outside the table goes to `default`.
+        let mut index = builder.ins().iadd_imm(cond, (min as i64).wrapping_neg());
+        if builder.func.dfg.value_type(index) != types::I64 {
+            index = builder.ins().uextend(types::I64, index);
+        }
+        builder.ins().br_table(index, default, jump_table);
+    }
     fn case(
         &mut self,
         constexpr: u64,
@@ -280,34 +530,98 @@ impl Compiler {
         location: Location,
         builder: &mut FunctionBuilder,
     ) -> CompileResult<()> {
-        let (switch, _, _) = match self.switches.last_mut() {
+        if self.case_enters_vla_scope() {
+            let err = SemanticError::GotoIntoVlaScope(format!("case {}", constexpr as i64));
+            return Err(location.error(err));
+        }
+        let SwitchContext {
+            cases,
+            ctype,
+            planned,
+            ..
+        } = match self.switches.last_mut() {
             Some(x) => x,
             None => {
                 return Err(location.error(SemanticError::CaseOutsideSwitch { is_default: false }))
             }
         };
-        if builder.is_pristine() {
-            let current = builder.cursor().current_ebb().unwrap();
-            switch.set_entry(constexpr, current);
-        } else {
-            let new = builder.create_ebb();
-            switch.set_entry(constexpr, new);
-            Self::jump_to_block(new, builder);
-            builder.switch_to_block(new);
-        };
+        // C11 6.8.4.2p5: the label is converted to the promoted type of the condition
+        let promoted = ctype.clone().integer_promote();
+        let converted = convert_case(constexpr, &promoted);
+        if converted != constexpr {
+            let warning =
+                Warning::CaseConversion(constexpr as i64, converted as i64, promoted.clone());
+            self.error_handler.warn(warning, location);
+        } else if convert_case(constexpr, ctype) != constexpr {
+            // the condition had this type before it was promoted, so it can't have this value
+            let warning = Warning::CaseOutOfRange(constexpr as i64, ctype.clone());
+            self.error_handler.warn(warning, location);
+        }
+        if cases.iter().any(|&(label, _)| label == converted) {
+            let label = if promoted.is_signed() {
+                (converted as i64).to_string()
+            } else {
+                converted.to_string()
+            };
+            return Err(location.error(SemanticError::DuplicateCase(label)));
+        }
+        let constexpr = converted;
+        let planned = planned.as_ref().and_then(|(planned, _)| {
+            planned
+                .iter()
+                .find(|&&(label, _)| label == constexpr)
+                .map(|&(_, ebb)| ebb)
+        });
+        let ebb = Self::label_block(planned, builder);
+        cases.push((constexpr, ebb));
         if let Some(stmt) = stmt {
             self.compile_stmt(*stmt, builder)
         } else {
             Ok(())
         }
     }
+    /// Start the block for a `case` or `default` label, falling through from the current block.
+    ///
+    /// If the `Switch` was already emitted, this is the `planned` block it jumps to.
+    /// Otherwise, the current block is reused if it's empty.
+    fn label_block(planned: Option<Ebb>, builder: &mut FunctionBuilder) -> Ebb {
+        let current = builder.cursor().current_ebb().unwrap();
+        match planned {
+            Some(ebb) if ebb == current => ebb,
+            None if builder.is_pristine() => current,
+            _ => {
+                let new = planned.unwrap_or_else(|| builder.create_ebb());
+                Self::jump_to_block(new, builder);
+                builder.switch_to_block(new);
+                new
+            }
+        }
+    }
+    /// Whether a variable-length array declared inside the innermost switch is in scope.
+    ///
+    /// C11 6.8.4.2p2: the switch would jump past its declaration,
+    /// so a `case` or `default` label isn't allowed there.
+    fn case_enters_vla_scope(&self) -> bool {
+        match self.switches.last() {
+            Some(switch) => self.vlas[switch.depth..]
+                .iter()
+                .any(|vlas| !vlas.is_empty()),
+            None => false,
+        }
+    }
     fn default(
         &mut self,
         inner: Option<Box<Stmt>>,
         location: Location,
         builder: &mut FunctionBuilder,
     ) -> CompileResult<()> {
-        let (_, default, _) = match self.switches.last_mut() {
+        if self.case_enters_vla_scope() {
+            let err = SemanticError::GotoIntoVlaScope("default".into());
+            return Err(location.error(err));
+        }
+        let SwitchContext {
+            default, planned, ..
+        } = match self.switches.last_mut() {
             Some(x) => x,
             None => {
                 return Err(location.error(SemanticError::CaseOutsideSwitch { is_default: true }));
@@ -316,15 +630,8 @@ impl Compiler {
         if default.is_some() {
             Err(location.error(SemanticError::MultipleDefaultCase))
         } else {
-            let default_ebb = if builder.is_pristine() {
-                builder.cursor().current_ebb().unwrap()
-            } else {
-                let new = builder.create_ebb();
-                Self::jump_to_block(new, builder);
-                builder.switch_to_block(new);
-                new
-            };
-            *default = Some(default_ebb);
+            let planned = planned.as_ref().and_then(|&(_, ebb)| ebb);
+            *default = Some(Self::label_block(planned, builder));
             if let Some(stmt) = inner {
                 self.compile_stmt(*stmt, builder)
             } else {
@@ -332,41 +639,41 @@ impl Compiler {
             }
         }
     }
+    /// `break` jumps to the end of the innermost loop or switch;
+    /// `continue` jumps to the next iteration of the innermost loop, ignoring switches.
     fn loop_exit(
         &mut self,
         is_break: bool,
         location: Location,
         builder: &mut FunctionBuilder,
     ) -> CompileResult<()> {
-        if self.last_saw_loop {
-            // break from loop
-            if let Some((loop_start, loop_end)) = self.loops.last() {
-                if is_break {
-                    Self::jump_to_block(*loop_end, builder);
-                } else {
-                    Self::jump_to_block(*loop_start, builder);
-                }
-                Ok(())
-            } else {
-                semantic_err!(
-                    format!(
-                        "'{}' statement not in loop or switch statement",
-                        if is_break { "break" } else { "continue" }
-                    ),
-                    location
-                );
+        let (target, depth) = match (is_break, self.breakable.last()) {
+            (true, Some(Breakable::Switch)) => {
+                let switch = self
+                    .switches
+                    .last()
+                    .expect("should be in a switch if the innermost breakable is a switch");
+                (switch.end, switch.depth)
             }
-        } else if !is_break {
-            semantic_err!("'continue' not in loop".into(), location);
-        } else {
-            // break from switch
-            let (_, _, end_block) = self
-                .switches
-                .last()
-                .expect("should be in a switch if last_saw_loop is false");
-            builder.ins().jump(*end_block, &[]);
-            Ok(())
-        }
+            (true, Some(Breakable::Loop)) => {
+                let (_, loop_end, depth) = *self
+                    .loops
+                    .last()
+                    .expect("should be in a loop if the innermost breakable is a loop");
+                (loop_end, depth)
+            }
+            (true, None) => return Err(location.error(SemanticError::BreakOutsideLoop)),
+            (false, _) => match self.loops.last() {
+                Some(&(continue_target, _, depth)) => (continue_target, depth),
+                None => {
+                    let in_switch = !self.breakable.is_empty();
+                    return Err(location.error(SemanticError::ContinueOutsideLoop { in_switch }));
+                }
+            },
+        };
+        self.free_vlas(depth, builder);
+        Self::jump_to_block(target, builder);
+        Ok(())
     }
     #[inline]
     fn jump_to_block(ebb: Ebb, builder: &mut FunctionBuilder) {
@@ -376,11 +683,240 @@ impl Compiler {
     }
 }
 
+/// Whether `condition` was hinted with `__builtin_expect` to be nonzero (`Some(true)`)
+/// or zero (`Some(false)`).
+fn expected_value(condition: &Expr) -> Option<bool> {
+    match &condition.expr {
+        ExprType::Expect(_, expected) => Some(*expected != 0),
+        // conditions of loops are converted to bool with `expr != 0`
+        ExprType::Compare(inner, zero, ComparisonToken::NotEqual) if is_zero(zero) => {
+            expected_value(inner)
+        }
+        _ => None,
+    }
+}
+
+/// The value of a `case` label after conversion to `ctype`.
+///
+/// `value` holds the bits of an `i64` or `u64`, see `StmtType::Case`.
+fn convert_case(value: u64, ctype: &Type) -> u64 {
+    if *ctype == Type::Bool {
+        return (value != 0) as u64;
+    }
+    let bits = ctype.sizeof().expect("integer types have a size") * 8;
+    if bits >= 64 {
+        return value;
+    }
+    let mask = (1 << bits) - 1;
+    let truncated = value & mask;
+    if ctype.is_signed() && truncated >> (bits - 1) != 0 {
+        // sign extend
+        truncated | !mask
+    } else {
+        truncated
+    }
+}
+
+/// If the case labels of a switch are dense enough to use a jump table,
+/// the smallest label and the number of entries in the table.
+///
+/// `density` is the percentage of the entries which must have a label.
+fn jump_table_range(
+    labels: impl Iterator<Item = u64>,
+    signed: bool,
+    density: u8,
+) -> Option<(u64, u64)> {
+    // the labels have already been converted to the type of the condition
+    let labels: Vec<i128> = labels
+        .map(|label| {
+            if signed {
+                i128::from(label as i64)
+            } else {
+                i128::from(label)
+            }
+        })
+        .collect();
+    if labels.len() < MIN_JUMP_TABLE_CASES {
+        return None;
+    }
+    let min = *labels.iter().min().unwrap();
+    let len = *labels.iter().max().unwrap() - min + 1;
+    if len > MAX_JUMP_TABLE_LEN || labels.len() as i128 * 100 < i128::from(density) * len {
+        return None;
+    }
+    Some((min as u64, len as u64))
+}
+
+/// Add the case labels in the body of a switch to `labels`, and whether it has a default case,
+/// not including those of any nested switches.
+fn switch_labels(stmt: &Stmt, labels: &mut Vec<u64>, has_default: &mut bool) {
+    fn boxed(stmt: &Option<Box<Stmt>>) -> Vec<&Stmt> {
+        stmt.iter().map(|stmt| &**stmt).collect()
+    }
+    let children: Vec<&Stmt> = match &stmt.data {
+        StmtType::Case(label, stmt) => {
+            labels.push(*label);
+            boxed(stmt)
+        }
+        StmtType::Default(stmt) => {
+            *has_default = true;
+            boxed(stmt)
+        }
+        StmtType::Label(_, stmt) | StmtType::While(_, stmt) => boxed(stmt),
+        StmtType::For(init, _, _, body) => init.iter().chain(body).map(|stmt| &**stmt).collect(),
+        StmtType::If(_, body, otherwise) => std::iter::once(body)
+            .chain(otherwise)
+            .map(|stmt| &**stmt)
+            .collect(),
+        StmtType::Do(body, _) => vec![&**body],
+        StmtType::Compound(stmts) => stmts.iter().collect(),
+        StmtType::Switch(_, _)
+        | StmtType::Expr(_)
+        | StmtType::Goto(_)
+        | StmtType::Continue
+        | StmtType::Break
+        | StmtType::Return(_)
+        | StmtType::Decl(_) => Vec::new(),
+    };
+    for child in children {
+        switch_labels(child, labels, has_default);
+    }
+}
+
+fn is_zero(expr: &Expr) -> bool {
+    match &expr.expr {
+        ExprType::Cast(inner) => is_zero(inner),
+        _ => expr.is_zero(),
+    }
+}
+
+impl Expr {
+    /// Whether this is `__builtin_unreachable()`, possibly cast to `void`.
+    fn is_unreachable(&self) -> bool {
+        match &self.expr {
+            ExprType::Unreachable => true,
+            ExprType::Cast(inner) | ExprType::Noop(inner) => inner.is_unreachable(),
+            _ => false,
+        }
+    }
+}
+
 impl StmtType {
+    /// Whether control can reach this statement from a `goto` or `switch`,
+    /// even if the code before it always jumps elsewhere.
     fn is_jump_target(&self) -> bool {
         match self {
             StmtType::Case(_, _) | StmtType::Default(_) | StmtType::Label(_, _) => true,
+            // a label inside a block is in scope for the whole function
+            StmtType::Compound(stmts) => stmts.iter().any(|stmt| stmt.data.is_jump_target()),
             _ => false,
         }
     }
+    /// The `case` or `default` label at the very start of this statement, if there is one.
+    fn first_label(&self) -> Option<&StmtType> {
+        match self {
+            StmtType::Case(_, _) | StmtType::Default(_) => Some(self),
+            StmtType::Compound(stmts) => stmts.first().and_then(|stmt| stmt.data.first_label()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Initializer;
+    use cranelift::codegen::ir::Function;
+
+    /// Compile the first function in `program`, after `configure` has set up the compiler.
+    fn compile_func(program: &str, configure: impl FnOnce(&mut Compiler)) -> Function {
+        let (decls, _) = crate::parse(program, "<test suite>".into(), &Default::default());
+        let decl = decls.unwrap().remove(0);
+        let mut compiler = Compiler::new("<test suite>".into(), false, Endianness::Little);
+        configure(&mut compiler);
+        let Declaration { symbol, init } = decl.data;
+        let (ftype, stmts) = match (symbol.ctype, init) {
+            (Type::Function(ftype), Some(Initializer::FunctionBody(stmts))) => (ftype, stmts),
+            other => panic!("expected a function definition, got {:?}", other),
+        };
+        let (_, func) = compiler
+            .build_func(symbol.id, ftype, symbol.storage_class, stmts, decl.location)
+            .unwrap();
+        func
+    }
+    /// The number of blocks in `int f(int x) { switch (x) ... }`.
+    fn switch_blocks(switch_dummy_block: bool) -> usize {
+        let program = "int f(int x) {
+            switch (x) { case 1: return 2; case 5: return 3; default: return 4; }
+        }";
+        let func = compile_func(program, |compiler| {
+            compiler.switch_dummy_block = switch_dummy_block
+        });
+        func.layout.ebbs().count()
+    }
+    #[test]
+    fn switch_dummy_block() {
+        assert_eq!(switch_blocks(true), switch_blocks(false) + 1);
+        // the IR is checked by the verifier, so these only have to compile
+        let programs = [
+            // fallthrough, and a default before the other labels
+            "int f(int x) { switch (x) { default: x++; case 1: case 2: return x; } }",
+            // unreachable code before the first label, and no default
+            "int f(int x) { switch (x) { x = 3; case 1: break; } return x; }",
+            "int f(int x) {
+                switch (x) { case 1: switch (x + 1) { case 2: return 1; } case 3: return 4; }
+                return 0;
+            }",
+            // labels inside a loop
+            "int f(int x) {
+                int n = 0;
+                switch (x) { case 0: do { n++; case 1: n++; } while (--x > 0); }
+                return n;
+            }",
+            // a jump table
+            "int f(char c) { switch (c) { case 0: case 1: case 2: case 3: case 5: return 1; } return 0; }",
+        ];
+        for program in &programs {
+            compile_func(program, |compiler| compiler.switch_dummy_block = false);
+        }
+    }
+    #[test]
+    fn jump_table_ir() {
+        // dense, but with gaps, so `Switch` wouldn't use a jump table on its own
+        let program = "int f(int x) {
+            switch (x) {
+                case 0: return 10; case 2: return 12; case 4: return 14; case 6: return 16;
+                case 8: return 18; case 10: return 20; case 12: return 22; case 14: return 24;
+            }
+            return -1;
+        }";
+        let ir = |density| {
+            compile_func(program, |compiler| compiler.jump_table_density = density).to_string()
+        };
+        assert!(ir(DEFAULT_JUMP_TABLE_DENSITY).contains("br_table"));
+        // more than 100% of the range can never be used
+        assert!(!ir(101).contains("br_table"));
+    }
+    #[test]
+    fn jump_tables() {
+        let range = |labels: &[i64], signed| {
+            let labels = labels.iter().map(|&label| label as u64);
+            jump_table_range(labels, signed, DEFAULT_JUMP_TABLE_DENSITY)
+        };
+        // dense switches use a jump table
+        assert_eq!(range(&[0, 1, 2, 3, 4, 5, 6, 7], true), Some((0, 8)));
+        assert_eq!(range(&[7, 3, 5, 1, 0, 2, 6, 4], false), Some((0, 8)));
+        assert_eq!(range(&[10, 12, 14, 16, 18], true), Some((10, 9)));
+        assert_eq!(range(&[-2, -1, 0, 1], true), Some((-2i64 as u64, 4)));
+        // sparse or small switches don't
+        assert_eq!(range(&[0, 1, 2], true), None);
+        assert_eq!(range(&[0, 10, 20, 30, 40], true), None);
+        assert_eq!(range(&[-2, -1, 0, 1], false), None);
+        assert_eq!(range(&[0, 1, 2, 1 << 40], true), None);
+        // huge tables aren't allocated even if every table is dense enough
+        let labels = [0, 1, 2, 0x7fff_ffff].iter().map(|&label| label as u64);
+        assert_eq!(jump_table_range(labels, true, 0), None);
+        let labels = [0, 1, 2, 0x7fff].iter().map(|&label| label as u64);
+        assert_eq!(jump_table_range(labels, true, 0), Some((0, 0x8000)));
+    }
 }
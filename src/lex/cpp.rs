@@ -1,11 +1,12 @@
 use lazy_static::lazy_static;
 
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
 
 use super::{Lexer, Token};
-use crate::data::error::CppError;
-use crate::data::lex::{Keyword, Literal};
+use crate::data::error::{CppError, LexError, Phase, Suppression, Warning};
+use crate::data::lex::{ComparisonToken, Keyword, Literal, StrPrefix};
 use crate::data::prelude::*;
 use crate::get_str;
 
@@ -26,9 +27,11 @@ use crate::get_str;
 /// and a variable defined to be empty using
 /// `#if defined(var)` (not currently implemented) and `#if var`.
 ///
-/// Currently, the only implemented directives are `#if`, `#ifdef`, and `#endif`.
-/// Since `#define` is not implemented, `#ifdef var` is currently exactly the same
-/// as `#if 0`.
+/// Currently, the only implemented directives are `#if`, `#ifdef`, `#endif`,
+/// `#define`, `#undef`, `#include`, and `#pragma`, along with the `_Pragma` operator.
+/// Only object-like macros can be defined.
+/// Tokens from a macro have the location where the macro was used,
+/// with the name of the macro in `Location::expansion`.
 ///
 /// Examples:
 ///
@@ -37,7 +40,7 @@ use crate::get_str;
 ///
 /// let cpp = PreProcessor::new("<stdin>".to_string(),
 ///                        "int main(void) { char *hello = \"hi\"; }".chars(),
-///                         false);
+///                         false, Default::default());
 /// for token in cpp {
 ///     assert!(token.is_ok());
 /// }
@@ -47,14 +50,36 @@ pub struct PreProcessor<'a> {
     lexer: Lexer<'a>,
     /// Note that this is a simple HashMap and not a Scope, because
     /// the preprocessor has no concept of scope other than `undef`
-    definitions: HashMap<InternedStr, InternedStr>,
+    definitions: HashMap<InternedStr, Definition>,
     error_handler: ErrorHandler,
     /// Whether or not to display each token as it is processed
     debug: bool,
-    /// Keeps track of the _start_ of all `#if` directives
-    nested_ifs: Vec<u32>,
+    /// Keeps track of the _start_ of all `#if` directives,
+    /// along with the identifiers in each condition
+    nested_ifs: Vec<(u32, Vec<InternedStr>)>,
+    /// Whether to warn when the comment after an `#endif` names a different macro
+    warn_mismatched_endif: bool,
+    /// Tokens which have already been preprocessed but not yet returned,
+    /// used for lookahead when concatenating adjacent string literals
+    pending: VecDeque<CppResult<Token>>,
+    /// The lexers for each file which `#include`d the current file, innermost last
+    includes: Vec<Lexer<'a>>,
+    /// Every file that has been `#include`d so far, in the order they were first seen
+    dependencies: Vec<PathBuf>,
+    /// The files which contained `#pragma once`, which are skipped if they're included again
+    once: HashSet<PathBuf>,
 }
 
+/// The directories searched for `#include <file>`
+const SYSTEM_INCLUDE_DIRS: &[&str] = &["/usr/local/include", "/usr/include"];
+
+/// The maximum number of nested `#include`s, to avoid looping forever on recursive headers
+const MAX_INCLUDE_DEPTH: usize = 200;
+
+/// The maximum number of macros being replaced at once, to avoid overflowing the stack
+/// on long chains of macros which refer to each other
+const MAX_EXPANSION_DEPTH: usize = 200;
+
 type CppResult<T> = Result<Locatable<T>, CompileError>;
 
 macro_rules! ret_err {
@@ -70,21 +95,20 @@ impl Iterator for PreProcessor<'_> {
     /// The preprocessor hides all internal complexity and returns only tokens.
     type Item = CppResult<Token>;
     fn next(&mut self) -> Option<Self::Item> {
-        let next_token = match self.next_cpp_token()? {
-            Err(err) => return Some(Err(err)),
-            Ok(loc) => match loc.data {
-                CppToken::Directive(directive) => {
-                    let start = loc.location.span.start().to_usize() as u32;
-                    self.directive(directive, start)
-                }
-                CppToken::Token(mut token) => {
-                    if let Token::Id(id) = token {
-                        token = ret_err!(self.replace_id(id)?).data;
-                    }
-                    Self::replace_keywords(&mut token);
-                    Some(Ok(Locatable::new(token, loc.location)))
-                }
-            },
+        let next = self
+            .pending
+            .pop_front()
+            .or_else(|| self.next_replaced_token());
+        let next_token = match next? {
+            Ok(Locatable {
+                data: Token::Id(name),
+                location,
+            }) if name == InternedStr::get_or_intern("_Pragma") => {
+                ret_err!(self.pragma_operator(location));
+                return self.next();
+            }
+            Ok(token) => self.concat_strings(token),
+            Err(err) => Some(Err(err)),
         };
         if self.debug {
             if let Some(Ok(token)) = &next_token {
@@ -101,15 +125,27 @@ impl<'a> PreProcessor<'a> {
         file: T,
         chars: std::str::Chars<'a>,
         debug: bool,
+        std: Std,
     ) -> Self {
         Self {
-            lexer: Lexer::new(file, chars),
+            lexer: Lexer::new(file, chars, std),
             definitions: Default::default(),
             debug,
-            error_handler: Default::default(),
+            error_handler: ErrorHandler::for_phase(Phase::Preprocess),
             nested_ifs: Default::default(),
+            warn_mismatched_endif: false,
+            pending: Default::default(),
+            includes: Default::default(),
+            dependencies: Default::default(),
+            once: Default::default(),
         }
     }
+    /// Warn when the comment after an `#endif` names a macro which isn't part of
+    /// the condition of the matching `#if` or `#ifdef`, like `#endif /* FOO */`.
+    pub fn warn_mismatched_endif(mut self, warn: bool) -> Self {
+        self.warn_mismatched_endif = warn;
+        self
+    }
     /// Return the first valid token in the file,
     /// or None if there are no valid tokens.
     ///
@@ -130,15 +166,130 @@ impl<'a> PreProcessor<'a> {
     /// These warnings are consumed and will not be returned if you call
     /// `warnings()` again.
     pub fn warnings(&mut self) -> VecDeque<CompileWarning> {
-        std::mem::replace(&mut self.error_handler.warnings, Default::default())
+        let mut warnings: VecDeque<_> = self.lexer.error_handler.drain_warnings().collect();
+        warnings.extend(self.error_handler.drain_warnings());
+        warnings
+    }
+
+    /// Return every comment seen so far which silences warnings, in any file.
+    pub(crate) fn suppressions(&self) -> Vec<Suppression> {
+        self.includes
+            .iter()
+            .chain(std::iter::once(&self.lexer))
+            .flat_map(|lexer| lexer.suppressions.iter().cloned())
+            .collect()
+    }
+
+    /// Return every file that has been `#include`d so far.
+    ///
+    /// This is the same information as `cc -M`, minus the original source file.
+    pub fn dependencies(&self) -> &[PathBuf] {
+        &self.dependencies
     }
 
     /* internal functions */
+    /// Return the next token after directives and macro replacement,
+    /// but before strings are concatenated.
+    fn next_replaced_token(&mut self) -> Option<CppResult<Token>> {
+        match self.next_cpp_token()? {
+            Err(err) => Some(Err(err)),
+            Ok(loc) => match loc.data {
+                CppToken::Directive(directive) => {
+                    let start = loc.location.span.start().to_usize() as u32;
+                    self.directive(directive, start)
+                }
+                CppToken::Token(Token::Id(name)) if self.definitions.contains_key(&name) => {
+                    let location = Location {
+                        expansion: Some(name),
+                        ..loc.location
+                    };
+                    let replacement = ret_err!(self.expand(name, location, &mut Vec::new()));
+                    let mut replacement = replacement.into_iter();
+                    match replacement.next() {
+                        Some(first) => {
+                            for token in replacement.rev() {
+                                self.pending.push_front(Ok(token));
+                            }
+                            Some(Ok(first))
+                        }
+                        // the macro was defined to be empty
+                        None => self.next_replaced_token(),
+                    }
+                }
+                CppToken::Token(mut token) => {
+                    Self::replace_keywords(&mut token);
+                    Some(Ok(Locatable::new(token, loc.location)))
+                }
+            },
+        }
+    }
+    /// Concatenate adjacent string literals into a single literal.
+    ///
+    /// See section 5.1.1.2 phase 6 of the C11 standard.
+    /// This happens after macro replacement since a macro can expand to a string.
+    /// If the strings have incompatible prefixes, the concatenated string is returned
+    /// immediately after the error.
+    fn concat_strings(&mut self, mut token: Locatable<Token>) -> Option<CppResult<Token>> {
+        let (first, mut prefix) = match token.data {
+            Token::Literal(Literal::Str(first, prefix)) => (first, prefix),
+            _ => return Some(Ok(token)),
+        };
+        let mut literal = None;
+        let mut err = None;
+        loop {
+            let next = self
+                .pending
+                .pop_front()
+                .or_else(|| self.next_replaced_token());
+            let (string, next_prefix, location) = match next {
+                Some(Ok(Locatable {
+                    data: Token::Literal(Literal::Str(string, next_prefix)),
+                    location,
+                })) => (string, next_prefix, location),
+                other => {
+                    if let Some(other) = other {
+                        self.pending.push_front(other);
+                    }
+                    break;
+                }
+            };
+            let literal = literal.get_or_insert_with(|| first.resolve_and_clone());
+            // remove the null terminator from the previous string
+            literal.pop();
+            literal.push_str(get_str!(string));
+            match prefix.concat(next_prefix) {
+                Some(combined) => prefix = combined,
+                None => {
+                    if err.is_none() {
+                        let mismatch = LexError::IncompatibleStringPrefixes(prefix, next_prefix);
+                        err = Some(location.error(mismatch));
+                    }
+                }
+            }
+            token.location.span = (token.location.span.start().to_usize() as u32
+                ..location.span.end().to_usize() as u32)
+                .into();
+        }
+        if let Some(literal) = literal {
+            token.data = Literal::Str(InternedStr::get_or_intern(literal), prefix).into();
+        }
+        Some(match err {
+            Some(err) => {
+                self.pending.push_front(Ok(token));
+                Err(err)
+            }
+            None => Ok(token),
+        })
+    }
     fn tokens_until_newline(&mut self) -> impl Iterator<Item = CompileResult<Locatable<Token>>> {
         let mut tokens = Vec::new();
         let line = self.lexer.line;
         loop {
-            self.lexer.consume_whitespace();
+            // a `//` comment ends the line, but a `/* */` comment doesn't
+            if let Err(err) = self.lexer.consume_whitespace_and_comments() {
+                tokens.push(Err(err));
+                break;
+            }
             if self.lexer.line != line {
                 break;
             }
@@ -159,33 +310,55 @@ impl<'a> PreProcessor<'a> {
         }
     }
     fn next_cpp_token(&mut self) -> Option<CppResult<CppToken>> {
-        let next_token = self.lexer.next();
-        let is_hash = match next_token {
-            Some(Ok(Locatable {
-                data: Token::Hash, ..
-            })) => true,
-            _ => false,
-        };
-        if is_hash && !self.lexer.seen_line_token {
+        let mut next_token = self.lexer.next();
+        // at the end of an included file, go back to the file that included it
+        while next_token.is_none() {
+            let outer = self.includes.pop()?;
+            let mut finished = std::mem::replace(&mut self.lexer, outer);
+            for warning in finished.error_handler.drain_warnings() {
+                self.error_handler.warn(warning.data, warning.location);
+            }
+            self.lexer.suppressions.extend(finished.suppressions);
+            next_token = self.lexer.next();
+        }
+        loop {
+            let is_hash = match next_token {
+                Some(Ok(Locatable {
+                    data: Token::Hash, ..
+                })) => true,
+                _ => false,
+            };
+            if !is_hash || self.lexer.seen_line_token {
+                return next_token.map(|maybe_err| maybe_err.map(Locatable::from));
+            }
             let line = self.lexer.line;
-            Some(match self.lexer.next()? {
+            let next = match self.lexer.next() {
+                Some(next) => next,
+                // `#` at the end of the file is a null directive,
+                // there may still be tokens left in the file that included this one
+                None => return self.next_cpp_token(),
+            };
+            return Some(match next {
                 Ok(Locatable {
                     data: Token::Id(id),
                     location,
-                }) => {
+                }) if self.lexer.line == line => {
                     if let Ok(directive) = DirectiveKind::try_from(get_str!(id)) {
                         Ok(Locatable::new(CppToken::Directive(directive), location))
                     } else {
-                        Err(Locatable::new(CppError::InvalidDirective.into(), location))
+                        Err(location.error(CppError::InvalidDirective))
                     }
                 }
-                Ok(other) if self.lexer.line == line => {
-                    Err(other.map(|tok| CppError::UnexpectedToken("directive", tok).into()))
+                Ok(other) if self.lexer.line == line => Err(other
+                    .map(|tok| CppError::UnexpectedToken("directive", tok))
+                    .into()),
+                // `#` on a line by itself is a null directive, which does nothing.
+                // The next line may start with another directive, so check it again.
+                other => {
+                    next_token = Some(other);
+                    continue;
                 }
-                other => other.map(Locatable::from),
-            })
-        } else {
-            next_token.map(|maybe_err| maybe_err.map(Locatable::from))
+            });
         }
     }
     fn expect_id(&mut self) -> CppResult<InternedStr> {
@@ -199,53 +372,128 @@ impl<'a> PreProcessor<'a> {
                     location,
                 })) => Ok(Locatable::new(name, location)),
                 Some(Err(err)) => Err(err),
-                Some(Ok(other)) => {
-                    Err(other.map(|tok| CppError::UnexpectedToken("identifier", tok).into()))
-                }
-                None => Err(CompileError {
-                    data: CppError::EndOfFile("identifier").into(),
-                    location,
-                }),
+                Some(Ok(other)) => Err(other
+                    .map(|tok| CppError::UnexpectedToken("identifier", tok))
+                    .into()),
+                None => Err(location.error(CppError::EndOfFile("identifier"))),
             }
         }
         let location = self.lexer.span(self.lexer.location.offset);
-        let name = err_handler(self.lexer.next(), location)?;
-        let actual = self.replace_id(name.data);
-        err_handler(actual, name.location)
+        err_handler(self.lexer.next(), location)
     }
     fn directive(&mut self, kind: DirectiveKind, start: u32) -> Option<CppResult<Token>> {
         use DirectiveKind::*;
         match kind {
             If => {
-                let condition = ret_err!(self.boolean_expr());
-                self.if_directive(condition, start)
+                let mut names = Vec::new();
+                let condition = ret_err!(self.boolean_expr(&mut names));
+                self.if_directive(condition, names, start)
             }
             IfDef => {
                 let name = ret_err!(self.expect_id());
-                self.if_directive(self.definitions.contains_key(&name.data), start)
+                let condition = self.definitions.contains_key(&name.data);
+                self.if_directive(condition, vec![name.data], start)
             }
-            EndIf => {
-                if self.nested_ifs.pop().is_none() {
-                    Some(Err(CompileError::new(
-                        CppError::UnexpectedEndIf.into(),
-                        self.lexer.span(start),
-                    )))
-                } else {
+            EndIf => match self.nested_ifs.pop() {
+                None => Some(Err(CompileError::new(
+                    CppError::UnexpectedEndIf.into(),
+                    self.lexer.span(start),
+                ))),
+                Some((_, names)) => {
+                    self.check_endif_comment(&names, start);
                     self.next()
                 }
+            },
+            Include => {
+                ret_err!(self.include(start));
+                self.next()
+            }
+            Pragma => {
+                ret_err!(self.pragma(start));
+                self.next()
             }
-            _ => unimplemented!("preprocessing directives besides if/ifdef"),
+            Define => {
+                ret_err!(self.define(start));
+                self.next()
+            }
+            Undef => {
+                let name = ret_err!(self.expect_id());
+                self.definitions.remove(&name.data);
+                self.next()
+            }
+            _ => unimplemented!(
+                "preprocessing directives besides if/ifdef/define/undef/include/pragma"
+            ),
         }
     }
-    fn replace_id(&mut self, name: InternedStr) -> Option<CppResult<Token>> {
-        let start = self.lexer.location.offset;
-        // TODO: actually implement #define
-        Some(Ok(Locatable::new(Token::Id(name), self.lexer.span(start))))
+    /// #define
+    ///
+    /// Only object-like macros are supported: `#define NAME replacement tokens`.
+    fn define(&mut self, start: u32) -> Result<(), CompileError> {
+        let name = self.expect_id()?;
+        // `#define f(a)` is a function-like macro, but `#define f (a)` is not
+        if self.lexer.peek() == Some('(') {
+            self.tokens_until_newline().for_each(drop);
+            return Err(self.lexer.span(start).error(CppError::Generic(
+                "function-like macros are not yet supported".into(),
+            )));
+        }
+        let tokens = self.tokens_until_newline().collect::<Result<Vec<_>, _>>()?;
+        let spelling = Spelling::new(&tokens, &self.lexer);
+        if let Some(existing) = self.definitions.get(&name.data) {
+            if existing.spelling != spelling {
+                self.error_handler
+                    .warn(Warning::MacroRedefinition(name.data), name.location);
+            }
+        }
+        let replacement = tokens.into_iter().map(|token| token.data).collect();
+        self.definitions.insert(
+            name.data,
+            Definition {
+                replacement,
+                spelling,
+            },
+        );
+        Ok(())
+    }
+    /// Replace the macro `name` with its definition, replacing any macros in the definition.
+    ///
+    /// Every token is given `location`, the place where the outermost macro was used.
+    /// Macros in `active` are currently being replaced and are not replaced again,
+    /// so a macro which refers to itself stops after one replacement.
+    /// See section 6.10.3.4 of the C11 standard.
+    ///
+    /// Returns an error if more than `MAX_EXPANSION_DEPTH` macros are being replaced at once.
+    fn expand(
+        &self,
+        name: InternedStr,
+        location: Location,
+        active: &mut Vec<InternedStr>,
+    ) -> Result<Vec<Locatable<Token>>, CompileError> {
+        if active.len() >= MAX_EXPANSION_DEPTH {
+            return Err(location.error(CppError::ExpansionDepth));
+        }
+        active.push(name);
+        let mut tokens = Vec::new();
+        for token in &self.definitions[&name].replacement {
+            match token {
+                Token::Id(id) if self.definitions.contains_key(id) && !active.contains(id) => {
+                    tokens.extend(self.expand(*id, location, active)?);
+                }
+                _ => {
+                    let mut token = token.clone();
+                    Self::replace_keywords(&mut token);
+                    tokens.push(location.with(token));
+                }
+            }
+        }
+        active.pop();
+        Ok(tokens)
     }
     // convienience function around cpp_expr
-    fn boolean_expr(&mut self) -> Result<bool, CompileError> {
+    fn boolean_expr(&mut self, names: &mut Vec<InternedStr>) -> Result<bool, CompileError> {
         // TODO: is this unwrap safe? there should only be scalar types in a cpp directive...
-        match self.cpp_expr()?.truthy().unwrap().constexpr()?.data {
+        match self.cpp_expr(names)?.truthy().unwrap().constexpr()?.data {
             (Literal::Int(i), Type::Bool) => Ok(i != 0),
             _ => unreachable!("bug in const_fold or parser: cpp cond should be boolean"),
         }
@@ -254,13 +502,17 @@ impl<'a> PreProcessor<'a> {
     ///
     /// Note that identifiers are replaced with a constant 0,
     /// as per [6.10.1](http://port70.net/~nsz/c/c11/n1570.html#6.10.1p4).
-    fn cpp_expr(&mut self) -> Result<Expr, CompileError> {
+    /// The identifiers that were replaced are added to `names`.
+    fn cpp_expr(&mut self, names: &mut Vec<InternedStr>) -> Result<Expr, CompileError> {
         let start = self.lexer.location.offset;
         let mut line_tokens = self.tokens_until_newline().map(|result| match result {
             Ok(Locatable {
-                data: Token::Id(_),
+                data: Token::Id(name),
                 location,
-            }) => Ok(location.with(Token::Literal(Literal::Int(0)))),
+            }) => {
+                names.push(name);
+                Ok(location.with(Token::Literal(Literal::Int(0))))
+            }
             _ => result,
         });
         // NOTE: This only returns the first error because anything else requires a refactor
@@ -270,21 +522,328 @@ impl<'a> PreProcessor<'a> {
                 self.lexer.span(start),
             ))
         })?;
-        let mut parser = crate::Parser::new(first, line_tokens, self.debug);
+        let implicit_functions = ImplicitFunctions::default_for(self.lexer.std);
+        let mut parser = crate::Parser::new(
+            first,
+            line_tokens,
+            self.debug,
+            self.lexer.std,
+            implicit_functions,
+        );
         // TODO: catch expressions that aren't allowed
         // (see https://github.com/jyn514/rcc/issues/5#issuecomment-575339427)
         // TODO: can semantic errors happen here? should we check?
         parser.expr().map_err(CompileError::from)
     }
+    /// #include
+    ///
+    /// `#include "file"` searches the directory of the current file first,
+    /// then the system directories; `#include <file>` only searches the system directories.
+    /// Any other `#include` is macro-replaced first, see `computed_header_name`.
+    /// The included file is lexed until it runs out of tokens,
+    /// then lexing continues after the `#include` in the original file.
+    fn include(&mut self, start: u32) -> Result<(), CompileError> {
+        let line = self.lexer.line;
+        self.lexer.consume_whitespace();
+        let (name, local) = match self.lexer.peek() {
+            Some('"') | Some('<') if self.lexer.line == line => self.header_name(start)?,
+            Some(_) if self.lexer.line == line => self.computed_header_name(start)?,
+            _ => {
+                return Err(self.lexer.span(start).error(CppError::Generic(
+                    "expected \"FILENAME\" or <FILENAME> after #include".into(),
+                )))
+            }
+        };
+        let location = self.lexer.span(start);
+        if self.includes.len() >= MAX_INCLUDE_DEPTH {
+            return Err(location.error(CppError::IncludeDepth));
+        }
+
+        let current_dir = Path::new(get_str!(self.lexer.location.filename))
+            .parent()
+            .map(Path::to_path_buf);
+        let local_dir = if local { current_dir } else { None };
+        let path = local_dir
+            .into_iter()
+            .chain(SYSTEM_INCLUDE_DIRS.iter().map(PathBuf::from))
+            .map(|dir| dir.join(&name))
+            .find(|path| path.is_file())
+            .ok_or_else(|| location.error(CppError::HeaderNotFound(name)))?;
+        if self.once.contains(&canonical(&path)) {
+            return Ok(());
+        }
+        let source = std::fs::read_to_string(&path).map_err(|err| {
+            location.error(CppError::Generic(format!(
+                "failed to read {}: {}",
+                path.display(),
+                err
+            )))
+        })?;
+        // the lexer owns the header, so it's freed when the include is finished
+        let lexer = Lexer::new(path.to_string_lossy(), source, self.lexer.std);
+        let outer = std::mem::replace(&mut self.lexer, lexer);
+        self.includes.push(outer);
+        if !self.dependencies.contains(&path) {
+            self.dependencies.push(path);
+        }
+        Ok(())
+    }
+    /// The `"file"` or `<file>` after `#include`, and whether it was in quotes.
+    ///
+    /// Header names aren't tokens, so this reads characters directly from the lexer.
+    fn header_name(&mut self, start: u32) -> Result<(String, bool), CompileError> {
+        let local = self.lexer.next_char() == Some('"');
+        let terminator = if local { '"' } else { '>' };
+        let mut name = String::new();
+        loop {
+            match self.lexer.next_char() {
+                Some(c) if c == terminator => break,
+                Some('\n') | None => {
+                    return Err(self.lexer.span(start).error(CppError::Generic(format!(
+                        "missing terminating {} character in #include",
+                        terminator
+                    ))))
+                }
+                Some(c) => name.push(c),
+            }
+        }
+        if let Some(extra) = self.tokens_until_newline().next() {
+            let extra = extra?;
+            return Err(extra
+                .map(|tok| CppError::UnexpectedToken("newline", tok))
+                .into());
+        }
+        Ok((name, local))
+    }
+    /// `#include MACRO`, where the rest of the line expands to a header name.
+    ///
+    /// The replacement has to be a single string literal or a sequence of tokens
+    /// between `<` and `>`, which are joined without whitespace.
+    /// See section 6.10.2p4 of the C11 standard.
+    fn computed_header_name(&mut self, start: u32) -> Result<(String, bool), CompileError> {
+        let line = self.tokens_until_newline().collect::<Result<Vec<_>, _>>()?;
+        let mut tokens = Vec::new();
+        for token in line {
+            match token.data {
+                Token::Id(name) if self.definitions.contains_key(&name) => {
+                    let location = Location {
+                        expansion: Some(name),
+                        ..token.location
+                    };
+                    tokens.extend(self.expand(name, location, &mut Vec::new())?);
+                }
+                _ => tokens.push(token),
+            }
+        }
+        let tokens: Vec<_> = tokens.into_iter().map(|token| token.data).collect();
+        if let [Token::Literal(Literal::Str(name, StrPrefix::Plain))] = tokens.as_slice() {
+            let name = get_str!(name).trim_end_matches('\0').to_string();
+            return Ok((name, true));
+        }
+        let (less, greater) = (
+            Token::Comparison(ComparisonToken::Less),
+            Token::Comparison(ComparisonToken::Greater),
+        );
+        if tokens.len() > 2 && tokens[0] == less && tokens[tokens.len() - 1] == greater {
+            let name = tokens[1..tokens.len() - 1]
+                .iter()
+                .map(Token::to_string)
+                .collect();
+            return Ok((name, false));
+        }
+        let replacement = tokens
+            .iter()
+            .map(Token::to_string)
+            .collect::<Vec<_>>()
+            .join(" ");
+        Err(self
+            .lexer
+            .span(start)
+            .error(CppError::InvalidHeaderName(replacement)))
+    }
+    /// #pragma
+    ///
+    /// Only `#pragma once`, which stops the current file from being included again,
+    /// and `#pragma message("...")`, which emits the message as a warning, are currently recognized.
+    /// All other pragmas are ignored with a warning.
+    fn pragma(&mut self, start: u32) -> Result<(), CompileError> {
+        let tokens = self
+            .tokens_until_newline()
+            .map(|token| token.map(|token| token.data))
+            .collect::<Result<Vec<_>, _>>()?;
+        let location = self.lexer.span(start);
+        self.run_pragma(&tokens, location)
+    }
+    /// _Pragma("...")
+    ///
+    /// The string literal is destringized and processed
+    /// exactly like the tokens of a `#pragma` directive.
+    /// Since this is an operator and not a directive, it can appear in a macro.
+    /// See section 6.10.9 of the C11 standard.
+    fn pragma_operator(&mut self, location: Location) -> Result<(), CompileError> {
+        match self.pragma_operand("'('", location)? {
+            Locatable {
+                data: Token::LeftParen,
+                ..
+            } => {}
+            other => {
+                return Err(other
+                    .map(|tok| CppError::UnexpectedToken("'('", tok))
+                    .into())
+            }
+        }
+        let string = match self.pragma_operand("string literal", location)? {
+            Locatable {
+                data: Token::Literal(Literal::Str(string, _)),
+                ..
+            } => string,
+            other => {
+                return Err(other
+                    .map(|tok| CppError::UnexpectedToken("string literal", tok))
+                    .into())
+            }
+        };
+        match self.pragma_operand("')'", location)? {
+            Locatable {
+                data: Token::RightParen,
+                ..
+            } => {}
+            other => {
+                return Err(other
+                    .map(|tok| CppError::UnexpectedToken("')'", tok))
+                    .into())
+            }
+        }
+        // escape sequences were already replaced by the lexer,
+        // so all that's left is the null terminator
+        let source = get_str!(string).trim_end_matches('\0').to_string();
+        let filename = location.filename.resolve_and_clone();
+        let tokens = Lexer::new(filename, source, self.lexer.std)
+            .map(|token| token.map(|token| token.data))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.run_pragma(&tokens, location)
+    }
+    /// Return the next token after `_Pragma`, without concatenating strings.
+    fn pragma_operand(&mut self, expected: &'static str, location: Location) -> CppResult<Token> {
+        self.pending
+            .pop_front()
+            .or_else(|| self.next_replaced_token())
+            .unwrap_or_else(|| Err(location.error(CppError::EndOfFile(expected))))
+    }
+    fn run_pragma(&mut self, tokens: &[Token], location: Location) -> Result<(), CompileError> {
+        let (name, args) = match tokens.split_first() {
+            Some((Token::Id(name), args)) => (*name, args),
+            // `#pragma` on its own does nothing
+            None => return Ok(()),
+            Some(_) => {
+                self.error_handler.warn(Warning::UnknownPragma, location);
+                return Ok(());
+            }
+        };
+        if name == InternedStr::get_or_intern("once") && args.is_empty() {
+            let current = Path::new(get_str!(self.lexer.location.filename));
+            self.once.insert(canonical(current));
+            return Ok(());
+        }
+        if name != InternedStr::get_or_intern("message") {
+            self.error_handler.warn(Warning::UnknownPragma, location);
+            return Ok(());
+        }
+        // both `#pragma message("...")` and `#pragma message "..."` are allowed
+        let message = match args {
+            [Token::LeftParen, Token::Literal(Literal::Str(message, _)), Token::RightParen]
+            | [Token::Literal(Literal::Str(message, _))] => {
+                get_str!(message).trim_end_matches('\0').to_string()
+            }
+            _ => {
+                return Err(location.error(CppError::Generic(
+                    "expected string literal after #pragma message".into(),
+                )))
+            }
+        };
+        self.error_handler
+            .warn(Warning::PragmaMessage(message), location);
+        Ok(())
+    }
     /// #if
-    fn if_directive(&mut self, condition: bool, start: u32) -> Option<CppResult<Token>> {
+    fn if_directive(
+        &mut self,
+        condition: bool,
+        names: Vec<InternedStr>,
+        start: u32,
+    ) -> Option<CppResult<Token>> {
         if condition {
-            self.nested_ifs.push(start);
+            self.nested_ifs.push((start, names));
         } else {
-            ret_err!(self.consume_if_directive(start));
+            ret_err!(self.consume_if_directive(&names, start));
         }
         self.next()
     }
+    /// #endif
+    ///
+    /// If enabled, warn when the comment after `#endif` names a macro
+    /// which isn't in `names`, the identifiers in the condition of the matching `#if`.
+    /// Only the first identifier in the comment is checked,
+    /// so `#endif /* !defined(FOO) */` is compared against `FOO`.
+    fn check_endif_comment(&mut self, names: &[InternedStr], start: u32) {
+        if !self.warn_mismatched_endif {
+            return;
+        }
+        let comment = match self.endif_comment() {
+            Some(comment) => comment,
+            None => return,
+        };
+        let named = comment
+            .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .find(|word| {
+                !word.is_empty()
+                    && *word != "defined"
+                    && !word.starts_with(|c: char| c.is_ascii_digit())
+            });
+        if let Some(named) = named {
+            let named = InternedStr::get_or_intern(named);
+            if !names.contains(&named) {
+                let location = self.lexer.span(start);
+                self.error_handler
+                    .warn(Warning::MismatchedEndif(named), location);
+            }
+        }
+    }
+    /// Consume a comment on the same line as an `#endif` and return its contents.
+    ///
+    /// Comments are normally discarded by the lexer, so this has to look at the source directly.
+    fn endif_comment(&mut self) -> Option<String> {
+        while self.lexer.peek().map_or(false, |c| c == ' ' || c == '\t') {
+            self.lexer.next_char();
+        }
+        if self.lexer.peek() != Some('/') {
+            return None;
+        }
+        let multi_line = match self.lexer.peek_next() {
+            Some('*') => true,
+            Some('/') => false,
+            _ => return None,
+        };
+        self.lexer.next_char();
+        self.lexer.next_char();
+        let mut comment = String::new();
+        loop {
+            match self.lexer.peek() {
+                None => break,
+                Some('\n') if !multi_line => break,
+                Some('*') if multi_line && self.lexer.peek_next() == Some('/') => {
+                    self.lexer.next_char();
+                    self.lexer.next_char();
+                    break;
+                }
+                Some(c) => {
+                    comment.push(c);
+                    self.lexer.next_char();
+                }
+            }
+        }
+        Some(comment)
+    }
     /// Assuming we've just seen `#if 0`, keep consuming tokens until `#endif`
     /// This has to take into account nesting of #if directives.
     ///
@@ -299,7 +858,14 @@ impl<'a> PreProcessor<'a> {
     /// int g() { return 0; }
     /// ```
     /// should yield `int` as the next token, not `void`.
-    fn consume_if_directive(&mut self, start: u32) -> Result<(), CompileError> {
+    ///
+    /// The comment after the final `#endif` is checked against `names`,
+    /// the identifiers in the condition of the `#if`.
+    fn consume_if_directive(
+        &mut self,
+        names: &[InternedStr],
+        start: u32,
+    ) -> Result<(), CompileError> {
         fn match_directive(token: &CppResult<CppToken>, expected: DirectiveKind) -> bool {
             match token {
                 Ok(Locatable {
@@ -327,12 +893,58 @@ impl<'a> PreProcessor<'a> {
                 depth += 1;
             } else if match_directive(&token, DirectiveKind::EndIf) {
                 depth -= 1;
+                if depth == 0 {
+                    let endif_start = token?.location.span.start().to_usize() as u32;
+                    self.check_endif_comment(names, endif_start);
+                }
             }
         }
         Ok(())
     }
 }
 
+/// The path used to recognize a file which has already been included,
+/// or `path` itself if it doesn't exist, like `<stdin>`.
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// The replacement list of an object-like macro
+#[derive(Debug)]
+struct Definition {
+    replacement: Vec<Token>,
+    /// used to check whether a redefinition is identical
+    spelling: Spelling,
+}
+
+/// A replacement list with the locations removed:
+/// the spelling of each token and whether there was whitespace before it.
+///
+/// Two definitions of a macro are identical if their spellings are equal,
+/// so `#define A 1+2` and `#define A 1 + 2` are different, and so are
+/// `#define A 0x10` and `#define A 16`, even though the tokens have the same value.
+/// Whitespace before the first token is not part of the replacement list,
+/// and a comment counts as whitespace. See section 6.10.3 of the C11 standard.
+#[derive(Debug, PartialEq, Eq)]
+struct Spelling(Vec<(String, bool)>);
+
+impl Spelling {
+    /// `tokens` must have been lexed by `lexer`, which holds their source text.
+    fn new(tokens: &[Locatable<Token>], lexer: &Lexer) -> Self {
+        let mut previous_end = None;
+        let spelling = tokens
+            .iter()
+            .map(|token| {
+                let span = token.location.span;
+                let whitespace = previous_end.map_or(false, |end| end != span.start());
+                previous_end = Some(span.end());
+                (lexer.spelling(&token.location).to_string(), whitespace)
+            })
+            .collect();
+        Spelling(spelling)
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 enum DirectiveKind {
     If,
@@ -420,6 +1032,7 @@ lazy_static! {
         "restrict" => Keyword::Restrict,
         "_Atomic" => Keyword::Atomic,
         "_Thread_local" => Keyword::ThreadLocal,
+        "__thread" => Keyword::GnuThreadLocal,
 
         // function qualifiers
         "inline" => Keyword::Inline,
@@ -437,6 +1050,8 @@ lazy_static! {
         "_Alignas" => Keyword::Alignas,
         "_Generic" => Keyword::Generic,
         "_Static_assert" => Keyword::StaticAssert,
+        "__builtin_expect" => Keyword::BuiltinExpect,
+        "__builtin_unreachable" => Keyword::BuiltinUnreachable,
     };
 }
 
@@ -445,7 +1060,7 @@ mod tests {
     use super::{CppResult, Keyword, PreProcessor, KEYWORDS};
     use crate::data::prelude::*;
     fn cpp(input: &str) -> PreProcessor {
-        PreProcessor::new("<test suite>", input.chars(), false)
+        PreProcessor::new("<test suite>", input.chars(), false, Default::default())
     }
     fn assert_keyword(token: Option<CppResult<Token>>, expected: Keyword) {
         match token {
@@ -495,4 +1110,333 @@ mod tests {
         let same_line = "#ifdef a #endif\nint main() {}";
         assert!(cpp(same_line).next().unwrap().is_err());
     }
+    #[test]
+    fn null_directive() {
+        for code in &[
+            "#\nint",
+            "#   \nint",
+            "  #\t\n#\nint",
+            "# /* comment */\nint",
+            "#\n#define X int\nX",
+        ] {
+            let mut cpp = cpp(code);
+            assert_keyword(cpp.next(), Keyword::Int);
+            assert!(cpp.next().is_none(), "{}", code);
+            assert!(cpp.warnings().is_empty(), "{}", code);
+        }
+        let mut at_end = cpp("int\n#");
+        assert_keyword(at_end.next(), Keyword::Int);
+        assert!(at_end.next().is_none());
+        assert!(at_end.warnings().is_empty());
+    }
+    #[test]
+    fn macro_redefinition() {
+        use super::Spelling;
+        use crate::data::error::Warning;
+
+        let spelling = |code: &str| {
+            let mut cpp = cpp(code);
+            let tokens: Vec<_> = cpp.by_ref().map(Result::unwrap).collect();
+            Spelling::new(&tokens, &cpp.lexer)
+        };
+        assert_eq!(spelling("1 + 2"), spelling("1 + 2"));
+        assert_eq!(spelling("1 + 2"), spelling("1   +\t2"));
+        assert_eq!(spelling("1 + 2"), spelling("1 /* comment */ + 2"));
+        assert_ne!(spelling("1+2"), spelling("1 + 2"));
+        assert_ne!(spelling("1 + 2"), spelling("1 - 2"));
+        // the same value spelled differently
+        assert_ne!(spelling("0x10"), spelling("16"));
+        assert_ne!(spelling("1.0"), spelling("1.00"));
+
+        let warnings = |code: &str| -> Vec<_> {
+            let mut cpp = cpp(code);
+            assert_keyword(cpp.next(), Keyword::Int);
+            cpp.warnings().into_iter().map(|w| w.data).collect()
+        };
+        let a = InternedStr::get_or_intern("A");
+        assert_eq!(
+            warnings("#define A 1+2\n#define A 1 + 2\nint"),
+            vec![Warning::MacroRedefinition(a)]
+        );
+        assert_eq!(
+            warnings("#define A 0x10\n#define A 16\nint"),
+            vec![Warning::MacroRedefinition(a)]
+        );
+        assert!(warnings("#define A 1 + 2\n#define A  1 + 2\nint").is_empty());
+        assert!(warnings("#define A 1\n#undef A\n#define A 2\nint").is_empty());
+    }
+    #[test]
+    fn pragma() {
+        use crate::data::error::Warning;
+
+        let mut message = cpp("#pragma message(\"building module X\")\nint");
+        assert_keyword(message.next(), Keyword::Int);
+        let warnings: Vec<_> = message.warnings().into_iter().map(|w| w.data).collect();
+        assert_eq!(
+            warnings,
+            vec![Warning::PragmaMessage("building module X".into())]
+        );
+
+        let mut unknown = cpp("#pragma once\n#pragma once again\n#pragma GCC poison\nint");
+        assert_keyword(unknown.next(), Keyword::Int);
+        let warnings: Vec<_> = unknown.warnings().into_iter().map(|w| w.data).collect();
+        assert_eq!(
+            warnings,
+            vec![Warning::UnknownPragma, Warning::UnknownPragma]
+        );
+
+        assert!(cpp("#pragma message(1)").next().unwrap().is_err());
+    }
+    #[test]
+    fn mismatched_endif() {
+        use crate::data::error::Warning;
+
+        let warnings = |code: &str, warn: bool| -> Vec<_> {
+            let mut cpp = cpp(code).warn_mismatched_endif(warn);
+            assert_keyword(cpp.next(), Keyword::Int);
+            cpp.warnings().into_iter().map(|w| w.data).collect()
+        };
+        let bar = InternedStr::get_or_intern("BAR");
+        let mismatched = "#define FOO\n#ifdef FOO\n#endif /* BAR */\nint";
+        assert_eq!(
+            warnings(mismatched, true),
+            vec![Warning::MismatchedEndif(bar)]
+        );
+        assert!(warnings(mismatched, false).is_empty());
+        // also checked when the condition is false
+        let skipped = "#ifdef FOO\nint\n#endif // BAR\nint";
+        assert_eq!(warnings(skipped, true), vec![Warning::MismatchedEndif(bar)]);
+
+        for matching in &[
+            "#define FOO\n#ifdef FOO\n#endif /* FOO */\nint",
+            "#ifdef FOO\n#endif // FOO\nint",
+            "#if FOO > 1 || BAR\n#endif /* !BAR */\nint",
+            "#if 1\n#endif\nint",
+            "#ifdef FOO\n#endif /* 1 */ int",
+        ] {
+            assert!(warnings(matching, true).is_empty(), "{}", matching);
+        }
+        // nested conditionals are matched with the innermost #if
+        let nested =
+            "#define A\n#define B\n#ifdef A\n#ifdef B\n#endif /* B */\n#endif /* A */\nint";
+        assert!(warnings(nested, true).is_empty());
+    }
+    #[test]
+    fn pragma_operator() {
+        use crate::data::error::Warning;
+
+        let warnings = |code: &str| -> Vec<_> {
+            let mut cpp = cpp(code);
+            assert_keyword(cpp.next(), Keyword::Int);
+            cpp.warnings().into_iter().map(|w| w.data).collect()
+        };
+        assert_eq!(
+            warnings("_Pragma(\"once\")\nint"),
+            warnings("#pragma once\nint")
+        );
+        assert!(warnings("_Pragma(\"once\") int").is_empty());
+        assert_eq!(
+            warnings("_Pragma(\"message(\\\"building module X\\\")\") int"),
+            warnings("#pragma message(\"building module X\")\nint")
+        );
+        // unlike `#pragma`, `_Pragma` can be used in a macro
+        let in_macro = "#define MESSAGE _Pragma(\"message \\\"from a macro\\\"\")\nMESSAGE int";
+        assert_eq!(
+            warnings(in_macro),
+            vec![Warning::PragmaMessage("from a macro".into())]
+        );
+
+        assert!(cpp("_Pragma(123)").next().unwrap().is_err());
+        assert!(cpp("_Pragma \"once\"").next().unwrap().is_err());
+        assert!(cpp("_Pragma(\"once\"").next().unwrap().is_err());
+    }
+    #[test]
+    fn define() {
+        let tokens = |code: &str| -> Vec<_> {
+            cpp(code)
+                .map(|token| token.unwrap().data)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            tokens(
+                "#define N 1 + 2
+N;"
+            ),
+            tokens("1 + 2;"),
+        );
+        assert_eq!(
+            tokens(
+                "#define EMPTY
+EMPTY int"
+            ),
+            tokens("int")
+        );
+        assert_eq!(
+            tokens(
+                "#define T int
+T x;"
+            ),
+            tokens("int x;")
+        );
+        // macros in the replacement are also replaced, but a macro never replaces itself
+        assert_eq!(
+            tokens(
+                "#define A B
+#define B A 1
+A"
+            ),
+            tokens("A 1")
+        );
+        assert_eq!(tokens("#define A A\nA"), tokens("A"));
+        // a long enough chain of different macros is an error, not a stack overflow
+        let chain: String = (0..1000)
+            .map(|i| format!("#define A{} A{}\n", i, i + 1))
+            .chain(std::iter::once("A0".to_string()))
+            .collect();
+        match cpp(&chain).next() {
+            Some(Err(err)) => {
+                assert_eq!(
+                    err.data,
+                    crate::data::error::CppError::ExpansionDepth.into()
+                )
+            }
+            other => panic!("expected expansion depth error, got {:?}", other),
+        }
+        assert_eq!(
+            tokens(
+                "#define X 1
+#undef X
+X"
+            ),
+            tokens("X")
+        );
+        assert_eq!(
+            tokens(
+                "#define X 1
+#ifdef X
+int
+#endif"
+            ),
+            tokens("int")
+        );
+        assert!(cpp("#define F(a) a
+")
+        .next()
+        .unwrap()
+        .is_err());
+
+        let mut replaced = cpp("#define TWO 2
+int x = TWO;");
+        let two = replaced.nth(3).unwrap().unwrap();
+        assert_eq!(two.location.span, (22..25).into());
+        assert_eq!(
+            two.location.expansion,
+            Some(InternedStr::get_or_intern("TWO"))
+        );
+    }
+    #[test]
+    fn comments_in_directives() {
+        let tokens = |code: &str| -> Vec<_> {
+            cpp(code)
+                .map(|token| token.unwrap().data)
+                .collect::<Vec<_>>()
+        };
+        // a block comment is a single space, even if it spans lines
+        assert_eq!(
+            tokens(
+                "#define N 1 /* one
+more */ + 2
+N;"
+            ),
+            tokens("1 + 2;")
+        );
+        // but a line comment ends the directive
+        assert_eq!(tokens("#define N 1 // one\nN;"), tokens("1;"));
+        assert_eq!(tokens("/* a\nb */ #define N 1\nN"), tokens("1"));
+        assert_eq!(tokens("int /* a\nb */ N"), tokens("int N"));
+        // function-like macros are an error, but the arguments shouldn't cause a crash
+        let mut function_like = cpp("#define F(a, b) a
+F(1 /* a comment */,
+  2
+#define G
+);");
+        assert!(function_like.next().unwrap().is_err());
+        function_like.for_each(drop);
+    }
+    #[test]
+    fn computed_include() {
+        use crate::data::error::CppError;
+        let include_err = |code: &str| match cpp(code).next() {
+            Some(Err(err)) => match err.data {
+                Error::PreProcessor(err) => err,
+                other => panic!("expected a preprocessor error, got {:?}", other),
+            },
+            other => panic!("expected an error, got {:?}", other),
+        };
+        let not_found = |name: &str| CppError::HeaderNotFound(name.into());
+        // the header names are computed correctly, even if the files don't exist
+        assert_eq!(
+            include_err("#define HDR <rcc-missing/header.h>\n#include HDR"),
+            not_found("rcc-missing/header.h")
+        );
+        assert_eq!(
+            include_err("#define HDR \"rcc-missing.h\"\n#include HDR"),
+            not_found("rcc-missing.h")
+        );
+        assert_eq!(
+            include_err("#define NAME rcc-missing.h\n#define HDR <NAME>\n#include HDR"),
+            not_found("rcc-missing.h")
+        );
+        assert_eq!(
+            include_err("#define NAME rcc-missing\n#include <NAME.h>"),
+            not_found("NAME.h")
+        );
+        // anything else is an error
+        assert_eq!(
+            include_err("#define HDR 1 + 2\n#include HDR"),
+            CppError::InvalidHeaderName("1 + 2".into())
+        );
+        assert_eq!(
+            include_err("#include HDR"),
+            CppError::InvalidHeaderName("HDR".into())
+        );
+        assert_eq!(
+            include_err("#define HDR <>\n#include HDR"),
+            CppError::InvalidHeaderName("< >".into())
+        );
+        match include_err("#define EMPTY\n#include EMPTY") {
+            CppError::InvalidHeaderName(_) => {}
+            other => panic!("expected an invalid header name, got {:?}", other),
+        }
+    }
+    #[test]
+    fn concat_strings() {
+        use crate::data::{error::LexError, lex::StrPrefix};
+
+        fn assert_str(token: Option<CppResult<Token>>, expected: &str, prefix: StrPrefix) {
+            let expected = InternedStr::get_or_intern(format!("{}\0", expected));
+            assert_eq!(
+                token.unwrap().unwrap().data,
+                Literal::Str(expected, prefix).into()
+            );
+        }
+
+        let mut tokens = cpp("\"hello, \"\n  \"world\" \"!\" int");
+        assert_str(tokens.next(), "hello, world!", StrPrefix::Plain);
+        assert_keyword(tokens.next(), Keyword::Int);
+        assert!(tokens.next().is_none());
+
+        assert_str(cpp("\"a\" L\"b\"").next(), "ab", StrPrefix::Wide);
+        assert_str(cpp("u8\"a\" \"b\"").next(), "ab", StrPrefix::Utf8);
+        assert_str(cpp("U\"a\" U\"b\"").next(), "ab", StrPrefix::Utf32);
+
+        let mut mismatch = cpp("L\"a\" u\"b\";");
+        assert_eq!(
+            mismatch.next().unwrap().unwrap_err().data,
+            LexError::IncompatibleStringPrefixes(StrPrefix::Wide, StrPrefix::Utf16).into()
+        );
+        // the concatenated string is still returned so the parser can recover
+        assert_str(mismatch.next(), "ab", StrPrefix::Wide);
+        assert_eq!(mismatch.next().unwrap().unwrap().data, Token::Semicolon);
+    }
 }
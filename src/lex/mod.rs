@@ -1,7 +1,12 @@
+use std::borrow::Cow;
 use std::convert::TryFrom;
 use std::str::Chars;
 
-use super::data::{error::LexError, lex::*, prelude::*};
+use super::data::{
+    error::{LexError, Phase, Suppression, Warning},
+    lex::*,
+    prelude::*,
+};
 use super::intern::InternedStr;
 
 mod cpp;
@@ -22,7 +27,7 @@ pub use cpp::PreProcessor;
 #[derive(Debug)]
 struct Lexer<'a> {
     location: SingleLocation,
-    chars: Chars<'a>,
+    chars: Source<'a>,
     /// used for 2-character tokens
     current: Option<char>,
     /// used for 3-character tokens
@@ -32,9 +37,57 @@ struct Lexer<'a> {
     /// but `int main() { # line 5` is not)
     seen_line_token: bool,
     line: usize,
+    /// the offset of the start of the current physical line
+    line_start: u32,
+    /// the comments which silence warnings, see `Suppression`
+    suppressions: Vec<Suppression>,
+    /// suppressions whose end hasn't been seen yet,
+    /// with the number of newlines left before the end
+    open_suppressions: Vec<(usize, u8)>,
+    /// the version of C being lexed, used to warn on `//` comments before C99
+    std: Std,
     error_handler: ErrorHandler,
 }
 
+/// The characters of the file being lexed.
+///
+/// Headers are read by the preprocessor, so their lexer owns the contents,
+/// which are freed once the end of the header is reached.
+/// The whole file is kept so the preprocessor can look up the spelling of a token.
+#[derive(Debug)]
+struct Source<'a> {
+    text: Cow<'a, str>,
+    /// the byte offset of the next character
+    offset: usize,
+}
+
+impl<'a> From<Chars<'a>> for Source<'a> {
+    fn from(chars: Chars<'a>) -> Self {
+        Source {
+            text: Cow::Borrowed(chars.as_str()),
+            offset: 0,
+        }
+    }
+}
+
+impl From<String> for Source<'_> {
+    fn from(text: String) -> Self {
+        Source {
+            text: Cow::Owned(text),
+            offset: 0,
+        }
+    }
+}
+
+impl Iterator for Source<'_> {
+    type Item = char;
+    fn next(&mut self) -> Option<char> {
+        let c = self.text[self.offset..].chars().next()?;
+        self.offset += c.len_utf8();
+        Some(c)
+    }
+}
+
 // returned when lexing a string literal
 enum CharError {
     Eof,
@@ -50,18 +103,26 @@ struct SingleLocation {
 
 impl<'a> Lexer<'a> {
     /// Creates a Lexer from a filename and the contents of a file
-    fn new<T: AsRef<str> + Into<String>>(file: T, chars: Chars<'a>) -> Lexer<'a> {
+    fn new<T: AsRef<str> + Into<String>, S: Into<Source<'a>>>(
+        file: T,
+        chars: S,
+        std: Std,
+    ) -> Lexer<'a> {
         Lexer {
             location: SingleLocation {
                 offset: 0,
                 filename: InternedStr::get_or_intern(file),
             },
-            chars,
+            chars: chars.into(),
             seen_line_token: false,
             line: 0,
+            line_start: 0,
+            suppressions: Vec::new(),
+            open_suppressions: Vec::new(),
             current: None,
             lookahead: None,
-            error_handler: ErrorHandler::new(),
+            std,
+            error_handler: ErrorHandler::for_phase(Phase::Lex),
         }
     }
 
@@ -92,6 +153,10 @@ impl<'a> Lexer<'a> {
             if c == '\n' {
                 self.seen_line_token = false;
                 self.line += 1;
+                self.line_start = self.location.offset;
+                if !self.open_suppressions.is_empty() {
+                    self.close_suppressions();
+                }
             }
             c
         })
@@ -125,8 +190,14 @@ impl<'a> Lexer<'a> {
         Location {
             span: (start..self.location.offset).into(),
             filename: self.location.filename,
+            expansion: None,
         }
     }
+    /// The text of a token lexed from this file, exactly as it was written in the source.
+    fn spelling(&self, location: &Location) -> &str {
+        let span = location.span;
+        &self.chars.text[span.start().to_usize()..span.end().to_usize()]
+    }
     /// Remove all consecutive whitespace pending in the stream.
     ///
     /// Before: chars{"    hello   "}
@@ -136,33 +207,96 @@ impl<'a> Lexer<'a> {
             self.next_char();
         }
     }
+    /// Remove all consecutive whitespace and comments pending in the stream.
+    ///
+    /// Before: chars{"  /* a */ // b\n  hello"}
+    /// After:  chars{"hello"}
+    fn consume_whitespace_and_comments(&mut self) -> CompileResult<()> {
+        // loop instead of recursing to avoid stack overflow on lots of comments
+        loop {
+            self.consume_whitespace();
+            match (self.peek(), self.peek_next()) {
+                (Some('/'), Some('/')) => {
+                    self.next_char();
+                    if self.std < Std::C99 {
+                        let start = self.location.offset - 1;
+                        let location = self.span(start);
+                        self.error_handler.warn(
+                            format!("'//' comments are not allowed in {}", self.std),
+                            location,
+                        );
+                    }
+                    self.consume_line_comment();
+                }
+                (Some('/'), Some('*')) => {
+                    self.next_char();
+                    // discard '*' so /*/ doesn't look like a complete comment
+                    self.next_char();
+                    self.consume_multi_comment()?;
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
     /// Remove all characters between now and the next '\n' character.
     ///
     /// Before: chars{"blah `invalid tokens``\nhello // blah"}
     /// After:  chars{"hello // blah"}
     fn consume_line_comment(&mut self) {
-        while let Some(c) = self.next_char() {
+        let mut comment = String::new();
+        while let Some(c) = self.peek() {
             if c == '\n' {
                 break;
             }
+            comment.push(c);
+            self.next_char();
         }
+        self.check_suppression(&comment);
+        self.next_char();
     }
     /// Remove a multi-line C-style comment, i.e. until the next '*/'.
     ///
     /// Before: chars{"hello this is a lot of text */ int main(){}"}
     /// After:  chars{" int main(){}"}
+    ///
+    /// The comment counts as a single space (section 5.1.1.2 phase 3 of the C11 standard),
+    /// so newlines inside it don't end the current line for the preprocessor.
     fn consume_multi_comment(&mut self) -> CompileResult<()> {
         let start = self.location.offset - 2;
+        let (line, seen_line_token) = (self.line, self.seen_line_token);
+        let mut comment = String::new();
         while let Some(c) = self.next_char() {
             if c == '*' && self.peek() == Some('/') {
                 self.next_char();
+                self.line = line;
+                self.seen_line_token = seen_line_token;
+                self.check_suppression(&comment);
                 return Ok(());
             }
+            comment.push(c);
         }
-        Err(CompileError {
-            location: self.span(start),
-            data: LexError::UnterminatedComment.into(),
-        })
+        Err(self.span(start).error(LexError::UnterminatedComment))
+    }
+    /// If `comment` silences warnings, record a suppression
+    /// from the start of the current line to the end of the next one.
+    fn check_suppression(&mut self, comment: &str) {
+        let filename = self.location.filename;
+        if let Some(suppression) = Suppression::from_comment(comment, filename, self.line_start) {
+            self.open_suppressions.push((self.suppressions.len(), 2));
+            self.suppressions.push(suppression);
+        }
+    }
+    /// Called at each newline to end the suppressions which reach the end of their line.
+    fn close_suppressions(&mut self) {
+        // the newline itself is not part of the line
+        let end = self.location.offset - 1;
+        for (index, newlines) in &mut self.open_suppressions {
+            *newlines -= 1;
+            if *newlines == 0 {
+                self.suppressions[*index].end = end;
+            }
+        }
+        self.open_suppressions.retain(|&(_, newlines)| newlines > 0);
     }
     /// Parse a number literal, given the starting character and whether floats are allowed.
     ///
@@ -226,13 +360,22 @@ impl<'a> Lexer<'a> {
             return float.map(float_literal);
         }
         let literal = if self.match_next('u') || self.match_next('U') {
-            let unsigned = u64::try_from(digits)
-                .map_err(|_| "overflow while parsing unsigned integer literal")?;
-            Literal::UnsignedInt(unsigned)
+            Literal::UnsignedInt(digits)
         } else {
-            let long = i64::try_from(digits)
-                .map_err(|_| "overflow while parsing signed integer literal")?;
-            Literal::Int(long)
+            match i64::try_from(digits) {
+                Ok(long) => Literal::Int(long),
+                // C11 6.4.4.1p5: an octal or hexadecimal constant without a suffix
+                // has an unsigned type if it doesn't fit in a signed type.
+                Err(_) if radix != 10 => Literal::UnsignedInt(digits),
+                // A decimal constant should never be unsigned without a suffix,
+                // but GCC and Clang allow it with a warning.
+                Err(_) => {
+                    let span = self.span(span_start);
+                    self.error_handler
+                        .warn(Warning::UnsignedConstant(digits), span);
+                    Literal::UnsignedInt(digits)
+                }
+            }
         };
         // get rid of 'l' and 'll' suffixes, we don't handle them
         if self.match_next('l') {
@@ -434,7 +577,7 @@ impl<'a> Lexer<'a> {
     ///
     /// Before: chars{"\0' blah"}
     /// After:  chars{" blah"}
-    fn parse_char(&mut self) -> Result<Token, String> {
+    fn parse_char(&mut self, prefix: StrPrefix) -> Result<Token, String> {
         fn consume_until_quote(lexer: &mut Lexer) {
             loop {
                 match lexer.parse_single_char(false) {
@@ -451,8 +594,8 @@ impl<'a> Lexer<'a> {
             Err(String::from("Illegal newline while parsing char literal")),
         );
         match self.parse_single_char(false) {
-            Ok(c) if c.is_ascii() => match self.next_char() {
-                Some('\'') => Ok(Literal::Char(c as u8).into()),
+            Ok(c) if c.is_ascii() || prefix != StrPrefix::Plain => match self.next_char() {
+                Some('\'') => Self::char_literal(c, prefix),
                 Some('\n') => newline_err,
                 None => term_err,
                 Some(_) => {
@@ -469,36 +612,71 @@ impl<'a> Lexer<'a> {
             Err(CharError::Terminator) => Err(String::from("Empty character constant")),
         }
     }
+    /// Convert a single character into the literal for a character constant.
+    ///
+    /// Wide character constants have the type of their element (C11 6.4.4.4p11):
+    /// `wchar_t`, `char16_t`, or `char32_t`.
+    fn char_literal(c: char, prefix: StrPrefix) -> Result<Token, String> {
+        let literal = match prefix {
+            StrPrefix::Plain => Literal::Char(c as u8),
+            StrPrefix::Utf16 if u32::from(c) > 0xffff => {
+                return Err(format!(
+                    "character '{}' is too large for a char16_t character constant",
+                    c
+                ))
+            }
+            StrPrefix::Wide | StrPrefix::Utf16 | StrPrefix::Utf32 => {
+                Literal::WideChar(u32::from(c), prefix)
+            }
+            StrPrefix::Utf8 => unreachable!("u8 prefix is only allowed on string literals"),
+        };
+        Ok(literal.into())
+    }
+    /// Check whether `start` begins the encoding prefix of a string or character literal.
+    ///
+    /// If so, consume the rest of the prefix, stopping before the opening quote.
+    ///
+    /// Before: chars{8"hello"} (start = 'u')
+    /// After:  chars{"hello"}
+    fn literal_prefix(&mut self, start: char) -> Option<StrPrefix> {
+        let quote = self.peek() == Some('"') || self.peek() == Some('\'');
+        match start {
+            'L' if quote => Some(StrPrefix::Wide),
+            'u' if quote => Some(StrPrefix::Utf16),
+            'U' if quote => Some(StrPrefix::Utf32),
+            // `u8` is only allowed on string literals, not character constants
+            'u' if self.peek() == Some('8') && self.peek_next() == Some('"') => {
+                self.next_char();
+                Some(StrPrefix::Utf8)
+            }
+            _ => None,
+        }
+    }
     /// Parse a string literal, starting before the opening quote.
     ///
-    /// Concatenates multiple adjacent literals into one string.
     /// Adds a terminating null character, even if a null character has already been found.
+    /// Adjacent string literals are concatenated later, by the preprocessor.
     ///
     /// Before: chars{"hello" "you" "it's me" mary}
-    /// After:  chars{mary}
-    fn parse_string(&mut self) -> Result<Token, String> {
+    /// After:  chars{ "you" "it's me" mary}
+    fn parse_string(&mut self, prefix: StrPrefix) -> Result<Token, LexError> {
         let mut literal = String::new();
-        // allow multiple adjacent strings
-        while self.peek() == Some('"') {
-            self.next_char(); // start quote
-            loop {
-                match self.parse_single_char(true) {
-                    Ok(c) => literal.push(c),
-                    Err(CharError::Eof) => {
-                        return Err(String::from(
-                            "Missing terminating \" character in string literal",
-                        ))
-                    }
-                    Err(CharError::Newline) => {
-                        return Err(String::from("Illegal newline while parsing string literal"))
-                    }
-                    Err(CharError::Terminator) => break,
+        self.next_char(); // start quote
+        loop {
+            match self.parse_single_char(true) {
+                Ok(c) => literal.push(c),
+                Err(CharError::Eof) => {
+                    return Err(LexError::UnterminatedString { at_newline: false })
+                }
+                // a raw newline; `\` followed by a newline was already spliced out
+                Err(CharError::Newline) => {
+                    return Err(LexError::UnterminatedString { at_newline: true })
                 }
+                Err(CharError::Terminator) => break,
             }
-            self.consume_whitespace();
         }
         literal.push('\0');
-        Ok(Literal::Str(InternedStr::get_or_intern(literal)).into())
+        Ok(Literal::Str(InternedStr::get_or_intern(literal), prefix).into())
     }
     /// Parse an identifier or keyword, given the starting letter.
     ///
@@ -531,32 +709,16 @@ impl<'a> Iterator for Lexer<'a> {
     /// Any item may be an error, but items will always have an associated location.
     /// The file may be empty to start, in which case the iterator will return None.
     fn next(&mut self) -> Option<Self::Item> {
-        self.consume_whitespace();
+        if let Err(err) = self.consume_whitespace_and_comments() {
+            return Some(Err(err));
+        }
         let mut c = self.next_char();
         // Section 5.1.1.2 phase 2: discard backslashes before newlines
         while c == Some('\\') && self.match_next('\n') {
-            self.consume_whitespace();
-            c = self.next_char();
-        }
-        // avoid stack overflow on lots of comments
-        while c == Some('/') {
-            c = match self.peek() {
-                Some('/') => {
-                    self.consume_line_comment();
-                    self.consume_whitespace();
-                    self.next_char()
-                }
-                Some('*') => {
-                    // discard '*' so /*/ doesn't look like a complete comment
-                    self.next_char();
-                    if let Err(err) = self.consume_multi_comment() {
-                        return Some(Err(err));
-                    }
-                    self.consume_whitespace();
-                    self.next_char()
-                }
-                _ => break,
+            if let Err(err) = self.consume_whitespace_and_comments() {
+                return Some(Err(err));
             }
+            c = self.next_char();
         }
         let c = c.and_then(|c| {
             let span_start = self.location.offset - c.len_utf8() as u32;
@@ -698,7 +860,7 @@ impl<'a> Iterator for Lexer<'a> {
                         Ok(f) => Literal::Float(f).into(),
                         Err(err) => {
                             return Some(Err(Locatable {
-                                data: err,
+                                data: err.into(),
                                 location: self.span(span_start),
                             }))
                         }
@@ -719,26 +881,35 @@ impl<'a> Iterator for Lexer<'a> {
                     Ok(num) => num,
                     Err(err) => {
                         let span = self.span(span_start);
-                        return Some(Err(span.with(err)));
+                        return Some(Err(span.with(err.into())));
                     }
                 },
-                'a'..='z' | 'A'..='Z' | '_' => match self.parse_id(c) {
-                    Ok(id) => id,
-                    Err(err) => {
-                        let span = self.span(span_start);
-                        return Some(Err(span.with(err)));
+                'a'..='z' | 'A'..='Z' | '_' => {
+                    let token = match self.literal_prefix(c) {
+                        Some(prefix) if self.match_next('\'') => {
+                            self.parse_char(prefix).map_err(LexError::from)
+                        }
+                        Some(prefix) => self.parse_string(prefix),
+                        None => self.parse_id(c).map_err(LexError::from),
+                    };
+                    match token {
+                        Ok(id) => id,
+                        Err(err) => {
+                            let span = self.span(span_start);
+                            return Some(Err(span.with(err)));
+                        }
                     }
-                },
-                '\'' => match self.parse_char() {
+                }
+                '\'' => match self.parse_char(StrPrefix::Plain) {
                     Ok(id) => id,
                     Err(err) => {
                         let span = self.span(span_start);
-                        return Some(Err(span.with(err)));
+                        return Some(Err(span.with(err.into())));
                     }
                 },
                 '"' => {
                     self.current = Some('"');
-                    match self.parse_string() {
+                    match self.parse_string(StrPrefix::Plain) {
                         Ok(id) => id,
                         Err(err) => {
                             let span = self.span(span_start);
@@ -748,7 +919,7 @@ impl<'a> Iterator for Lexer<'a> {
                 }
                 x => {
                     return Some(Err(Locatable {
-                        data: format!("unknown token {:?}", x),
+                        data: format!("unknown token {:?}", x).into(),
                         location: self.span(span_start),
                     }))
                 }
@@ -760,6 +931,6 @@ impl<'a> Iterator for Lexer<'a> {
             }))
         });
         // oof
-        c.map(|result| result.map_err(|err| err.map(|err| LexError::Generic(err).into())))
+        c.map(|result| result.map_err(CompileError::from))
     }
 }
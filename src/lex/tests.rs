@@ -1,4 +1,5 @@
-use super::{CompileResult, Lexer, Literal, Locatable, Location, Token};
+use super::{CompileResult, Lexer, Literal, Locatable, Location, Std, StrPrefix, Token};
+use crate::data::error::{Error, LexError, Warning};
 use crate::intern::InternedStr;
 
 type LexType = CompileResult<Locatable<Token>>;
@@ -14,7 +15,7 @@ fn lex(input: &str) -> Option<LexType> {
     lexed.pop()
 }
 fn lex_all(input: &str) -> Vec<LexType> {
-    Lexer::new("<test suite>".to_string(), input.chars()).collect()
+    Lexer::new("<test suite>".to_string(), input.chars(), Std::default()).collect()
 }
 
 fn match_data<T>(lexed: Option<LexType>, closure: T) -> bool
@@ -39,8 +40,12 @@ fn match_char(lexed: Option<LexType>, expected: u8) -> bool {
 }
 
 fn match_str(lexed: Option<LexType>, expected: &str) -> bool {
+    match_prefixed_str(lexed, StrPrefix::Plain, expected)
+}
+
+fn match_prefixed_str(lexed: Option<LexType>, prefix: StrPrefix, expected: &str) -> bool {
     let string = InternedStr::get_or_intern(format!("{}\0", expected));
-    match_data(lexed, |c| c == Ok(&Literal::Str(string).into()))
+    match_data(lexed, |c| c == Ok(&Literal::Str(string, prefix).into()))
 }
 
 fn match_all(lexed: &[LexType], expected: &[Token]) -> bool {
@@ -91,6 +96,7 @@ fn test_plus() {
             location: Location {
                 filename: InternedStr::get_or_intern("<stdin>"),
                 span: (0..1).into(),
+                expansion: None,
             }
         }))
     )
@@ -119,6 +125,42 @@ fn test_overflow() {
     })
 }
 
+#[test]
+fn test_unsigned_constants() {
+    let lex_with_warnings = |input: &str| {
+        let mut lexer = Lexer::new("<test suite>".to_string(), input.chars(), Std::default());
+        let token = lexer.next().unwrap().unwrap().data;
+        let warnings: Vec<_> = lexer
+            .error_handler
+            .drain_warnings()
+            .map(|warning| warning.data)
+            .collect();
+        (token, warnings)
+    };
+    // doesn't fit in `int`, but still fits in `long`
+    assert_eq!(
+        lex_with_warnings("4294967296"),
+        (Literal::Int(4_294_967_296).into(), vec![])
+    );
+    // only fits in `unsigned long`
+    assert_eq!(
+        lex_with_warnings("18446744073709551615"),
+        (
+            Literal::UnsignedInt(std::u64::MAX).into(),
+            vec![Warning::UnsignedConstant(std::u64::MAX)]
+        )
+    );
+    // hex and octal constants can be unsigned without a warning
+    assert_eq!(
+        lex_with_warnings("0xffffffffffffffff"),
+        (Literal::UnsignedInt(std::u64::MAX).into(), vec![])
+    );
+    assert_eq!(
+        lex_with_warnings("18446744073709551615u"),
+        (Literal::UnsignedInt(std::u64::MAX).into(), vec![])
+    );
+}
+
 #[test]
 fn test_int_literals() {
     assert_int("10", 10);
@@ -231,6 +273,17 @@ fn test_comments() {
     assert_eq!(lex(&"/* */".repeat(10_000)), None);
 }
 #[test]
+fn test_line_comments_c89() {
+    let warnings = |input: &str, std| {
+        let mut lexer = Lexer::new("<test suite>".to_string(), input.chars(), std);
+        assert!(lexer.by_ref().all(|token| token.is_ok()));
+        lexer.error_handler.warning_count()
+    };
+    assert_eq!(warnings("// a comment\nint i;", Std::C89), 1);
+    assert_eq!(warnings("/* a comment */ int i;", Std::C89), 0);
+    assert_eq!(warnings("// a comment\nint i;", Std::C99), 0);
+}
+#[test]
 fn test_characters() {
     assert!(match_char(lex("'a'"), b'a'));
     assert!(match_char(lex("'0'"), b'0'));
@@ -253,11 +306,72 @@ fn test_strings() {
         lex("\"this is a sample string\""),
         "this is a sample string"
     ));
-    assert!(match_str(
-        lex("\"consecutive \" \"strings\""),
-        "consecutive strings"
-    ));
     assert!(match_str(lex("\"string with \\0\""), "string with \0"));
+    // adjacent strings are concatenated by the preprocessor, not the lexer
+    assert_eq!(lex_all("\"consecutive \" \"strings\"").len(), 2);
+}
+#[test]
+fn test_string_continuation() {
+    assert!(match_str(lex("\"foo\\\nbar\""), "foobar"));
+    assert!(match_prefixed_str(
+        lex("u8\"foo\\\nbar\""),
+        StrPrefix::Utf8,
+        "foobar"
+    ));
+    // a raw newline is not allowed, only a spliced one
+    for s in &["\"foo\nbar\"", "L\"foo\nbar\""] {
+        let lexed = lex_all(s);
+        assert_eq!(
+            lexed[0].as_ref().unwrap_err().data,
+            Error::Lex(LexError::UnterminatedString { at_newline: true }),
+            "{}",
+            s
+        );
+    }
+    // the hint about newlines only applies when there is one
+    for s in &["\"foo", "u8\"foo\\\n"] {
+        let lexed = lex_all(s);
+        assert_eq!(
+            lexed[0].as_ref().unwrap_err().data,
+            Error::Lex(LexError::UnterminatedString { at_newline: false }),
+            "{}",
+            s
+        );
+    }
+    let err = LexError::UnterminatedString { at_newline: false };
+    assert!(!err.to_string().contains("newline"));
+}
+#[test]
+fn test_prefixes() {
+    assert!(match_prefixed_str(lex("L\"ab\""), StrPrefix::Wide, "ab"));
+    assert!(match_prefixed_str(lex("u\"ab\""), StrPrefix::Utf16, "ab"));
+    assert!(match_prefixed_str(lex("U\"ab\""), StrPrefix::Utf32, "ab"));
+    assert!(match_prefixed_str(lex("u8\"ab\""), StrPrefix::Utf8, "ab"));
+    // u8 strings are stored as UTF-8 bytes
+    assert!(match_data(lex("u8\"\u{e9}\""), |c| match c {
+        Ok(Token::Literal(Literal::Str(s, StrPrefix::Utf8))) => {
+            s.resolve_and_clone().as_bytes() == b"\xc3\xa9\0"
+        }
+        _ => false,
+    }));
+    // identifiers that look like prefixes
+    assert!(match_all(
+        &lex_all("L u8 u8'a'"),
+        &[
+            Token::Id(InternedStr::get_or_intern("L")),
+            Token::Id(InternedStr::get_or_intern("u8")),
+            Token::Id(InternedStr::get_or_intern("u8")),
+            Literal::Char(b'a').into(),
+        ]
+    ));
+    // wide character constants
+    let wide = |c, prefix| -> Token { Literal::WideChar(c, prefix).into() };
+    assert!(match_data(lex("L'a'"), |c| c == Ok(&wide(97, StrPrefix::Wide))));
+    assert!(match_data(lex("L'\u{e9}'"), |c| c == Ok(&wide(0xe9, StrPrefix::Wide))));
+    assert!(match_data(lex("u'\u{e9}'"), |c| c == Ok(&wide(0xe9, StrPrefix::Utf16))));
+    assert!(match_data(lex("U'\u{1f600}'"), |c| c
+        == Ok(&wide(0x1f600, StrPrefix::Utf32))));
+    assert!(lex("u'\u{1f600}'").unwrap().is_err());
 }
 
 // Integration tests
@@ -271,3 +385,24 @@ fn test_for_loop() {
     .into_iter()
     .all(|x| x.is_ok()))
 }
+#[test]
+fn test_locations_share_filename() {
+    // the filename is interned once per file, not stored in each location
+    assert!(std::mem::size_of::<Location>() <= 16);
+    let program = "int x;\nint y = x + 1;\n".repeat(100);
+    let locations: Vec<_> = lex_all(&program)
+        .into_iter()
+        .map(|token| token.unwrap().location)
+        .collect();
+    assert_eq!(locations.len(), 1000);
+    let filename = InternedStr::get_or_intern("<test suite>");
+    assert!(locations.iter().all(|loc| loc.filename == filename));
+    assert_eq!(filename.resolve_and_clone(), "<test suite>");
+    // the spans still point to the right text
+    let text = |loc: &Location| &program[loc.span.start().to_usize()..loc.span.end().to_usize()];
+    let texts: Vec<_> = locations[..10].iter().map(text).collect();
+    assert_eq!(
+        texts,
+        ["int", "x", ";", "int", "y", "=", "x", "+", "1", ";"]
+    );
+}
@@ -9,7 +9,7 @@
 use std::collections::VecDeque;
 use std::fs::File;
 use std::io::{self, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 use cranelift_module::Backend;
@@ -17,8 +17,10 @@ use cranelift_object::ObjectBackend;
 
 pub type Product = <ObjectBackend as Backend>::Product;
 
+use data::error::Suppression;
 use data::prelude::CompileError;
 pub use data::prelude::*;
+pub use ir::{CallGraph, Callee, CompiledFunction, Relocation};
 pub use lex::PreProcessor;
 pub use parse::Parser;
 
@@ -57,20 +59,100 @@ impl From<VecDeque<CompileError>> for Error {
     }
 }
 
-/// Compile and return the declarations and warnings.
-pub fn compile(
+/// Options which affect how a program is compiled.
+#[derive(Clone, Debug, Default)]
+pub struct Opt {
+    /// If set, print all tokens found by the lexer in addition to compiling.
+    pub debug_lex: bool,
+
+    /// If set, print the parsed abstract syntax tree in addition to compiling
+    pub debug_ast: bool,
+
+    /// If set, print the intermediate representation of the program in addition to compiling
+    pub debug_asm: bool,
+
+    /// If set, emit DWARF line-number information for the main file.
+    pub debug_info: bool,
+
+    /// The version of the C standard to follow.
+    pub std: Std,
+
+    /// What to do when a function is called without being declared.
+    /// If unset, follow the behavior of `std`.
+    pub implicit_functions: Option<ImplicitFunctions>,
+
+    /// If set, warn about local variables which are never used.
+    pub warn_unused_variables: bool,
+
+    /// If set, reject variable-length arrays even where the standard allows them.
+    pub forbid_vlas: bool,
+
+    /// If set, warn when the comment after an `#endif` names a different macro
+    /// than the matching `#if` or `#ifdef`.
+    pub warn_mismatched_endif: bool,
+
+    /// If set, signed integer overflow traps at runtime (like `-ftrapv`)
+    /// instead of wrapping around.
+    pub trap_overflow: bool,
+
+    /// What to do when the program uses a GNU extension.
+    pub pedantic: Pedantic,
+
+    /// The percentage of the range of a `switch`'s case labels which must be used
+    /// before it is compiled to a jump table instead of a series of comparisons.
+    /// If unset, use 40%. Values above 100 never use a jump table.
+    pub jump_table_density: Option<u8>,
+
+    /// The byte order to use for initialized data.
+    /// If unset, use the byte order of the target.
+    pub endianness: Option<Endianness>,
+
+    /// Declarations which are visible before the start of the program,
+    /// as if they had been declared at the top of the file.
+    pub predeclared: Vec<Symbol>,
+
+    /// The most warnings to return. Any more are collapsed into a single
+    /// `Warning::Suppressed`. If unset, return every warning.
+    pub warning_limit: Option<usize>,
+}
+
+/// Preprocess and parse a translation unit without generating any code.
+///
+/// Returns the declarations (with their locations) and any warnings.
+///
+/// Warnings on the same line as a `// NOLINT` comment, or the line after it, are not returned;
+/// see `Warning::name` for silencing only one kind of warning.
+pub fn parse(
     buf: &str,
     filename: String,
-    debug_lex: bool,
-    debug_ast: bool,
-    debug_ir: bool,
-) -> (Result<Product, Error>, VecDeque<CompileWarning>) {
+    opt: &Opt,
+) -> (
+    Result<Vec<Locatable<Declaration>>, Error>,
+    VecDeque<CompileWarning>,
+) {
+    let (result, warnings, _) = parse_with_suppressions(buf, filename, opt);
+    (result, limit_warnings(warnings, opt))
+}
+
+/// Like `parse`, but also return the comments which silence warnings,
+/// so that warnings from code generation can be silenced too.
+fn parse_with_suppressions(
+    buf: &str,
+    filename: String,
+    opt: &Opt,
+) -> (
+    Result<Vec<Locatable<Declaration>>, Error>,
+    VecDeque<CompileWarning>,
+    Vec<Suppression>,
+) {
     let filename_ref = InternedStr::get_or_intern(&filename);
-    let mut cpp = PreProcessor::new(filename, buf.chars(), debug_lex);
+    let mut cpp = PreProcessor::new(filename, buf.chars(), opt.debug_lex, opt.std)
+        .warn_mismatched_endif(opt.warn_mismatched_endif);
     let (first, mut errs) = cpp.first_token();
     let eof = || Location {
         span: (buf.len() as u32..buf.len() as u32).into(),
         filename: filename_ref,
+        expansion: None,
     };
 
     let first = match first {
@@ -79,25 +161,160 @@ pub fn compile(
             if errs.is_empty() {
                 errs.push_back(eof().error(SemanticError::EmptyProgram));
             }
-            return (Err(Error::Source(errs)), cpp.warnings());
+            return (Err(Error::Source(errs)), cpp.warnings(), cpp.suppressions());
         }
     };
 
-    let mut parser = Parser::new(first, &mut cpp, debug_ast);
+    let implicit_functions = opt
+        .implicit_functions
+        .unwrap_or_else(|| ImplicitFunctions::default_for(opt.std));
+    let mut parser = Parser::new(first, &mut cpp, opt.debug_ast, opt.std, implicit_functions)
+        .with_symbol_table(opt.predeclared.iter().cloned())
+        .warn_unused_variables(opt.warn_unused_variables)
+        .forbid_vlas(opt.forbid_vlas)
+        .pedantic(opt.pedantic);
     let (hir, parse_errors) = parser.collect_results();
     errs.extend(parse_errors.into_iter());
-    if hir.is_empty() && errs.is_empty() {
+    // a translation unit with only typedefs or tags is allowed,
+    // one with only whitespace, comments, or semicolons is not
+    if hir.is_empty() && errs.is_empty() && !parser.seen_declaration() {
         errs.push_back(eof().error(SemanticError::EmptyProgram));
     }
 
     let mut warnings = parser.warnings();
     warnings.extend(cpp.warnings());
+    let suppressions = cpp.suppressions();
+    suppress(&mut warnings, &suppressions);
     if !errs.is_empty() {
-        return (Err(Error::Source(errs)), warnings);
+        return (Err(Error::Source(errs)), warnings, suppressions);
+    }
+    (Ok(hir), warnings, suppressions)
+}
+
+/// Collapse all but the first `opt.warning_limit` warnings of a translation unit.
+///
+/// This has to happen after the warnings of every phase have been merged,
+/// so it can't be left to the handler of each phase.
+fn limit_warnings(warnings: VecDeque<CompileWarning>, opt: &Opt) -> VecDeque<CompileWarning> {
+    let mut handler = data::error::ErrorHandler::new();
+    handler.set_warning_limit(opt.warning_limit);
+    for warning in warnings {
+        handler.warn(warning.data, warning.location);
     }
-    let (result, ir_warnings) = ir::compile(hir, debug_ir);
+    handler.drain_warnings().collect()
+}
+
+/// Remove the warnings silenced by a suppression comment.
+fn suppress(warnings: &mut VecDeque<CompileWarning>, suppressions: &[Suppression]) {
+    warnings.retain(|warning| {
+        !suppressions
+            .iter()
+            .any(|suppression| suppression.covers(warning))
+    });
+}
+
+/// Compile and return the declarations and warnings.
+pub fn compile(
+    buf: &str,
+    filename: String,
+    opt: &Opt,
+) -> (Result<Product, Error>, VecDeque<CompileWarning>) {
+    let (result, warnings) = compile_with_call_graph(buf, filename, opt);
+    (result.map(|(product, _)| product), warnings)
+}
+
+/// Compile a translation unit and return the functions called by each function it defines.
+///
+/// Calls through a function pointer are recorded as `Callee::Indirect`.
+pub fn call_graph(
+    buf: &str,
+    filename: String,
+    opt: &Opt,
+) -> (Result<CallGraph, Error>, VecDeque<CompileWarning>) {
+    let (result, warnings) = compile_with_call_graph(buf, filename, opt);
+    (result.map(|(_, graph)| graph), warnings)
+}
+
+fn compile_with_call_graph(
+    buf: &str,
+    filename: String,
+    opt: &Opt,
+) -> (
+    Result<(Product, CallGraph), Error>,
+    VecDeque<CompileWarning>,
+) {
+    let filename_ref = InternedStr::get_or_intern(&filename);
+    let (hir, mut warnings, suppressions) = parse_with_suppressions(buf, filename, opt);
+    let hir = match hir {
+        Ok(hir) => hir,
+        Err(err) => return (Err(err), warnings),
+    };
+    let debug_info = if opt.debug_info {
+        Some((filename_ref, buf))
+    } else {
+        None
+    };
+    let endianness = opt.endianness.unwrap_or_else(Endianness::target);
+    let (result, mut ir_warnings) = ir::compile(
+        hir,
+        opt.debug_asm,
+        debug_info,
+        endianness,
+        opt.trap_overflow,
+        opt.jump_table_density,
+    );
+    suppress(&mut ir_warnings, &suppressions);
     warnings.extend(ir_warnings);
-    (result.map_err(Error::from), warnings)
+    (result.map_err(Error::from), limit_warnings(warnings, opt))
+}
+
+/// Compile only the function `name` in a translation unit and return its machine code.
+///
+/// No object file is written. Calls to other functions, even ones defined in the same
+/// translation unit, and references to global variables and string literals
+/// are returned as relocations for the caller to resolve.
+pub fn function_code(
+    buf: &str,
+    filename: String,
+    opt: &Opt,
+    name: &str,
+) -> (Result<CompiledFunction, Error>, VecDeque<CompileWarning>) {
+    let (hir, mut warnings, suppressions) = parse_with_suppressions(buf, filename, opt);
+    let hir = match hir {
+        Ok(hir) => hir,
+        Err(err) => return (Err(err), warnings),
+    };
+    let endianness = opt.endianness.unwrap_or_else(Endianness::target);
+    let (result, mut ir_warnings) = ir::function_code(
+        hir,
+        InternedStr::get_or_intern(name),
+        opt.debug_asm,
+        endianness,
+        opt.trap_overflow,
+        opt.jump_table_density,
+    );
+    suppress(&mut ir_warnings, &suppressions);
+    warnings.extend(ir_warnings);
+    (result.map_err(Error::from), limit_warnings(warnings, opt))
+}
+
+/// Preprocess a translation unit and return every file it `#include`s.
+///
+/// This does not parse or compile the program, so it's useful for build systems
+/// which want to know whether a file needs to be recompiled.
+pub fn dependencies(
+    buf: &str,
+    filename: String,
+    opt: &Opt,
+) -> (Result<Vec<PathBuf>, Error>, VecDeque<CompileWarning>) {
+    let mut cpp = PreProcessor::new(filename, buf.chars(), opt.debug_lex, opt.std)
+        .warn_mismatched_endif(opt.warn_mismatched_endif);
+    let errs: VecDeque<_> = (&mut cpp).filter_map(Result::err).collect();
+    let warnings = limit_warnings(cpp.warnings(), opt);
+    if !errs.is_empty() {
+        return (Err(Error::Source(errs)), warnings);
+    }
+    (Ok(cpp.dependencies().to_vec()), warnings)
 }
 
 pub fn assemble(product: Product, output: &Path) -> Result<(), Error> {
@@ -134,7 +351,7 @@ pub fn link(obj_file: &Path, output: &Path) -> Result<(), io::Error> {
 mod tests {
     use super::*;
     fn compile(src: &str) -> Result<Product, Error> {
-        super::compile(src, "<test-suite>".to_owned(), false, false, false).0
+        super::compile(src, "<test-suite>".to_owned(), &Opt::default()).0
     }
     fn compile_err(src: &str) -> VecDeque<CompileError> {
         match compile(src).err().unwrap() {
@@ -159,4 +376,845 @@ mod tests {
         assert!(parse_err.is_empty());
         assert!(err.unwrap().data.is_syntax_err());
     }
+    #[test]
+    fn error_phases() {
+        use data::error::Phase;
+        let phase = |src| compile_err(src).pop_front().unwrap().phase;
+        assert_eq!(phase("`"), Phase::Lex);
+        assert_eq!(phase("#endif"), Phase::Preprocess);
+        assert_eq!(phase("+++"), Phase::Parse);
+        assert_eq!(phase("int i = j;"), Phase::Sema);
+        assert_eq!(phase("int main(void) { goto nowhere; }"), Phase::Codegen);
+    }
+    #[test]
+    fn empty_translation_unit() {
+        for program in &[
+            " \t\n\n  ",
+            "/* just a comment */",
+            "// another comment\n",
+            "#if 0\nint i;\n#endif\n",
+            ";;",
+        ] {
+            let mut errs = compile_err(program);
+            let err = errs.pop_front().unwrap().data;
+            assert_eq!(err, SemanticError::EmptyProgram.into(), "{}", program);
+            assert!(errs.is_empty(), "{}", program);
+        }
+        assert!(compile("int i;").is_ok());
+        assert!(compile("typedef int i;").is_ok());
+        assert!(compile("struct s { int i; };").is_ok());
+    }
+    #[test]
+    fn extern_function_call() {
+        let program = "int puts(const char *s);
+        int main(void) { return puts(\"hi\"); }";
+        let object = compile(program).unwrap().emit().unwrap();
+        assert!(object.windows(4).any(|symbol| symbol == b"puts"));
+    }
+    #[test]
+    fn debug_info() {
+        let program = "int main(void) {\n    return 0;\n}";
+        let has_line_table = |debug_info| {
+            let opt = Opt {
+                debug_info,
+                ..Opt::default()
+            };
+            let (result, _) = super::compile(program, "<test-suite>".to_owned(), &opt);
+            let object = result.unwrap().emit().unwrap();
+            let name = b".debug_line";
+            object.windows(name.len()).any(|section| section == name)
+        };
+        assert!(has_line_table(true));
+        assert!(!has_line_table(false));
+    }
+    #[test]
+    fn forbid_vlas() {
+        let program = "int main(void) { int n = 3; int a[n]; a[2] = n; return a[2]; }";
+        let compile = |forbid_vlas| {
+            let opt = Opt {
+                forbid_vlas,
+                ..Opt::default()
+            };
+            super::compile(program, "<test-suite>".to_owned(), &opt).0
+        };
+        match compile(true) {
+            Err(Error::Source(errs)) => {
+                assert_eq!(errs.len(), 1, "{:?}", errs);
+                assert_eq!(errs[0].data, SemanticError::VlaForbidden.into());
+            }
+            other => panic!("expected an error, got {:?}", other.map(|_| ())),
+        }
+        assert!(compile(false).is_ok());
+    }
+    #[test]
+    fn goto_into_vla_scope() {
+        let errs = |body: &str| -> Vec<_> {
+            let program = format!("int main(void) {{ int n = 3; {} return 0; }}", body);
+            match compile(&program) {
+                Ok(_) => Vec::new(),
+                Err(Error::Source(errs)) => errs.into_iter().map(|err| err.data).collect(),
+                Err(other) => panic!("unexpected error {:?}", other),
+            }
+        };
+        let into_scope = |target: &str| -> Vec<data::error::Error> {
+            vec![SemanticError::GotoIntoVlaScope(target.into()).into()]
+        };
+        // forward into the scope
+        assert_eq!(
+            errs("if (n) goto end; { int a[n]; end: a[0] = 1; }"),
+            into_scope("end")
+        );
+        // backward into the scope
+        assert_eq!(
+            errs("int i = 0; { int a[n]; again: i++; } if (i < 2) goto again;"),
+            into_scope("again")
+        );
+        // a switch jumps past the declaration
+        assert_eq!(
+            errs("switch (n) { int a[n]; case 3: a[0] = 1; }"),
+            into_scope("case 3")
+        );
+        assert_eq!(
+            errs("switch (n) { case 1: { int a[n]; default: a[0] = 1; } }"),
+            into_scope("default")
+        );
+        // leaving the scope, or jumping within it, is fine
+        assert_eq!(errs("{ int a[n]; goto end; } end: ;"), vec![]);
+        assert_eq!(
+            errs("{ int a[n]; int i = 0; again: a[0] = i++; if (i < 2) goto again; }"),
+            vec![]
+        );
+        assert_eq!(errs("int a[n]; switch (n) { case 3: a[0] = 1; }"), vec![]);
+        assert_eq!(
+            errs("switch (n) { case 3: { int a[n]; a[0] = 1; } default: ; }"),
+            vec![]
+        );
+    }
+    #[test]
+    fn pedantic() {
+        use crate::data::error::Warning;
+        // rcc doesn't support case ranges, so use `?:` instead
+        let program = "int main(void) { int x = 2; return x ?: 1; }";
+        let compile = |pedantic| {
+            let opt = Opt {
+                pedantic,
+                ..Opt::default()
+            };
+            super::compile(program, "<test-suite>".to_owned(), &opt)
+        };
+        let extension = "omitting the middle operand of '?:'";
+        let (result, warnings) = compile(Pedantic::Off);
+        assert!(result.is_ok());
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        let (result, warnings) = compile(Pedantic::Warn);
+        assert!(result.is_ok());
+        assert_eq!(warnings.len(), 1, "{:?}", warnings);
+        assert_eq!(warnings[0].data, Warning::Extension(extension));
+        match compile(Pedantic::Error).0 {
+            Err(Error::Source(errs)) => {
+                assert_eq!(errs.len(), 1, "{:?}", errs);
+                assert_eq!(errs[0].data, SemanticError::Extension(extension).into());
+            }
+            other => panic!("expected an error, got {:?}", other.map(|_| ())),
+        }
+        // every GNU extension is reported, not just `?:`
+        let extensions = [
+            (
+                "int x __attribute__((unused));",
+                "'__attribute__' specifiers",
+            ),
+            ("static __thread int x;", "the '__thread' keyword"),
+            (
+                "int f(int x) { return __builtin_expect(x, 1); }",
+                "calls to '__builtin_expect'",
+            ),
+            (
+                "void f(void) { __builtin_unreachable(); }",
+                "calls to '__builtin_unreachable'",
+            ),
+        ];
+        for (program, extension) in &extensions {
+            let opt = Opt {
+                pedantic: Pedantic::Warn,
+                ..Opt::default()
+            };
+            let warnings = parse_with_suppressions(program, "<test-suite>".to_owned(), &opt).1;
+            assert_eq!(
+                warnings.iter().map(|w| &w.data).collect::<Vec<_>>(),
+                vec![&Warning::Extension(extension)],
+                "{}",
+                program
+            );
+        }
+    }
+    #[test]
+    fn constant_global_initializers() {
+        // folded at compile time: 0x123456
+        let program = "int g = 0x1234 * 0x100 + 0x56;";
+        let object = compile(program).unwrap().emit().unwrap();
+        assert!(object
+            .windows(4)
+            .any(|bytes| bytes == [0x56, 0x34, 0x12, 0]));
+
+        for program in &[
+            "int x; int g = x;",
+            "int f(void); int g = f();",
+            "int x; int g = x + 1;",
+        ] {
+            let errs = compile_err(program);
+            assert_eq!(errs.len(), 1, "{}: {:?}", program, errs);
+            assert!(
+                match &errs[0].data {
+                    data::error::Error::Semantic(SemanticError::NotConstant(_)) => true,
+                    _ => false,
+                },
+                "{}: {:?}",
+                program,
+                errs
+            );
+        }
+    }
+    #[test]
+    fn global_address_relocation() {
+        let has_relocation = |program| {
+            let object = compile(program).unwrap().emit().unwrap();
+            let name = b".rela.data";
+            object.windows(name.len()).any(|section| section == name)
+        };
+        assert!(has_relocation("int g; int *p = &g;"));
+        assert!(!has_relocation("int g; int *p = 0;"));
+    }
+    #[test]
+    fn endianness() {
+        let program = "short s[2] = { 0x0102, 0x0304 };";
+        let contains = |endianness, bytes: &[u8]| {
+            let opt = Opt {
+                endianness: Some(endianness),
+                ..Opt::default()
+            };
+            let (result, _) = super::compile(program, "<test-suite>".to_owned(), &opt);
+            let object = result.unwrap().emit().unwrap();
+            object.windows(bytes.len()).any(|window| window == bytes)
+        };
+        assert!(contains(Endianness::Big, &[1, 2, 3, 4]));
+        assert!(!contains(Endianness::Big, &[2, 1, 4, 3]));
+        assert!(contains(Endianness::Little, &[2, 1, 4, 3]));
+        assert!(!contains(Endianness::Little, &[1, 2, 3, 4]));
+    }
+    #[test]
+    fn predeclared_symbols() {
+        use crate::data::{types::FunctionType, Qualifiers, StorageClass};
+
+        let symbol = |name: &str, ctype| Symbol {
+            id: InternedStr::get_or_intern(name),
+            ctype,
+            qualifiers: Qualifiers::NONE,
+            storage_class: StorageClass::Extern,
+            init: false,
+            attributes: Default::default(),
+        };
+        let host_fn = symbol(
+            "host_fn",
+            Type::Function(FunctionType {
+                return_type: Box::new(Type::Int(true)),
+                params: vec![Symbol {
+                    storage_class: StorageClass::Auto,
+                    ..symbol("x", Type::Int(true))
+                }],
+                varargs: false,
+            }),
+        );
+        let host_var = symbol("host_var", Type::Int(true));
+        let opt = Opt {
+            predeclared: vec![host_fn, host_var],
+            ..Opt::default()
+        };
+        let program = "int main(void) { return host_fn(host_var); }";
+        let (result, warnings) = super::compile(program, "<test-suite>".to_owned(), &opt);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        let object = result.unwrap().emit().unwrap();
+        assert!(object.windows(7).any(|symbol| symbol == b"host_fn"));
+        assert!(compile(program).is_err());
+    }
+    #[test]
+    fn implicit_function_declaration() {
+        use crate::data::error::Warning;
+
+        let program = "int main(void) { return puts(\"hi\"); }";
+        let opt = Opt {
+            std: Std::C89,
+            ..Opt::default()
+        };
+        let (result, warnings) = super::compile(program, "<test-suite>".to_owned(), &opt);
+        assert!(result.is_ok());
+        let expected = Warning::ImplicitFunctionDeclaration("puts".into());
+        assert!(warnings.iter().any(|warning| warning.data == expected));
+
+        let mut errs = compile_err(program);
+        let err = errs.pop_front().unwrap().data;
+        assert_eq!(err, SemanticError::UndeclaredVar("puts".into()).into());
+        assert!(errs.is_empty());
+    }
+    #[test]
+    fn implicit_function_modes() {
+        use crate::data::error::Warning;
+
+        let program = "int main(void) { return frob(); }";
+        let compile_with = |implicit_functions| {
+            let opt = Opt {
+                std: Std::C89,
+                implicit_functions: Some(implicit_functions),
+                ..Opt::default()
+            };
+            super::compile(program, "<test-suite>".to_owned(), &opt)
+        };
+        let (result, warnings) = compile_with(ImplicitFunctions::Warn);
+        assert!(result.is_ok());
+        let expected = Warning::ImplicitFunctionDeclaration("frob".into());
+        assert_eq!(
+            warnings.into_iter().map(|w| w.data).collect::<Vec<_>>(),
+            vec![expected]
+        );
+
+        let (result, warnings) = compile_with(ImplicitFunctions::Allow);
+        assert!(result.is_ok());
+        assert!(warnings.is_empty());
+
+        let (result, _) = compile_with(ImplicitFunctions::Error);
+        match result {
+            Err(Error::Source(errs)) => assert_eq!(
+                errs.into_iter().map(|err| err.data).collect::<Vec<_>>(),
+                vec![SemanticError::UndeclaredVar("frob".into()).into()]
+            ),
+            _ => panic!("implicit function declaration should be an error"),
+        }
+    }
+    #[test]
+    fn array_too_large() {
+        let program = "int main(void) { long a[0x2000000000000000]; return 0; }";
+        let errs = compile_err(program);
+        assert_eq!(errs.len(), 1);
+        assert!(errs[0].data.is_semantic_err());
+        assert!(compile("int main(void) { long a[0x1000]; return 0; }").is_ok());
+    }
+    #[test]
+    fn break_and_continue_context() {
+        let only_err = |program| {
+            let mut errs = compile_err(program);
+            assert_eq!(errs.len(), 1, "{}", program);
+            errs.pop_front().unwrap().data
+        };
+        assert_eq!(
+            only_err("int main(void) { switch (1) { case 1: continue; } }"),
+            SemanticError::ContinueOutsideLoop { in_switch: true }.into()
+        );
+        assert_eq!(
+            only_err("int main(void) { continue; }"),
+            SemanticError::ContinueOutsideLoop { in_switch: false }.into()
+        );
+        assert_eq!(
+            only_err("int main(void) { break; }"),
+            SemanticError::BreakOutsideLoop.into()
+        );
+        assert!(compile("int main(void) { switch (1) { case 1: break; } return 0; }").is_ok());
+        // `continue` skips over the switch to the enclosing loop
+        assert!(compile(
+            "int main(void) { for (int i = 0; i < 3; i++) { switch (i) { default: continue; } } }"
+        )
+        .is_ok());
+        // leaving a switch goes back to breaking out of the loop
+        assert!(compile("int main(void) { while (1) { switch (1) {} break; } }").is_ok());
+    }
+    #[test]
+    fn unreachable_labels() {
+        use crate::data::error::Warning;
+
+        let warnings = |program: &str| -> Vec<Warning> {
+            let (result, warnings) =
+                super::compile(program, "<test-suite>".to_owned(), &Opt::default());
+            assert!(result.is_ok(), "{}", program);
+            warnings.into_iter().map(|warning| warning.data).collect()
+        };
+        assert_eq!(
+            warnings("int main() { return 0; end: return 1; }"),
+            vec![Warning::UnreachableLabel("end".into())]
+        );
+        let looped = "int main() { int i = 0; start: i++; if (i < 3) goto start; return i; }";
+        assert_eq!(warnings(looped), vec![]);
+        let forward = "int main() { int i = 1; if (i) goto end; i = 2; end: return i; }";
+        assert_eq!(warnings(forward), vec![]);
+        // labels are local to each function
+        let repeated = "int f() { end: return 1; } int main() { end: return 0; }";
+        assert_eq!(warnings(repeated), vec![]);
+    }
+    #[test]
+    fn undeclared_labels() {
+        // every `goto` to a missing label is reported, in order, once the function is done
+        let program = "int main(void) {
+            if (1) goto nowhere;
+            if (0) goto later;
+            goto missing;
+            goto nowhere;
+            later: return 0;
+        }";
+        let errs = compile_err(program);
+        let undeclared: Vec<_> = errs
+            .iter()
+            .map(|err| match &err.data {
+                data::error::Error::Semantic(SemanticError::UndeclaredLabel(name)) => {
+                    let start = err.location.span.start().to_usize();
+                    (name.resolve_and_clone(), &program[start..start + 4])
+                }
+                other => panic!("expected undeclared label, got {}", other),
+            })
+            .collect();
+        assert_eq!(
+            undeclared,
+            vec![
+                ("nowhere".into(), "goto"),
+                ("missing".into(), "goto"),
+                ("nowhere".into(), "goto")
+            ]
+        );
+        let starts: Vec<_> = errs.iter().map(|err| err.location.span.start()).collect();
+        assert!(starts.windows(2).all(|pair| pair[0] < pair[1]));
+        // a forward `goto` to a label which is declared later is fine
+        assert!(compile("int main(void) { goto end; end: return 0; }").is_ok());
+    }
+    #[test]
+    fn too_many_array_initializers() {
+        let expected: data::error::Error =
+            SemanticError::InvalidArrayInitializer { size: 2, count: 3 }.into();
+        for program in &[
+            "int a[2] = {1, 2, 3};",
+            "int main(void) { int a[2] = {1, 2, 3}; return a[0]; }",
+            "char s[2] = \"abc\";",
+            "int main(void) { char s[2] = \"abc\"; return s[0]; }",
+        ] {
+            let errs = compile_err(program);
+            assert_eq!(errs.len(), 1, "{:?}", errs);
+            assert_eq!(errs[0].data, expected);
+        }
+        // missing elements are zero-initialized
+        assert!(compile("int main(void) { int a[3] = {1}; return a[2]; }").is_ok());
+        // the null terminator is dropped if there's no room for it
+        assert!(compile("int main(void) { char s[3] = \"abc\"; return s[2]; }").is_ok());
+    }
+    #[test]
+    fn non_integer_switch() {
+        for (program, ctype) in &[
+            (
+                "int main(void) { switch (3.0) { default: return 0; } }",
+                Type::Double,
+            ),
+            (
+                "int main(void) { int *p = 0; switch (p) { default: return 0; } }",
+                Type::Pointer(Box::new(Type::Int(true))),
+            ),
+        ] {
+            let errs = compile_err(program);
+            assert_eq!(errs.len(), 1, "{:?}", errs);
+            assert_eq!(
+                errs[0].data,
+                SemanticError::NonIntegerSwitch(ctype.clone()).into()
+            );
+        }
+        assert!(compile("int main(void) { switch ('a') { default: return 0; } }").is_ok());
+    }
+    #[test]
+    fn thread_local_unsupported() {
+        for program in &[
+            "_Thread_local int x; int main(void) { return x; }",
+            "int main(void) { static _Thread_local int x; return x; }",
+        ] {
+            let mut errs = compile_err(program);
+            let x = InternedStr::get_or_intern("x");
+            assert_eq!(
+                errs.pop_front().unwrap().data,
+                SemanticError::ThreadLocalUnsupported(x).into()
+            );
+        }
+    }
+    #[test]
+    fn main_signatures() {
+        use crate::data::error::Warning;
+
+        let warnings = |program: &str| -> Vec<Warning> {
+            let (result, warnings) =
+                super::compile(program, "<test-suite>".to_owned(), &Opt::default());
+            assert!(result.is_ok(), "{}", program);
+            warnings.into_iter().map(|warning| warning.data).collect()
+        };
+        assert_eq!(warnings("int main(int argc, char **argv) {}"), vec![]);
+        match warnings("int main(int argc, char **argv, char **envp) { return 0; }").as_slice() {
+            [Warning::NonstandardMain(_)] => {}
+            other => panic!("expected nonstandard main, got {:?}", other),
+        }
+        assert!(compile("void main(void) {}").is_err());
+    }
+    #[test]
+    fn case_conversion() {
+        use crate::data::error::Warning;
+
+        let warnings = |ctype: &str, label: &str| -> Vec<Warning> {
+            let program = format!(
+                "int main(void) {{ int x = 300; switch (({})x) {{ case {}: return 1; }} return 0; }}",
+                ctype, label
+            );
+            let (result, warnings) =
+                super::compile(&program, "<test-suite>".to_owned(), &Opt::default());
+            assert!(result.is_ok(), "{}", program);
+            warnings.into_iter().map(|warning| warning.data).collect()
+        };
+        assert_eq!(
+            warnings("char", "300"),
+            vec![Warning::CaseOutOfRange(300, Type::Char(true))]
+        );
+        assert_eq!(
+            warnings("unsigned char", "-1"),
+            vec![Warning::CaseOutOfRange(-1, Type::Char(false))]
+        );
+        assert_eq!(
+            warnings("unsigned", "-1"),
+            vec![Warning::CaseConversion(-1, 4_294_967_295, Type::Int(false))]
+        );
+        assert_eq!(warnings("char", "-1"), vec![]);
+        assert_eq!(warnings("char", "'a'"), vec![]);
+        assert_eq!(warnings("long", "300"), vec![]);
+    }
+    #[test]
+    fn dangling_local_address() {
+        use crate::data::error::Warning;
+
+        let warnings = |program: &str| -> Vec<Warning> {
+            let program = format!("int *g; {} int main(void) {{ return 0; }}", program);
+            let (result, warnings) =
+                super::compile(&program, "<test-suite>".to_owned(), &Opt::default());
+            assert!(result.is_ok(), "{}", program);
+            warnings.into_iter().map(|warning| warning.data).collect()
+        };
+        let x = InternedStr::get_or_intern("x");
+        let dangling = vec![Warning::DanglingLocalAddress(x)];
+        assert_eq!(warnings("int *f(void) { int x = 1; return &x; }"), dangling);
+        assert_eq!(warnings("int *f(int x) { return &x; }"), dangling);
+        assert_eq!(warnings("int *f(void) { int x[2]; return x; }"), dangling);
+        assert_eq!(
+            warnings("long f(void) { int x; return (long)&x; }"),
+            dangling
+        );
+        assert_eq!(warnings("void f(void) { int x; g = &x; }"), dangling);
+        // none of these addresses outlive the variable they point to
+        assert_eq!(warnings("int *f(void) { return g; }"), vec![]);
+        assert_eq!(warnings("int *f(int *x) { return x; }"), vec![]);
+        assert_eq!(warnings("void f(void) { int x, *p; p = &x; }"), vec![]);
+    }
+    #[test]
+    fn function_code() {
+        let code = |program: &str, name: &str| {
+            let (result, _) =
+                super::function_code(program, "<test-suite>".to_owned(), &Opt::default(), name);
+            result
+        };
+        let constant = code("int f(void) { return 42; }", "f").unwrap();
+        assert!(!constant.code.is_empty());
+        assert!(constant.relocations.is_empty());
+
+        // calls are left for the caller to resolve, even to functions defined in the program
+        let program = "int g(void); int h(void) { return 1; }
+            int f(void) { return g() + h(); }";
+        let calls = code(program, "f").unwrap();
+        let mut symbols: Vec<_> = calls
+            .relocations
+            .iter()
+            .map(|reloc| reloc.symbol.as_str())
+            .collect();
+        symbols.sort();
+        assert_eq!(symbols, vec!["g", "h"]);
+        assert!(calls
+            .relocations
+            .iter()
+            .all(|reloc| (reloc.offset as usize) < calls.code.len()));
+
+        assert!(code("int x = 1; int f(void) { return x; }", "f")
+            .unwrap()
+            .relocations
+            .iter()
+            .any(|reloc| reloc.symbol == "x"));
+        assert!(code("int f(void) { return 42; }", "g").is_err());
+    }
+    #[test]
+    fn suppression_comments() {
+        use crate::data::error::Warning;
+
+        let warnings = |body: &str| -> Vec<Warning> {
+            let program = format!("int main(void) {{\n{}\nreturn 0; }}", body);
+            let opt = Opt {
+                warn_unused_variables: true,
+                ..Opt::default()
+            };
+            let (result, warnings) = super::compile(&program, "<test-suite>".to_owned(), &opt);
+            assert!(result.is_ok(), "{}", program);
+            warnings.into_iter().map(|warning| warning.data).collect()
+        };
+        let unused = |name| vec![Warning::UnusedVariable(InternedStr::get_or_intern(name))];
+        assert_eq!(warnings("int x;"), unused("x"));
+        assert_eq!(warnings("int x; // NOLINT"), vec![]);
+        assert_eq!(warnings("/* NOLINT */\nint x;"), vec![]);
+        assert_eq!(warnings("int x; // rcc:allow(unused-variable)"), vec![]);
+        // only the named warning is silenced
+        assert_eq!(
+            warnings("int x; // rcc:allow(self-assignment)"),
+            unused("x")
+        );
+        // only the line of the comment and the one after it
+        assert_eq!(warnings("// NOLINT\nint x;\nint y;"), unused("y"));
+        // ordinary comments don't silence anything
+        assert_eq!(warnings("int x; // lint"), unused("x"));
+        // warnings from code generation are silenced too
+        assert_eq!(warnings("int x = 1; x = x; // NOLINT"), vec![]);
+    }
+    #[test]
+    fn self_assignment() {
+        use crate::data::error::Warning;
+
+        let warnings = |body: &str| -> Vec<Warning> {
+            let program = format!(
+                "struct s {{ int i; }} t; volatile int v; int main(void) {{ int x = 1, y = 2; {} return 0; }}",
+                body
+            );
+            let (result, warnings) =
+                super::compile(&program, "<test-suite>".to_owned(), &Opt::default());
+            assert!(result.is_ok(), "{}", program);
+            warnings.into_iter().map(|warning| warning.data).collect()
+        };
+        let x = InternedStr::get_or_intern("x");
+        assert_eq!(warnings("x = x;"), vec![Warning::SelfAssignment(x)]);
+        assert_eq!(
+            warnings("t = t;"),
+            vec![Warning::SelfAssignment(InternedStr::get_or_intern("t"))]
+        );
+        assert_eq!(warnings("x = y;"), vec![]);
+        assert_eq!(warnings("v = v;"), vec![]);
+        assert_eq!(warnings("x = x++;"), vec![]);
+        assert_eq!(warnings("x += x;"), vec![]);
+    }
+    #[test]
+    fn statements_without_effect() {
+        use crate::data::error::Warning;
+
+        let warnings = |body: &str| -> Vec<Warning> {
+            let program = format!(
+                "int f(void); volatile int v; int main(void) {{ int a = 1, b = 2; {} return 0; }}",
+                body
+            );
+            let (result, warnings) =
+                super::compile(&program, "<test-suite>".to_owned(), &Opt::default());
+            assert!(result.is_ok(), "{}", program);
+            warnings.into_iter().map(|warning| warning.data).collect()
+        };
+        assert_eq!(warnings("a + b;"), vec![Warning::NoEffect]);
+        assert_eq!(warnings("a == b;"), vec![Warning::NoEffect]);
+        assert_eq!(warnings("f();"), vec![]);
+        assert_eq!(warnings("a = b;"), vec![]);
+        assert_eq!(warnings("a++;"), vec![]);
+        assert_eq!(warnings("v;"), vec![]);
+        assert_eq!(warnings("(void)a;"), vec![]);
+        assert_eq!(warnings("(void)(a + b);"), vec![]);
+        assert_eq!(warnings("(void)(a + f());"), vec![]);
+        assert_eq!(warnings("a && f();"), vec![]);
+    }
+    #[test]
+    fn warning_limit() {
+        use crate::data::error::Warning;
+        // one warning each from the preprocessor, the parser, and codegen
+        let program = "#pragma apples
+        int main(void) { char c = 1; c + 1; switch (c) { case 300: break; } return 0; }";
+        let warnings = |warning_limit| -> Vec<Warning> {
+            let opt = Opt {
+                warning_limit,
+                ..Opt::default()
+            };
+            let (result, warnings) = super::compile(program, "<test-suite>".to_owned(), &opt);
+            assert!(result.is_ok());
+            warnings.into_iter().map(|warning| warning.data).collect()
+        };
+        assert_eq!(warnings(None).len(), 3);
+        assert_eq!(warnings(Some(3)).len(), 3);
+        let limited = warnings(Some(2));
+        assert_eq!(limited.len(), 3);
+        assert_eq!(limited[2], Warning::Suppressed(1));
+        assert_eq!(warnings(Some(0)), vec![Warning::Suppressed(3)]);
+    }
+    #[test]
+    fn macro_errors() {
+        let program = "#define BAD 1 % 2.0\nint main(void) { return BAD; }";
+        let errs = compile_err(program);
+        assert_eq!(errs.len(), 1, "{:?}", errs);
+        let location = errs[0].location();
+        let use_site = program.rfind("BAD").unwrap() as u32;
+        assert_eq!(location.span, (use_site..use_site + 3).into());
+        assert_eq!(location.expansion, Some(InternedStr::get_or_intern("BAD")));
+    }
+    #[test]
+    fn parse_only() {
+        let program = "int f(void) { return 1; }
+        int main(void) { goto missing; }";
+        let (hir, warnings) = super::parse(program, "<test-suite>".to_owned(), &Opt::default());
+        assert!(warnings.is_empty());
+        let hir = hir.unwrap();
+        let names: Vec<_> = hir
+            .iter()
+            .map(|decl| decl.data.symbol.id.resolve_and_clone())
+            .collect();
+        assert_eq!(names, vec!["f", "main"]);
+        assert!(hir.iter().all(|decl| match &decl.data.init {
+            Some(data::Initializer::FunctionBody(body)) => body.len() == 1,
+            _ => false,
+        }));
+        assert!(hir[0].location.span.start() < hir[1].location.span.start());
+        // undeclared labels are only caught during codegen
+        assert!(compile(program).is_err());
+    }
+    #[test]
+    fn include_dependencies() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.h"), "int f(void);\n").unwrap();
+        std::fs::write(dir.path().join("b.h"), "#include \"a.h\"\nint g(void);\n").unwrap();
+        let program = "#include \"a.h\"\n#include \"b.h\"\nint main(void) { return f() + g(); }";
+        let main = dir.path().join("main.c").to_string_lossy().into_owned();
+
+        let (deps, warnings) = super::dependencies(program, main.clone(), &Opt::default());
+        assert!(warnings.is_empty());
+        assert_eq!(
+            deps.unwrap(),
+            vec![dir.path().join("a.h"), dir.path().join("b.h")]
+        );
+        let (result, _) = super::compile(program, main.clone(), &Opt::default());
+        assert!(result.is_ok());
+
+        let (deps, _) = super::dependencies("#include \"missing.h\"\n", main, &Opt::default());
+        assert!(deps.is_err());
+    }
+    #[test]
+    fn computed_include() {
+        use crate::data::error::CppError;
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("rcc_computed.h"),
+            "int f(void) { return 1; }\n",
+        )
+        .unwrap();
+        let main = dir.path().join("main.c").to_string_lossy().into_owned();
+        let program = "#define LOCAL \"rcc_computed.h\"
+#include LOCAL
+int main(void) { return f(); }";
+        let (deps, warnings) = super::dependencies(program, main.clone(), &Opt::default());
+        assert!(warnings.is_empty());
+        assert_eq!(deps.unwrap(), vec![dir.path().join("rcc_computed.h")]);
+        let (result, _) = super::compile(program, main.clone(), &Opt::default());
+        assert!(result.is_ok());
+
+        // `<...>` only searches the system directories, not the directory of the file
+        let program = "#define SYSTEM <rcc_computed.h>\n#include SYSTEM\n";
+        let (deps, _) = super::dependencies(program, main, &Opt::default());
+        match deps {
+            Err(Error::Source(errs)) => assert_eq!(
+                errs.into_iter().map(|err| err.data).collect::<Vec<_>>(),
+                vec![CppError::HeaderNotFound("rcc_computed.h".into()).into()]
+            ),
+            other => panic!("expected a missing header, got {:?}", other),
+        }
+    }
+    #[test]
+    fn pragma_once() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("a.h"),
+            "#pragma once\nint f(void) { return 1; }\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("b.h"),
+            "_Pragma(\"once\")\nint g(void) { return 2; }\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("c.h"), "int h(void) { return 3; }\n").unwrap();
+        let main = dir.path().join("main.c").to_string_lossy().into_owned();
+        let compile = |program: &str| super::compile(program, main.clone(), &Opt::default());
+
+        let program = "#include \"a.h\"\n#include \"b.h\"\n#include \"a.h\"\n#include \"b.h\"
+int main(void) { return f() + g(); }";
+        let (result, warnings) = compile(program);
+        assert!(result.is_ok());
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        // without `#pragma once`, the second include redefines `h`
+        let (result, _) = compile("#include \"c.h\"\n#include \"c.h\"\n");
+        assert!(result.is_err());
+    }
+    #[test]
+    fn call_graph() {
+        let program = "int c(void) { return 1; }
+        int b(void) { return c() + c(); }
+        int a(int (*f)(void)) { return b() + f(); }
+        int main(void) { return a(c); }";
+        let (graph, warnings) =
+            super::call_graph(program, "<test-suite>".to_owned(), &Opt::default());
+        assert!(warnings.is_empty());
+        let graph = graph.unwrap();
+        let callees = |caller: &str| {
+            let mut callees: Vec<_> = graph[&InternedStr::get_or_intern(caller)]
+                .iter()
+                .map(Callee::to_string)
+                .collect();
+            callees.sort();
+            callees
+        };
+        assert_eq!(graph.len(), 4);
+        assert_eq!(callees("main"), vec!["a"]);
+        assert_eq!(callees("a"), vec!["<indirect>", "b"]);
+        assert_eq!(callees("b"), vec!["c"]);
+        assert!(callees("c").is_empty());
+    }
+    #[test]
+    fn call_later_definition() {
+        // `b` is implicitly declared as `int b()` when `a` calls it
+        let program = "int a(void) { return b(2); }
+        int b(int x) { return x + 1; }
+        int main(void) { return a(); }";
+        let opt = Opt {
+            std: Std::C89,
+            ..Opt::default()
+        };
+        let (result, warnings) = super::compile(program, "<test-suite>".to_owned(), &opt);
+        assert_eq!(warnings.len(), 1, "{:?}", warnings);
+        let dir = tempfile::tempdir().unwrap();
+        let (object, binary) = (dir.path().join("a.o"), dir.path().join("a.out"));
+        assemble(result.unwrap(), &object).unwrap();
+        link(&object, &binary).unwrap();
+        let status = Command::new(&binary).status().unwrap();
+        assert_eq!(status.code(), Some(3));
+    }
+    #[test]
+    fn trap_overflow() {
+        let run = |trap_overflow| {
+            let program = "int add(int a, int b) { return a + b; }
+            int main(void) { return add(2147483647, 1) < 0; }";
+            let opt = Opt {
+                trap_overflow,
+                ..Opt::default()
+            };
+            let (result, _) = super::compile(program, "<test-suite>".to_owned(), &opt);
+            let dir = tempfile::tempdir().unwrap();
+            let (object, binary) = (dir.path().join("a.o"), dir.path().join("a.out"));
+            assemble(result.unwrap(), &object).unwrap();
+            link(&object, &binary).unwrap();
+            Command::new(&binary).status().unwrap()
+        };
+        // signed overflow wraps by default
+        assert_eq!(run(false).code(), Some(1));
+        // and is killed by the trap with the flag set
+        let status = run(true);
+        assert!(!status.success());
+        assert_eq!(status.code(), None);
+    }
 }
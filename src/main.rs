@@ -1,9 +1,8 @@
 use std::collections::VecDeque;
 use std::fs::File;
 use std::io::{self, Read};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process;
-use std::sync::atomic::{AtomicUsize, Ordering};
 
 extern crate ansi_term;
 extern crate codespan;
@@ -13,22 +12,19 @@ extern crate pico_args;
 extern crate rcc;
 
 use ansi_term::{ANSIString, Colour};
-use codespan::{FileId, Files};
+use codespan::{ByteIndex, FileId, Files, Span};
 use pico_args::Arguments;
 use rcc::{
-    assemble, compile,
+    assemble, call_graph, compile,
     data::{
-        error::{CompileWarning, RecoverableResult},
+        error::{CompileWarning, ErrorHandler, FixIt, RecoverableResult},
         lex::Location,
     },
-    link, utils, Error,
+    dependencies, link, utils, CallGraph, Callee, Error, ImplicitFunctions, Opt, Pedantic,
 };
 use std::ffi::OsStr;
 use tempfile::NamedTempFile;
 
-static ERRORS: AtomicUsize = AtomicUsize::new(0);
-static WARNINGS: AtomicUsize = AtomicUsize::new(0);
-
 const HELP: &str = concat!(
     env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"), "\n",
     env!("CARGO_PKG_AUTHORS"), "\n",
@@ -41,12 +37,45 @@ FLAGS:
         --debug-asm    If set, print the intermediate representation of the program in addition to compiling
     -a, --debug-ast    If set, print the parsed abstract syntax tree in addition to compiling
         --debug-lex    If set, print all tokens found by the lexer in addition to compiling.
+    -g                 Emit DWARF line-number information for the main file
     -h, --help         Prints help information
+        --pedantic     Warn about uses of GNU extensions
+        --pedantic-errors
+                       Reject uses of GNU extensions
+        --rich-diagnostics
+                       Show diagnostics with the source line they refer to and their notes
+                       in a bordered layout, like codespan-reporting
+        -Werror-implicit-function-declaration
+                       Reject calls to functions which have not been declared
+        -Wimplicit-function-declaration
+                       Warn about calls to functions which have not been declared
+        -Wno-implicit-function-declaration
+                       Silently allow calls to functions which have not been declared
+        -Wunused-variable
+                       Warn about local variables which are never used
+        -Wmismatched-endif
+                       Warn when the comment after an #endif names a different macro than its #if
+        -Werror=vla    Reject variable-length arrays
+        -ftrapv        Trap on signed integer overflow instead of wrapping
     -c, --no-link      If set, compile and assemble but do not link. Object file is machine-dependent.
     -V, --version      Prints version information
 
 OPTIONS:
+        --endian <order>     The byte order of initialized data in the object file.
+                             One of big or little. [default: the byte order of the target]
+        --max-warnings <n>   Show at most n warnings, then a count of the rest. [default: no limit]
+        --jump-table-density <percent>
+                             The percentage of the range of a switch's case labels which
+                             must be used before it becomes a jump table,
+                             from 1 to 100. [default: 40]
+        --emit <kind>        Print information about the program instead of compiling it.
+                             One of `deps`, which prints a Makefile rule listing every file
+                             the program includes (like `cc -M`), or `callgraph`, which prints
+                             the functions called by each function in DOT format.
     -o, --output <output>    The output file to use. [default: a.out]
+        --std <std>          The version of the C standard to follow.
+                             One of c89, c90, ansi, c99, or c11. [default: c11]
+        --tab-width <width>  The number of columns a tab advances to in diagnostics. [default: 1]
 
 ARGS:
     <file>    The file to read C source from. \"-\" means stdin (use ./- to read a file called '-').
@@ -54,56 +83,121 @@ ARGS:
 
 const USAGE: &str = "\
 usage: rcc [--help] [--version | -V] [--debug-asm] [--debug-ast | -a]
-           [--debug-lex] [--no-link | -c] [<file>]";
+           [--debug-lex] [-g] [--no-link | -c] [--emit <kind>] [--std <std>]
+           [--endian <order>] [--jump-table-density <percent>] [--tab-width <width>]
+           [--max-warnings <n>]
+           [-W[error-|no-]implicit-function-declaration] [-Wunused-variable]
+           [-Wmismatched-endif] [-Werror=vla] [-ftrapv] [--pedantic[-errors]]
+           [--rich-diagnostics] [<file>]";
 
 #[derive(Debug)]
-struct Opt {
+struct BinOpt {
+    /// The options passed through to the compiler.
+    opt: Opt,
+
     /// The file to read C source from.
     /// "-" means stdin (use ./- to read a file called '-').
     /// Only one file at a time is currently accepted.
     filename: PathBuf,
 
-    /// If set, print all tokens found by the lexer in addition to compiling.
-    debug_lex: bool,
-
-    /// If set, print the parsed abstract syntax tree in addition to compiling
-    debug_ast: bool,
-
-    /// If set, print the intermediate representation of the program in addition to compiling
-    debug_asm: bool,
-
     /// If set, compile and assemble but do not link. Object file is machine-dependent.
     no_link: bool,
 
+    /// If set, print information about the program instead of compiling it.
+    emit: Option<Emit>,
+
     /// The output file to use.
     output: PathBuf,
+
+    /// The number of columns a tab advances to when showing diagnostics.
+    tab_width: usize,
+
+    /// If set, show diagnostics in the style of `codespan-reporting`.
+    rich_diagnostics: bool,
+}
+
+/// How to show errors and warnings
+#[derive(Copy, Clone, Debug)]
+struct DiagnosticStyle {
+    /// The number of columns a tab advances to
+    tab_width: usize,
+    /// Whether to use `rich_print` instead of `pretty_print`
+    rich: bool,
 }
 
-impl Default for Opt {
+impl BinOpt {
+    fn diagnostic_style(&self) -> DiagnosticStyle {
+        DiagnosticStyle {
+            tab_width: self.tab_width,
+            rich: self.rich_diagnostics,
+        }
+    }
+}
+
+/// What to print instead of compiling the program
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Emit {
+    /// A Makefile rule for the program's dependencies
+    Deps,
+    /// The functions called by each function, in DOT format
+    CallGraph,
+}
+
+impl Default for BinOpt {
     fn default() -> Self {
-        Opt {
+        BinOpt {
+            opt: Opt::default(),
             filename: "<default>".into(),
-            debug_lex: false,
-            debug_ast: false,
-            debug_asm: false,
             no_link: false,
+            emit: None,
             output: PathBuf::from("a.out"),
+            tab_width: 1,
+            rich_diagnostics: false,
         }
     }
 }
 
-// TODO: when std::process::termination is stable, make err_exit an impl for CompilerError
+// TODO: when std::process::termination is stable, make report_err an impl for CompilerError
 // TODO: then we can move this into `main` and have main return `Result<(), Error>`
-fn real_main(file_db: &Files<String>, file_id: FileId, opt: Opt) -> Result<(), Error> {
+//
+// Every diagnostic printed is also added to `diagnostics`, so `main` can decide the exit code.
+fn real_main(
+    file_db: &Files<String>,
+    file_id: FileId,
+    opt: BinOpt,
+    diagnostics: &mut ErrorHandler,
+) -> Result<(), Error> {
     env_logger::init();
+    let style = opt.diagnostic_style();
+    match opt.emit {
+        Some(Emit::Deps) => {
+            let (result, warnings) = dependencies(
+                file_db.source(file_id),
+                opt.filename.to_string_lossy().into_owned(),
+                &opt.opt,
+            );
+            handle_warnings(warnings, diagnostics, file_id, file_db, style);
+            println!("{}", make_rule(&opt.filename, &result?));
+            return Ok(());
+        }
+        Some(Emit::CallGraph) => {
+            let (result, warnings) = call_graph(
+                file_db.source(file_id),
+                opt.filename.to_string_lossy().into_owned(),
+                &opt.opt,
+            );
+            handle_warnings(warnings, diagnostics, file_id, file_db, style);
+            print!("{}", dot_graph(&result?));
+            return Ok(());
+        }
+        None => {}
+    }
     let (result, warnings) = compile(
         file_db.source(file_id),
         opt.filename.to_string_lossy().into_owned(),
-        opt.debug_lex,
-        opt.debug_ast,
-        opt.debug_asm,
+        &opt.opt,
     );
-    handle_warnings(warnings, file_id, file_db);
+    handle_warnings(warnings, diagnostics, file_id, file_db, style);
 
     let product = result?;
     if opt.no_link {
@@ -114,14 +208,95 @@ fn real_main(file_db: &Files<String>, file_id: FileId, opt: Opt) -> Result<(), E
     link(tmp_file.as_ref(), opt.output.as_path()).map_err(io::Error::into)
 }
 
-fn handle_warnings(warnings: VecDeque<CompileWarning>, file: FileId, file_db: &Files<String>) {
-    WARNINGS.fetch_add(warnings.len(), Ordering::Relaxed);
+/// Format the dependencies of `filename` the same way as `cc -M`:
+/// `file.o: file.c header.h ...`
+fn make_rule(filename: &Path, deps: &[PathBuf]) -> String {
+    let target = filename.with_extension("o");
+    let target = target.file_name().unwrap_or_else(|| target.as_os_str());
+    let mut rule = format!("{}: {}", target.to_string_lossy(), filename.display());
+    for dep in deps {
+        rule.push(' ');
+        rule.push_str(&dep.to_string_lossy());
+    }
+    rule
+}
+
+/// Format a call graph in the DOT language, sorted by function name
+/// so the output is the same every time.
+fn dot_graph(graph: &CallGraph) -> String {
+    let mut edges: Vec<_> = graph
+        .iter()
+        .map(|(caller, callees)| {
+            let mut callees: Vec<_> = callees.iter().map(Callee::to_string).collect();
+            callees.sort();
+            (caller.to_string(), callees)
+        })
+        .collect();
+    edges.sort();
+    let mut dot = String::from("digraph callgraph {\n");
+    for (caller, callees) in edges {
+        dot.push_str(&format!("    \"{}\";\n", caller));
+        for callee in callees {
+            dot.push_str(&format!("    \"{}\" -> \"{}\";\n", caller, callee));
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+fn handle_warnings(
+    warnings: VecDeque<CompileWarning>,
+    diagnostics: &mut ErrorHandler,
+    file: FileId,
+    file_db: &Files<String>,
+    style: DiagnosticStyle,
+) {
     let tag = Colour::Yellow.bold().paint("warning");
     for warning in warnings {
-        print!(
-            "{}",
-            pretty_print(tag.clone(), warning.data, warning.location, file, file_db)
+        let fixit = warning.fixit();
+        let location = warning.location;
+        print_diagnostic(
+            tag.clone(),
+            &warning.data,
+            location,
+            fixit,
+            file,
+            file_db,
+            style,
+        );
+        diagnostics.warn(warning.data, location);
+    }
+}
+
+/// Print a diagnostic, along with its suggested fix if it has one.
+fn print_diagnostic<T: std::fmt::Display>(
+    prefix: ANSIString,
+    msg: T,
+    location: Location,
+    fixit: Option<FixIt>,
+    file: FileId,
+    file_db: &Files<String>,
+    style: DiagnosticStyle,
+) {
+    if style.rich {
+        let fixit = fixit.map(|fixit| format!("fix-it: {}", fixit));
+        let notes: Vec<_> = fixit.into_iter().collect();
+        let output = rich_print(
+            prefix,
+            msg,
+            location,
+            &notes,
+            file,
+            file_db,
+            style.tab_width,
         );
+        print!("{}", output);
+    } else {
+        let output = pretty_print(prefix, msg, location, file, file_db, style.tab_width);
+        print!("{}", output);
+        if let Some(fixit) = fixit {
+            println!("note: fix-it: {}", fixit);
+        }
     }
 }
 
@@ -161,7 +336,46 @@ fn main() {
     let mut file_db = Files::new();
     // TODO: remove `lossy` call
     let file_id = file_db.add(opt.filename.to_string_lossy(), buf);
-    real_main(&file_db, file_id, opt).unwrap_or_else(|err| err_exit(err, file_id, &file_db));
+    let style = opt.diagnostic_style();
+    let mut diagnostics = ErrorHandler::new();
+    if let Err(err) = real_main(&file_db, file_id, opt, &mut diagnostics) {
+        report_err(err, &mut diagnostics, file_id, &file_db, style);
+    }
+    if diagnostics.has_errors() {
+        print_issues(&diagnostics);
+        process::exit(2);
+    }
+}
+
+fn parse_emit(kind: &str) -> Result<Emit, String> {
+    match kind {
+        "deps" => Ok(Emit::Deps),
+        "callgraph" => Ok(Emit::CallGraph),
+        _ => Err(format!(
+            "unknown kind '{}' for --emit, expected 'deps' or 'callgraph'",
+            kind
+        )),
+    }
+}
+
+fn parse_tab_width(width: &str) -> Result<usize, String> {
+    match width.parse() {
+        Ok(0) | Err(_) => Err(format!(
+            "invalid tab width '{}', expected a positive integer",
+            width
+        )),
+        Ok(width) => Ok(width),
+    }
+}
+
+fn parse_jump_table_density(density: &str) -> Result<u8, String> {
+    match density.parse() {
+        Ok(density @ 1..=100) => Ok(density),
+        _ => Err(format!(
+            "invalid jump table density '{}', expected a percentage from 1 to 100",
+            density
+        )),
+    }
 }
 
 fn os_str_to_path_buf(os_str: &OsStr) -> Result<PathBuf, bool> {
@@ -173,7 +387,7 @@ macro_rules! type_sizes {
         $(println!("{}: {}", stringify!($type), std::mem::size_of::<$type>());)*
     };
 }
-fn parse_args() -> Result<Opt, pico_args::Error> {
+fn parse_args() -> Result<BinOpt, pico_args::Error> {
     let mut input = Arguments::from_env();
     if input.contains(["-h", "--help"]) {
         println!("{}", HELP);
@@ -200,40 +414,82 @@ fn parse_args() -> Result<Opt, pico_args::Error> {
             RecoverableResult<Expr>
         );
     }
-    Ok(Opt {
-        debug_lex: input.contains("--debug-lex"),
-        debug_asm: input.contains("--debug-asm"),
-        debug_ast: input.contains(["-a", "--debug-ast"]),
+    let implicit_functions = if input.contains("-Werror-implicit-function-declaration") {
+        Some(ImplicitFunctions::Error)
+    } else if input.contains("-Wno-implicit-function-declaration") {
+        Some(ImplicitFunctions::Allow)
+    } else if input.contains("-Wimplicit-function-declaration") {
+        Some(ImplicitFunctions::Warn)
+    } else {
+        None
+    };
+    let pedantic = if input.contains("--pedantic-errors") {
+        Pedantic::Error
+    } else if input.contains("--pedantic") {
+        Pedantic::Warn
+    } else {
+        Pedantic::Off
+    };
+    Ok(BinOpt {
+        opt: Opt {
+            debug_lex: input.contains("--debug-lex"),
+            debug_asm: input.contains("--debug-asm"),
+            debug_ast: input.contains(["-a", "--debug-ast"]),
+            debug_info: input.contains("-g"),
+            std: input.opt_value_from_str("--std")?.unwrap_or_default(),
+            implicit_functions,
+            warn_unused_variables: input.contains("-Wunused-variable"),
+            warn_mismatched_endif: input.contains("-Wmismatched-endif"),
+            forbid_vlas: input.contains("-Werror=vla"),
+            trap_overflow: input.contains("-ftrapv"),
+            pedantic,
+            jump_table_density: input
+                .opt_value_from_fn("--jump-table-density", parse_jump_table_density)?,
+            endianness: input.opt_value_from_str("--endian")?,
+            predeclared: Vec::new(),
+            warning_limit: input.opt_value_from_str("--max-warnings")?,
+        },
         no_link: input.contains(["-c", "--no-link"]),
+        emit: input.opt_value_from_fn("--emit", parse_emit)?,
         output: input
             .opt_value_from_os_str(["-o", "--output"], os_str_to_path_buf)?
             .unwrap_or_else(|| "a.out".into()),
+        tab_width: input
+            .opt_value_from_fn("--tab-width", parse_tab_width)?
+            .unwrap_or(1),
+        rich_diagnostics: input.contains("--rich-diagnostics"),
         filename: input
             .free_from_os_str(os_str_to_path_buf)?
             .unwrap_or_else(|| "-".into()),
     })
 }
 
-fn err_exit(err: Error, file: FileId, file_db: &Files<String>) -> ! {
+/// Print the errors in `err`, or exit immediately if it didn't come from the program.
+fn report_err(
+    err: Error,
+    diagnostics: &mut ErrorHandler,
+    file: FileId,
+    file_db: &Files<String>,
+    style: DiagnosticStyle,
+) {
     use Error::*;
     match err {
         Source(errs) => {
             for err in errs {
-                error(&err.data, err.location(), file, file_db);
+                error(&err.data, err.location(), err.fixit(), file, file_db, style);
+                diagnostics.push_back(err);
             }
-            let (num_warnings, num_errors) = (get_warnings(), get_errors());
-            print_issues(num_warnings, num_errors);
-            process::exit(2);
         }
         IO(err) => utils::fatal(&err, 3),
         Platform(err) => utils::fatal(&err, 4),
     }
 }
 
-fn print_issues(warnings: usize, errors: usize) {
-    if warnings == 0 && errors == 0 {
+fn print_issues(diagnostics: &ErrorHandler) {
+    if !diagnostics.has_warnings() && !diagnostics.has_errors() {
         return;
     }
+    let (warnings, errors) = (diagnostics.warning_count(), diagnostics.error_count());
     let warn_msg = if warnings > 1 { "warnings" } else { "warning" };
     let err_msg = if errors > 1 { "errors" } else { "error" };
     let msg = match (warnings, errors) {
@@ -244,18 +500,16 @@ fn print_issues(warnings: usize, errors: usize) {
     eprintln!("{} generated", msg);
 }
 
-fn error<T: std::fmt::Display>(msg: T, location: Location, file: FileId, file_db: &Files<String>) {
-    ERRORS.fetch_add(1, Ordering::Relaxed);
-    print!(
-        "{}",
-        pretty_print(
-            Colour::Red.bold().paint("error"),
-            msg,
-            location,
-            file,
-            file_db,
-        )
-    );
+fn error<T: std::fmt::Display>(
+    msg: T,
+    location: Location,
+    fixit: Option<FixIt>,
+    file: FileId,
+    file_db: &Files<String>,
+    style: DiagnosticStyle,
+) {
+    let tag = Colour::Red.bold().paint("error");
+    print_diagnostic(tag, msg, location, fixit, file, file_db, style);
 }
 
 #[must_use]
@@ -265,49 +519,189 @@ pub fn pretty_print<T: std::fmt::Display, S: AsRef<str>>(
     location: Location,
     file: FileId,
     file_db: &Files<S>,
+    tab_width: usize,
 ) -> String {
-    let start = file_db
-        .location(file, location.span.start())
-        .expect("start location should be in bounds");
+    let mut buf = show_source(prefix, msg, location, file, file_db, tab_width);
+    if let Some(name) = location.expansion {
+        buf.push_str(&format!("note: in expansion of macro '{}'\n", name));
+    }
+    buf
+}
+
+/// Show a diagnostic in the style of `codespan-reporting`:
+/// a header with the message, then the location and the source line it refers to
+/// in a bordered gutter, then each of `notes`.
+///
+/// A macro expansion is shown as the first note.
+/// A span covering several lines is underlined until the end of its first line.
+#[must_use]
+pub fn rich_print<T: std::fmt::Display, S: AsRef<str>>(
+    prefix: ANSIString,
+    msg: T,
+    location: Location,
+    notes: &[String],
+    file: FileId,
+    file_db: &Files<S>,
+    tab_width: usize,
+) -> String {
+    let mut buf = format!("{}: {}\n", prefix, msg);
+    let mut all_notes = Vec::new();
+    if let Some(name) = location.expansion {
+        all_notes.push(format!("in expansion of macro '{}'", name));
+    }
+    all_notes.extend(notes.iter().cloned());
+
+    let filename = location.filename.resolve_and_clone();
+    let gutter = match source_position(location, file, file_db, tab_width) {
+        None => {
+            buf.push_str(&format!("  ┌─ {}\n", filename));
+            " ".to_string()
+        }
+        Some((line, start_column, end_column, text)) => {
+            let number = line.to_string();
+            let gutter = " ".repeat(number.len());
+            buf.push_str(&format!(
+                "{} ┌─ {}:{}:{}\n",
+                gutter,
+                filename,
+                number,
+                start_column + 1
+            ));
+            if let Some(text) = text {
+                buf.push_str(&format!("{} │\n", gutter));
+                buf.push_str(&format!("{} │ {}\n", number, expand_tabs(text, tab_width)));
+                buf.push_str(&format!(
+                    "{} │ {}{}\n",
+                    gutter,
+                    " ".repeat(start_column),
+                    "^".repeat(std::cmp::max(end_column - start_column, 1))
+                ));
+            }
+            gutter
+        }
+    };
+    if !all_notes.is_empty() {
+        buf.push_str(&format!("{} │\n", gutter));
+    }
+    for note in all_notes {
+        buf.push_str(&format!("{} = note: {}\n", gutter, note));
+    }
+    buf
+}
+
+/// Show the message with the line of source code it refers to, if available.
+///
+/// Columns count a tab as advancing to the next multiple of `tab_width`,
+/// and tabs in the source line are expanded the same way so the carets line up.
+fn show_source<T: std::fmt::Display, S: AsRef<str>>(
+    prefix: ANSIString,
+    msg: T,
+    location: Location,
+    file: FileId,
+    file_db: &Files<S>,
+    tab_width: usize,
+) -> String {
+    let (line, start_column, end_column, text) =
+        match source_position(location, file, file_db, tab_width) {
+            Some(position) => position,
+            None => {
+                let filename = location.filename.resolve_and_clone();
+                return format!("{}: {}: {}\n", filename, prefix, msg);
+            }
+        };
     let buf = format!(
         "{}:{}:{}: {}: {}\n",
         file_db.name(file),
-        start.line.number(),
-        start.column.number(),
+        line,
+        start_column + 1,
         prefix,
         msg
     );
-    // avoid printing spurious newline for errors and EOF
-    if location.span.end() == 0.into() {
-        return buf;
+    match text {
+        Some(text) => format!(
+            "{}{}\n{}{}\n",
+            buf,
+            expand_tabs(text, tab_width),
+            " ".repeat(start_column),
+            "^".repeat(std::cmp::max(end_column - start_column, 1))
+        ),
+        None => buf,
+    }
+}
+
+/// Where `location` is in `file`, for showing it along with its line of source code.
+///
+/// Returns the line number, the columns where the span starts and ends,
+/// and the text of the line, without its newline.
+/// A span covering several lines ends at the end of its first line.
+/// The text is `None` for a location at the start of the file and EOF,
+/// which would only show an empty line.
+///
+/// Returns `None` if `location` is in another file, since errors in `#include`d files
+/// don't have their source available.
+fn source_position<'a, S: AsRef<str>>(
+    location: Location,
+    file: FileId,
+    file_db: &'a Files<S>,
+    tab_width: usize,
+) -> Option<(usize, usize, usize, Option<&'a str>)> {
+    if location.filename.resolve_and_clone() != file_db.name(file) {
+        return None;
     }
+    let start = file_db
+        .location(file, location.span.start())
+        .expect("start location should be in bounds");
     let end = file_db
         .location(file, location.span.end())
         .expect("end location should be in bounds");
-    if start.line == end.line {
-        let line = file_db
-            .line_span(file, start.line)
-            .expect("line should be in bounds");
-        format!(
-            "{}{}{}{}\n",
-            buf,
-            file_db.source_slice(file, line).unwrap(),
-            " ".repeat(start.column.0 as usize),
-            "^".repeat((end.column - start.column).0 as usize)
-        )
+    let line = file_db
+        .line_span(file, start.line)
+        .expect("line should be in bounds");
+    let text = file_db.source_slice(file, line).unwrap();
+    let text = text.trim_end_matches(|c| c == '\n' || c == '\r');
+    let width_until = |end: ByteIndex| {
+        let text = file_db
+            .source_slice(file, Span::new(line.start(), end))
+            .expect("span should be in bounds");
+        display_width(text, tab_width)
+    };
+    let start_column = width_until(location.span.start());
+    let end_column = if start.line == end.line {
+        width_until(location.span.end())
     } else {
-        buf
-    }
+        display_width(text, tab_width)
+    };
+    let text = if location.span.end() == 0.into() {
+        None
+    } else {
+        Some(text)
+    };
+    Some((start.line.number(), start_column, end_column, text))
 }
 
-#[inline]
-fn get_warnings() -> usize {
-    ERRORS.load(Ordering::SeqCst)
+/// The number of columns `text` takes up, where a tab advances to the next multiple of `tab_width`.
+fn display_width(text: &str, tab_width: usize) -> usize {
+    text.chars().fold(0, |width, c| {
+        if c == '\t' {
+            width + tab_width - width % tab_width
+        } else {
+            width + 1
+        }
+    })
 }
 
-#[inline]
-fn get_errors() -> usize {
-    ERRORS.load(Ordering::SeqCst)
+/// Replace each tab in `line` with as many spaces as `display_width` counts for it.
+fn expand_tabs(line: &str, tab_width: usize) -> String {
+    let mut expanded = String::with_capacity(line.len());
+    for c in line.chars() {
+        if c == '\t' {
+            let width = display_width(&expanded, tab_width);
+            expanded.push_str(&" ".repeat(tab_width - width % tab_width));
+        } else {
+            expanded.push(c);
+        }
+    }
+    expanded
 }
 
 #[cfg(test)]
@@ -318,14 +712,18 @@ mod test {
     use rcc::intern::InternedStr;
 
     fn pp<S: Into<Span>>(span: S, source: &str) -> String {
+        pp_with_tab_width(span, source, 1)
+    }
+    fn pp_with_tab_width<S: Into<Span>>(span: S, source: &str, tab_width: usize) -> String {
         let location = Location {
             filename: InternedStr::get_or_intern("<test-suite>"),
             span: span.into(),
+            expansion: None,
         };
         let mut file_db = Files::new();
         let file = file_db.add("<test-suite>", source);
         let ansi_str = Style::new().paint("");
-        super::pretty_print(ansi_str, "", location, file, &file_db)
+        super::pretty_print(ansi_str, "", location, file, &file_db, tab_width)
     }
     #[test]
     fn pretty_print() {
@@ -335,4 +733,124 @@ mod test {
         );
         pp(0..0, "");
     }
+    #[test]
+    fn tab_width() {
+        // `x` comes right after a tab
+        let source = "int\tx = 1;\n";
+        let output = pp_with_tab_width(4..5, source, 1);
+        let lines: Vec<_> = output.lines().collect();
+        assert!(lines[0].starts_with("<test-suite>:1:5:"), "{}", lines[0]);
+        assert_eq!(&lines[1..], &["int x = 1;", "    ^"]);
+
+        let output = pp_with_tab_width(4..5, source, 8);
+        let lines: Vec<_> = output.lines().collect();
+        assert!(lines[0].starts_with("<test-suite>:1:9:"), "{}", lines[0]);
+        assert_eq!(&lines[1..], &["int     x = 1;", "        ^"]);
+    }
+    #[test]
+    fn jump_table_density() {
+        use super::parse_jump_table_density as parse;
+        assert_eq!(parse("1"), Ok(1));
+        assert_eq!(parse("100"), Ok(100));
+        assert!(parse("0").is_err());
+        assert!(parse("101").is_err());
+        assert!(parse("-1").is_err());
+    }
+    #[test]
+    fn macro_note() {
+        let location = Location {
+            filename: InternedStr::get_or_intern("<test-suite>"),
+            span: (4..7).into(),
+            expansion: Some(InternedStr::get_or_intern("TWO")),
+        };
+        let mut file_db = Files::new();
+        let file = file_db.add("<test-suite>", "int TWO;\n");
+        let ansi_str = Style::new().paint("");
+        let output = super::pretty_print(ansi_str, "", location, file, &file_db, 1);
+        assert_eq!(
+            output.lines().last(),
+            Some("note: in expansion of macro 'TWO'")
+        );
+    }
+    #[test]
+    fn rich_print() {
+        use rcc::data::error::{Error, SemanticError};
+        let location = Location {
+            filename: InternedStr::get_or_intern("<test-suite>"),
+            span: (17..20).into(),
+            expansion: Some(InternedStr::get_or_intern("ONE")),
+        };
+        let mut file_db = Files::new();
+        let file = file_db.add("<test-suite>", "int main(void) { ONE = 2; }\n");
+        let err = Error::from(SemanticError::NotAssignable("rvalue of type 'int'".into()));
+        let notes = ["fix-it: remove the assignment".to_string()];
+        let ansi_str = Style::new().paint("error");
+        let output = super::rich_print(ansi_str, err, location, &notes, file, &file_db, 1);
+        assert_eq!(
+            output,
+            "\
+error: invalid program: cannot assign to rvalue of type 'int'
+  ┌─ <test-suite>:1:18
+  │
+1 │ int main(void) { ONE = 2; }
+  │                  ^^^
+  │
+  = note: in expansion of macro 'ONE'
+  = note: fix-it: remove the assignment
+"
+        );
+        // without the source, only the file is shown
+        let location = Location {
+            filename: InternedStr::get_or_intern("header.h"),
+            span: (0..3).into(),
+            expansion: None,
+        };
+        let ansi_str = Style::new().paint("warning");
+        let output = super::rich_print(ansi_str, "oops", location, &[], file, &file_db, 1);
+        assert_eq!(output, "warning: oops\n  ┌─ header.h\n");
+    }
+    #[test]
+    fn start_of_file() {
+        // both renderers show the position, but no source
+        let location = Location {
+            filename: InternedStr::get_or_intern("<test-suite>"),
+            span: (0..0).into(),
+            expansion: None,
+        };
+        let mut file_db = Files::new();
+        let file = file_db.add("<test-suite>", "");
+        let ansi_str = Style::new().paint("error");
+        let output = super::pretty_print(ansi_str.clone(), "oops", location, file, &file_db, 1);
+        assert_eq!(output, "<test-suite>:1:1: error: oops\n");
+        let output = super::rich_print(ansi_str, "oops", location, &[], file, &file_db, 1);
+        assert_eq!(output, "error: oops\n  ┌─ <test-suite>:1:1\n");
+    }
+    #[test]
+    fn dot_graph() {
+        use rcc::{CallGraph, Callee};
+        let name = |s| InternedStr::get_or_intern(s);
+        let mut graph = CallGraph::new();
+        graph
+            .entry(name("b"))
+            .or_default()
+            .insert(Callee::Named(name("c")));
+        graph.entry(name("a")).or_default().insert(Callee::Indirect);
+        graph
+            .entry(name("a"))
+            .or_default()
+            .insert(Callee::Named(name("b")));
+        graph.entry(name("c")).or_default();
+        assert_eq!(
+            super::dot_graph(&graph),
+            "digraph callgraph {
+    \"a\";
+    \"a\" -> \"<indirect>\";
+    \"a\" -> \"b\";
+    \"b\";
+    \"b\" -> \"c\";
+    \"c\";
+}
+"
+        );
+    }
 }
@@ -6,10 +6,11 @@ use std::mem;
 use super::{FunctionData, Lexeme, Parser, SyntaxResult, TagEntry};
 use crate::arch::SIZE_T;
 use crate::data::{
+    error::Warning,
     lex::Keyword,
     prelude::*,
     types::{ArrayType, FunctionType},
-    Initializer, Qualifiers, StorageClass,
+    Attributes, Initializer, LengthError, Qualifiers, Scope, StorageClass,
 };
 
 impl<I: Iterator<Item = Lexeme>> Parser<I> {
@@ -30,7 +31,7 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
     ///
     /// Used for casts and `sizeof` builtin.
     pub fn type_name(&mut self) -> SyntaxResult<Locatable<(Type, Qualifiers)>> {
-        let (sc, qualifiers, ctype, _) = self.declaration_specifiers()?;
+        let (sc, qualifiers, _, ctype, _) = self.declaration_specifiers()?;
         if sc != None {
             self.semantic_err("type cannot have a storage class", self.last_location);
         }
@@ -65,7 +66,8 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
      * and return the last.
      */
     pub fn declaration(&mut self) -> SyntaxResult<VecDeque<Locatable<Declaration>>> {
-        let (sc, mut qualifiers, ctype, seen_compound_type) = self.declaration_specifiers()?;
+        let (sc, mut qualifiers, attributes, ctype, seen_compound_type) =
+            self.declaration_specifiers()?;
         if self.match_next(&Token::Semicolon).is_some() {
             if !seen_compound_type {
                 let loc = self.next_location();
@@ -79,7 +81,11 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
         let declarator = self
             .declarator(false, qualifiers)?
             .expect("declarator should return id when called with allow_abstract: false");
-        let (id, first_type) = declarator
+        let old_style = declarator.has_identifier_list();
+        let mut array_params = declarator.array_params();
+        let mut declarator_attributes = attributes;
+        self.attributes(&mut declarator_attributes)?;
+        let (id, mut first_type) = declarator
             .parse_type(
                 ctype.clone(),
                 sc == Some(StorageClass::Typedef),
@@ -87,6 +93,11 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
             )
             .recover(&mut self.error_handler);
         let id = id.expect("declarator should return id when called with allow_abstract: false");
+        if let Type::Function(ftype) = &mut first_type {
+            if old_style && self.next_is_declaration_specifier() {
+                self.old_style_parameters(&mut ftype.params, &mut array_params)?;
+            }
+        }
         let sc = match sc {
             Some(sc) => sc,
             None if first_type.is_function() => StorageClass::Extern,
@@ -104,6 +115,7 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
             qualifiers,
             storage_class: sc,
             init: false,
+            attributes: declarator_attributes,
         };
         // if it's not a function, we still need to handle it
         let init = match (&symbol.ctype, self.peek_token()) {
@@ -114,6 +126,7 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                 Some(Initializer::FunctionBody(self.function_body(
                     symbol.id.clone(),
                     ftype,
+                    array_params,
                     id.location.clone(),
                 )?))
             }
@@ -125,7 +138,9 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
             }
             (ctype, Some(t)) if *t == Token::EQUAL => {
                 self.next_token();
-                let init = Some(self.initializer(ctype)?);
+                let init = self.initializer(ctype)?;
+                self.check_static_initializer(sc, &init);
+                let init = Some(init);
                 symbol.init = true;
                 self.declare(&mut symbol, &id.location);
                 init
@@ -156,7 +171,7 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
             self.expect(Token::Comma)?;
         }
         loop {
-            let mut decl = self.init_declarator(sc, qualifiers, ctype.clone())?;
+            let mut decl = self.init_declarator(sc, qualifiers, attributes, ctype.clone())?;
             self.declare(&mut decl.data.symbol, &decl.location);
             pending.push_back(decl);
             if self.match_next(&Token::Comma).is_none() {
@@ -181,11 +196,22 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
         if types == vec![&Type::Void] {
             return true;
         }
-        // TODO: allow 'int main(int argc, char *argv[], char *environ[])'
-        if types.len() != 2 || *types[0] != Type::Int(true) {
-            return false;
-        }
-        match types[1] {
+        types.len() == 2 && *types[0] == Type::Int(true) && Self::is_argv(types[1])
+    }
+    // 'int main(int argc, char *argv[], char *envp[])' is a common extension,
+    // but not guaranteed to work by the standard
+    fn is_main_with_environment(ftype: &FunctionType) -> bool {
+        let types: Vec<&Type> = ftype.params.iter().map(|param| &param.ctype).collect();
+        *ftype.return_type == Type::Int(true)
+            && !ftype.varargs
+            && types.len() == 3
+            && *types[0] == Type::Int(true)
+            && Self::is_argv(types[1])
+            && Self::is_argv(types[2])
+    }
+    // `char **` or `char *[]`
+    fn is_argv(ctype: &Type) -> bool {
+        match ctype {
             Type::Pointer(t) | Type::Array(t, _) => match &**t {
                 Type::Pointer(inner) => inner.is_char(),
                 _ => false,
@@ -233,6 +259,7 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
             qualifiers,
             storage_class: StorageClass::Typedef,
             init: true,
+            attributes: Default::default(),
         };
         if let Some(existing_def) = self.scope.insert(id.data.clone(), typedef) {
             let message = if existing_def.storage_class == StorageClass::Typedef {
@@ -251,9 +278,36 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
         }
     }
     fn declare(&mut self, decl: &mut Symbol, location: &Location) {
+        if decl.qualifiers.thread_local {
+            if decl.ctype.is_function() {
+                self.semantic_err("'_Thread_local' is only allowed on variables", *location);
+            } else if !self.scope.is_global()
+                && decl.storage_class != StorageClass::Static
+                && decl.storage_class != StorageClass::Extern
+            {
+                self.semantic_err(
+                    format!(
+                        "'_Thread_local' variable '{}' at block scope must also be 'static' or 'extern'",
+                        decl.id
+                    ),
+                    *location,
+                );
+            }
+        }
+        if !self.scope.is_global() {
+            // only variables which are defined here can be unused;
+            // everything else is recorded as used so it shadows outer variables correctly
+            let used = decl.attributes.unused
+                || decl.ctype.is_function()
+                || decl.storage_class == StorageClass::Extern;
+            self.local_uses.insert(decl.id, (*location, used));
+        }
         if decl.id == InternedStr::get_or_intern("main") {
             if let Type::Function(ftype) = &decl.ctype {
-                if !Self::is_main_func_signature(ftype) {
+                if Self::is_main_with_environment(ftype) {
+                    self.error_handler
+                        .warn(Warning::NonstandardMain(decl.ctype.clone()), *location);
+                } else if !Self::is_main_func_signature(ftype) {
                     self.semantic_err(
                         "illegal signature for main function (expected 'int main(void)' or 'int main(int, char **)'",
                         *location,
@@ -271,10 +325,21 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
             decl.storage_class = StorageClass::Auto;
         }
         if let Some(existing) = self.scope.get_immediate(&decl.id) {
+            decl.attributes.deprecated = decl
+                .attributes
+                .deprecated
+                .or(existing.attributes.deprecated);
             if existing == decl {
                 if decl.init && existing.init {
                     self.semantic_err(format!("redefinition of '{}'", decl.id), *location);
                 }
+            } else if existing.ctype != decl.ctype {
+                let previous = self.declared_at.get_immediate(&decl.id).copied();
+                let err = SemanticError::ConflictingTypes {
+                    name: decl.id,
+                    previous: previous.unwrap_or(*location),
+                };
+                self.error_handler.push_back(location.error(err));
             } else {
                 let err = format!(
                     "redeclaration of '{}' with different type or qualifiers (originally {}, now {})",
@@ -285,12 +350,14 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
             self.scope.insert(decl.id.clone(), decl.clone());
         } else {
             self.scope.insert(decl.id.clone(), decl.clone());
+            self.declared_at.insert(decl.id, *location);
         }
     }
     fn init_declarator(
         &mut self,
         sc: StorageClass,
         qualifiers: Qualifiers,
+        mut attributes: Attributes,
         ctype: Type,
     ) -> SyntaxResult<Locatable<Declaration>> {
         // parse declarator
@@ -298,6 +365,7 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
         let decl = self
             .declarator(false, qualifiers)?
             .expect("declarator should never return None when called with allow_abstract: false");
+        self.attributes(&mut attributes)?;
         let (id, ctype) = decl
             .parse_type(ctype, false, &self.last_location)
             .recover(&mut self.error_handler);
@@ -305,7 +373,9 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
 
         // optionally, parse an initializer
         let init = if self.match_next(&Token::EQUAL).is_some() {
-            Some(self.initializer(&ctype)?)
+            let init = self.initializer(&ctype)?;
+            self.check_static_initializer(sc, &init);
+            Some(init)
         } else {
             None
         };
@@ -321,6 +391,7 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
             },
             ctype,
             init: init.is_some(),
+            attributes,
         };
         Ok(Locatable {
             data: Declaration { symbol, init },
@@ -344,11 +415,12 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
      */
     fn declaration_specifiers(
         &mut self,
-    ) -> SyntaxResult<(Option<StorageClass>, Qualifiers, Type, bool)> {
+    ) -> SyntaxResult<(Option<StorageClass>, Qualifiers, Attributes, Type, bool)> {
         // TODO: initialization is a mess
         let mut keywords = HashSet::new();
         let mut storage_class = None;
         let mut qualifiers = Qualifiers::NONE;
+        let mut attributes = Attributes::default();
         let mut ctype = None;
         let mut signed = None;
         let mut seen_compound = false;
@@ -379,6 +451,14 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                     continue;
                 }
                 Token::Keyword(k) if k.is_decl_specifier() => (locatable.location, k),
+                Token::Id(id) if is_attribute(id) => {
+                    self.unput(Some(Locatable {
+                        data: Token::Id(id),
+                        location: locatable.location,
+                    }));
+                    self.attributes(&mut attributes)?;
+                    continue;
+                }
                 Token::Id(id) => match self.scope.get(&id) {
                     Some(typedef)
                         if typedef.storage_class == StorageClass::Typedef && !seen_typedef =>
@@ -400,6 +480,12 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                     break;
                 }
             };
+            if keyword == Keyword::Bool && self.std < Std::C99 {
+                self.semantic_err(
+                    format!("'{}' is not allowed in {}", keyword, self.std),
+                    location,
+                );
+            }
             if keywords.insert(keyword) {
                 self.declaration_specifier(
                     keyword,
@@ -421,8 +507,15 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                         &format!("duplicate declaration specifier '{}'", keyword),
                         location,
                     );
+                } else if keyword == Keyword::Long {
+                    if self.std < Std::C99 {
+                        self.semantic_err(
+                            format!("'long long' is not allowed in {}", self.std),
+                            location,
+                        );
+                    }
                 // what is `short short` supposed to be?
-                } else if keyword != Keyword::Long {
+                } else {
                     self.semantic_err(
                         format!("duplicate basic type '{}' in declarator", keyword),
                         location,
@@ -447,14 +540,19 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                     // other parts of the parser will have a better error message
                     if let Some(Token::Id(_)) = self.peek_token() {
                         let loc = self.next_location();
-                        self.error_handler
-                            .warn("type specifier missing, defaults to int", loc);
+                        // implicit int was removed in C99
+                        if self.std < Std::C99 {
+                            self.error_handler.warn(Warning::ImplicitInt, loc);
+                        } else {
+                            let err = SemanticError::MissingTypeSpecifier(self.std);
+                            self.error_handler.push_back(loc.error(err));
+                        }
                     }
                 }
                 Type::Int(signed.unwrap_or(true))
             }
         };
-        Ok((storage_class, qualifiers, ctype, seen_compound))
+        Ok((storage_class, qualifiers, attributes, ctype, seen_compound))
     }
     /*
     rewritten grammar:
@@ -623,6 +721,7 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                 storage_class: StorageClass::Register,
                 init: true,
                 ctype: Type::Enum(None, vec![(name, current)]),
+                attributes: Default::default(),
             };
             self.scope.insert(name, tmp_symbol);
             // allow trailing commas
@@ -657,6 +756,7 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                             storage_class: StorageClass::Register,
                             qualifiers: Qualifiers::NONE,
                             ctype: ctype.clone(),
+                            attributes: Default::default(),
                         },
                     );
                 }
@@ -741,10 +841,24 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
         ;
     */
     fn struct_declarator_list(&mut self, members: &mut Vec<Symbol>) -> SyntaxResult<()> {
-        let (sc, qualifiers, original_ctype, _) = self.declaration_specifiers()?;
+        let (sc, qualifiers, _, original_ctype, _) = self.declaration_specifiers()?;
         if let Some(token) = self.match_next(&Token::Semicolon) {
-            self.error_handler
-                .warn("declaration does not declare anything", token.location);
+            match original_ctype {
+                // C11 anonymous struct or union: its members are visible in the outer struct
+                Type::Struct(StructType::Anonymous(_)) | Type::Union(StructType::Anonymous(_)) => {
+                    members.push(Symbol {
+                        storage_class: StorageClass::Auto,
+                        qualifiers,
+                        ctype: original_ctype,
+                        init: false,
+                        id: anonymous_member(members.len()),
+                        attributes: Default::default(),
+                    })
+                }
+                _ => self
+                    .error_handler
+                    .warn("declaration does not declare anything", token.location),
+            }
             return Ok(());
         }
         let mut last_location;
@@ -770,6 +884,7 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                 ctype,
                 init: false,
                 id,
+                attributes: Default::default(),
             };
             if let Some(token) = self.match_next(&Token::Colon) {
                 let bit_size = self.bitfield()?;
@@ -780,17 +895,25 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                         symbol.id
                     );
                     self.semantic_err(err, self.last_location);
-                } else if bit_size > type_size * u64::from(crate::arch::CHAR_BIT) {
+                } else if !symbol.ctype.is_integral() {
+                    let err = format!(
+                        "bitfield {} has type '{}', bitfields must have an integer type",
+                        symbol.id, symbol.ctype
+                    );
+                    self.semantic_err(err, token.location);
+                } else if bit_size > type_size * u64::from(crate::arch::CHAR_BIT)
+                    // _Bool only has one value bit (C11 6.7.2.1p4)
+                    || symbol.ctype.is_bool() && bit_size > 1
+                {
                     let err = format!(
                         "cannot have bitfield {} with size {} larger than containing type {}",
                         symbol.id, bit_size, symbol.ctype
                     );
                     self.semantic_err(err, token.location);
+                } else {
+                    // the check above guarantees bit_size is at most 64
+                    symbol.ctype = Type::Bitfield(Box::new(symbol.ctype), bit_size as u16);
                 }
-                self.error_handler.warn(
-                    "bitfields are not implemented and will be ignored",
-                    token.location,
-                );
             };
             match symbol.ctype {
                 Type::Struct(StructType::Named(_, inner_members))
@@ -831,6 +954,47 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
         }
         Ok(())
     }
+    /// The size of an array declarator, after the `[` and before the `]`.
+    ///
+    /// Inside a function, a size which is not a constant expression
+    /// declares a variable-length array (C99 section 6.7.6.2).
+    fn array_size(&mut self) -> SyntaxResult<ArrayType> {
+        let expr = self.conditional_expr()?;
+        let location = expr.location;
+        if !expr.constexpr && self.current_function.is_some() {
+            if self.forbid_vlas {
+                self.error_handler
+                    .push_back(location.error(SemanticError::VlaForbidden));
+                return Ok(ArrayType::Fixed(1));
+            }
+            if !expr.ctype.is_integral() {
+                self.semantic_err(LengthError::NonIntegral, location);
+            }
+            if self.std < Std::C99 {
+                self.semantic_err(
+                    format!("variable length arrays are not allowed in {}", self.std),
+                    location,
+                );
+            }
+            let length = expr
+                .rval()
+                .cast(&Type::Long(false))
+                .recover(&mut self.error_handler);
+            return Ok(ArrayType::Variable(Box::new(length)));
+        }
+        if !expr.constexpr {
+            self.error_handler
+                .push_back(location.error(SemanticError::NotConstant(expr.clone())));
+        }
+        let length = expr.const_int().unwrap_or_else(|err| {
+            self.error_handler.push_back(err);
+            1
+        });
+        if length == 0 {
+            self.semantic_err(LengthError::Zero, location);
+        }
+        Ok(ArrayType::Fixed(length))
+    }
     /*
      * function parameters
      * reference grammar:
@@ -856,12 +1020,21 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
         self.expect(Token::LeftParen)
             .expect("parameter_type_list should only be called with '(' as the next token");
         let mut params = vec![];
+        let mut array_params = vec![];
         if self.match_next(&Token::RightParen).is_some() {
             return Ok(DeclaratorType::Function(FunctionDeclarator {
                 params,
                 varargs: false,
+                identifier_list: false,
+                array_params,
             }));
         }
+        // `f(a, b)`: an old-style identifier list, as long as `a` isn't a typedef
+        if let Some(Token::Id(_)) = self.peek_token() {
+            if !self.next_is_declaration_specifier() {
+                return self.identifier_list();
+            }
+        }
         loop {
             if let Some(locatable) = self.match_next(&Token::Ellipsis) {
                 if params.is_empty() {
@@ -875,11 +1048,15 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                 return Ok(DeclaratorType::Function(FunctionDeclarator {
                     params,
                     varargs: true,
+                    identifier_list: false,
+                    array_params,
                 }));
             }
-            let (sc, quals, param_type, _) = self.declaration_specifiers()?;
+            // parameters are never warned about, so their attributes have no effect
+            let (sc, quals, _, param_type, _) = self.declaration_specifiers()?;
             // true: allow abstract_declarators
             let declarator = self.declarator(true, quals)?;
+            self.attributes(&mut Attributes::default())?;
             if let Some(storage_class) = sc {
                 self.semantic_err(
                     format!(
@@ -912,6 +1089,9 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                 // TODO: parse int f(int a[static 5])
                 if let Type::Array(to, _) = ctype {
                     ctype = Type::Pointer(to);
+                    if let Some(id) = &id {
+                        array_params.push(id.data);
+                    }
                 }
                 // I will probably regret this in the future
                 // default() for String is "",
@@ -938,6 +1118,7 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                         qualifiers: quals,
                         storage_class: StorageClass::Auto,
                         init: true,
+                        attributes: Default::default(),
                     },
                 });
             // int f(int, void);
@@ -956,6 +1137,7 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                         qualifiers: quals,
                         storage_class: StorageClass::Auto,
                         init: true,
+                        attributes: Default::default(),
                     },
                 });
             }
@@ -964,10 +1146,214 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                 return Ok(DeclaratorType::Function(FunctionDeclarator {
                     params,
                     varargs: false,
+                    identifier_list: false,
+                    array_params,
+                }));
+            }
+        }
+    }
+    /// Whether the next token can start `declaration_specifiers`.
+    fn next_is_declaration_specifier(&mut self) -> bool {
+        match self.peek_token() {
+            Some(Token::Keyword(k)) => k.is_decl_specifier(),
+            Some(Token::Id(id)) if is_attribute(*id) => true,
+            Some(Token::Id(id)) => {
+                let id = *id;
+                match self.scope.get(&id) {
+                    Some(symbol) => symbol.storage_class == StorageClass::Typedef,
+                    None => false,
+                }
+            }
+            _ => false,
+        }
+    }
+    /// Parse any number of GNU attribute specifiers, e.g. `__attribute__((unused, aligned(8)))`.
+    ///
+    /// `unused` and `deprecated` are recorded in `attributes`.
+    /// Any other attribute is ignored with a warning.
+    fn attributes(&mut self, attributes: &mut Attributes) -> SyntaxResult<()> {
+        while let Some(Token::Id(id)) = self.peek_token() {
+            if !is_attribute(*id) {
+                break;
+            }
+            let location = self.next_token().unwrap().location;
+            self.extension_used("'__attribute__' specifiers", location);
+            self.expect(Token::LeftParen)?;
+            self.expect(Token::LeftParen)?;
+            while self.match_next(&Token::RightParen).is_none() {
+                let Locatable { data, location } = match self.next_token() {
+                    Some(token) => token,
+                    None => {
+                        return Err(self.last_location.with(SyntaxError::EndOfFile("attribute")))
+                    }
+                };
+                let name = match data {
+                    Token::Id(name) => name.resolve_and_clone(),
+                    // e.g. `__attribute__((const))`
+                    Token::Keyword(keyword) => keyword.to_string(),
+                    Token::Comma => continue,
+                    other => {
+                        return Err(location.with(SyntaxError::from(format!(
+                            "expected attribute name, got '{}'",
+                            other
+                        ))))
+                    }
+                };
+                // `__unused__` is the same as `unused`
+                let trimmed = if name.len() > 4 && name.starts_with("__") && name.ends_with("__") {
+                    &name[2..name.len() - 2]
+                } else {
+                    &name[..]
+                };
+                match trimmed {
+                    "unused" => attributes.unused = true,
+                    // `deprecated` takes an optional message: `deprecated("use g instead")`
+                    "deprecated" => {
+                        let mut message = InternedStr::default();
+                        if let (Some(Token::LeftParen), Some(Token::Literal(Literal::Str(s, _)))) =
+                            (self.peek_token().cloned(), self.peek_next_token())
+                        {
+                            message = InternedStr::get_or_intern(
+                                s.resolve_and_clone().trim_end_matches('\0'),
+                            );
+                        }
+                        attributes.deprecated = Some(message);
+                    }
+                    _ => {
+                        let warning =
+                            Warning::UnknownAttribute(InternedStr::get_or_intern(trimmed));
+                        self.error_handler.warn(warning, location);
+                    }
+                }
+                // any other arguments, e.g. `aligned(8)`, are ignored
+                if self.match_next(&Token::LeftParen).is_some() {
+                    let mut depth = 1;
+                    while depth > 0 {
+                        match self.next_token().map(|token| token.data) {
+                            Some(Token::LeftParen) => depth += 1,
+                            Some(Token::RightParen) => depth -= 1,
+                            Some(_) => {}
+                            None => {
+                                return Err(self.last_location.with(SyntaxError::EndOfFile("')'")))
+                            }
+                        }
+                    }
+                }
+            }
+            self.expect(Token::RightParen)?;
+        }
+        Ok(())
+    }
+    /*
+     * identifier_list
+     *      : identifier
+     *      | identifier_list ',' identifier
+     *      ;
+     *
+     * The parameters default to `int` until `old_style_parameters` sees their declarations.
+     */
+    fn identifier_list(&mut self) -> SyntaxResult<DeclaratorType> {
+        let mut params: Vec<Locatable<Symbol>> = vec![];
+        loop {
+            let Locatable { data, location } = self.expect(Token::Id(Default::default()))?;
+            let id = match data {
+                Token::Id(id) => id,
+                _ => unreachable!("expect is broken"),
+            };
+            if params.iter().any(|p| p.data.id == id) {
+                self.semantic_err(
+                    format!("duplicate parameter name '{}' in function declaration", id),
+                    location,
+                );
+            }
+            params.push(Locatable {
+                location,
+                data: Symbol {
+                    id,
+                    ctype: Type::Int(true),
+                    qualifiers: Qualifiers::default(),
+                    storage_class: StorageClass::Auto,
+                    init: true,
+                    attributes: Default::default(),
+                },
+            });
+            if self.match_next(&Token::Comma).is_none() {
+                self.expect(Token::RightParen)?;
+                return Ok(DeclaratorType::Function(FunctionDeclarator {
+                    params,
+                    varargs: false,
+                    identifier_list: true,
+                    array_params: Vec::new(),
                 }));
             }
         }
     }
+    /*
+     * declaration_list
+     *      : declaration
+     *      | declaration_list declaration
+     *      ;
+     *
+     * The declarations between an identifier list and the function body, e.g. `int a, b;` in
+     * `int f(a, b) int a, b; { ... }`. Each must declare one of the parameters.
+     * Any parameters declared as arrays are added to `array_params`.
+     */
+    fn old_style_parameters(
+        &mut self,
+        params: &mut [Symbol],
+        array_params: &mut Vec<InternedStr>,
+    ) -> SyntaxResult<()> {
+        let mut declared = Vec::new();
+        while self.next_is_declaration_specifier() {
+            let (sc, quals, _, base_type, _) = self.declaration_specifiers()?;
+            loop {
+                let declarator = self
+                    .declarator(false, quals)?
+                    .expect("declarator should return id when called with allow_abstract: false");
+                let (id, mut ctype) = declarator
+                    .parse_type(base_type.clone(), false, &self.last_location)
+                    .recover(&mut self.error_handler);
+                let id =
+                    id.expect("declarator should return id when called with allow_abstract: false");
+                match sc {
+                    None | Some(StorageClass::Register) => {}
+                    Some(storage_class) => self.semantic_err(
+                        format!(
+                            "cannot specify storage class '{}' for parameter {}",
+                            storage_class, id.data
+                        ),
+                        id.location,
+                    ),
+                }
+                // int f(a) int a[]; is the same as int f(int *a)
+                if let Type::Array(to, _) = ctype {
+                    ctype = Type::Pointer(to);
+                    array_params.push(id.data);
+                }
+                if declared.contains(&id.data) {
+                    self.semantic_err(
+                        format!("redefinition of parameter '{}'", id.data),
+                        id.location,
+                    );
+                }
+                match params.iter_mut().find(|param| param.id == id.data) {
+                    Some(param) => {
+                        param.ctype = ctype;
+                        param.qualifiers = quals;
+                        declared.push(id.data);
+                    }
+                    None => self
+                        .error_handler
+                        .push_back(id.location.error(SemanticError::NotAParameter(id.data))),
+                }
+                if self.match_next(&Token::Comma).is_none() {
+                    self.expect(Token::Semicolon)?;
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
     /*
      * not in original reference, see comments to next function
      *
@@ -1007,14 +1393,10 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                             }
                         }
 
-                        let expr = self.constant_expr()?;
+                        let size = self.array_size()?;
                         self.expect(Token::RightBracket)?;
-                        let length = expr.const_int().unwrap_or_else(|err| {
-                            self.error_handler.push_back(err);
-                            1
-                        });
                         Some(Declarator {
-                            current: DeclaratorType::Array(ArrayType::Fixed(length)),
+                            current: DeclaratorType::Array(size),
                             next: prefix.map(Box::new),
                         })
                     }
@@ -1189,6 +1571,7 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                         &Token::Keyword(Keyword::Restrict),
                         &Token::Keyword(Keyword::Atomic),
                         &Token::Keyword(Keyword::ThreadLocal),
+                        &Token::Keyword(Keyword::GnuThreadLocal),
                     ]) {
                         if keyword == Keyword::Const {
                             if pointer_qualifiers.c_const {
@@ -1268,15 +1651,22 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
         // The only time (that I know of) that an expression will initialize a non-scalar
         // is for character literals.
         let is_char_array = match ctype {
-            Type::Array(inner, _) => inner.is_char(),
+            Type::Array(inner, _) => match &expr.expr {
+                // wide strings initialize arrays of `wchar_t`, `char16_t`, or `char32_t`
+                ExprType::Literal(Literal::Str(_, prefix)) => {
+                    inner.is_char() || **inner == prefix.element_type()
+                }
+                _ => inner.is_char(),
+            },
             _ => false,
         };
         // See section 6.7.9 of the C11 standard:
         // The initializer for a scalar shall be a single expression, optionally enclosed in braces.
         // The initial value of the object is that of the expression (after conversion)
         if !is_char_array {
+            expr = expr.rval();
+            self.check_implicit_conversion(&expr, ctype);
             expr = expr
-                .rval()
                 // if ctype is not a scalar, this will report an error, so we don't have to handle it specially
                 .cast(ctype)
                 .recover(&mut self.error_handler);
@@ -1292,29 +1682,62 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
         }
         Ok(Initializer::Scalar(Box::new(expr)))
     }
+    /// Objects with static storage duration are initialized before the program starts,
+    /// so their initializer can't take the address of an automatic variable.
+    ///
+    /// At file scope there are no automatic variables,
+    /// so this only matters for `static` variables at block scope.
+    fn check_static_initializer(&mut self, sc: StorageClass, init: &Initializer) {
+        fn automatic_address(expr: &Expr, scope: &Scope<InternedStr, Symbol>) -> bool {
+            match &expr.expr {
+                // an rvalue `Id` is the address of the variable, see `Expr::rval`
+                ExprType::Id(symbol) if !expr.lval && expr.ctype.is_pointer() => {
+                    let automatic = match symbol.storage_class {
+                        StorageClass::Auto | StorageClass::Register => true,
+                        _ => false,
+                    };
+                    // variables at file scope are also `Auto`
+                    automatic && scope.is_local(&symbol.id)
+                }
+                ExprType::Cast(inner) | ExprType::Noop(inner) => automatic_address(inner, scope),
+                ExprType::Add(left, right) | ExprType::Sub(left, right) => {
+                    automatic_address(left, scope) || automatic_address(right, scope)
+                }
+                _ => false,
+            }
+        }
+        if sc != StorageClass::Static {
+            return;
+        }
+        match init {
+            Initializer::Scalar(expr) if automatic_address(expr, &self.scope) => {
+                let err = SemanticError::NotConstant((**expr).clone());
+                self.error_handler.push_back(expr.location.error(err));
+            }
+            Initializer::InitializerList(inits) => {
+                for init in inits {
+                    self.check_static_initializer(sc, init);
+                }
+            }
+            Initializer::Scalar(_) | Initializer::FunctionBody(_) => {}
+        }
+    }
     fn function_body(
         &mut self,
         id: InternedStr,
         ftype: FunctionType,
+        array_params: Vec<InternedStr>,
         location: Location,
     ) -> SyntaxResult<Vec<Stmt>> {
         // if it's a function, set up state so we know the return type
         // TODO: rework all of this so semantic analysis is done _after_ parsing
         // TODO: that will remove a lot of clones and also make the logic much simpler
-        if self.current_function.is_some() {
-            // TODO: allow function _declarations_ at local scope
-            // e.g. int main() { int f(); return f(); }
-            self.semantic_err(
-                format!(
-                    "functions cannot be nested. hint: try declaring {} as `static` at file scope",
-                    id
-                ),
-                location,
-            );
-        }
+        // NOTE: nested function definitions are reported by `compound_statement`,
+        // since they may turn out to be the result of a missing '}'
         let existing = self.current_function.take();
         // add parameters to scope
         self.enter_scope();
+        let param_depth = self.scope.depth();
         let len = ftype.params.len();
         for (i, param) in ftype.params.into_iter().enumerate() {
             if param.id == Default::default() {
@@ -1336,6 +1759,8 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
             return_type: *ftype.return_type,
             location,
             id,
+            array_params,
+            param_depth,
         });
 
         // function body
@@ -1380,6 +1805,11 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
             qualifiers.volatile = true;
         } else if keyword == Keyword::Inline {
             qualifiers.inline = true;
+        } else if keyword == Keyword::ThreadLocal {
+            qualifiers.thread_local = true;
+        } else if keyword == Keyword::GnuThreadLocal {
+            self.extension_used("the '__thread' keyword", location);
+            qualifiers.thread_local = true;
         } else if keyword == Keyword::Signed || keyword == Keyword::Unsigned {
             if *ctype == Some(Type::Float) || *ctype == Some(Type::Double) {
                 self.semantic_err(
@@ -1489,8 +1919,8 @@ impl Keyword {
         match self {
             Unsigned | Signed | Bool | Char | Short | Int | Long | Float | Double | Void
             | Struct | Union | Enum | VaList | Complex | Imaginary | Extern | Static | Auto
-            | Register | Typedef | Const | Volatile | Restrict | Atomic | ThreadLocal | Inline
-            | NoReturn => true,
+            | Register | Typedef | Const | Volatile | Restrict | Atomic | ThreadLocal
+            | GnuThreadLocal | Inline | NoReturn => true,
             _ => false,
         }
     }
@@ -1515,6 +1945,34 @@ impl TryFrom<Keyword> for Type {
 }
 
 impl Declarator {
+    /// Whether this declares a function with an old-style identifier list, like `*f(a, b)`.
+    fn has_identifier_list(&self) -> bool {
+        match (&self.current, &self.next) {
+            // the return type can be a pointer
+            (DeclaratorType::Pointer(_), Some(next)) => next.has_identifier_list(),
+            (DeclaratorType::Function(func), Some(next)) => {
+                func.identifier_list
+                    && match next.current {
+                        DeclaratorType::Id(_, _) => true,
+                        _ => false,
+                    }
+            }
+            _ => false,
+        }
+    }
+    /// The parameters declared as arrays by the function being declared, like `a` in `*f(int a[])`.
+    ///
+    /// Array parameters in the prototypes of its parameters or return type aren't included.
+    fn array_params(&self) -> Vec<InternedStr> {
+        match (&self.current, &self.next) {
+            (DeclaratorType::Function(func), Some(next)) => match next.current {
+                DeclaratorType::Id(_, _) => func.array_params.clone(),
+                _ => next.array_params(),
+            },
+            (_, Some(next)) => next.array_params(),
+            (_, None) => Vec::new(),
+        }
+    }
     fn id(&self) -> Option<Locatable<InternedStr>> {
         match &self.current {
             DeclaratorType::Id(id, location) => Some(Locatable {
@@ -1651,7 +2109,11 @@ impl Type {
                             index
                         ))
                     },
-                    |symbol| Ok(symbol.ctype.clone()),
+                    |symbol| match &symbol.ctype {
+                        // bit-fields are initialized with a value of their declared type
+                        Type::Bitfield(inner, _) => Ok((**inner).clone()),
+                        ctype => Ok(ctype.clone()),
+                    },
                 )
             }
             Type::Union(struct_type) => {
@@ -1683,6 +2145,10 @@ enum DeclaratorType {
 struct FunctionDeclarator {
     params: Vec<Locatable<Symbol>>,
     varargs: bool,
+    // old-style (K&R) definition: `int f(a, b) int a, b; { ... }`
+    identifier_list: bool,
+    /// the parameters declared as arrays, which decayed to pointers
+    array_params: Vec<InternedStr>,
 }
 
 #[derive(Clone, Debug)]
@@ -1691,15 +2157,36 @@ struct Declarator {
     next: Option<Box<Declarator>>,
 }
 
+/// The name of the anonymous struct or union which is member `index` of its parent.
+///
+/// Anonymous members still need a unique name so that `ExprType::Member` can refer to them.
+/// The `<` guarantees this will never conflict with an identifier in the program.
+fn anonymous_member(index: usize) -> InternedStr {
+    InternedStr::get_or_intern(format!("<anonymous member {}>", index))
+}
+
+/// Whether `id` was created by `anonymous_member`.
+pub(super) fn is_anonymous_member(id: InternedStr) -> bool {
+    use crate::get_str;
+    get_str!(id).starts_with("<anonymous member ")
+}
+
+/// Whether `id` starts a GNU attribute specifier.
+pub(super) fn is_attribute(id: InternedStr) -> bool {
+    id == InternedStr::get_or_intern("__attribute__")
+}
+
 #[cfg(test)]
 mod tests {
     use crate::data::{
         prelude::*,
         types::{ArrayType, FunctionType},
-        Declaration, Initializer, Qualifiers, Symbol,
+        Declaration, Initializer, Qualifiers, StorageClass, Symbol,
     };
     use crate::intern::InternedStr;
-    use crate::parse::tests::{match_all, match_data, parse, parse_all, ParseType};
+    use crate::parse::tests::{
+        match_all, match_data, parse, parse_all, parser, parser_with_std, ParseType,
+    };
     use std::boxed::Box;
     use Type::*;
 
@@ -1744,12 +2231,11 @@ mod tests {
         assert!(parse("short double i;").unwrap().is_err());
         assert!(parse("int void i;").unwrap().is_err());
         assert!(parse("void int i;").unwrap().is_err());
-        // default to int if we don't have a type
         // don't panic if we see duplicate specifiers
         assert!(match_type(parse("unsigned unsigned i;"), Type::Int(false)));
-        assert!(match_type(parse("extern extern i;"), Type::Int(true)));
-        assert!(match_type(parse("const const i;"), Type::Int(true)));
-        assert!(match_type(parse("const volatile i;"), Type::Int(true)));
+        assert!(match_type(parse("extern extern int i;"), Type::Int(true)));
+        assert!(match_type(parse("const const int i;"), Type::Int(true)));
+        assert!(match_type(parse("const volatile int i;"), Type::Int(true)));
     }
     #[test]
     fn test_arrays() {
@@ -1771,6 +2257,30 @@ mod tests {
                 ArrayType::Unbounded
             )
         ));
+        assert!(match_type(
+            parse("int a[2 * 3];"),
+            Array(Box::new(Int(true)), ArrayType::Fixed(6))
+        ));
+        assert!(match_type(
+            parse("int a[1 > 2 ? 4 : 8];"),
+            Array(Box::new(Int(true)), ArrayType::Fixed(8))
+        ));
+        assert!(match_type(
+            parse("int a[sizeof(int) == 4 ? 4 : 8];"),
+            Array(Box::new(Int(true)), ArrayType::Fixed(4))
+        ));
+    }
+    #[test]
+    fn test_array_size_errors() {
+        assert!(parse("int a[0];").unwrap().is_err());
+        assert!(parse("int a[-1];").unwrap().is_err());
+        assert!(parse("int a[1.5];").unwrap().is_err());
+        assert!(parse("int a[1 ? 0 : 1];").unwrap().is_err());
+        let overflow = parse("int a[0x7fffffffffffffffL * 2];").unwrap();
+        assert_eq!(
+            overflow.unwrap_err().data,
+            SemanticError::ConstOverflow { is_positive: true }.into()
+        );
     }
     #[test]
     fn test_pointers() {
@@ -1843,6 +2353,7 @@ mod tests {
                         qualifiers: Default::default(),
                         init: true,
                         storage_class: Default::default()
+                        attributes: Default::default(),
                     },
                     Symbol {
                         id: Default::default(),
@@ -1850,6 +2361,7 @@ mod tests {
                         qualifiers: Default::default(),
                         init: true,
                         storage_class: Default::default()
+                        attributes: Default::default(),
                     },
                     Symbol {
                         id: Default::default(),
@@ -1857,6 +2369,7 @@ mod tests {
                         qualifiers: Default::default(),
                         init: true,
                         storage_class: Default::default()
+                        attributes: Default::default(),
                     }
                 ],
                 varargs: false,
@@ -1877,6 +2390,7 @@ mod tests {
                     qualifiers: Default::default(),
                     storage_class: Default::default(),
                     init: true,
+                    attributes: Default::default(),
                 }],
                 varargs: false,
             }),),)
@@ -1891,6 +2405,7 @@ mod tests {
                     qualifiers: Default::default(),
                     init: true,
                     storage_class: Default::default()
+                    attributes: Default::default(),
                 }],
                 varargs: true,
             })
@@ -1908,6 +2423,7 @@ mod tests {
                     qualifiers: Default::default(),
                     storage_class: Default::default(),
                     init: true,
+                    attributes: Default::default(),
                 }],
                 varargs: false
             })
@@ -1916,6 +2432,137 @@ mod tests {
         assert!(parse("int b[static 10];").unwrap().is_err());
     }
     #[test]
+    fn old_style_functions() {
+        let param = |name, ctype| Symbol {
+            id: InternedStr::get_or_intern(name),
+            ctype,
+            qualifiers: Default::default(),
+            storage_class: Default::default(),
+            init: true,
+            attributes: Default::default(),
+        };
+        assert!(match_type(
+            parse("int f(a, b, c) char *b; double c[]; { return a; }"),
+            Function(FunctionType {
+                return_type: Box::new(Int(true)),
+                params: vec![
+                    param("a", Int(true)),
+                    param("b", Pointer(Box::new(Char(true)))),
+                    param("c", Pointer(Box::new(Double))),
+                ],
+                varargs: false
+            })
+        ));
+        let errors = |program| {
+            let (_, errs) = parser(program).collect_results();
+            errs.into_iter().map(|err| err.data).collect::<Vec<_>>()
+        };
+        assert_eq!(errors("typedef int a; int f(a) { return 1; }").len(), 1);
+        match errors("int f(a) int b; { return a; }").as_slice() {
+            [Error::Semantic(SemanticError::NotAParameter(name))] => {
+                assert_eq!(*name, InternedStr::get_or_intern("b"))
+            }
+            other => panic!("expected undeclared parameter, got {:?}", other),
+        }
+    }
+    #[test]
+    fn thread_local() {
+        for program in &["_Thread_local int x;", "static __thread int x;"] {
+            let decl = parse(program).unwrap().unwrap();
+            assert!(decl.data.symbol.qualifiers.thread_local, "{}", program);
+        }
+        assert!(parse("_Thread_local int f();").unwrap().is_err());
+        let (_, errs) = parser("int main(void) { _Thread_local int x; }").collect_results();
+        assert_eq!(errs.len(), 1);
+        let (_, errs) = parser("int main(void) { static _Thread_local int x; }").collect_results();
+        assert!(errs.is_empty(), "{:?}", errs);
+    }
+    #[test]
+    fn unused_attribute() {
+        use crate::data::error::Warning;
+        let unused = |body: &str| {
+            let program = format!("int f(void) {{ {} return 0; }}", body);
+            let mut p = parser(&program).warn_unused_variables(true);
+            let (_, errs) = p.collect_results();
+            assert!(errs.is_empty(), "{}: {:?}", program, errs);
+            p.warnings()
+                .into_iter()
+                .map(|warning| warning.data)
+                .collect::<Vec<_>>()
+        };
+        let x = || vec![Warning::UnusedVariable(InternedStr::get_or_intern("x"))];
+        assert_eq!(unused("int x;"), x());
+        assert_eq!(unused("int x = 1;"), x());
+        assert_eq!(unused("int x; { int x = 1; x; }"), x());
+        assert_eq!(unused("int x = 1; return x;"), vec![]);
+        assert_eq!(unused("int x __attribute__((unused));"), vec![]);
+        assert_eq!(unused("__attribute__((unused)) int x;"), vec![]);
+        assert_eq!(unused("int __attribute__((__unused__)) x = 1;"), vec![]);
+        assert_eq!(unused("int y __attribute__((unused)), x;"), x());
+        assert_eq!(unused("extern int x; int g(void);"), vec![]);
+        // unknown attributes are ignored
+        let warnings = unused("int x __attribute__((unused, aligned(8)));");
+        assert_eq!(
+            warnings,
+            vec![Warning::UnknownAttribute(InternedStr::get_or_intern(
+                "aligned"
+            ))]
+        );
+        // parameters can have attributes too
+        let (_, errs) =
+            parser("int f(int x __attribute__((unused)), __attribute__((unused)) int y);")
+                .collect_results();
+        assert!(errs.is_empty(), "{:?}", errs);
+    }
+    #[test]
+    fn deprecated_attribute() {
+        use crate::data::error::Warning;
+        let deprecated = |program: &str| {
+            let mut p = parser(program);
+            let (_, errs) = p.collect_results();
+            assert!(errs.is_empty(), "{}: {:?}", program, errs);
+            p.warnings()
+                .into_iter()
+                .map(|warning| (warning.data, warning.location.span))
+                .collect::<Vec<_>>()
+        };
+        let f = InternedStr::get_or_intern("f");
+        let program = "int f(void) __attribute__((deprecated)); int g(void); int h(void) { return g() + f(); }";
+        let call = program.rfind("f()").unwrap() as u32;
+        let warnings = deprecated(program);
+        assert_eq!(warnings.len(), 1);
+        match &warnings[0] {
+            (
+                Warning::Deprecated {
+                    name,
+                    message,
+                    declared,
+                },
+                span,
+            ) => {
+                assert_eq!(*name, f);
+                assert_eq!(*message, InternedStr::default());
+                // the warning points to the use, and remembers the declaration
+                assert_eq!(*span, (call..call + 1).into());
+                assert_eq!(declared.span.start().to_usize(), program.find("f").unwrap());
+            }
+            other => panic!("expected a deprecation warning, got {:?}", other),
+        }
+        let warnings =
+            deprecated("__attribute__((deprecated(\"use g\"))) int f; int h(void) { return f; }");
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].0.to_string(), "'f' is deprecated: use g");
+        // the attribute is kept when the function is redeclared or defined
+        let warnings = deprecated(
+            "int f(void) __attribute__((deprecated)); int f(void) { return 1; } int h(void) { return f(); }",
+        );
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            deprecated("int f(void); int h(void) { return f(); }"),
+            vec![]
+        );
+    }
+    #[test]
     fn test_inline_keyword() {
         // Correct usage
         assert!(match_type(
@@ -1952,6 +2599,7 @@ mod tests {
                         id: Default::default(),
                         qualifiers: Qualifiers::NONE,
                         init: true,
+                        attributes: Default::default(),
                     }],
                     varargs: false,
                 })),)),
@@ -1972,6 +2620,7 @@ mod tests {
                     id: Default::default(),
                     qualifiers: Default::default(),
                     init: true,
+                    attributes: Default::default(),
                 }],
                 varargs: false,
             })),)
@@ -2062,6 +2711,65 @@ mod tests {
         assert!(parse("int f(int a, int a);").unwrap().is_err());
     }
     #[test]
+    fn conflicting_types() {
+        let program = "int f(void);\nlong f(void);";
+        let (_, errs) = parser(program).collect_results();
+        match errs.as_slice() {
+            [err] => match &err.data {
+                Error::Semantic(SemanticError::ConflictingTypes { name, previous }) => {
+                    assert_eq!(*name, InternedStr::get_or_intern("f"));
+                    assert_eq!(previous.span.start().to_usize(), program.find("f").unwrap());
+                    assert!(err.location.span.start().to_usize() > program.find('\n').unwrap());
+                }
+                other => panic!("expected conflicting types, got {:?}", other),
+            },
+            errs => panic!("expected one error, got {:?}", errs),
+        }
+        let (_, errs) = parser("int main(void) { int i; char i; }").collect_results();
+        assert_eq!(errs.len(), 1, "{:?}", errs);
+        assert!(match &errs[0].data {
+            Error::Semantic(SemanticError::ConflictingTypes { .. }) => true,
+            _ => false,
+        });
+        // compatible redeclarations are fine
+        for program in &[
+            "int f(void); int f(void);",
+            "int f(void); int f(void) { return 1; }",
+            "extern int i; int i;",
+            "int main(void) { int f(void); { long f; } return 0; }",
+        ] {
+            let (_, errs) = parser(program).collect_results();
+            assert!(errs.is_empty(), "{}: {:?}", program, errs);
+        }
+    }
+    #[test]
+    fn static_initializer_address() {
+        let errors = |program: &str| parser(program).collect_results().1;
+        for init in &["&x", "a", "&a[1]", "a + 1", "(int *)&a"] {
+            let program = format!(
+                "int main(void) {{ int x; int a[2]; static int *p = {}; }}",
+                init
+            );
+            let errs = errors(&program);
+            assert_eq!(errs.len(), 1, "{}: {:?}", program, errs);
+            assert!(match &errs[0].data {
+                Error::Semantic(SemanticError::NotConstant(_)) => true,
+                _ => false,
+            });
+        }
+        let program = "int main(void) { int x; static int *p[] = { 0, &x }; }";
+        assert_eq!(errors(program).len(), 1);
+        // the address of a static object is a constant
+        for program in &[
+            "int g; int *p = &g;",
+            "int main(void) { static int x; static int *p = &x; }",
+            "int g; int main(void) { static int *p = &g; }",
+            "int main(void) { int x; int *p = &x; }",
+        ] {
+            assert!(errors(program).is_empty(), "{}", program);
+        }
+    }
+    #[test]
     fn test_initializers() {
         // scalars
         assert!(parse("int i = 3;").unwrap().is_ok());
@@ -2128,6 +2836,98 @@ mod tests {
         assert!(parse("struct { int a:5; } b;").unwrap().is_ok());
         assert!(parse("struct { int a:5, b:6; } c;").unwrap().is_ok());
         assert!(parse("struct { extern int a:5; } d;").unwrap().is_err());
+        assert!(parse("struct { char a:9; } e;").unwrap().is_err());
+        assert!(parse("struct { int a:-1; } f;").unwrap().is_err());
+        assert!(parse("struct { float a:1; } g;").unwrap().is_err());
+        assert!(parse("struct { _Bool a:1, b:1; } i;").unwrap().is_ok());
+        assert!(parse("struct { _Bool a:2; } j;").unwrap().is_err());
+        assert!(match_type(
+            parse("struct { unsigned a:3, b:5; } h;"),
+            Type::Struct(StructType::Anonymous(std::rc::Rc::new(vec![
+                Symbol {
+                    id: InternedStr::get_or_intern("a"),
+                    ctype: Type::Bitfield(Box::new(Type::Int(false)), 3),
+                    qualifiers: Qualifiers::NONE,
+                    storage_class: StorageClass::Auto,
+                    init: false,
+                    attributes: Default::default(),
+                },
+                Symbol {
+                    id: InternedStr::get_or_intern("b"),
+                    ctype: Type::Bitfield(Box::new(Type::Int(false)), 5),
+                    qualifiers: Qualifiers::NONE,
+                    storage_class: StorageClass::Auto,
+                    init: false,
+                    attributes: Default::default(),
+                },
+            ])))
+        ));
+    }
+    #[test]
+    fn c89_types() {
+        let errors = |input, std| parser_with_std(input, std).collect_results().1.len();
+        assert_eq!(errors("long long i;", Std::C89), 1);
+        assert_eq!(errors("_Bool b;", Std::C89), 1);
+        assert_eq!(errors("long i;", Std::C89), 0);
+        assert_eq!(errors("long long i;", Std::C99), 0);
+        assert_eq!(errors("_Bool b;", Std::C99), 0);
+    }
+    #[test]
+    fn array_parameters_decay() {
+        let param = |program: &str| match parse(program) {
+            Some(Ok(decl)) => match decl.data.symbol.ctype {
+                Type::Function(ftype) => ftype.params[0].clone(),
+                other => panic!("expected a function, got {}", other),
+            },
+            other => panic!("expected one declaration, got {:?}", other),
+        };
+        let int_ptr = Type::Pointer(Box::new(Type::Int(true)));
+        assert_eq!(param("void f(int a[10]);").ctype, int_ptr);
+        assert_eq!(param("void f(int a[]);").ctype, int_ptr);
+        let a = param("void f(const int a[]);");
+        assert_eq!(a.ctype, int_ptr);
+        // the same representation as `const int *a`
+        assert_eq!(a, param("void f(const int *a);"));
+        assert_eq!(
+            param("void f(int a[][3]);").ctype,
+            Type::Pointer(Box::new(Array(Box::new(Int(true)), ArrayType::Fixed(3))))
+        );
+    }
+    #[test]
+    fn implicit_int() {
+        use crate::data::error::Warning;
+
+        let results = |input, std| {
+            let mut p = parser_with_std(input, std);
+            let (decls, errs) = p.collect_results();
+            let warnings: Vec<_> = p.warnings().into_iter().map(|w| w.data).collect();
+            let errs: Vec<_> = errs.into_iter().map(|err| err.data).collect();
+            (decls, errs, warnings)
+        };
+        let (decls, errs, warnings) = results("x = 1;", Std::C89);
+        assert!(errs.is_empty(), "{:?}", errs);
+        assert_eq!(warnings, vec![Warning::ImplicitInt]);
+        assert_eq!(decls[0].data.symbol.ctype, Type::Int(true));
+        let (_, errs, warnings) = results("static y;", Std::C89);
+        assert!(errs.is_empty(), "{:?}", errs);
+        assert_eq!(warnings, vec![Warning::ImplicitInt]);
+
+        // an error since C99, but still declared as `int`
+        let (decls, errs, warnings) = results("x = 1;", Std::C99);
+        assert_eq!(
+            errs,
+            vec![SemanticError::MissingTypeSpecifier(Std::C99).into()]
+        );
+        assert!(warnings.is_empty());
+        assert_eq!(decls[0].data.symbol.ctype, Type::Int(true));
+        let (_, errs, _) = results("const z;", Std::C11);
+        assert_eq!(
+            errs,
+            vec![SemanticError::MissingTypeSpecifier(Std::C11).into()]
+        );
+        // `unsigned` on its own is a type specifier
+        let (_, errs, warnings) = results("unsigned u;", Std::C99);
+        assert!(errs.is_empty() && warnings.is_empty());
     }
     #[test]
     fn lol() {
@@ -2,8 +2,10 @@ use super::{Lexeme, Parser, SyntaxResult};
 use crate::arch::SIZE_T;
 use crate::data::prelude::*;
 use crate::data::{
-    lex::{AssignmentToken, ComparisonToken, Keyword},
+    error::Warning,
+    lex::{AssignmentToken, ComparisonToken, Keyword, StrPrefix},
     types::ArrayType,
+    Qualifiers,
     StorageClass::Typedef,
 };
 
@@ -114,6 +116,7 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
             Ok(lval)
         } else {
             if rval.ctype != lval.ctype {
+                self.check_implicit_conversion(&rval, &lval.ctype);
                 rval = rval.cast(&lval.ctype).recover(&mut self.error_handler);
             }
             Ok(Expr {
@@ -129,6 +132,7 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
     /// conditional_expr
     /// : logical_or_expr
     /// | logical_or_expr '?' expr ':' conditional_expr
+    /// | logical_or_expr '?' ':' conditional_expr
     /// ;
     ///
     /// Ternary operator. If logical_or_expr evaluates to true,
@@ -137,17 +141,36 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
     ///
     /// Note that comma operators are allowed within ternaries (!!).
     ///
+    /// As a GNU extension, `expr` may be omitted: `x ?: y` is the same as `x ? x : y`,
+    /// except that `x` is only evaluated once.
+    ///
     /// The C standard requires that `expr` and `conditional_expr` have compatible types;
     /// see https://stackoverflow.com/questions/13318336/ or section 6.5.15 of
     /// http://www.open-std.org/jtc1/sc22/wg14/www/docs/n1570.pdf for formal requirements.
     /// It does not specify what happens if this is not the case.
     /// Clang and GCC give a warning; we are more strict and emit an error.
-    fn conditional_expr(&mut self) -> SyntaxResult {
+    pub(super) fn conditional_expr(&mut self) -> SyntaxResult {
         let condition = self.logical_or_expr()?;
         if let Some(Locatable { location, .. }) = self.match_next(&Token::Question) {
-            let condition = condition.truthy().recover(&mut self.error_handler);
-            let mut then = self.expr()?.rval();
-            self.expect(Token::Colon)?;
+            let (condition, mut then) = if self.match_next(&Token::Colon).is_some() {
+                self.extension_used("omitting the middle operand of '?:'", location);
+                let then = condition.rval();
+                if !then.ctype.is_scalar() {
+                    self.semantic_err(
+                        format!(
+                            "expression of type '{}' cannot be converted to bool",
+                            then.ctype
+                        ),
+                        then.location,
+                    );
+                }
+                (None, then)
+            } else {
+                let condition = condition.truthy().recover(&mut self.error_handler);
+                let then = self.expr()?.rval();
+                self.expect(Token::Colon)?;
+                (Some(condition), then)
+            };
             let mut otherwise = self.conditional_expr()?.rval();
             if then.ctype.is_arithmetic() && otherwise.ctype.is_arithmetic() {
                 let (tmp1, tmp2) =
@@ -163,14 +186,25 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                     location,
                 );
             }
-            Ok(Expr {
-                ctype: then.ctype.clone(),
+            let ctype = then.ctype.clone();
+            let (constexpr, expr) = match condition {
                 // TODO: evaluate condition and only require the corresponding
                 // expression to be constexpr
-                constexpr: condition.constexpr && then.constexpr && otherwise.constexpr,
+                Some(condition) => (
+                    condition.constexpr && then.constexpr && otherwise.constexpr,
+                    ExprType::Ternary(Box::new(condition), Box::new(then), Box::new(otherwise)),
+                ),
+                None => (
+                    then.constexpr && otherwise.constexpr,
+                    ExprType::OmittedTernary(Box::new(then), Box::new(otherwise)),
+                ),
+            };
+            Ok(Expr {
+                ctype,
+                constexpr,
                 lval: false,
                 location,
-                expr: ExprType::Ternary(Box::new(condition), Box::new(then), Box::new(otherwise)),
+                expr,
             })
         } else {
             Ok(condition)
@@ -339,6 +373,9 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
             Self::multiplicative_expr,
             &[&Token::Plus, &Token::Minus],
             |mut left, mut right, token| {
+                if let Some(err) = Expr::void_operand(&left, &right) {
+                    return Err((err, *left));
+                }
                 match (&left.ctype, &right.ctype) {
                     (Type::Pointer(to), i)
                     | (Type::Array(to, _), i) if i.is_integral() && to.is_complete() => {
@@ -353,6 +390,12 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                         let (left, right) = (left.rval(), right.rval());
                         return Expr::pointer_arithmetic(right, left, &*to, token.location);
                     }
+                    (Type::Pointer(_), Type::Pointer(_))
+                    | (Type::Pointer(_), Type::Array(_, _))
+                    | (Type::Array(_, _), Type::Pointer(_))
+                    | (Type::Array(_, _), Type::Array(_, _)) if token.data == Token::Minus => {
+                        return Expr::pointer_difference(left.rval(), right.rval(), token.location);
+                    }
                     _ => {}
                 };
                 let (ctype, lval) = if left.ctype.is_arithmetic() && right.ctype.is_arithmetic() {
@@ -361,9 +404,6 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                     right = Box::new(tmp.1);
                     (left.ctype.clone(), false)
                 // `p1 + p2` for pointers p1 and p2 is not valid
-                } else if token.data == Token::Minus && left.ctype.is_pointer_to_complete_object() && left.ctype == right.ctype {
-                    // not sure what type to use here, C11 standard doesn't mention it
-                    (left.ctype.clone(), true)
                 } else {
                     return Err((token.location.with(
                         SemanticError::from(format!(
@@ -389,6 +429,98 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
         )
     }
 
+    /// Warn if a pointer cast removes `const` or `volatile` from the pointed-to type.
+    ///
+    /// `qualifiers` are the qualifiers on the type being cast to.
+    fn check_discarded_qualifiers(
+        &mut self,
+        expr: &Expr,
+        ctype: &Type,
+        qualifiers: Qualifiers,
+        location: Location,
+    ) {
+        let pointee = match &expr.ctype {
+            Type::Pointer(pointee) | Type::Array(pointee, _) => pointee,
+            _ => return,
+        };
+        let original = pointee_qualifiers(expr, pointee);
+        let discards_const = original.c_const && !qualifiers.c_const;
+        let discards_volatile = original.volatile && !qualifiers.volatile;
+        if ctype.is_pointer() && (discards_const || discards_volatile) {
+            let warning =
+                Warning::CastDiscardsQualifiers(original, (**pointee).clone(), ctype.clone());
+            self.error_handler.warn(warning, location);
+        }
+    }
+
+    /// Warn if converting `expr` to `ctype` without a cast could lose precision:
+    /// either a floating value converted to an integer,
+    /// or a `double` converted to a `float`.
+    ///
+    /// Constants which can be represented exactly are not warned about.
+    ///
+    /// Also warn if the result of a left shift is widened,
+    /// since the shift has already discarded any bits past the narrower type.
+    pub(super) fn check_implicit_conversion(&mut self, expr: &Expr, ctype: &Type) {
+        self.check_narrow_shift(expr, ctype);
+        let literal = match expr.expr {
+            ExprType::Literal(Literal::Float(f)) => Some(f),
+            _ => None,
+        };
+        let loses_precision = if expr.ctype.is_floating() && ctype.is_integral() && !ctype.is_bool()
+        {
+            literal.map_or(true, |f| f.fract() != 0.0)
+        } else if expr.ctype == Type::Double && *ctype == Type::Float {
+            literal.map_or(true, |f| f64::from(f as f32) != f)
+        } else {
+            false
+        };
+        if loses_precision {
+            let warning = Warning::ImplicitConversion(expr.ctype.clone(), ctype.clone());
+            self.error_handler.warn(warning, expr.location);
+        }
+    }
+
+    /// Warn if `expr`, the operand of `sizeof`, is a parameter declared as an array,
+    /// since it has already decayed to a pointer.
+    fn check_sizeof_array_parameter(&mut self, expr: &Expr) {
+        let name = match &expr.expr {
+            ExprType::Id(symbol) if expr.ctype.is_pointer() => symbol.id,
+            _ => return,
+        };
+        // a local variable with the same name shadows the parameter
+        let is_array_param = self.current_function.as_ref().map_or(false, |func| {
+            func.array_params.contains(&name)
+                && self.scope.depth_of(&name) == Some(func.param_depth)
+        });
+        if is_array_param {
+            let warning = Warning::SizeofArrayParameter(name, expr.ctype.clone());
+            self.error_handler.warn(warning, expr.location);
+        }
+    }
+
+    /// Warn if `expr` is a left shift which is converted to a wider integer `ctype`.
+    ///
+    /// Constant shifts are only warned about if folding them overflows.
+    fn check_narrow_shift(&mut self, expr: &Expr, ctype: &Type) {
+        match expr.expr {
+            ExprType::Shift(_, _, true) => {}
+            _ => return,
+        }
+        if !(expr.ctype.is_integral() && ctype.is_integral()) {
+            return;
+        }
+        match (expr.ctype.sizeof(), ctype.sizeof()) {
+            (Ok(from), Ok(to)) if from < to => {}
+            _ => return,
+        }
+        if expr.constexpr && expr.clone().const_fold().is_ok() {
+            return;
+        }
+        let warning = Warning::NarrowShift(expr.ctype.clone(), ctype.clone());
+        self.error_handler.warn(warning, expr.location);
+    }
+
     /// multiplicative_expr
     /// : cast_expr
     /// | multiplicative_expr '*' cast_expr
@@ -400,6 +532,9 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
             Self::cast_expr,
             &[&Token::Star, &Token::Divide, &Token::Mod],
             |left, right, token| {
+                if let Some(err) = Expr::void_operand(&left, &right) {
+                    return Err((err, *left));
+                }
                 if token.data == Token::Mod
                     && !(left.ctype.is_integral() && right.ctype.is_integral())
                 {
@@ -457,10 +592,12 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
             self.next_token();
             let Locatable {
                 location,
-                data: (ctype, _),
+                data: (ctype, qualifiers),
             } = self.type_name()?;
             self.expect(Token::RightParen)?;
-            let expr = self.cast_expr()?.rval();
+            let expr = self.cast_expr()?;
+            self.check_discarded_qualifiers(&expr, &ctype, qualifiers, location);
+            let expr = expr.rval();
             if ctype == Type::Void {
                 // casting anything to void is allowed
                 return Ok(Expr {
@@ -489,7 +626,8 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                 // not implemented: galaga (https://github.com/jyn514/rcc/issues/98)
                 self.semantic_err("cannot cast a struct to any type", location);
             } else if expr.ctype == Type::Void {
-                self.semantic_err("cannot cast void to any type", location);
+                self.error_handler
+                    .push_back(expr.location.error(SemanticError::VoidValueUsed));
             }
             Ok(Expr {
                 lval: false,
@@ -541,11 +679,13 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                                 (ty.location, ty.data.0)
                             } else {
                                 let expr = self.expr()?;
+                                self.check_sizeof_array_parameter(&expr);
                                 (expr.location, expr.ctype)
                             }
                         }
                         _ => {
                             let expr = self.expr()?;
+                            self.check_sizeof_array_parameter(&expr);
                             (expr.location, expr.ctype)
                         }
                     };
@@ -553,8 +693,18 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                     ret
                 } else {
                     let result = self.unary_expr()?;
+                    self.check_sizeof_array_parameter(&result);
                     (result.location, result.ctype)
                 };
+                let constexpr = !ctype.is_vla();
+                let expr = if ctype.is_complete() {
+                    ExprType::Sizeof(ctype)
+                } else {
+                    let err = SemanticError::SizeofIncompleteType(ctype);
+                    self.error_handler.push_back(location.error(err));
+                    // same as GCC, which allows `sizeof(void)` as an extension
+                    ExprType::Literal(Literal::UnsignedInt(1))
+                };
                 Ok(Expr {
                     // the C11 standard states (6.5.3.4)
                     // "If the type of the operand is a variable length array type, the operand is evaluated; otherwise, the operand is not evaluated and the result is an integer constant."
@@ -564,9 +714,9 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                     //   char b[n+3];
                     //   return sizeof b;
                     // }
-                    // We do not currently handle this case.
-                    constexpr: true,
-                    expr: ExprType::Sizeof(ctype),
+                    // The length of a VLA is computed at runtime, but the operand is not evaluated.
+                    constexpr,
+                    expr,
                     lval: false,
                     location,
                     ctype: Type::Int(false),
@@ -577,16 +727,22 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                 let Locatable { location, data: op } = self.next_token().unwrap();
                 let expr = self.cast_expr()?;
                 match op {
-                    // TODO: semantic checking for expr
                     Token::Ampersand => match expr.expr {
                         // parse &*p as p
                         ExprType::Deref(inner) => Ok(*inner),
+                        ExprType::Member(ref cstruct, id)
+                            if cstruct.ctype.bitfield_offset(id).is_some() =>
+                        {
+                            self.error_handler.push_back(
+                                location.error(SemanticError::InvalidAddressOf("bit-field")),
+                            );
+                            Ok(expr)
+                        }
                         ExprType::Id(ref sym) if sym.storage_class == StorageClass::Register => {
-                            self.error_handler.push_back(location.error(
-                                SemanticError::InvalidAddressOf(
-                                    "variable declared with `register`",
-                                ),
-                            ));
+                            self.error_handler.push_back(
+                                location
+                                    .error(SemanticError::InvalidAddressOf("register variable")),
+                            );
                             Ok(expr)
                         }
                         _ if expr.lval => Ok(Expr {
@@ -597,8 +753,13 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                             expr: expr.expr,
                         }),
                         _ => {
+                            let category = match (&expr.expr, &expr.ctype) {
+                                (ExprType::Literal(_), Type::Enum(_, _)) => "enumeration constant",
+                                (ExprType::Literal(_), _) => "literal",
+                                _ => "rvalue",
+                            };
                             self.error_handler.push_back(
-                                location.error(SemanticError::InvalidAddressOf("value")),
+                                location.error(SemanticError::InvalidAddressOf(category)),
                             );
                             Ok(expr)
                         }
@@ -714,6 +875,12 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                             continue;
                         }
                     };
+                    if !index.ctype.is_integral() {
+                        let err = SemanticError::ArraySubscriptNotInteger(index.ctype.clone());
+                        self.error_handler.push_back(location.error(err));
+                        expr = array;
+                        continue;
+                    }
                     let mut addr = Expr::pointer_arithmetic(array, index, &target_type, location)
                         .recover(&mut self.error_handler);
                     addr.ctype = target_type;
@@ -741,10 +908,8 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                         Type::Function(ref functype) => functype,
                         Type::Error => continue, // we've already reported this error
                         _ => {
-                            self.semantic_err(
-                                format!("called object of type '{}' is not a function", expr.ctype),
-                                location,
-                            );
+                            let err = SemanticError::NotAFunction(expr.ctype.clone());
+                            self.error_handler.push_back(location.error(err));
                             continue;
                         }
                     };
@@ -757,8 +922,10 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                     {
                         self.semantic_err(
                             format!(
-                                "too {} arguments to function call: expected {}, have {}",
+                                "too {} arguments to function call: expected {}{}, have {}",
                                 if args.len() > expected { "many" } else { "few" },
+                                // variadic functions can take more arguments than they declare
+                                if functype.varargs { "at least " } else { "" },
                                 expected,
                                 args.len(),
                             ),
@@ -768,12 +935,21 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                     let mut promoted_args = vec![];
                     for (i, arg) in args.into_iter().enumerate() {
                         let maybe_err = match functype.params.get(i) {
-                            Some(expected) => arg.rval().cast(&expected.ctype),
+                            Some(expected) => {
+                                let arg = arg.rval();
+                                self.check_implicit_conversion(&arg, &expected.ctype);
+                                arg.cast(&expected.ctype)
+                            }
                             None => arg.default_promote(),
                         };
                         let promoted = maybe_err.recover(&mut self.error_handler);
                         promoted_args.push(promoted);
                     }
+                    if let ExprType::Id(symbol) = &expr.expr {
+                        if functype.varargs {
+                            self.check_format(symbol.id, &promoted_args, location);
+                        }
+                    }
                     Expr {
                         location,
                         constexpr: false,
@@ -852,19 +1028,107 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
         Ok(args)
     }
 
+    /// GNU extension: `__builtin_expect(expr, c)` has the value of `expr` (converted to `long`),
+    /// and tells the backend that `expr` will probably be equal to the integer constant `c`.
+    fn builtin_expect(&mut self, location: Location) -> SyntaxResult {
+        self.extension_used("calls to '__builtin_expect'", location);
+        self.expect(Token::LeftParen)?;
+        let expr = self.assignment_expr()?;
+        self.expect(Token::Comma)?;
+        let expected = self.constant_expr()?;
+        self.expect(Token::RightParen)?;
+        let expected = match expected.expr {
+            ExprType::Literal(Literal::Int(i)) => i,
+            ExprType::Literal(Literal::UnsignedInt(u)) => u as i64,
+            ExprType::Literal(Literal::Char(c)) => i64::from(c),
+            _ => {
+                self.semantic_err(
+                    "second argument to '__builtin_expect' must be an integer constant",
+                    expected.location,
+                );
+                0
+            }
+        };
+        let expr = expr
+            .rval()
+            .cast(&Type::Long(true))
+            .recover(&mut self.error_handler);
+        Ok(Expr {
+            constexpr: false,
+            lval: false,
+            ctype: expr.ctype.clone(),
+            expr: ExprType::Expect(Box::new(expr), expected),
+            location,
+        })
+    }
+    /// GNU extension: `({ stmt; ...; expr; })`, called after the opening '('.
+    ///
+    /// The value and type are those of the last statement if it is an expression statement;
+    /// otherwise (including for `({})`), the type is `void`.
+    fn statement_expr(&mut self, location: Location) -> SyntaxResult {
+        self.extension_used("braced-groups within expressions", location);
+        if self.current_function.is_none() {
+            self.semantic_err(
+                "statement expressions are only allowed inside functions",
+                location,
+            );
+        }
+        self.enter_scope();
+        let compound = self.compound_statement();
+        self.leave_scope(self.last_location);
+        let mut stmts = match compound? {
+            Some(Stmt {
+                data: StmtType::Compound(stmts),
+                ..
+            }) => stmts,
+            Some(_) => unreachable!("compound_statement should return a compound statement"),
+            None => Vec::new(),
+        };
+        self.expect(Token::RightParen)?;
+        let value = match stmts.pop() {
+            Some(Stmt {
+                data: StmtType::Expr(expr),
+                ..
+            }) => Some(Box::new(expr.rval())),
+            Some(stmt) => {
+                stmts.push(stmt);
+                None
+            }
+            None => None,
+        };
+        Ok(Expr {
+            constexpr: false,
+            lval: false,
+            ctype: value
+                .as_ref()
+                .map_or(Type::Void, |value| value.ctype.clone()),
+            expr: ExprType::StmtExpr(stmts, value),
+            location,
+        })
+    }
     /// primary_expr
     /// : identifier
     /// | INT_CONSTANT
     /// | DOUBLE_CONSTANT
     /// | STRING_LITERAL
     /// | '(' expr ')'
+    /// | '(' compound_statement ')'
     /// ;
+    /// GNU extension: `__builtin_unreachable()` has type `void` and traps if it is ever executed.
     fn primary_expr(&mut self) -> SyntaxResult {
         use crate::data::StorageClass;
         let mut pretend_zero = Expr::zero(self.next_location());
         pretend_zero.ctype = Type::Error;
         if let Some(Locatable { location, data }) = self.next_token() {
             match data {
+                // C89 allows calling functions which have never been declared
+                Token::Id(name)
+                    if self.implicit_functions != ImplicitFunctions::Error
+                        && self.scope.get(&name).is_none()
+                        && self.peek_token() == Some(&Token::LeftParen) =>
+                {
+                    Ok(self.implicit_function(name, location))
+                }
                 Token::Id(name) => match self.scope.get(&name) {
                     None => {
                         self.error_handler.push_back(CompileError::new(
@@ -880,6 +1144,18 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                             );
                             return Ok(pretend_zero);
                         }
+                        if let Some((_, used)) = self.local_uses.get_mut(&name) {
+                            *used = true;
+                        }
+                        if let Some(message) = symbol.attributes.deprecated {
+                            let declared = self.declared_at.get(&name).copied();
+                            let warning = Warning::Deprecated {
+                                name,
+                                message,
+                                declared: declared.unwrap_or(location),
+                            };
+                            self.error_handler.warn(warning, location);
+                        }
                         if let Type::Enum(ident, members) = &symbol.ctype {
                             let enumerator = members.iter().find_map(|(member, value)| {
                                 if name == *member {
@@ -902,9 +1178,35 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                     }
                 },
                 Token::Literal(literal) => Ok(Expr::from((literal, location))),
+                Token::Keyword(Keyword::BuiltinExpect) => self.builtin_expect(location),
+                Token::Keyword(Keyword::BuiltinUnreachable) => {
+                    self.extension_used("calls to '__builtin_unreachable'", location);
+                    self.expect(Token::LeftParen)?;
+                    self.expect(Token::RightParen)?;
+                    Ok(Expr {
+                        constexpr: false,
+                        lval: false,
+                        ctype: Type::Void,
+                        expr: ExprType::Unreachable,
+                        location,
+                    })
+                }
                 Token::LeftParen => {
-                    let expr = self.expr()?;
+                    if self.peek_token() == Some(&Token::LeftBrace) {
+                        return self.statement_expr(location);
+                    }
+                    let mut expr = self.expr()?;
                     self.expect(Token::RightParen)?;
+                    // remember the parentheses, see `check_assignment_condition`
+                    if let ExprType::Assign(_, _, _) = expr.expr {
+                        expr = Expr {
+                            ctype: expr.ctype.clone(),
+                            location: expr.location,
+                            constexpr: false,
+                            lval: false,
+                            expr: ExprType::Noop(Box::new(expr)),
+                        };
+                    }
                     Ok(expr)
                 }
                 other => {
@@ -930,39 +1232,81 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
         }
     }
 
+    /// Declare a function which was called without being declared.
+    ///
+    /// The function is treated as if it had been declared `extern int name();`
+    /// in the current scope. This was removed in C99.
+    /// Whether this warns is controlled by `ImplicitFunctions`.
+    fn implicit_function(&mut self, name: InternedStr, location: Location) -> Expr {
+        use crate::data::{types::FunctionType, StorageClass};
+        if self.implicit_functions == ImplicitFunctions::Warn {
+            self.error_handler
+                .warn(Warning::ImplicitFunctionDeclaration(name), location);
+        }
+        let symbol = Symbol {
+            id: name,
+            ctype: Type::Function(FunctionType {
+                return_type: Box::new(Type::Int(true)),
+                params: Vec::new(),
+                varargs: false,
+            }),
+            qualifiers: Qualifiers::NONE,
+            storage_class: StorageClass::Extern,
+            init: false,
+            attributes: Default::default(),
+        };
+        let expr = Expr::id(&symbol, location);
+        self.scope.insert(name, symbol);
+        expr
+    }
+
     // parse a struct member
     // used for both s.a and s->a
     fn struct_member(&mut self, expr: Expr, id: InternedStr, location: Location) -> SyntaxResult {
-        match &expr.ctype {
-            Type::Struct(stype) | Type::Union(stype) => {
-                let members = stype.members();
-                if members.is_empty() {
-                    self.semantic_err(format!("{} has not yet been defined", expr.ctype), location);
-                    Ok(expr)
-                } else if let Some(member) = members.iter().find(|member| member.id == id) {
-                    Ok(Expr {
-                        ctype: member.ctype.clone(),
-                        constexpr: expr.constexpr,
-                        lval: true,
-                        location,
-                        expr: ExprType::Member(Box::new(expr), id),
-                    })
-                } else {
-                    self.semantic_err(
-                        format!("no member named '{}' in '{}'", id, expr.ctype),
-                        location,
-                    );
-                    Ok(expr)
-                }
-            }
+        let members = match &expr.ctype {
+            Type::Struct(stype) | Type::Union(stype) => stype.members(),
             _ => {
                 self.semantic_err(
                     format!("expected struct or union, got type '{}'", expr.ctype),
                     location,
                 );
-                Ok(expr)
+                return Ok(expr);
             }
+        };
+        if members.is_empty() {
+            self.semantic_err(format!("{} has not yet been defined", expr.ctype), location);
+            return Ok(expr);
+        }
+        let mut paths = member_paths(&members, id);
+        if paths.len() > 1 {
+            self.error_handler
+                .push_back(location.error(SemanticError::AmbiguousMember(id)));
         }
+        let path = match paths.pop() {
+            Some(path) => path,
+            None => {
+                self.semantic_err(
+                    format!("no member named '{}' in '{}'", id, expr.ctype),
+                    location,
+                );
+                return Ok(expr);
+            }
+        };
+        // `s.a` where `a` is in an anonymous union is `s.<anonymous member>.a`
+        Ok(path.into_iter().fold(expr, |expr, member| {
+            let ctype = match member.ctype {
+                // the bit-field itself is only visible in codegen
+                Type::Bitfield(inner, _) => *inner,
+                ctype => ctype,
+            };
+            Expr {
+                ctype,
+                constexpr: expr.constexpr,
+                lval: true,
+                location,
+                expr: ExprType::Member(Box::new(expr), member.id),
+            }
+        }))
     }
 
     /// Parse a grammar rule of the form
@@ -987,6 +1331,9 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
         G: Fn(&mut Self) -> SyntaxResult,
     {
         self.left_associative_binary_op(next_grammar_func, &[token], move |left, right, token| {
+            if let Some(err) = Expr::void_operand(&left, &right) {
+                return Err((err, *left));
+            }
             let non_scalar = if !left.ctype.is_scalar() {
                 Some(left.ctype.clone())
             } else if !right.ctype.is_scalar() {
@@ -1033,6 +1380,9 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
         G: Fn(&mut Self) -> SyntaxResult,
     {
         self.left_associative_binary_op(next_grammar_func, tokens, |expr, next, token| {
+            if let Some(err) = Expr::void_operand(&expr, &next) {
+                return Err((err, *expr));
+            }
             let non_scalar = if !expr.ctype.is_integral() {
                 Some(&expr.ctype)
             } else if !next.ctype.is_integral() {
@@ -1123,6 +1473,14 @@ impl Expr {
             expr: ExprType::Noop(Box::new(self.rval())),
         }
     }
+    /// The value of a `void` expression can't be used as the operand of an arithmetic
+    /// or comparison operator. Either operand of the comma operator may be `void`.
+    fn void_operand(left: &Expr, right: &Expr) -> Option<Locatable<SemanticError>> {
+        [left, right]
+            .iter()
+            .find(|expr| expr.ctype == Type::Void)
+            .map(|void| void.location.with(SemanticError::VoidValueUsed))
+    }
     fn is_null(&self) -> bool {
         if let ExprType::Literal(token) = &self.expr {
             match token {
@@ -1138,38 +1496,18 @@ impl Expr {
     /// does not  have an incomplete type, does not have a const-qualified type,
     /// and if it is a structure or union, does not have any member with a const-qualified type"
     fn modifiable_lval(&self) -> Result<(), SemanticError> {
-        let err = |e| Err(SemanticError::NotAssignable(e));
-        // rval
-        if !self.lval {
-            return err("rvalue".to_string());
-        }
-        // incomplete type
-        if !self.ctype.is_complete() {
-            return err(format!("expression with incomplete type '{}'", self.ctype));
-        }
-        // const-qualified type
-        if let ExprType::Id(sym) = &self.expr {
-            if sym.qualifiers.c_const {
-                return err(format!("variable '{}' with `const` qualifier", sym.id));
-            }
-        }
-        match &self.ctype {
-            // array type
-            Type::Array(_, _) => err("array".to_string()),
-            // member with const-qualified type
-            Type::Struct(stype) | Type::Union(stype) => {
-                if stype
-                    .members()
-                    .iter()
-                    .map(|sym| sym.qualifiers.c_const)
-                    .any(|x| x)
-                {
-                    err("struct or union with `const` qualified member".to_string())
-                } else {
-                    Ok(())
-                }
-            }
-            _ => Ok(()),
+        let qualifiers = match &self.expr {
+            ExprType::Id(sym) => sym.qualifiers,
+            _ => Qualifiers::NONE,
+        };
+        let reason = if self.lval {
+            assignability_reason(&self.ctype, qualifiers)
+        } else {
+            Some(format!("rvalue of type '{}'", self.ctype))
+        };
+        match reason {
+            Some(reason) => Err(SemanticError::NotAssignable(reason)),
+            None => Ok(()),
         }
     }
     // ensure an expression has a value. convert
@@ -1245,6 +1583,9 @@ impl Expr {
         if self.ctype == Type::Bool {
             return Ok(self);
         }
+        if self.ctype == Type::Void {
+            return Err((self.location.with(SemanticError::VoidValueUsed), self));
+        }
         if !self.ctype.is_scalar() {
             Err((
                 self.location.with(SemanticError::Generic(format!(
@@ -1284,6 +1625,8 @@ impl Expr {
     pub fn cast(mut self, ctype: &Type) -> RecoverableResult<Expr, Locatable<SemanticError>> {
         if self.ctype == *ctype {
             Ok(self)
+        } else if self.ctype == Type::Void {
+            Err((self.location.with(SemanticError::VoidValueUsed), self))
         } else if self.ctype.is_arithmetic() && ctype.is_arithmetic()
             || self.is_null() && ctype.is_pointer()
             || self.ctype.is_pointer() && ctype.is_bool()
@@ -1378,6 +1721,57 @@ impl Expr {
             expr: ExprType::Add(Box::new(base), Box::new(offset)),
         })
     }
+    /// `left - right` for pointers `left` and `right`: the number of elements between them.
+    ///
+    /// This is the difference in bytes divided by the size of the pointed-to type,
+    /// with type `ptrdiff_t` (which is `long` on all supported platforms).
+    fn pointer_difference(
+        left: Expr,
+        right: Expr,
+        location: Location,
+    ) -> RecoverableResult<Expr, Locatable<SemanticError>> {
+        if left.ctype != right.ctype {
+            let err = SemanticError::IncompatibleTypes(left.ctype.clone(), right.ctype.clone());
+            return Err((location.with(err), left));
+        }
+        let size = match &left.ctype {
+            Type::Pointer(pointee) if !pointee.is_function() => pointee.sizeof(),
+            _ => Err("pointer to function"),
+        };
+        let size = match size {
+            Ok(s) if s != 0 => s,
+            _ => {
+                let err = format!(
+                    "cannot subtract pointers when size of pointed type is unknown (got '{}')",
+                    left.ctype
+                );
+                return Err((location.with(err.into()), left));
+            }
+        };
+        let ptrdiff = Type::Long(true);
+        let size_literal = Expr::from((Literal::UnsignedInt(size), location));
+        let size = Expr {
+            lval: false,
+            location,
+            ctype: ptrdiff.clone(),
+            constexpr: true,
+            expr: ExprType::Cast(Box::new(size_literal)),
+        };
+        let bytes = Expr {
+            lval: false,
+            location,
+            ctype: ptrdiff.clone(),
+            constexpr: left.constexpr && right.constexpr,
+            expr: ExprType::Sub(Box::new(left), Box::new(right)),
+        };
+        Ok(Expr {
+            lval: false,
+            location,
+            ctype: ptrdiff,
+            constexpr: bytes.constexpr,
+            expr: ExprType::Div(Box::new(bytes), Box::new(size)),
+        })
+    }
     fn increment_op(
         prefix: bool,
         increment: bool,
@@ -1457,6 +1851,9 @@ impl Expr {
         mut right: Box<Expr>,
         token: Locatable<Token>,
     ) -> RecoverableResult<Expr, Locatable<SemanticError>> {
+        if let Some(err) = Expr::void_operand(&left, &right) {
+            return Err((err, *left));
+        }
         let token = match token.data {
             Token::Comparison(c) => token.location.with(c),
             _ => unreachable!("bad use of relational_expr"),
@@ -1517,12 +1914,24 @@ impl Expr {
 
 impl From<(Literal, Location)> for Expr {
     fn from((literal, location): (Literal, Location)) -> Self {
-        let ctype = match literal {
-            Literal::Char(_) => Type::Char(true),
-            Literal::Int(_) => Type::Long(true),
-            Literal::UnsignedInt(_) => Type::Long(false),
-            Literal::Float(_) => Type::Double,
-            Literal::Str(s) => Type::for_string_literal(s.len() as SIZE_T),
+        let (literal, ctype) = match literal {
+            Literal::Char(_) => (literal, Type::Char(true)),
+            Literal::Int(_) => (literal, Type::Long(true)),
+            Literal::UnsignedInt(_) => (literal, Type::Long(false)),
+            Literal::Float(_) => (literal, Type::Double),
+            Literal::Str(s, prefix) => (
+                literal,
+                Type::for_string_literal(prefix, prefix.encoded_len(s) as SIZE_T),
+            ),
+            // the type of a wide character constant is kept in the expression, not the literal
+            Literal::WideChar(c, prefix) => {
+                let ctype = prefix.element_type();
+                if ctype.is_signed() {
+                    (Literal::Int(i64::from(c)), ctype)
+                } else {
+                    (Literal::UnsignedInt(u64::from(c)), ctype)
+                }
+            }
         };
         Expr {
             constexpr: true,
@@ -1665,8 +2074,8 @@ impl Type {
             _ => std::usize::MAX,
         }
     }
-    pub fn for_string_literal(len: SIZE_T) -> Type {
-        Type::Array(Box::new(Type::Char(true)), ArrayType::Fixed(len))
+    pub fn for_string_literal(prefix: StrPrefix, len: SIZE_T) -> Type {
+        Type::Array(Box::new(prefix.element_type()), ArrayType::Fixed(len))
     }
 }
 
@@ -1679,9 +2088,95 @@ fn is_typedef(s: InternedStr, scope: &crate::data::Scope<InternedStr, Symbol>) -
     }
 }
 
+/// Find the members which have to be accessed to get to `id`,
+/// looking inside anonymous structs and unions (C11 6.7.2.1p13).
+///
+/// Returns one path for every member named `id`, so more than one path means `id` is ambiguous.
+fn member_paths(members: &[Symbol], id: InternedStr) -> Vec<Vec<Symbol>> {
+    let mut paths = Vec::new();
+    for member in members {
+        if member.id == id {
+            paths.push(vec![member.clone()]);
+        } else if super::decl::is_anonymous_member(member.id) {
+            let inner = match &member.ctype {
+                Type::Struct(stype) | Type::Union(stype) => stype.members(),
+                _ => continue,
+            };
+            for mut path in member_paths(&inner, id) {
+                path.insert(0, member.clone());
+                paths.push(path);
+            }
+        }
+    }
+    paths
+}
+
+/// The qualifiers of `pointee`, the type that `expr` points to, as far as they are known.
+///
+/// Qualifiers aren't part of `Type`. The only ones known are those on the declaration
+/// of a variable or member, which belong to the innermost type of its declarator:
+/// `const int *p` points to a `const int`, but `int *const p` points to an `int`.
+/// This works the same way for `p`, `&p`, `*p`, and `p.member`,
+/// since they all point to part of the same declaration.
+fn pointee_qualifiers(expr: &Expr, pointee: &Type) -> Qualifiers {
+    fn declared(expr: &Expr) -> Option<Qualifiers> {
+        match &expr.expr {
+            ExprType::Id(symbol) => Some(symbol.qualifiers),
+            ExprType::Member(inner, name) => match &inner.ctype {
+                Type::Struct(stype) | Type::Union(stype) => stype
+                    .members()
+                    .iter()
+                    .find(|member| member.id == *name)
+                    .map(|member| member.qualifiers),
+                _ => None,
+            },
+            ExprType::Deref(inner) | ExprType::Noop(inner) => declared(inner),
+            _ => None,
+        }
+    }
+    fn is_innermost(ctype: &Type) -> bool {
+        match ctype {
+            Type::Array(inner, _) => is_innermost(inner),
+            Type::Pointer(_) => false,
+            _ => true,
+        }
+    }
+    match declared(expr) {
+        Some(qualifiers) if is_innermost(pointee) => Qualifiers {
+            inline: false,
+            thread_local: false,
+            ..qualifiers
+        },
+        // the pointee is itself a pointer, whose qualifiers aren't stored anywhere
+        _ => Qualifiers::NONE,
+    }
+}
+
+/// Why an lvalue of type `ctype` can't be assigned to, or `None` if it can.
+///
+/// `qualifiers` are the qualifiers of the lvalue itself, which for a variable
+/// are stored on the symbol rather than in its type.
+fn assignability_reason(ctype: &Type, qualifiers: Qualifiers) -> Option<String> {
+    match ctype {
+        Type::Array(_, _) => Some(format!("array of type '{}'", ctype)),
+        Type::Function(_) => Some(format!("function of type '{}'", ctype)),
+        _ if !ctype.is_complete() => Some(format!("incomplete type '{}'", ctype)),
+        _ if qualifiers.c_const => Some(format!(
+            "read-only object of type '{}{}'",
+            qualifiers, ctype
+        )),
+        Type::Struct(stype) | Type::Union(stype)
+            if stype.members().iter().any(|sym| sym.qualifiers.c_const) =>
+        {
+            Some(format!("'{}' with `const` qualified member", ctype))
+        }
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 pub(crate) mod tests {
-    use crate::data::{prelude::*, types, Scope, StorageClass};
+    use crate::data::{lex::StrPrefix, prelude::*, types, Qualifiers, Scope, StorageClass};
     use crate::intern::InternedStr;
     use crate::parse::tests::*;
     pub(crate) fn parse_expr(input: &str) -> CompileResult<Expr> {
@@ -1732,7 +2227,7 @@ pub(crate) mod tests {
         assert_eq!(
             parsed,
             Ok(Expr::from((
-                Literal::Str(InternedStr::get_or_intern("hi there\0")),
+                Literal::Str(InternedStr::get_or_intern("hi there\0"), StrPrefix::Plain),
                 get_location(&parsed)
             )))
         );
@@ -1748,6 +2243,7 @@ pub(crate) mod tests {
             qualifiers: Default::default(),
             storage_class: Default::default(),
             init: false,
+            attributes: Default::default(),
         };
         let parsed = parse_expr_with_scope("x", &[&x]);
         assert_eq!(
@@ -1762,6 +2258,12 @@ pub(crate) mod tests {
         );
     }
     #[test]
+    fn test_large_constants() {
+        assert_type("4294967296", Type::Long(true));
+        assert_type("0xffffffffffffffff", Type::Long(false));
+        assert_type("18446744073709551615", Type::Long(false));
+    }
+    #[test]
     fn test_mul() {
         assert_type("1*1.0", Type::Double);
         assert_type("1*2.0 / 1.3", Type::Double);
@@ -1781,10 +2283,12 @@ pub(crate) mod tests {
                     init: false,
                     qualifiers: Default::default(),
                     storage_class: StorageClass::Auto,
+                    attributes: Default::default(),
                 }],
                 return_type: Box::new(Type::Int(true)),
                 varargs: false,
             }),
+            attributes: Default::default(),
         };
         assert!(parse_expr_with_scope("f(1,2,3)", &[&f]).is_err());
         let parsed = parse_expr_with_scope("f()", &[&f]);
@@ -1797,6 +2301,457 @@ pub(crate) mod tests {
         },);
     }
     #[test]
+    fn test_variadic_call() {
+        let symbol = |name, ctype| Symbol {
+            id: InternedStr::get_or_intern(name),
+            init: false,
+            qualifiers: Default::default(),
+            storage_class: Default::default(),
+            ctype,
+            attributes: Default::default(),
+        };
+        let format = symbol("format", Type::Pointer(Box::new(Type::Char(true))));
+        let printf = symbol(
+            "printf",
+            Type::Function(types::FunctionType {
+                params: vec![format],
+                return_type: Box::new(Type::Int(true)),
+                varargs: true,
+            }),
+        );
+        let c = symbol("c", Type::Char(true));
+        let f = symbol("f", Type::Float);
+        let scope = [&printf, &c, &f];
+        let args = match parse_expr_with_scope("printf(\"%d %f\", c, f)", &scope) {
+            Ok(Expr {
+                expr: ExprType::FuncCall(_, args),
+                ..
+            }) => args,
+            other => panic!("not a function call: {:?}", other),
+        };
+        // the default argument promotions apply to the variadic arguments
+        let types: Vec<_> = args.into_iter().map(|arg| arg.ctype).collect();
+        assert_eq!(
+            types,
+            vec![
+                Type::Pointer(Box::new(Type::Char(true))),
+                Type::Int(true),
+                Type::Double
+            ]
+        );
+        // the fixed arguments are still required
+        assert!(parse_expr_with_scope("printf()", &scope).is_err());
+        assert!(parse_expr_with_scope("printf(\"%d\", 5)", &scope).is_ok());
+    }
+    #[test]
+    fn test_address_of() {
+        let int = |name, storage_class| Symbol {
+            id: InternedStr::get_or_intern(name),
+            init: true,
+            qualifiers: Default::default(),
+            storage_class,
+            ctype: Type::Int(true),
+            attributes: Default::default(),
+        };
+        let (a, b) = (int("a", StorageClass::Auto), int("b", StorageClass::Auto));
+        let r = int("r", StorageClass::Register);
+        let scope = [&a, &b, &r];
+        let address_of = |input| match parse_expr_with_scope(input, &scope) {
+            Err(err) => match err.data {
+                Error::Semantic(SemanticError::InvalidAddressOf(category)) => category,
+                other => panic!("wrong error for {}: {}", input, other),
+            },
+            Ok(expr) => panic!("{} should be an error, got {}", input, expr),
+        };
+        assert_eq!(address_of("&5"), "literal");
+        assert_eq!(address_of("&(a+b)"), "rvalue");
+        assert_eq!(address_of("&r"), "register variable");
+        let expr = parse_expr_with_scope("&a", &scope).unwrap();
+        assert_eq!(expr.ctype, Type::Pointer(Box::new(Type::Int(true))));
+    }
+    #[test]
+    fn test_not_assignable() {
+        let symbol = |name, ctype, c_const| Symbol {
+            id: InternedStr::get_or_intern(name),
+            init: true,
+            qualifiers: Qualifiers {
+                c_const,
+                ..Qualifiers::NONE
+            },
+            storage_class: StorageClass::Auto,
+            ctype,
+            attributes: Default::default(),
+        };
+        let array = Type::Array(Box::new(Type::Int(true)), types::ArrayType::Fixed(3));
+        let a = symbol("a", array, false);
+        let c = symbol("c", Type::Int(true), true);
+        let i = symbol("i", Type::Int(true), false);
+        let scope = [&a, &c, &i];
+        let reason = |input| match parse_expr_with_scope(input, &scope) {
+            Err(err) => match err.data {
+                Error::Semantic(SemanticError::NotAssignable(reason)) => reason,
+                other => panic!("wrong error for {}: {}", input, other),
+            },
+            Ok(expr) => panic!("{} should be an error, got {}", input, expr),
+        };
+        assert_eq!(reason("a = 0"), "array of type 'int[3]'");
+        assert_eq!(reason("c = 1"), "read-only object of type 'const int'");
+        assert_eq!(reason("c++"), "read-only object of type 'const int'");
+        assert_eq!(reason("i + 1 = 2"), "rvalue of type 'int'");
+        assert!(parse_expr_with_scope("i = 1", &scope).is_ok());
+        let err = parse_expr_with_scope("c += 1", &scope).unwrap_err();
+        assert_eq!(
+            err.data.to_string(),
+            "invalid program: cannot assign to read-only object of type 'const int'"
+        );
+    }
+    #[test]
+    fn test_pointer_difference() {
+        let pointer = |name, to| Symbol {
+            id: InternedStr::get_or_intern(name),
+            init: true,
+            qualifiers: Default::default(),
+            storage_class: StorageClass::Auto,
+            ctype: Type::Pointer(Box::new(to)),
+            attributes: Default::default(),
+        };
+        let (p, q) = (pointer("p", Type::Int(true)), pointer("q", Type::Int(true)));
+        let c = pointer("c", Type::Char(true));
+        let scope = [&p, &q, &c];
+        let diff = parse_expr_with_scope("p - q", &scope).unwrap();
+        assert_eq!(diff.ctype, Type::Long(true));
+        match parse_expr_with_scope("p - c", &scope).unwrap_err().data {
+            Error::Semantic(SemanticError::IncompatibleTypes(left, right)) => {
+                assert_eq!(left, p.ctype);
+                assert_eq!(right, c.ctype);
+            }
+            other => panic!("expected incompatible types, got {}", other),
+        }
+    }
+    #[test]
+    fn test_omitted_ternary() {
+        let expr = parse_expr("1 ?: 2.5").unwrap();
+        assert_eq!(expr.ctype, Type::Double);
+        match expr.expr {
+            ExprType::OmittedTernary(_, _) => {}
+            other => panic!("expected omitted ternary, got {}", other),
+        }
+        assert!(parse_expr("1 ?: 2").unwrap().constexpr);
+        assert!(parse_expr("1 ?: ").is_err());
+    }
+    #[test]
+    fn test_call_non_function() {
+        let errors = |program: &str| -> Vec<Error> {
+            let (_, errs) = parser(program).collect_results();
+            errs.into_iter().map(|err| err.data).collect()
+        };
+        let program = "int sq(int x) { return x * x; }
+            int main(void) { int (*fp)(int) = &sq; return fp(3) + (*fp)(4) + sq(5); }";
+        assert!(errors(program).is_empty());
+        for (decl, ctype) in &[
+            ("int x", Type::Int(true)),
+            ("int *x", Type::Pointer(Box::new(Type::Int(true)))),
+        ] {
+            let program = format!("int main(void) {{ {}; return x(3); }}", decl);
+            assert_eq!(
+                errors(&program),
+                vec![SemanticError::NotAFunction(ctype.clone()).into()]
+            );
+        }
+    }
+    #[test]
+    fn test_unary_plus() {
+        // unary plus performs integer promotions
+        assert_type("'a'", Type::Char(true));
+        assert_type("+'a'", Type::Int(true));
+        assert_type("+1.5", Type::Double);
+        assert_type("+4294967296", Type::Long(true));
+
+        let errors = |program: &str| parser(program).collect_results().1;
+        let program = "int main(void) { char c = 'a'; int i = +c; return +i; }";
+        assert!(errors(program).is_empty());
+        for operand in &["p", "s"] {
+            let program = format!(
+                "int main(void) {{ int *p = 0; struct {{ int x; }} s; +{}; }}",
+                operand
+            );
+            let errs = errors(&program);
+            assert_eq!(errs.len(), 1, "{:?}", errs);
+            assert!(errs[0].data.is_semantic_err());
+        }
+    }
+    #[test]
+    fn test_sizeof_incomplete() {
+        let errors = |program: &str| parser(program).collect_results().1;
+        let sizeof_err = |ctype| vec![SemanticError::SizeofIncompleteType(ctype).into()];
+        assert_eq!(
+            errors("int main(void) { return sizeof(void); }"),
+            sizeof_err(Type::Void)
+        );
+        let program = "int f(void); int main(void) { return sizeof f; }";
+        match errors(program).as_slice() {
+            [err] => match &err.data {
+                Error::Semantic(SemanticError::SizeofIncompleteType(Type::Function(_))) => {}
+                other => panic!("expected sizeof a function, got {:?}", other),
+            },
+            errs => panic!("expected one error, got {:?}", errs),
+        }
+        assert_eq!(
+            errors("extern int a[]; int main(void) { return sizeof a; }"),
+            sizeof_err(Type::Array(
+                Box::new(Type::Int(true)),
+                types::ArrayType::Unbounded
+            ))
+        );
+        assert!(errors("int main(void) { return sizeof(int); }").is_empty());
+    }
+    #[test]
+    fn test_sizeof_array_parameter() {
+        use crate::data::error::Warning;
+        let warnings = |program: &str| {
+            let mut p = parser(program);
+            let (_, errs) = p.collect_results();
+            assert!(errs.is_empty(), "{:?}", errs);
+            p.warnings()
+                .into_iter()
+                .map(|warning| warning.data)
+                .collect::<Vec<_>>()
+        };
+        let a = InternedStr::get_or_intern("a");
+        let int_ptr = Type::Pointer(Box::new(Type::Int(true)));
+        let decayed = || vec![Warning::SizeofArrayParameter(a, int_ptr.clone())];
+        assert_eq!(
+            warnings("unsigned long f(int a[10]) { return sizeof(a); }"),
+            decayed()
+        );
+        assert_eq!(
+            warnings("unsigned long f(const int a[]) { return sizeof a; }"),
+            decayed()
+        );
+        assert_eq!(
+            warnings("unsigned long f(a) int a[3]; { return sizeof a; }"),
+            decayed()
+        );
+        // the parameter really is a pointer
+        let program = "int f(int a[10]) { return sizeof(a) == sizeof(int *); }";
+        assert_eq!(warnings(program).len(), 1);
+        // parameters declared as pointers, local arrays, and other functions' parameters don't warn
+        assert!(warnings("unsigned long f(int *a) { return sizeof a; }").is_empty());
+        assert!(warnings("unsigned long f(void) { int a[3]; return sizeof a; }").is_empty());
+        assert!(
+            warnings("void g(int a[]); unsigned long f(int *a) { return sizeof a; }").is_empty()
+        );
+        assert!(warnings("unsigned long f(int a[]) { return sizeof *a; }").is_empty());
+        // only the parameter itself warns, not a local which shadows it
+        assert!(
+            warnings("unsigned long f(int a[]) { { int *a = 0; return sizeof a; } }").is_empty()
+        );
+        // or a parameter with the same name in the prototype of another parameter
+        let program = "unsigned long f(int *a, void (*g)(int a[])) { return sizeof a; }";
+        assert!(warnings(program).is_empty());
+        let program = "unsigned long (*f(int *a))(int a[]) { return 0; }";
+        assert!(warnings(program).is_empty());
+    }
+    #[test]
+    fn test_statement_expr() {
+        let errors = |program: &str| parser(program).collect_results().1;
+        let program = "int main(void) { int x = ({ int y = 2; y + 1; }); return x; }";
+        assert!(errors(program).is_empty());
+        // without a final expression statement, the type is `void`
+        for value in &["({})", "({ 1; if (1) 2; })", "({ int y = 1; })"] {
+            let program = format!("int main(void) {{ int x = {}; }}", value);
+            assert!(!errors(&program).is_empty(), "{}", program);
+        }
+        // declarations are local to the statement expression
+        assert!(!errors("int main(void) { ({ int y = 2; y; }); return y; }").is_empty());
+        assert!(!errors("int x = ({ 1; });").is_empty());
+    }
+    #[test]
+    fn test_array_subscript() {
+        let subscript_errors = |index: &str| {
+            let program = format!(
+                "int main(void) {{ int a[3]; int i = 1; struct s {{ int x; }} st; a[{}]; }}",
+                index
+            );
+            let (_, errs) = parser(&program).collect_results();
+            errs.into_iter().map(|err| err.data).collect::<Vec<_>>()
+        };
+        assert!(subscript_errors("i").is_empty());
+        assert!(subscript_errors("'a'").is_empty());
+        for index in &["1.5", "st"] {
+            match subscript_errors(index).as_slice() {
+                [Error::Semantic(SemanticError::ArraySubscriptNotInteger(_))] => {}
+                other => panic!(
+                    "expected non-integer subscript for {}, got {:?}",
+                    index, other
+                ),
+            }
+        }
+    }
+    #[test]
+    fn test_void_value_used() {
+        let void_errors = |body: &str| {
+            let program = format!("void f(void); int g(void) {{ {} return 0; }}", body);
+            let (_, errs) = parser(&program).collect_results();
+            errs.into_iter().map(|err| err.data).collect::<Vec<_>>()
+        };
+        assert!(void_errors("f();").is_empty());
+        assert!(void_errors("(void)f();").is_empty());
+        assert!(void_errors("f(), 1;").is_empty());
+        assert!(void_errors("int i; for (i = 0; i < 1; i++, f()) {}").is_empty());
+        for body in &[
+            "int x = f();",
+            "int y; y = f();",
+            "1 + f();",
+            "f() < 1;",
+            "f() | 1;",
+            "while (f()) {}",
+        ] {
+            match void_errors(body).as_slice() {
+                [Error::Semantic(SemanticError::VoidValueUsed)] => {}
+                other => panic!("expected void value error for {}, got {:?}", body, other),
+            }
+        }
+        let (_, errs) = parser("void f(void); int h(void) { return f(); }").collect_results();
+        match errs.as_slice() {
+            [err] => assert_eq!(err.data, SemanticError::VoidValueUsed.into()),
+            other => panic!("expected void value error for return, got {:?}", other),
+        }
+    }
+    #[test]
+    fn anonymous_members() {
+        let member_errors = |decl: &str, body: &str| {
+            let program = format!("{} int f(void) {{ struct s s; {} return 0; }}", decl, body);
+            let (_, errs) = parser(&program).collect_results();
+            errs.into_iter().map(|err| err.data).collect::<Vec<_>>()
+        };
+        let nested = "struct s { int i; union { int a; struct { float b, c; }; }; };";
+        for body in &[
+            "s.i = 1;",
+            "s.a = 1;",
+            "s.b = 1;",
+            "s.c = s.b;",
+            "(&s)->c = 1;",
+        ] {
+            assert!(member_errors(nested, body).is_empty(), "{}", body);
+        }
+        let named = "struct s { struct { int a; } inner; };";
+        assert_eq!(member_errors(named, "s.inner.a = 1;"), vec![]);
+        // named members don't make their members visible
+        assert_eq!(member_errors(named, "s.a = 1;").len(), 1);
+        let ambiguous = "struct s { union { int a; }; struct { float a; }; };";
+        assert_eq!(
+            member_errors(ambiguous, "s.a = 1;"),
+            vec![SemanticError::AmbiguousMember(InternedStr::get_or_intern("a")).into()]
+        );
+    }
+    #[test]
+    fn test_cast_discards_qualifiers() {
+        use crate::data::error::Warning;
+        let cast_warnings = |program: &str| {
+            let mut p = parser(&format!("int main(void) {{ {} }}", program));
+            let (_, errs) = p.collect_results();
+            assert!(errs.is_empty(), "{:?}", errs);
+            p.warnings()
+                .into_iter()
+                .map(|warning| warning.data)
+                .collect::<Vec<_>>()
+        };
+        let warnings = cast_warnings("const int *p = 0; int *q = (int*)p;");
+        assert_eq!(warnings.len(), 1);
+        match &warnings[0] {
+            Warning::CastDiscardsQualifiers(qualifiers, from, to) => {
+                assert!(qualifiers.c_const);
+                assert_eq!(from, &Type::Int(true));
+                assert_eq!(to, &Type::Pointer(Box::new(Type::Int(true))));
+            }
+            other => panic!("wrong warning: {}", other),
+        }
+        assert_eq!(
+            cast_warnings("volatile int v = 1; int *q = (int*)&v;").len(),
+            1
+        );
+        assert!(cast_warnings("const int *p = 0; const int *q = (const int*)p;").is_empty());
+        // the pointer itself is const, not what it points to
+        assert!(cast_warnings("int *const p = 0; int *q = (int*)p;").is_empty());
+        assert!(cast_warnings("const int *p = 0; int **q = (int**)&p;").is_empty());
+        // not just variables
+        let program = "struct s { const int *p; } s; int *q = (int*)s.p;";
+        assert_eq!(cast_warnings(program).len(), 1);
+        let program = "const int *p = 0; const int **pp = &p; int *q = (int*)*pp;";
+        assert_eq!(cast_warnings(program).len(), 1);
+        assert!(cast_warnings("int *p = 0; long *q = (long*)p;").is_empty());
+    }
+    #[test]
+    fn test_implicit_conversion() {
+        use crate::data::error::Warning;
+        let conversion_warnings = |program: &str| {
+            let program = format!("void f(int); {}", program);
+            let mut p = parser(&program);
+            let (_, errs) = p.collect_results();
+            assert!(errs.is_empty(), "{:?}", errs);
+            p.warnings()
+                .into_iter()
+                .map(|warning| warning.data)
+                .collect::<Vec<_>>()
+        };
+        let double_to_int = || vec![Warning::ImplicitConversion(Type::Double, Type::Int(true))];
+        assert_eq!(conversion_warnings("int x = 3.9;"), double_to_int());
+        assert_eq!(
+            conversion_warnings("double d; int g() { int x; x = d; return 0; }"),
+            double_to_int()
+        );
+        assert_eq!(
+            conversion_warnings("double d; int g() { f(d); return 0; }"),
+            double_to_int()
+        );
+        assert_eq!(
+            conversion_warnings("double d; int g() { return d; }"),
+            double_to_int()
+        );
+        assert_eq!(
+            conversion_warnings("double d; void g() { float x = d; }"),
+            vec![Warning::ImplicitConversion(Type::Double, Type::Float)]
+        );
+        // explicit casts and exact constants don't warn
+        assert_eq!(conversion_warnings("int x = (int)3.9;"), vec![]);
+        assert_eq!(conversion_warnings("int x = 3.0;"), vec![]);
+        assert_eq!(conversion_warnings("float x = 1.5;"), vec![]);
+        assert_eq!(conversion_warnings("double x = 1;"), vec![]);
+    }
+    #[test]
+    fn test_narrow_shift() {
+        use crate::data::error::Warning;
+        let shift_warnings = |program: &str| {
+            let mut p = parser(&format!("int x; {}", program));
+            let (_, errs) = p.collect_results();
+            assert!(errs.is_empty(), "{:?}", errs);
+            p.warnings()
+                .into_iter()
+                .map(|warning| warning.data)
+                .collect::<Vec<_>>()
+        };
+        let int_to_long = || vec![Warning::NarrowShift(Type::Int(true), Type::Long(true))];
+        assert_eq!(shift_warnings("long y = (int)1 << 40;"), int_to_long());
+        assert_eq!(
+            shift_warnings("void f(long); void g() { long y; y = x << 20; f(x << 3); }"),
+            vec![
+                Warning::NarrowShift(Type::Int(true), Type::Long(true)),
+                Warning::NarrowShift(Type::Int(true), Type::Long(true)),
+            ]
+        );
+        assert_eq!(shift_warnings("long g() { return x << 1; }"), int_to_long());
+        // casting the left operand, shifts which fit, and right shifts don't warn
+        assert_eq!(shift_warnings("long y = (long)1 << 40;"), vec![]);
+        assert_eq!(
+            shift_warnings("void g() { long y = (long)x << 20; }"),
+            vec![]
+        );
+        assert_eq!(shift_warnings("long y = 1 << 4;"), vec![]);
+        assert_eq!(shift_warnings("void g() { long y = x >> 2; }"), vec![]);
+        assert_eq!(shift_warnings("void g() { int y = x << 2; }"), vec![]);
+    }
+    #[test]
     fn test_type_errors() {
         assert!(parse_expr("1 % 2.0").is_err());
     }
@@ -1809,5 +2764,39 @@ pub(crate) mod tests {
         assert_type("(double)4.2", Type::Double);
         assert!(parse_expr("(int*)4.2").is_err());
         assert_type("(int*)(int)4.2", Type::Pointer(Box::new(Type::Int(true))));
+        assert_type("(int*)5", Type::Pointer(Box::new(Type::Int(true))));
+        assert!(parse_expr("(struct s)5").is_err());
+    }
+
+    #[test]
+    fn test_string_prefixes() {
+        let array = |inner, len| Type::Array(Box::new(inner), types::ArrayType::Fixed(len));
+        assert_type("\"ab\"", array(Type::Char(true), 3));
+        assert_type("L\"ab\"", array(Type::Int(true), 3));
+        assert_type("u\"ab\"", array(Type::Short(false), 3));
+        assert_type("U\"ab\"", array(Type::Int(false), 3));
+        assert_type("u8\"ab\"", array(Type::Char(true), 3));
+        // the length counts elements, not bytes
+        assert_type("u8\"\u{e9}\"", array(Type::Char(true), 3));
+        assert_type("L\"\u{e9}\"", array(Type::Int(true), 2));
+        // surrogate pairs take up two elements in UTF-16
+        assert_type("u\"\u{1f600}\"", array(Type::Short(false), 3));
+        assert_type("U\"\u{1f600}\"", array(Type::Int(false), 2));
+        // unprefixed strings take the prefix of the string they are concatenated with
+        assert_type("L\"a\" \"b\"", array(Type::Int(true), 3));
+    }
+    #[test]
+    fn test_char_prefixes() {
+        assert_type("'a'", Type::Char(true));
+        assert_type("L'a'", Type::Int(true));
+        assert_type("u'a'", Type::Short(false));
+        assert_type("U'a'", Type::Int(false));
+        let size = |input| match parse_expr(input).unwrap().expr {
+            ExprType::Sizeof(ctype) => ctype.sizeof().unwrap(),
+            other => panic!("expected sizeof, got {:?}", other),
+        };
+        assert_eq!(size("sizeof(u'a')"), 2);
+        assert_eq!(size("sizeof(U'a')"), 4);
+        assert_eq!(size("sizeof(L'a')"), 4);
     }
 }
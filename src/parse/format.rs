@@ -0,0 +1,224 @@
+//! Check the arguments to `printf`-style functions against their format string.
+//!
+//! This only happens for well-known functions and only when the format is a string literal.
+//! Mismatches are warnings, not errors, since the call is still valid C.
+
+use super::{Lexeme, Parser};
+use crate::data::{error::Warning, lex::StrPrefix, prelude::*};
+
+/// The kind of argument a conversion specifier expects,
+/// after the default argument promotions have been applied.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum FormatArg {
+    Integer,
+    Floating,
+    String,
+    Pointer,
+}
+
+impl FormatArg {
+    fn accepts(self, ctype: &Type) -> bool {
+        match self {
+            FormatArg::Integer => ctype.is_integral(),
+            FormatArg::Floating => ctype.is_floating(),
+            FormatArg::String => match ctype {
+                Type::Pointer(inner) => inner.is_char(),
+                _ => false,
+            },
+            FormatArg::Pointer => ctype.is_pointer(),
+        }
+    }
+    fn description(self) -> &'static str {
+        match self {
+            FormatArg::Integer => "an integer",
+            FormatArg::Floating => "a floating point number",
+            FormatArg::String => "a string",
+            FormatArg::Pointer => "a pointer",
+        }
+    }
+}
+
+/// The index of the format string in the parameters of a known `printf`-style function.
+fn format_index(name: &str) -> Option<usize> {
+    match name {
+        "printf" => Some(0),
+        "fprintf" | "sprintf" | "dprintf" => Some(1),
+        "snprintf" => Some(2),
+        _ => None,
+    }
+}
+
+/// Parse a format string into the arguments it expects,
+/// paired with the conversion specifier which expects them.
+///
+/// See section 7.21.6.1 of the C11 standard.
+/// Returns the first invalid conversion specifier as an error.
+fn parse_format(format: &str) -> Result<Vec<(String, FormatArg)>, String> {
+    let mut expected = Vec::new();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            continue;
+        }
+        if chars.peek() == Some(&'%') {
+            chars.next();
+            continue;
+        }
+        let mut spec = String::from("%");
+        // flags
+        while let Some(&c) = chars.peek() {
+            if !"-+ #0".contains(c) {
+                break;
+            }
+            spec.push(c);
+            chars.next();
+        }
+        // field width and precision, either of which may be an `int` argument
+        let mut seen_precision = false;
+        while let Some(&c) = chars.peek() {
+            if c == '*' {
+                expected.push((format!("{}*", spec), FormatArg::Integer));
+            } else if c == '.' && !seen_precision {
+                seen_precision = true;
+            } else if !c.is_ascii_digit() {
+                break;
+            }
+            spec.push(c);
+            chars.next();
+        }
+        // length modifiers: hh, h, l, ll, j, z, t, L
+        while let Some(&c) = chars.peek() {
+            if !"hljztL".contains(c) {
+                break;
+            }
+            spec.push(c);
+            chars.next();
+        }
+        let conversion = match chars.next() {
+            Some(c) => c,
+            None => return Err(spec),
+        };
+        spec.push(conversion);
+        let arg = match conversion {
+            'd' | 'i' | 'o' | 'u' | 'x' | 'X' | 'c' => FormatArg::Integer,
+            'f' | 'F' | 'e' | 'E' | 'g' | 'G' | 'a' | 'A' => FormatArg::Floating,
+            's' => FormatArg::String,
+            'p' | 'n' => FormatArg::Pointer,
+            _ => return Err(spec),
+        };
+        expected.push((spec, arg));
+    }
+    Ok(expected)
+}
+
+impl<I: Iterator<Item = Lexeme>> Parser<I> {
+    /// Warn if the arguments to a known `printf`-style function don't match its format string.
+    ///
+    /// `args` should already have had the default argument promotions applied.
+    pub(super) fn check_format(&mut self, func: InternedStr, args: &[Expr], location: Location) {
+        let index = match format_index(&func.resolve_and_clone()) {
+            Some(index) => index,
+            None => return,
+        };
+        let mut format = match args.get(index) {
+            Some(format) => format,
+            None => return,
+        };
+        // the format may have been converted to `const char *`
+        while let ExprType::Cast(inner) = &format.expr {
+            format = inner;
+        }
+        let format = match &format.expr {
+            ExprType::Literal(Literal::Str(format, StrPrefix::Plain))
+            | ExprType::Literal(Literal::Str(format, StrPrefix::Utf8)) => {
+                format.resolve_and_clone()
+            }
+            _ => return,
+        };
+        let expected = match parse_format(format.trim_end_matches('\0')) {
+            Ok(expected) => expected,
+            Err(spec) => {
+                self.error_handler
+                    .warn(Warning::FormatSpecifier(spec), args[index].location);
+                return;
+            }
+        };
+        let given = &args[index + 1..];
+        if given.len() != expected.len() {
+            self.error_handler.warn(
+                Warning::FormatArgumentCount(expected.len(), given.len()),
+                location,
+            );
+        }
+        for ((spec, arg), given) in expected.into_iter().zip(given) {
+            if !arg.accepts(&given.ctype) && given.ctype != Type::Error {
+                let warning = Warning::FormatType(spec, arg.description(), given.ctype.clone());
+                self.error_handler.warn(warning, given.location);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_format, FormatArg};
+    use crate::data::error::Warning;
+    use crate::parse::tests::parser;
+
+    fn format_warnings(call: &str) -> Vec<Warning> {
+        let program = format!(
+            "int printf(const char *format, ...); int main() {{ int i = 1; {}; }}",
+            call
+        );
+        let mut p = parser(&program);
+        let (_, errs) = p.collect_results();
+        assert!(errs.is_empty(), "{:?}", errs);
+        p.warnings()
+            .into_iter()
+            .map(|warning| warning.data)
+            .collect()
+    }
+    #[test]
+    fn parse() {
+        assert_eq!(parse_format("hello %% world"), Ok(vec![]));
+        assert_eq!(
+            parse_format("%-5d %.2f %*s %lu %p"),
+            Ok(vec![
+                ("%-5d".into(), FormatArg::Integer),
+                ("%.2f".into(), FormatArg::Floating),
+                ("%*".into(), FormatArg::Integer),
+                ("%*s".into(), FormatArg::String),
+                ("%lu".into(), FormatArg::Integer),
+                ("%p".into(), FormatArg::Pointer),
+            ])
+        );
+        assert_eq!(parse_format("%y"), Err("%y".into()));
+        assert_eq!(parse_format("trailing %"), Err("%".into()));
+    }
+    #[test]
+    fn mismatches() {
+        assert_eq!(format_warnings("printf(\"%d %s\\n\", i, \"hi\")"), vec![]);
+        assert_eq!(
+            format_warnings("printf(\"%s\", i)"),
+            vec![Warning::FormatType(
+                "%s".into(),
+                "a string",
+                crate::data::Type::Int(true)
+            )]
+        );
+        assert_eq!(
+            format_warnings("printf(\"%d %d\", i)"),
+            vec![Warning::FormatArgumentCount(2, 1)]
+        );
+        assert_eq!(
+            format_warnings("printf(\"%d\", &i)"),
+            vec![Warning::FormatType(
+                "%d".into(),
+                "an integer",
+                crate::data::Type::Pointer(Box::new(crate::data::Type::Int(true)))
+            )]
+        );
+        // only string literals are checked
+        assert_eq!(format_warnings("char *f = \"%s\"; printf(f, i)"), vec![]);
+    }
+}
@@ -1,5 +1,6 @@
 mod decl;
 mod expr;
+mod format;
 mod stmt;
 
 use std::collections::VecDeque;
@@ -7,7 +8,11 @@ use std::fmt;
 use std::iter::Iterator;
 use std::mem;
 
-use crate::data::{prelude::*, Scope};
+use crate::data::{
+    error::{Phase, Warning},
+    prelude::*,
+    Scope,
+};
 
 type Lexeme = CompileResult<Locatable<Token>>;
 pub(crate) type TagScope = Scope<InternedStr, TagEntry>;
@@ -34,6 +39,10 @@ pub struct Parser<I: Iterator<Item = Lexeme>> {
     scope: Scope<InternedStr, Symbol>,
     /// the compound types that have been declared (struct/union/enum)
     tag_scope: TagScope,
+    /// where each variable at block scope was declared, and whether it has been used
+    local_uses: Scope<InternedStr, (Location, bool)>,
+    /// where each identifier in `scope` was first declared, used for redeclaration errors
+    declared_at: Scope<InternedStr, Location>,
     /// we iterate lazily over the tokens, so if we have a program that's mostly valid but
     /// breaks at the end, we don't only show lex errors
     tokens: I,
@@ -54,6 +63,20 @@ pub struct Parser<I: Iterator<Item = Lexeme>> {
     /// if `None`, we are in global scope.
     /// used for checking return types
     current_function: Option<FunctionData>,
+    /// whether we have seen at least one declaration, including typedefs
+    /// and tag declarations which do not show up in the output.
+    /// used to tell an empty translation unit apart from one which only declares types
+    seen_declaration: bool,
+    /// the version of C being parsed, used to reject features from later standards
+    std: Std,
+    /// what to do when a function is called without being declared
+    implicit_functions: ImplicitFunctions,
+    /// whether to warn about local variables which are never used
+    warn_unused_variables: bool,
+    /// whether to reject variable-length arrays
+    forbid_vlas: bool,
+    /// what to do when a GNU extension is used
+    pedantic: Pedantic,
     /// whether to debug each declaration
     debug: bool,
     /// Internal API which makes it easier to return errors lazily
@@ -70,6 +93,10 @@ struct FunctionData {
     location: Location,
     /// the return type of the function
     return_type: Type,
+    /// the parameters which were declared as arrays and decayed to pointers
+    array_params: Vec<InternedStr>,
+    /// the depth of the scope containing the parameters, see `Scope::depth`
+    param_depth: usize,
 }
 
 impl<I> Parser<I>
@@ -82,20 +109,67 @@ where
     /// I would rather ensure `I` has at least one token,
     /// but I don't know a good way to do that without requiring users to
     /// use `std::iter::once`.
-    pub fn new(first: Locatable<Token>, tokens: I, debug: bool) -> Self {
+    pub fn new(
+        first: Locatable<Token>,
+        tokens: I,
+        debug: bool,
+        std: Std,
+        implicit_functions: ImplicitFunctions,
+    ) -> Self {
         Parser {
             scope: Default::default(),
             tag_scope: Default::default(),
+            local_uses: Default::default(),
+            declared_at: Default::default(),
             tokens,
             pending: Default::default(),
             last_location: first.location,
             current: Some(first),
             next: None,
             current_function: None,
+            seen_declaration: false,
+            std,
+            implicit_functions,
+            warn_unused_variables: false,
+            forbid_vlas: false,
+            pedantic: Pedantic::Off,
             debug,
-            error_handler: ErrorHandler::new(),
+            error_handler: ErrorHandler::for_phase(Phase::Sema),
         }
     }
+    /// Declare `symbols` in the global scope before parsing anything.
+    ///
+    /// This is for programs which run in an environment that already provides
+    /// some functions or variables: they can be used as if they had been declared
+    /// at the top of the file, without an explicit `extern` declaration.
+    /// The declarations are returned before any others, so they are visible to codegen.
+    pub fn with_symbol_table<S: IntoIterator<Item = Symbol>>(mut self, symbols: S) -> Self {
+        let location = self.last_location;
+        for symbol in symbols {
+            self.scope.insert(symbol.id, symbol.clone());
+            self.declared_at.insert(symbol.id, location);
+            let decl = Declaration { symbol, init: None };
+            self.pending.push_back(location.with(decl));
+        }
+        self
+    }
+    /// Warn about variables at block scope which are never used,
+    /// unless they were declared with `__attribute__((unused))`.
+    pub fn warn_unused_variables(mut self, warn: bool) -> Self {
+        self.warn_unused_variables = warn;
+        self
+    }
+    /// Report an error for every variable-length array,
+    /// for codebases which don't allow them (e.g. MISRA C).
+    pub fn forbid_vlas(mut self, forbid: bool) -> Self {
+        self.forbid_vlas = forbid;
+        self
+    }
+    /// Warn about (or reject) GNU extensions, so strictly conforming code can be checked.
+    pub fn pedantic(mut self, pedantic: Pedantic) -> Self {
+        self.pedantic = pedantic;
+        self
+    }
 }
 
 impl<I: Iterator<Item = Lexeme>> Iterator for Parser<I> {
@@ -139,7 +213,11 @@ impl<I: Iterator<Item = Lexeme>> Iterator for Parser<I> {
                 } else {
                     match self.declaration() {
                         Ok(decls) => {
-                            self.pending.extend(decls.into_iter());
+                            self.seen_declaration = true;
+                            // definitions recovered from an unclosed function body
+                            // were pushed while parsing it, but should come after it
+                            let recovered = mem::replace(&mut self.pending, decls);
+                            self.pending.extend(recovered);
                         }
                         Err(err) => {
                             // there could be semantic errors that were reported in the meantime,
@@ -166,6 +244,8 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
     fn enter_scope(&mut self) {
         self.scope.enter_scope();
         self.tag_scope.enter_scope();
+        self.local_uses.enter_scope();
+        self.declared_at.enter_scope();
     }
     fn leave_scope(&mut self, location: Location) {
         use crate::data::StorageClass;
@@ -190,8 +270,24 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                 _ => {}
             }
         }
+        if self.warn_unused_variables {
+            let mut unused: Vec<_> = self
+                .local_uses
+                .get_all_immediate()
+                .iter()
+                .filter(|(_, (_, used))| !used)
+                .map(|(&id, &(location, _))| (id, location))
+                .collect();
+            unused.sort_by_key(|(_, location)| location.span.start());
+            for (id, location) in unused {
+                self.error_handler
+                    .warn(Warning::UnusedVariable(id), location);
+            }
+        }
         self.scope.leave_scope();
         self.tag_scope.leave_scope();
+        self.local_uses.leave_scope();
+        self.declared_at.leave_scope();
     }
     // don't use this, use next_token instead
     fn __impl_next_token(&mut self) -> Option<Locatable<Token>> {
@@ -292,8 +388,13 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
         if token.same_kind(&next) {
             Ok(self.next_token().unwrap())
         } else {
+            let data = if next == Token::Semicolon {
+                SyntaxError::MissingSemicolon(token.clone())
+            } else {
+                SyntaxError::from(format!("expected '{}', got '{}'", next, token))
+            };
             let err = Err(Locatable {
-                data: SyntaxError::from(format!("expected '{}', got '{}'", next, token)),
+                data,
                 location: self.next_location(),
             });
             self.panic();
@@ -321,12 +422,33 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
         }
         (decls, errs)
     }
+    /// Whether any declarations have been parsed so far.
+    ///
+    /// Unlike checking the output of `collect_results`, this includes declarations
+    /// which do not produce any HIR, such as `typedef int i;` or `struct s { int i; };`.
+    pub fn seen_declaration(&self) -> bool {
+        self.seen_declaration
+    }
+    /// Report the use of a GNU extension, as configured by `pedantic`.
+    ///
+    /// `description` completes the sentence 'ISO C forbids ...'.
+    fn extension_used(&mut self, description: &'static str, location: Location) {
+        match self.pedantic {
+            Pedantic::Off => {}
+            Pedantic::Warn => self
+                .error_handler
+                .warn(Warning::Extension(description), location),
+            Pedantic::Error => self
+                .error_handler
+                .push_back(location.error(SemanticError::Extension(description))),
+        }
+    }
     /// Return all warnings seen so far.
     ///
     /// These warnings are consumed and will not be returned if you call
     /// `warnings()` again.
     pub fn warnings(&mut self) -> VecDeque<CompileWarning> {
-        std::mem::replace(&mut self.error_handler.warnings, Default::default())
+        self.error_handler.drain_warnings().collect()
     }
 }
 
@@ -419,9 +541,18 @@ pub(crate) mod tests {
     }
     #[inline]
     pub(crate) fn parser(input: &str) -> Parser<Lexer> {
-        let mut lexer = Lexer::new("<test suite>".to_string(), input.chars(), false);
+        parser_with_std(input, Std::default())
+    }
+    pub(crate) fn parser_with_std(input: &str, std: Std) -> Parser<Lexer> {
+        let mut lexer = Lexer::new("<test suite>".to_string(), input.chars(), false, std);
         let first = lexer.next().unwrap().unwrap();
-        Parser::new(first, lexer, false)
+        Parser::new(
+            first,
+            lexer,
+            false,
+            std,
+            ImplicitFunctions::default_for(std),
+        )
     }
     #[test]
     fn peek() {
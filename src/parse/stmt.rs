@@ -1,6 +1,11 @@
+use super::decl::is_attribute;
 use super::{Lexeme, Parser, SyntaxResult};
 use crate::data::prelude::*;
-use crate::data::{lex::Keyword, StorageClass};
+use crate::data::{
+    error::Warning,
+    lex::{AssignmentToken, Keyword},
+    Initializer, StorageClass,
+};
 use std::iter::Iterator;
 
 type StmtResult = SyntaxResult<Stmt>;
@@ -10,11 +15,19 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
         let start = self
             .expect(Token::LeftBrace)
             .expect("compound_statement should be called with '{' as the next token");
-        let mut stmts = vec![];
+        let mut stmts: Vec<Stmt> = vec![];
         let mut pending_errs = vec![];
-        while self.peek_token() != Some(&Token::RightBrace) {
+        while self
+            .peek_token()
+            .map_or(false, |token| *token != Token::RightBrace)
+        {
             match self.statement() {
-                Ok(Some(stmt)) => stmts.push(stmt),
+                Ok(Some(stmt)) => {
+                    if self.std < Std::C99 {
+                        self.check_mixed_declaration(&stmts, &stmt);
+                    }
+                    stmts.push(stmt);
+                }
                 Ok(None) => {}
                 Err(err) => {
                     self.panic();
@@ -26,12 +39,32 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                 }
             }
         }
-        if self.expect(Token::RightBrace).is_err() {
+        if self.match_next(&Token::RightBrace).is_some() {
+            for stmt in stmts.iter().filter(|stmt| defines_function(stmt)) {
+                if let StmtType::Decl(decls) = &stmt.data {
+                    let decl = &decls[0];
+                    let err = SemanticError::NestedFunction(decl.data.symbol.id);
+                    self.error_handler.push_back(decl.location.error(err));
+                }
+            }
+        } else {
             assert!(self.peek_token().is_none()); // from the 'break' above
-            let actual_err = self
-                .last_location
-                .with(SyntaxError::from("unclosed '{' delimeter at end of file"));
-            pending_errs.push(actual_err);
+            pending_errs.push(start.location.with(SyntaxError::UnclosedBrace));
+            // Function definitions can't appear in a block,
+            // so this was probably meant to be closed before the first one.
+            // Recover by treating the definition and everything after it as top-level declarations.
+            if let Some(i) = stmts.iter().position(defines_function) {
+                for stmt in stmts.drain(i..) {
+                    if let StmtType::Decl(decls) = stmt.data {
+                        self.pending.extend(decls);
+                    }
+                }
+                self.error_handler.extend(pending_errs.into_iter());
+                return Ok(Some(Stmt {
+                    data: StmtType::Compound(stmts),
+                    location: start.location,
+                }));
+            }
         }
         if let Some(err) = pending_errs.pop() {
             self.error_handler.extend(pending_errs.into_iter());
@@ -79,17 +112,25 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                 // labeled_statement (excluding labels)
                 Keyword::Case => {
                     let kw = self.next_token().unwrap();
-                    let expr = self.constant_expr()?;
+                    let expr = self.conditional_expr()?;
                     self.expect(Token::Colon)?;
+                    let expr = if expr.constexpr {
+                        expr.const_fold().unwrap_or_else(|err| {
+                            let location = err.location();
+                            self.error_handler.push_back(err);
+                            Expr::zero(location)
+                        })
+                    } else {
+                        expr
+                    };
                     let int = match expr.expr {
                         ExprType::Literal(Literal::Int(i)) => i as u64,
                         ExprType::Literal(Literal::UnsignedInt(u)) => u,
                         ExprType::Literal(Literal::Char(c)) => u64::from(c),
                         _ => {
-                            self.semantic_err(
-                                "case expression is not an integer constant",
-                                expr.location,
-                            );
+                            let location = expr.location;
+                            let err = SemanticError::NonConstantCaseLabel(expr);
+                            self.error_handler.push_back(location.error(err));
                             0
                         }
                     };
@@ -143,7 +184,9 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                 | Keyword::StaticAssert
                 | Keyword::Alignas
                 | Keyword::Alignof
-                | Keyword::Generic => self.expression_statement(),
+                | Keyword::Generic
+                | Keyword::BuiltinExpect
+                | Keyword::BuiltinUnreachable => self.expression_statement(),
                 decl if decl.is_decl_specifier() => {
                     let decls = self.declaration()?;
                     let location = match decls.front() {
@@ -179,15 +222,15 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                         location: id.location,
                     }));
                 }
-                let is_typedef = match self.scope.get(&id.data) {
+                let is_declaration = match self.scope.get(&id.data) {
                     Some(typedef) => typedef.storage_class == StorageClass::Typedef,
-                    _ => false,
+                    _ => is_attribute(id.data),
                 };
                 self.unput(Some(Locatable {
                     data: Token::Id(id.data),
                     location: id.location,
                 }));
-                if is_typedef {
+                if is_declaration {
                     let decls = self.declaration()?;
                     let location = match decls.front() {
                         Some(decl) => decl.location,
@@ -236,8 +279,10 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
             (Some(expr), true) => {
                 let expr = expr.rval();
                 if expr.ctype != *ret_type {
+                    let ret_type = ret_type.clone();
+                    self.check_implicit_conversion(&expr, &ret_type);
                     StmtType::Return(Some(
-                        Expr::cast(expr, ret_type).recover(&mut self.error_handler),
+                        Expr::cast(expr, &ret_type).recover(&mut self.error_handler),
                     ))
                 } else {
                     StmtType::Return(Some(expr))
@@ -249,6 +294,15 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
             location: ret_token.location,
         })
     }
+    /// Warn about `if (x = y)`, which was probably meant to be `if (x == y)`.
+    ///
+    /// Putting the assignment in parentheses, `if ((x = y))`, silences the warning.
+    fn check_assignment_condition(&mut self, condition: &Expr) {
+        if let ExprType::Assign(_, _, AssignmentToken::Equal) = condition.expr {
+            let warning = Warning::AssignmentInCondition;
+            self.error_handler.warn(warning, condition.location);
+        }
+    }
     /// if_statement:
     ///     IF '(' expr ')' statement
     ///   | IF '(' expr ')' statement ELSE statement
@@ -258,14 +312,29 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
             .expect("parser shouldn't call if_statement without an if");
         self.expect(Token::LeftParen)?;
         let condition = self.expr()?.rval();
-        self.expect(Token::RightParen)?;
+        self.check_assignment_condition(&condition);
+        let right_paren = self.expect(Token::RightParen)?;
+        let empty_body = self.bare_semicolon(right_paren.location);
         let body = self.statement()?;
-        let otherwise = if self.match_next(&Token::Keyword(Keyword::Else)).is_some() {
-            // NOTE: `if (1) ; else ;` is legal!
-            self.statement()?
-        } else {
-            None
-        };
+        let (otherwise, empty_else) =
+            if let Some(keyword) = self.match_next(&Token::Keyword(Keyword::Else)) {
+                let empty_else = self.bare_semicolon(keyword.location);
+                // NOTE: `if (1) ; else ;` is legal!
+                (self.statement()?, empty_else)
+            } else {
+                (None, None)
+            };
+        match (&body, &otherwise, empty_body, empty_else) {
+            (None, Some(_), Some(location), _) => {
+                let warning = Warning::EmptyBranch { is_else: false };
+                self.error_handler.warn(warning, location);
+            }
+            (Some(_), None, _, Some(location)) => {
+                let warning = Warning::EmptyBranch { is_else: true };
+                self.error_handler.warn(warning, location);
+            }
+            _ => {}
+        }
         let stmt = match (body, otherwise) {
             (None, None) => {
                 self.not_executed_warning(
@@ -297,6 +366,12 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
         self.expect(Token::RightParen)?;
         let body = self.statement()?;
         let stmt = if let Some(body) = body {
+            let (mut cases, mut defaults) = (0, 0);
+            count_labels(&body, &mut cases, &mut defaults);
+            if cases == 0 && defaults == 1 {
+                self.error_handler
+                    .warn(Warning::SwitchOnlyDefault, start.location);
+            }
             StmtType::Switch(expr, Box::new(body))
         } else {
             self.not_executed_warning(
@@ -316,7 +391,9 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
     fn while_statement(&mut self) -> StmtResult {
         let start = self.expect(Token::Keyword(Keyword::While))?;
         self.expect(Token::LeftParen)?;
-        let condition = self.expr()?.truthy().recover(&mut self.error_handler);
+        let condition = self.expr()?;
+        self.check_assignment_condition(&condition);
+        let condition = condition.truthy().recover(&mut self.error_handler);
         self.expect(Token::RightParen)?;
         let body = self.statement()?;
         Ok(Stmt {
@@ -334,7 +411,9 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
         let body = self.statement()?;
         self.expect(Token::Keyword(Keyword::While))?;
         self.expect(Token::LeftParen)?;
-        let condition = self.expr()?.truthy().recover(&mut self.error_handler);
+        let condition = self.expr()?;
+        self.check_assignment_condition(&condition);
+        let condition = condition.truthy().recover(&mut self.error_handler);
         self.expect(Token::RightParen)?;
         self.expect(Token::Semicolon)?;
         let stmt = if let Some(body) = body {
@@ -361,10 +440,18 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
         let paren = self.expect(Token::LeftParen)?;
         self.enter_scope();
         let decl = match self.peek_token() {
-            Some(Token::Keyword(k)) if k.is_decl_specifier() => Some(Box::new(Stmt {
-                data: StmtType::Decl(self.declaration()?),
-                location: paren.location,
-            })),
+            Some(Token::Keyword(k)) if k.is_decl_specifier() => {
+                if self.std < Std::C99 {
+                    self.error_handler.warn(
+                        format!("declarations in for loops are not allowed in {}", self.std),
+                        paren.location,
+                    );
+                }
+                Some(Box::new(Stmt {
+                    data: StmtType::Decl(self.declaration()?),
+                    location: paren.location,
+                }))
+            }
             Some(Token::Id(id)) => {
                 let id = *id;
                 match self.scope.get(&id) {
@@ -396,9 +483,10 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
                     .with(SyntaxError::EndOfFile("expression or ';'")));
             }
         };
-        let controlling_expr = self
-            .expr_opt(Token::Semicolon)?
-            .map(|expr| Expr::truthy(expr).recover(&mut self.error_handler));
+        let controlling_expr = self.expr_opt(Token::Semicolon)?.map(|expr| {
+            self.check_assignment_condition(&expr);
+            Expr::truthy(expr).recover(&mut self.error_handler)
+        });
         let iter_expr = self.expr_opt(Token::RightParen)?;
         let body = self.statement()?.map(Box::new);
         self.leave_scope(self.last_location);
@@ -425,6 +513,49 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
             location: start.location,
         })
     }
+    /// C89 requires all declarations to come before any statements in a block
+    fn check_mixed_declaration(&mut self, previous: &[Stmt], current: &Stmt) {
+        let is_decl = |stmt: &Stmt| match stmt.data {
+            StmtType::Decl(_) => true,
+            _ => false,
+        };
+        if is_decl(current) && previous.iter().any(|stmt| !is_decl(stmt)) {
+            self.error_handler.warn(
+                format!(
+                    "mixing declarations and code is not allowed in {}",
+                    self.std
+                ),
+                current.location,
+            );
+        }
+    }
+    /// If the next token is a `;` right after `previous`, the location of the `;`.
+    ///
+    /// `if (x);` is usually a mistake, but `if (x) /* nothing */ ;`,
+    /// `;` on its own line, and `{}` are all assumed to be deliberate.
+    /// Tokens from a macro expansion or another file can't be compared, so they never warn.
+    fn bare_semicolon(&mut self, previous: Location) -> Option<Location> {
+        if self.peek_token() != Some(&Token::Semicolon) {
+            return None;
+        }
+        let location = self.next_location();
+        if location.expansion.is_some()
+            || previous.expansion.is_some()
+            || location.filename != previous.filename
+        {
+            return None;
+        }
+        let gap = location
+            .span
+            .start()
+            .to_usize()
+            .checked_sub(previous.span.end().to_usize())?;
+        if gap <= 1 {
+            Some(location)
+        } else {
+            None
+        }
+    }
     fn not_executed_warning(
         &mut self,
         description: &str,
@@ -440,6 +571,52 @@ impl<I: Iterator<Item = Lexeme>> Parser<I> {
     }
 }
 
+/// Count the `case` and `default` labels belonging to the switch with body `stmt`.
+///
+/// Labels in nested switches belong to those switches, so they are not counted.
+fn count_labels(stmt: &Stmt, cases: &mut usize, defaults: &mut usize) {
+    let count_inner = |inner: &Option<Box<Stmt>>, cases: &mut usize, defaults: &mut usize| {
+        if let Some(inner) = inner {
+            count_labels(inner, cases, defaults);
+        }
+    };
+    match &stmt.data {
+        StmtType::Case(_, inner) => {
+            *cases += 1;
+            count_inner(inner, cases, defaults);
+        }
+        StmtType::Default(inner) => {
+            *defaults += 1;
+            count_inner(inner, cases, defaults);
+        }
+        StmtType::Compound(stmts) => {
+            for stmt in stmts {
+                count_labels(stmt, cases, defaults);
+            }
+        }
+        StmtType::If(_, body, otherwise) => {
+            count_labels(body, cases, defaults);
+            count_inner(otherwise, cases, defaults);
+        }
+        StmtType::Do(body, _) => count_labels(body, cases, defaults),
+        StmtType::While(_, body) | StmtType::For(_, _, _, body) | StmtType::Label(_, body) => {
+            count_inner(body, cases, defaults)
+        }
+        _ => {}
+    }
+}
+
+/// Whether `stmt` is the definition of a (nested) function.
+fn defines_function(stmt: &Stmt) -> bool {
+    match &stmt.data {
+        StmtType::Decl(decls) => decls.iter().any(|decl| match decl.data.init {
+            Some(Initializer::FunctionBody(_)) => true,
+            _ => false,
+        }),
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::super::tests::*;
@@ -454,6 +631,171 @@ mod tests {
             exp.map_err(CompileError::from)
         }
     }
+    fn mixed_declaration_warnings(program: &str, std: Std) -> usize {
+        let mut p = parser_with_std(program, std);
+        let (_, errs) = p.collect_results();
+        assert!(errs.is_empty(), "{:?}", errs);
+        p.warnings()
+            .iter()
+            .filter(|warning| warning.data.to_string().contains("not allowed in"))
+            .count()
+    }
+    #[test]
+    fn mixed_declarations() {
+        let mixed = "int f(); int main() { f(); int x; return 0; }";
+        assert_eq!(mixed_declaration_warnings(mixed, Std::C89), 1);
+        assert_eq!(mixed_declaration_warnings(mixed, Std::C99), 0);
+        let top = "int f(); int main() { int x; int y; f(); return 0; }";
+        assert_eq!(mixed_declaration_warnings(top, Std::C89), 0);
+        let nested = "int f(); int main() { f(); { int x; } return 0; }";
+        assert_eq!(mixed_declaration_warnings(nested, Std::C89), 0);
+        let for_loop = "int main() { for (int i = 0; i < 1; i++); return 0; }";
+        assert_eq!(mixed_declaration_warnings(for_loop, Std::C89), 1);
+        assert_eq!(mixed_declaration_warnings(for_loop, Std::C99), 0);
+    }
+    #[test]
+    fn empty_branches() {
+        use crate::data::error::Warning;
+        let warnings = |body: &str| {
+            let program = format!("int f(); int main() {{ int x = 1; {} return 0; }}", body);
+            let mut p = parser(&program);
+            let (_, errs) = p.collect_results();
+            assert!(errs.is_empty(), "{:?}", errs);
+            p.warnings()
+                .into_iter()
+                .map(|warning| warning.data)
+                .collect::<Vec<_>>()
+        };
+        let empty_if = || vec![Warning::EmptyBranch { is_else: false }];
+        assert_eq!(warnings("if(x); else f();"), empty_if());
+        assert_eq!(warnings("if (x) ; else f();"), empty_if());
+        let empty_else = vec![Warning::EmptyBranch { is_else: true }];
+        assert_eq!(warnings("if (x) f(); else;"), empty_else);
+        assert_eq!(warnings("if (x) f(); else f();"), vec![]);
+        assert_eq!(warnings("if (x) f();"), vec![]);
+        assert_eq!(warnings("if (x) {} else f();"), vec![]);
+        assert_eq!(warnings("if (x) /* deliberate */ ; else f();"), vec![]);
+        assert_eq!(warnings("if (x)\n        ;\n    else f();"), vec![]);
+        // both tokens have the location of the macro use, so they can't be compared
+        let program = "#define EMPTY if (x) ;\nint main() { int x = 1; EMPTY return 0; }";
+        let mut p = parser(program);
+        let (_, errs) = p.collect_results();
+        assert!(errs.is_empty(), "{:?}", errs);
+        assert!(p.warnings().is_empty());
+    }
+    #[test]
+    fn switch_only_default() {
+        use crate::data::error::Warning;
+        let warnings = |body: &str| {
+            let program = format!("int y(); int main() {{ int x = 1; {} return 0; }}", body);
+            let mut p = parser(&program);
+            let (_, errs) = p.collect_results();
+            assert!(errs.is_empty(), "{:?}", errs);
+            p.warnings()
+                .into_iter()
+                .map(|warning| warning.data)
+                .collect::<Vec<_>>()
+        };
+        let only_default = vec![Warning::SwitchOnlyDefault];
+        assert_eq!(warnings("switch(x){ default: y(); }"), only_default);
+        assert_eq!(warnings("switch (x) default: y();"), only_default);
+        assert_eq!(
+            warnings("switch (x) { if (x) { default: y(); } }"),
+            only_default
+        );
+        assert_eq!(warnings("switch (x) { case 1: default: y(); }"), vec![]);
+        assert_eq!(warnings("switch (x) { case 1: y(); }"), vec![]);
+        // labels of a nested switch don't count for the outer one
+        assert_eq!(
+            warnings("switch (x) { case 1: switch (x) { default: y(); } }"),
+            only_default
+        );
+        assert_eq!(
+            warnings("switch (x) { default: switch (x) { case 1: y(); } }"),
+            only_default
+        );
+    }
+    #[test]
+    fn assignment_in_condition() {
+        use crate::data::error::{FixIt, Warning};
+        let warnings = |body: &str| {
+            let program = format!("int main() {{ int x = 1, y = 2; {} return 0; }}", body);
+            let mut p = parser(&program);
+            let (_, errs) = p.collect_results();
+            assert!(errs.is_empty(), "{:?}", errs);
+            (program, p.warnings())
+        };
+        for body in &[
+            "if (x = y) x++;",
+            "while (x = y) x++;",
+            "do x++; while (x = y);",
+            "for (;x = y;) x++;",
+        ] {
+            let (program, warnings) = warnings(body);
+            assert_eq!(warnings.len(), 1, "{}: {:?}", body, warnings);
+            assert_eq!(warnings[0].data, Warning::AssignmentInCondition);
+            let FixIt {
+                location,
+                replacement,
+            } = warnings[0].fixit().expect("should suggest '=='");
+            let (start, end) = (location.span.start(), location.span.end());
+            assert_eq!(&program[start.to_usize()..end.to_usize()], "=");
+            assert_eq!(replacement, "==");
+        }
+        for body in &["if ((x = y)) x++;", "if (x == y) x++;", "if (x += y) x++;"] {
+            assert!(warnings(body).1.is_empty(), "{}", body);
+        }
+    }
+    #[test]
+    fn missing_semicolon_fixit() {
+        let program = "int main() { int x; x = 1 return x; }";
+        let (_, errs) = parser(program).collect_results();
+        let fixit = errs[0].fixit().expect("should suggest ';'");
+        let insert_at = fixit.location.span.start().to_usize();
+        assert_eq!(fixit.location.span.end().to_usize(), insert_at);
+        assert_eq!(&program[insert_at..insert_at + 6], "return");
+        assert_eq!(fixit.replacement, ";");
+        assert_eq!(fixit.to_string(), "insert ';'");
+    }
+    #[test]
+    fn nested_function() {
+        let mut p = parser("int main() { int f() { return 1; } return f(); }");
+        let (_, errs) = p.collect_results();
+        assert_eq!(errs.len(), 1, "{:?}", errs);
+        assert_eq!(
+            errs[0].data,
+            SemanticError::NestedFunction(InternedStr::get_or_intern("f")).into()
+        );
+        // declarations are fine
+        let mut p = parser("int main() { int f(); return 0; }");
+        let (_, errs) = p.collect_results();
+        assert!(errs.is_empty(), "{:?}", errs);
+    }
+    #[test]
+    fn unclosed_brace() {
+        let program =
+            "int f(void) {\n    if (1) {\n        return 1;\n}\nint g(void) { return 2; }";
+        let (decls, errs) = parser(program).collect_results();
+        let names: Vec<_> = decls.iter().map(|decl| decl.data.symbol.id).collect();
+        assert_eq!(
+            names,
+            vec![
+                InternedStr::get_or_intern("f"),
+                InternedStr::get_or_intern("g")
+            ]
+        );
+        assert_eq!(errs.len(), 1, "{:?}", errs);
+        assert_eq!(errs[0].data, SyntaxError::UnclosedBrace.into());
+        // the error points to the '{' which wasn't closed
+        assert_eq!(errs[0].location.span, (12..13).into());
+
+        // every unclosed brace is reported
+        let (_, errs) = parser("int f(void) { if (1) { return 1;").collect_results();
+        assert_eq!(errs.len(), 2, "{:?}", errs);
+        assert!(errs
+            .iter()
+            .all(|err| err.data == SyntaxError::UnclosedBrace.into()));
+    }
     #[test]
     // NOTE: this seems to be one of the few tests that checks that the location
     // is correct. If it starts failing, maybe look at the lexer first
@@ -466,6 +808,7 @@ mod tests {
                 // TODO: this should really be 0..2
                 // but I haven't implemented merging spans yet
                 span: (1..2).into(),
+                expansion: None,
             },
         }));
         assert_eq!(parsed, expected);
@@ -474,4 +817,59 @@ mod tests {
             expected.unwrap().unwrap().location
         );
     }
+    #[test]
+    fn ternary_case_label() {
+        let stmt = parse_stmt("switch (1) { case 2 > 1 ? 3 : 4: break; }");
+        let body = match stmt.unwrap().unwrap().data {
+            StmtType::Switch(_, body) => body,
+            other => panic!("expected a switch, got {:?}", other),
+        };
+        match body.data {
+            StmtType::Compound(stmts) => match stmts[0].data {
+                StmtType::Case(3, _) => {}
+                ref other => panic!("expected 'case 3', got {:?}", other),
+            },
+            other => panic!("expected a block, got {:?}", other),
+        }
+        // every part of the ternary has to be constant
+        for label in &["x ? 1 : 2", "1 ? x : 2", "0 ? 1 : x"] {
+            let program = format!(
+                "int main() {{ int x = 1; switch (x) {{ case {}: break; }} return 0; }}",
+                label
+            );
+            let (_, errs) = parser(&program).collect_results();
+            assert_eq!(errs.len(), 1, "{:?}", errs);
+            match &errs[0].data {
+                Error::Semantic(SemanticError::NonConstantCaseLabel(_)) => {}
+                other => panic!("expected NonConstantCaseLabel, got {:?}", other),
+            }
+        }
+    }
+    #[test]
+    fn non_constant_case_label() {
+        let program = "int main() { int x = 1; switch (x) { case x: break; } return 0; }";
+        let (_, errs) = parser(program).collect_results();
+        assert_eq!(errs.len(), 1, "{:?}", errs);
+        match &errs[0].data {
+            Error::Semantic(SemanticError::NonConstantCaseLabel(expr)) => match &expr.expr {
+                ExprType::Id(symbol) => assert_eq!(symbol.id, InternedStr::get_or_intern("x")),
+                other => panic!("expected the label to be 'x', got {:?}", other),
+            },
+            other => panic!("expected NonConstantCaseLabel, got {:?}", other),
+        }
+        assert_eq!(
+            errs[0].data.to_string(),
+            "invalid program: case label does not reduce to an integer constant"
+        );
+        // a constant which isn't an integer
+        let program = "int main() { switch (1) { case 1.5: break; } return 0; }";
+        let (_, errs) = parser(program).collect_results();
+        match errs.as_slice() {
+            [err] => assert!(match err.data {
+                Error::Semantic(SemanticError::NonConstantCaseLabel(_)) => true,
+                _ => false,
+            }),
+            other => panic!("expected one error, got {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,2 @@
+// succeeds
+int main(int argc, char **argv, char **envp) { return 0; }
@@ -0,0 +1,12 @@
+// code: 1
+struct s {
+    unsigned a : 3;
+    unsigned b : 5;
+} s;
+int main() {
+    s.a = 5;
+    s.b = 17;
+    s.b += 3;
+    s.a++;
+    return s.a == 6 && s.b == 20 && sizeof(s) == sizeof(unsigned);
+}
@@ -0,0 +1,14 @@
+// code: 1
+union {
+    struct {
+        unsigned a : 3;
+        unsigned b : 5;
+    } bits;
+    unsigned all;
+} u;
+int main() {
+    u.bits.a = 5;
+    u.bits.b = 17;
+    // (17 << 3) | 5
+    return u.all == 141;
+}
@@ -0,0 +1,10 @@
+// code: 1
+struct {
+    int x : 4;
+    int y : 4;
+} s;
+int main() {
+    s.x = -3;
+    s.y = 7;
+    return s.x == -3 && s.y == 7;
+}
@@ -0,0 +1,3 @@
+// fail
+struct { int a : 33; } s;
+int main() {}
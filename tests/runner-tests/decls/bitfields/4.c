@@ -0,0 +1,5 @@
+// fail
+struct { int a : 3; } s;
+int main() {
+    int *p = &s.a;
+}
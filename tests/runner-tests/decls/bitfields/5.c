@@ -0,0 +1,18 @@
+// code: 1
+struct {
+    unsigned a : 3;
+    unsigned b : 5;
+    int c : 4;
+    char d;
+} s = {5, 17, -3, 'x'};
+union {
+    struct {
+        unsigned a : 3;
+        unsigned b : 5;
+    } bits;
+    unsigned all;
+} u = {{5, 17}};
+int main() {
+    // (17 << 3) | 5
+    return s.a == 5 && s.b == 17 && s.c == -3 && s.d == 'x' && u.all == 141;
+}
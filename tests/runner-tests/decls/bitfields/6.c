@@ -0,0 +1,11 @@
+// code: 1
+struct {
+    _Bool a : 1;
+    _Bool b : 1;
+    unsigned c : 6;
+} s;
+int main() {
+    s.a = 1;
+    s.c = 5;
+    return s.a && !s.b && s.c == 5;
+}
@@ -0,0 +1,11 @@
+// code: 7
+int main() {
+    int x = 1;
+    x = 2;
+    x = 5;
+    int *p = &x;
+    int y = 0;
+    y = 3;
+    *p = 4;
+    return x + y;
+}
@@ -0,0 +1,10 @@
+// code: 9
+long scale(x, by, unused)
+    long x;
+    char *unused;
+{
+    return x * by;
+}
+int main() {
+    return scale(3, 3, "ignored");
+}
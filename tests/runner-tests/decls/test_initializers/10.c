@@ -0,0 +1,10 @@
+// code: 3
+char global[3] = "abc";
+int main(void) {
+    char s[3] = "abc";
+    char padded[6] = "ab";
+    if (s[2] != 'c' || global[2] != 'c' || padded[2] || padded[5]) {
+        return 1;
+    }
+    return sizeof(s);
+}
@@ -0,0 +1,5 @@
+// fail
+int main(void) {
+    char s[2] = "abc";
+    return s[0];
+}
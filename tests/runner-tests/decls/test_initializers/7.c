@@ -0,0 +1,7 @@
+// code: 10
+int main(void) {
+    int a[5] = {1, 2, 3};
+    int b[2][3] = {{4}, {0, 0, 5}};
+    char c[3] = {6};
+    return a[0] + a[2] + a[3] + a[4] + b[0][1] + b[1][2] + c[1] + c[2] - a[1] + c[0] - 3;
+}
@@ -0,0 +1,8 @@
+// code: 4
+int main(void) {
+    char s[] = "abc";
+    if (s[0] != 'a' || s[2] != 'c' || s[3] != '\0') {
+        return 1;
+    }
+    return sizeof(s);
+}
@@ -0,0 +1,11 @@
+// code: 8
+union u {
+    char c;
+    int i;
+    long l;
+    short s[3];
+};
+int main() {
+    union u local;
+    return sizeof(union u) == sizeof(local) ? sizeof(union u) : 0;
+}
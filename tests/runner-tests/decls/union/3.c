@@ -0,0 +1,15 @@
+// code: 42
+union u {
+    char c;
+    int i;
+    double d;
+};
+int main() {
+    union u local;
+    local.d = 1.5;
+    local.i = 40;
+    int i = local.i;
+    union u *p = &local;
+    p->c = 2;
+    return i + p->c;
+}
@@ -0,0 +1,14 @@
+// code: 1
+// writing one member and reading another shares storage at offset 0
+union u {
+    int i;
+    char c;
+    float f;
+};
+int main() {
+    union u local;
+    local.i = 0;
+    local.f = 2.0;
+    local.c = 1;
+    return (char *)&local.i == &local.c && (void *)&local.f == (void *)&local && local.c;
+}
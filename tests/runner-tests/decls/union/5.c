@@ -0,0 +1,19 @@
+// code: 12
+struct tagged {
+    int tag;
+    union {
+        int i;
+        struct {
+            char low, high;
+        };
+    };
+};
+int main() {
+    struct tagged t;
+    struct tagged *p = &t;
+    t.tag = 1;
+    t.i = 0;
+    p->low = 4;
+    p->high = 7;
+    return sizeof(struct tagged) == 2 * sizeof(int) ? t.tag + t.low + p->high : 0;
+}
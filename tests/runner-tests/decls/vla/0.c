@@ -0,0 +1,21 @@
+// code: 42
+int sum(int n) {
+    int a[n];
+    for (int i = 0; i < n; i++) a[i] = i;
+    int total = 0;
+    for (int i = 0; i < n; i++) total += a[i];
+    return total;
+}
+int main() {
+    int n = 5;
+    long a[n];
+    for (int i = 0; i < n; i++) a[i] = i * 10;
+    // allocated and freed on every iteration
+    for (int i = 1; i < 4; i++) {
+        char c[i];
+        c[i - 1] = 'a';
+        if (c[i - 1] != 'a') return 1;
+        if (i == 3) break;
+    }
+    return a[4] + sum(3) - 1;
+}
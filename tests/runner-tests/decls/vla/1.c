@@ -0,0 +1,4 @@
+// fail
+int n = 3;
+int a[n];
+int main() {}
@@ -0,0 +1,12 @@
+// fail
+// a goto can't jump past the declaration of a variable length array
+int main(void) {
+    int n = 3;
+    if (n) goto inside;
+    {
+        int a[n];
+    inside:
+        a[0] = 1;
+        return a[0];
+    }
+}
@@ -0,0 +1,16 @@
+// code: 3
+// jumping out of a variable length array's scope, or within it, is fine
+int main(void) {
+    int n = 3, total = 0;
+    {
+        int a[n];
+        int i = 0;
+    again:
+        a[i] = i;
+        total += a[i];
+        if (++i < n) goto again;
+        goto done;
+    }
+done:
+    return total;
+}
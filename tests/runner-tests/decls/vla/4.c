@@ -0,0 +1,19 @@
+// code: 1
+unsigned long chars(int n) {
+    char c[n];
+    // the length was computed when `c` was declared
+    n = 100;
+    return sizeof c;
+}
+int main(void) {
+    int n = 4;
+    int a[n];
+    for (int i = 0; i < 3; i++) {
+        long b[n + i];
+        b[0] = i;
+        // frees `b` before leaving its scope
+        if (b[0] == 1) goto out;
+    }
+out:
+    return sizeof a == 4 * sizeof(int) && sizeof(int[n]) == sizeof a && chars(3) == 3;
+}
@@ -0,0 +1,10 @@
+// code: 7
+int calls;
+int bump(int x) { calls += x; return calls; }
+int main() {
+    int a = 3, b = 4;
+    (void)(a + b);
+    (void)(a + bump(b));
+    (void)bump(a);
+    return calls;
+}
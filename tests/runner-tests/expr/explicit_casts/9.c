@@ -0,0 +1,11 @@
+// code: 0
+int main() {
+    int big = 300;
+    double d = (double)5;
+    int *p = (int*)(long)&big;
+    if ((char)big != 44 || (unsigned char)-1 != 255) return 1;
+    if (d != 5.0 || (int)(d / 2) != 2) return 2;
+    if (*p != 300 || (long)(int*)8 != 8) return 3;
+    if ((_Bool)0.5 != 1 || (float)(_Bool)2 != 1.0f) return 4;
+    return 0;
+}
@@ -0,0 +1,11 @@
+// trapv code: 3
+// narrow types are promoted to int before the arithmetic, so this doesn't overflow
+int main(void) {
+    signed char c = 127;
+    short s = 32767;
+    c += 1;
+    ++s;
+    c *= 2;
+    s *= 3;
+    return (c == 0) + (s == -32768) * 2;
+}
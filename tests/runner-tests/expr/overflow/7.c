@@ -0,0 +1,11 @@
+// trapv code: 1
+// `int` is already promoted, so its overflow is still checked
+int main(void) {
+    signed char c = -128;
+    short s = -32768;
+    int i = 2147483647;
+    c -= 1;
+    s -= 1;
+    i -= 1;
+    return c == 127 && s == 32767 && i == 2147483646;
+}
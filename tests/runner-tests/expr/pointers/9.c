@@ -0,0 +1,7 @@
+// code: 3
+int main() {
+    long a[10];
+    int b[2];
+    if (&b[1] - &b[0] != 1 || b - &b[1] != -1) return 10;
+    return &a[5] - &a[2];
+}
@@ -0,0 +1,5 @@
+// code: 6
+int main(void) {
+    int x = ({ int y = 2; y * 3; });
+    return x;
+}
@@ -0,0 +1,12 @@
+// code: 13
+int main(void) {
+    int i = 1, total = 0;
+    // each operand is evaluated exactly once
+    total += ({ int _a = (i++), _b = (0); _a > _b ? _a : _b; });
+    total += ({ int _a = (0), _b = (i++); _a > _b ? _a : _b; });
+    ({ total += i; });
+    for (int j = 0; j < 3; j++) {
+        total += ({ int k = j; if (k == 2) k = 3; k; });
+    }
+    return total + i;
+}
@@ -0,0 +1,5 @@
+// fail
+int x = ({ 1; });
+int main(void) {
+    return x;
+}
@@ -0,0 +1,9 @@
+// code: 1
+int main(void) {
+    char *a = "hello", *b = "hello", *c = "help";
+    if (a[0] != 'h' || a[4] != 'o' || a[5] != '\0' || c[3] != 'p') {
+        return 2;
+    }
+    // identical literals share storage
+    return a == b && a != c;
+}
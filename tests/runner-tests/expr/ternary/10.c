@@ -0,0 +1,13 @@
+// code: 21
+int f_calls, g_calls;
+int f_result;
+int f() { f_calls++; return f_result; }
+int g() { g_calls++; return 7; }
+int main() {
+    f_result = 3;
+    if ((f() ?: g()) != 3 || f_calls != 1 || g_calls != 0) return 1;
+    f_result = 0;
+    if ((f() ?: g()) != 7 || f_calls != 2 || g_calls != 1) return 2;
+    double d = 0 ?: 1.5;
+    return f_calls + g_calls + (int)(d * 12);
+}
@@ -0,0 +1,6 @@
+// code: 98
+int main() {
+    char c = 'a';
+    int i = +c;
+    return +i + (sizeof +c == sizeof(int));
+}
@@ -0,0 +1,18 @@
+// succeeds
+int wide[] = L"ab";
+unsigned short utf16[] = u"é";
+unsigned int utf32[] = U"é";
+char utf8[] = u8"é";
+int main() {
+    if (sizeof(wide) != 3 * sizeof(int) || wide[0] != 'a' || wide[1] != 'b' || wide[2] != 0)
+        return 1;
+    if (sizeof(utf16) != 2 * sizeof(short) || utf16[0] != 0xe9)
+        return 2;
+    if (sizeof(utf32) != 2 * sizeof(int) || utf32[0] != 0xe9)
+        return 3;
+    if (sizeof(utf8) != 3 || (unsigned char)utf8[0] != 0xc3 || (unsigned char)utf8[1] != 0xa9)
+        return 4;
+    if (sizeof(L'a') != sizeof(int) || sizeof(u'a') != 2 || sizeof(U'a') != 4)
+        return 5;
+    return L'a' != 97 || u'é' != 0xe9;
+}
@@ -0,0 +1,9 @@
+// code: 40
+int sq(int x) { return x * x; }
+int twice(int (*f)(int), int x) { return f(f(x)); }
+int main(void) {
+    int (*fp)(int) = &sq;
+    int total = fp(3) + (*fp)(4);
+    fp = sq;
+    return total + twice(fp, 2) - fp(0) - 5 + 4;
+}
@@ -0,0 +1,18 @@
+// code: 7
+int counter;
+void bump() { counter += 1; }
+int triple(int x) { return 3 * x; }
+int main(void) {
+    // a function designator decays to a pointer without `&`
+    void (*p)() = bump;
+    int (*t)(int);
+    t = triple;
+    (*p)();
+    p();
+    // dereferencing a function pointer gives back the function, which decays again
+    (**p)();
+    if (p != bump || t != &triple) {
+        return 100;
+    }
+    return counter + (*t)(0) + t(1) + triple(0) + 1;
+}
@@ -1,2 +1,3 @@
-// no-main
+// errors: 1
+// implicit int is only allowed in C89
 i;
@@ -0,0 +1,18 @@
+// code: 3
+int calls;
+int check(int x) {
+    calls += 1;
+    return x;
+}
+int main() {
+    if (__builtin_expect(check(0), 0)) {
+        return 100;
+    }
+    if (!__builtin_expect(check(1), 1)) {
+        return 101;
+    }
+    while (__builtin_expect(check(0), 0)) {
+        return 102;
+    }
+    return calls;
+}
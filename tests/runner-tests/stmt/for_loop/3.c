@@ -0,0 +1,10 @@
+// output: BEGIN: 02 END
+int putchar(int c);
+int main() {
+    int i;
+    for (i = 0; i < 3; i++) {
+        if (i == 1) continue;
+        putchar('0' + i);
+    }
+    return i != 3;
+}
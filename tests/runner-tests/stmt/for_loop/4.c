@@ -0,0 +1,14 @@
+// code: 13
+int f_calls, g_calls;
+void f(void) { f_calls++; }
+void g(void) { g_calls++; }
+int main() {
+    int i = 0;
+    // no condition: the init runs once and the post-loop expression every iteration,
+    // including ones which `continue`
+    for (f();; g()) {
+        if (g_calls == 3) break;
+        if (i++ % 2) continue;
+    }
+    return f_calls * 10 + g_calls;
+}
@@ -1,4 +1,3 @@
-// ignore: https://github.com/jyn514/rcc/issues/95
 // succeeds
 int main() {
     int x = 0;
@@ -0,0 +1,16 @@
+// code: 12
+int main() {
+    int x = 5, y = 0;
+    if (x == 5) goto inside;
+    switch (x) {
+        case 1: return 1;
+        case 2:
+        inside:
+            y += 10;
+        case 3:
+            y += 2;
+            break;
+        default: return 4;
+    }
+    return y;
+}
@@ -0,0 +1,5 @@
+// fail
+int main() {
+    if (1) goto nowhere;
+    return 0;
+}
@@ -0,0 +1,9 @@
+// code: 3
+// labels and variables have separate namespaces
+int main() {
+    int x = 0;
+x:
+    x++;
+    if (x < 3) goto x;
+    return x;
+}
@@ -0,0 +1,20 @@
+// code: 7
+// a label can share its name with a function, a shadowed variable, or a label in another function
+int f() {
+    goto f;
+f:
+    return 2;
+}
+int main() {
+    int f_result = f();
+    int y = 1;
+    {
+        int y = 4;
+        goto y;
+    y:
+        f_result += y;
+    }
+    goto main;
+main:
+    return f_result + y;
+}
@@ -0,0 +1,36 @@
+// code: 42
+// labels are function-scoped, so jumps can cross any number of blocks
+int main() {
+    int x = 0;
+    {
+        int y = 1;
+        {
+            x += y;
+            {
+                int z = 40;
+                x += z;
+                // forward out of three blocks to the top level
+                if (x > 0) goto done;
+                x = 100;
+            }
+        }
+    }
+    return 1;
+done:
+    if (x == 41) {
+        x++;
+        // backward into the middle of a nested block
+        {
+            {
+                goto check;
+            }
+        }
+    }
+    return 2;
+    {
+        {
+            check:
+                return x;
+        }
+    }
+}
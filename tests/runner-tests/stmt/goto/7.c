@@ -0,0 +1,16 @@
+// code: 6
+// a backward jump out of nested blocks, used as a loop
+int main() {
+    int i = 0, total = 0;
+again:
+    {
+        {
+            {
+                total += i;
+                i++;
+                if (i < 4) goto again;
+            }
+        }
+    }
+    return total;
+}
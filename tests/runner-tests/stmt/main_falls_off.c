@@ -0,0 +1,7 @@
+// code: 0
+int main(void) {
+    int x = 5;
+    if (x > 3) {
+        x = 0;
+    }
+}
@@ -0,0 +1,10 @@
+// code: 1
+// the outer variable is visible again once the block shadowing it ends
+int main() {
+    int i = 1;
+    {
+        int i = 2;
+        i++;
+    }
+    return i;
+}
@@ -0,0 +1,14 @@
+// code: 7
+// sibling blocks can declare the same name
+int main() {
+    int total = 0;
+    {
+        int x = 3;
+        total += x;
+    }
+    {
+        int x = 4;
+        total += x;
+    }
+    return total;
+}
@@ -0,0 +1,7 @@
+// fail
+int main() {
+    {
+        int inner = 1;
+    }
+    return inner;
+}
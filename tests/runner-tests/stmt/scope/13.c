@@ -0,0 +1,12 @@
+// code: 8
+// a declaration in a for loop or a function body doesn't outlive it
+int i = 5;
+int f() {
+    int i = 100;
+    return i;
+}
+int main() {
+    for (int i = 0; i < 3; i++) {}
+    f();
+    return i + 3;
+}
@@ -0,0 +1,30 @@
+// code: 42
+int lookup(int i) {
+    switch (i) {
+        case -1: return 1;
+        case 0: return 2;
+        case 1: return 3;
+        case 2: return 4;
+        case 4: return 5;
+        case 5:
+        case 6: return 6;
+        default: return 0;
+    }
+}
+int main(void) {
+    int total = 0;
+    for (int i = -3; i <= 8; i++) {
+        total += lookup(i);
+    }
+    char c = -1;
+    switch (c) {
+        case -1: total += 3; break;
+        case 0: case 1: case 2: total += 100; break;
+    }
+    unsigned u = 3000000000u;
+    switch (u) {
+        case 0: case 1: case 2: case 3: total += 100; break;
+        default: total += 12;
+    }
+    return total;
+}
@@ -0,0 +1,10 @@
+// fail
+int main(void) {
+    unsigned u = 0;
+    // both labels are UINT_MAX after conversion to `unsigned`
+    switch (u) {
+        case -1: return 1;
+        case 4294967295u: return 2;
+    }
+    return 0;
+}
@@ -0,0 +1,11 @@
+// code: 5
+int f(int x) {
+    switch (x) {
+        case 1: return 2;
+        case 2: return 3;
+        default: __builtin_unreachable();
+    }
+}
+int main() {
+    return f(1) + f(2);
+}
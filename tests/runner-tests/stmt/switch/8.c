@@ -0,0 +1,10 @@
+// crash
+int f(int x) {
+    switch (x) {
+        case 1: return 2;
+        default: __builtin_unreachable();
+    }
+}
+int main() {
+    return f(3);
+}
@@ -0,0 +1,10 @@
+// code: 1
+int main(void) {
+    int x = 300;
+    // the condition is promoted to `int`, so `(char)x` is 44 and never 300
+    switch ((char)x) {
+        case 300: return 2;
+        case 44: return 1;
+    }
+    return 0;
+}
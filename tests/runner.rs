@@ -56,6 +56,12 @@ fn run_one(path: &path::Path) -> Result<(), io::Error> {
                     .expect("tests should have an integer after code:");
                 utils::assert_code(&program, code);
                 return Ok(());
+            } else if line.starts_with("// trapv code: ") {
+                let code = line["// trapv code: ".len()..]
+                    .parse()
+                    .expect("tests should have an integer after trapv code:");
+                utils::assert_trapv_code(&program, code);
+                return Ok(());
             } else if line.starts_with("// errors: ") {
                 let errors = line["// errors: ".len()..]
                     .parse()
@@ -9,7 +9,7 @@ extern crate rcc;
 extern crate tempfile;
 
 use log::info;
-use rcc::Error;
+use rcc::{Error, Opt};
 
 pub fn init() {
     env_logger::builder().is_test(true).init();
@@ -35,19 +35,21 @@ pub fn cpp() -> std::process::Command {
 }
 
 pub fn compile_and_run(program: &str, args: &[&str]) -> Result<Output, Error> {
-    let output = compile(program, false)?;
+    compile_and_run_with(program, args, &Default::default())
+}
+
+pub fn compile_and_run_with(program: &str, args: &[&str], opt: &Opt) -> Result<Output, Error> {
+    let output = compile_with(program, false, opt)?;
     info!("running file {:?}", output);
     run(&output, args).map_err(Error::IO)
 }
 
 pub fn compile(program: &str, no_link: bool) -> Result<tempfile::TempPath, Error> {
-    let (result, _warnings) = rcc::compile(
-        program,
-        "<integration-test>".to_string(),
-        false,
-        false,
-        false,
-    );
+    compile_with(program, no_link, &Default::default())
+}
+
+pub fn compile_with(program: &str, no_link: bool, opt: &Opt) -> Result<tempfile::TempPath, Error> {
+    let (result, _warnings) = rcc::compile(program, "<integration-test>".to_string(), opt);
     let module = result?;
     let output = tempfile::NamedTempFile::new()
         .expect("cannot create tempfile")
@@ -146,8 +148,21 @@ pub fn assert_succeeds(program: &str) {
 }
 
 pub fn assert_code(program: &str, code: i32) {
+    assert_code_with(program, code, &Default::default())
+}
+
+/// Like `assert_code`, but with signed overflow trapping (`-ftrapv`).
+pub fn assert_trapv_code(program: &str, code: i32) {
+    let opt = Opt {
+        trap_overflow: true,
+        ..Opt::default()
+    };
+    assert_code_with(program, code, &opt)
+}
+
+fn assert_code_with(program: &str, code: i32, opt: &Opt) {
     assert!(
-        match compile_and_run(program, &[]) {
+        match compile_and_run_with(program, &[], opt) {
             Err(_) => false,
             Ok(output) => match output.status.code() {
                 Some(actual) => actual == code,
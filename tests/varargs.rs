@@ -82,3 +82,17 @@ fn multiple_given_args() {
 fn floats() {
     printf_helper("%f < %f", &["1.0", "1.2"]);
 }
+
+#[test]
+fn promotions() {
+    utils::assert_output(
+        "int printf(const char *format, ...);
+        int main() {
+            char c = 'a';
+            short s = 2;
+            float f = 1.5;
+            printf(\"%c %d %.1f\\n\", c, s, f);
+        }",
+        "a 2 1.5\n",
+    );
+}